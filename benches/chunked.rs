@@ -0,0 +1,92 @@
+// 100 MB 文件的两种发送方式：整个读进内存再作为响应体，和 actix-files 按需读取、ChunkedBody 分块发送
+// 分别计时拿到第一块数据（time-to-first-byte）和读完整个响应体
+mod common;
+
+use actix_web::{
+    body::{self, MessageBody},
+    dev::{Service, ServiceResponse},
+    middleware::from_fn,
+    rt::Runtime,
+    test as actix_test, web, App, Error, HttpResponse,
+};
+use common::{config, web_config, Rng, SEED};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use local_crates_io_index::{chunked, listing};
+use std::{fs, future::poll_fn, path::PathBuf, pin::pin};
+use tempfile::TempDir;
+
+const FILE_SIZE: usize = 100 * 1024 * 1024;
+const FILE_NAME: &str = "big-1.0.0.crate";
+
+async fn buffered(path: web::Data<PathBuf>) -> HttpResponse {
+    HttpResponse::Ok().body(tokio::fs::read(path.as_ref()).await.unwrap())
+}
+
+async fn first_chunk<S, R, B>(app: &S, req: R) -> usize
+where
+    S: Service<R, Response = ServiceResponse<B>, Error = Error>,
+    B: MessageBody,
+{
+    let res = app.call(req).await.unwrap();
+    let mut body = pin!(res.into_body());
+    let chunk = poll_fn(|cx| body.as_mut().poll_next(cx)).await;
+    chunk.map_or(0, |chunk| chunk.ok().unwrap().len())
+}
+
+async fn full_body<S, R, B>(app: &S, req: R) -> usize
+where
+    S: Service<R, Response = ServiceResponse<B>, Error = Error>,
+    B: MessageBody,
+{
+    let res = app.call(req).await.unwrap();
+    body::to_bytes(res.into_body()).await.ok().unwrap().len()
+}
+
+fn chunked_transfer(c: &mut Criterion) {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join(FILE_NAME);
+    fs::write(&path, Rng::new(SEED).bytes(FILE_SIZE)).unwrap();
+
+    let request = || actix_test::TestRequest::get().uri(&format!("/{}", FILE_NAME)).to_request();
+    let rt = Runtime::new().unwrap();
+    let buffered_app = rt.block_on(actix_test::init_service(
+        App::new()
+            .app_data(web::Data::new(path.clone()))
+            .route("/{file}", web::get().to(buffered)),
+    ));
+    let config = web_config("use_chunked_transfer = true\nchunked_threshold_kb = 1024\nchunk_size_kb = 64");
+    let streaming_app = rt.block_on(actix_test::init_service(
+        App::new()
+            .app_data(web::Data::new(config))
+            .wrap(from_fn(chunked::chunk_large_responses))
+            .service(listing::files_service(dir.path(), false, 0)),
+    ));
+    assert_eq!(rt.block_on(full_body(&buffered_app, request())), FILE_SIZE);
+    assert_eq!(rt.block_on(full_body(&streaming_app, request())), FILE_SIZE);
+
+    let mut group = c.benchmark_group("chunked_first_byte");
+    group.bench_function(BenchmarkId::new("full_buffer", FILE_SIZE), |b| {
+        b.iter(|| rt.block_on(first_chunk(&buffered_app, request())))
+    });
+    group.bench_function(BenchmarkId::new("streaming", FILE_SIZE), |b| {
+        b.iter(|| rt.block_on(first_chunk(&streaming_app, request())))
+    });
+    group.finish();
+
+    let mut group = c.benchmark_group("chunked_full_body");
+    group.throughput(Throughput::Bytes(FILE_SIZE as u64));
+    group.bench_function(BenchmarkId::new("full_buffer", FILE_SIZE), |b| {
+        b.iter(|| rt.block_on(full_body(&buffered_app, request())))
+    });
+    group.bench_function(BenchmarkId::new("streaming", FILE_SIZE), |b| {
+        b.iter(|| rt.block_on(full_body(&streaming_app, request())))
+    });
+    group.finish();
+}
+
+criterion_group! {
+    name = benches;
+    config = config();
+    targets = chunked_transfer
+}
+criterion_main!(benches);
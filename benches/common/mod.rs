@@ -0,0 +1,158 @@
+// benches 共用：固定种子的随机数、生成索引行和索引目录、统一的 Criterion 配置
+// 数据全部由固定种子生成，两次运行的输入完全相同，--save-baseline 的结果才能互相比较
+#![allow(dead_code)]
+
+use criterion::Criterion;
+use git2::{Oid, Repository, Signature};
+use local_crates_io_index::{config::WebConfig, sparse};
+use std::{fs, path::Path, time::Duration};
+
+pub const SEED: u64 = 0x5eed_1dce_2024_0001;
+
+// SplitMix64，够用且不需要额外依赖
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Rng(seed)
+    }
+
+    pub fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    pub fn below(&mut self, n: u64) -> u64 {
+        self.next() % n
+    }
+
+    pub fn ident(&mut self, min: u64, max: u64) -> String {
+        let len = min + self.below(max - min + 1);
+        let first = (b'a' + self.below(26) as u8) as char;
+        std::iter::once(first)
+            .chain((1..len).map(|_| match self.below(30) {
+                0..=25 => (b'a' + self.below(26) as u8) as char,
+                26 | 27 => '-',
+                _ => (b'0' + self.below(10) as u8) as char,
+            }))
+            .collect()
+    }
+
+    pub fn hex(&mut self, len: usize) -> String {
+        (0..len).map(|_| char::from_digit(self.below(16) as u32, 16).unwrap()).collect()
+    }
+
+    // .crate 文件是压缩过的 tar，内容接近随机
+    pub fn bytes(&mut self, len: usize) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(len + 8);
+        while bytes.len() < len {
+            bytes.extend_from_slice(&self.next().to_le_bytes());
+        }
+        bytes.truncate(len);
+        bytes
+    }
+}
+
+// 和 crates.io 索引里的格式一致，带依赖和 features，字段顺序也相同
+pub fn index_line(rng: &mut Rng, name: &str, version: usize) -> String {
+    let deps: Vec<String> = (0..rng.below(6))
+        .map(|_| {
+            format!(
+                r#"{{"name":"{}","req":"^{}.{}","features":[],"optional":{},"default_features":true,"target":null,"kind":"normal"}}"#,
+                rng.ident(3, 16),
+                rng.below(3),
+                rng.below(20),
+                rng.below(4) == 0
+            )
+        })
+        .collect();
+    let features: Vec<String> = (0..rng.below(4))
+        .map(|_| format!(r#""{}":["{}/std"]"#, rng.ident(3, 10), rng.ident(3, 10)))
+        .collect();
+    format!(
+        r#"{{"name":"{}","vers":"0.{}.{}","deps":[{}],"cksum":"{}","features":{{{}}},"yanked":{},"rust_version":"1.{}"}}"#,
+        name,
+        version / 10,
+        version % 10,
+        deps.join(","),
+        rng.hex(64),
+        features.join(","),
+        rng.below(20) == 0,
+        56 + rng.below(30)
+    )
+}
+
+pub fn crate_names(rng: &mut Rng, count: usize) -> Vec<String> {
+    let mut names = std::collections::HashSet::with_capacity(count);
+    let mut ordered = Vec::with_capacity(count);
+    while ordered.len() < count {
+        let name = rng.ident(4, 18);
+        if names.insert(name.clone()) {
+            ordered.push(name);
+        }
+    }
+    ordered
+}
+
+pub fn write_index_file(root: &Path, name: &str, content: &str) {
+    let path = root.join(sparse::crate_index_path(name).unwrap());
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(path, content).unwrap();
+}
+
+// 每个 crate 1 到 8 个版本，返回每个 crate 的文件内容
+pub fn write_index(root: &Path, names: &[String], rng: &mut Rng) -> Vec<String> {
+    let files: Vec<String> = names
+        .iter()
+        .map(|name| {
+            let versions = 1 + rng.below(8) as usize;
+            (0..versions).map(|v| index_line(rng, name, v) + "\n").collect()
+        })
+        .collect();
+    for (name, content) in names.iter().zip(&files) {
+        write_index_file(root, name, content);
+    }
+    fs::write(root.join("config.json"), "{}\n").unwrap();
+    files
+}
+
+pub fn commit_all(repo: &Repository, message: &str) -> Oid {
+    let mut index = repo.index().unwrap();
+    index.add_all(["*"], git2::IndexAddOption::DEFAULT, None).unwrap();
+    index.write().unwrap();
+    let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+    let signature = Signature::now("bench", "bench@localhost").unwrap();
+    let parent = repo.head().ok().map(|head| head.peel_to_commit().unwrap());
+    let parents: Vec<_> = parent.iter().collect();
+    repo.commit(Some("HEAD"), &signature, &signature, message, &tree, &parents)
+        .unwrap()
+}
+
+// 在 changed 个随机选出的 crate 文件末尾各加一个版本
+pub fn add_versions(root: &Path, names: &[String], files: &mut [String], changed: usize, rng: &mut Rng) {
+    for i in 0..changed {
+        let index = (rng.below(names.len() as u64) as usize + i) % names.len();
+        let version = files[index].lines().count();
+        let line = index_line(rng, &names[index], version);
+        files[index].push_str(&line);
+        files[index].push('\n');
+        write_index_file(root, &names[index], &files[index]);
+    }
+}
+
+// 和配置文件里的 [web] 一样解析，只需要写出和默认值不同的字段
+pub fn web_config(extra: &str) -> WebConfig {
+    toml::from_str(&format!("address = \"127.0.0.1\"\nport = 0\n{}", extra)).unwrap()
+}
+
+// 样本数、预热和测量时间固定，不同机器上的 baseline 才有可比性
+pub fn config() -> Criterion {
+    Criterion::default()
+        .sample_size(20)
+        .warm_up_time(Duration::from_secs(2))
+        .measurement_time(Duration::from_secs(10))
+        .noise_threshold(0.05)
+}
@@ -0,0 +1,54 @@
+// 每个响应加 Strict-Transport-Security 的开销：每次格式化字符串和使用启动时算好的 HeaderValue
+mod common;
+
+use actix_web::http::header::{HeaderMap, HeaderValue, STRICT_TRANSPORT_SECURITY};
+use common::config;
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use local_crates_io_index::{
+    config::SecurityHeadersConfig,
+    security_headers::{hsts_value, HstsHeader},
+};
+use std::hint::black_box;
+
+// 一次迭代相当于 100k req/s 下一秒的请求
+const REQUESTS: u64 = 100_000;
+
+fn hsts(c: &mut Criterion) {
+    let config = SecurityHeadersConfig {
+        hsts: true,
+        hsts_max_age: 63072000,
+        hsts_include_subdomains: true,
+        hsts_preload: true,
+        ..Default::default()
+    };
+    let precomputed = HstsHeader::from_config(&config);
+    let mut headers = HeaderMap::new();
+
+    let mut group = c.benchmark_group("hsts_header");
+    group.throughput(Throughput::Elements(REQUESTS));
+    group.bench_function("format_per_request", |b| {
+        b.iter(|| {
+            for _ in 0..REQUESTS {
+                let value = HeaderValue::from_str(&hsts_value(black_box(&config))).unwrap();
+                headers.insert(STRICT_TRANSPORT_SECURITY, value);
+            }
+        })
+    });
+    group.bench_function("precomputed", |b| {
+        b.iter(|| {
+            for _ in 0..REQUESTS {
+                let value = black_box(&precomputed).value().unwrap().clone();
+                headers.insert(STRICT_TRANSPORT_SECURITY, value);
+            }
+        })
+    });
+    group.finish();
+    assert_eq!(headers.get(STRICT_TRANSPORT_SECURITY).unwrap(), "max-age=63072000; includeSubDomains; preload");
+}
+
+criterion_group! {
+    name = benches;
+    config = config();
+    targets = hsts
+}
+criterion_main!(benches);
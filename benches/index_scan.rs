@@ -0,0 +1,40 @@
+// 统计和搜索用的全量扫描：scan_parallelism = 1 相当于顺序扫描，和按 CPU 数并行比较
+mod common;
+
+use common::{config, crate_names, write_index, Rng, SEED};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use local_crates_io_index::{index::IndexScanner, snapshot::ServingRoot};
+use std::{hint::black_box, sync::Arc};
+use tempfile::TempDir;
+
+// crates.io 有几十万个文件，这里取一部分，目录结构相同
+const CRATES: usize = 20_000;
+
+fn index_scan(c: &mut Criterion) {
+    let dir = TempDir::new().unwrap();
+    let mut rng = Rng::new(SEED);
+    let names = crate_names(&mut rng, CRATES);
+    write_index(dir.path(), &names, &mut rng);
+    let root = Arc::new(ServingRoot::new(dir.path(), false, false));
+
+    // 第二组和 scan_parallelism 的默认值一样取 CPU 数，单核机器上也至少两个线程
+    let cpus = std::thread::available_parallelism().map_or(1, |n| n.get());
+
+    let mut group = c.benchmark_group("index_scan");
+    group.throughput(Throughput::Elements(CRATES as u64));
+    for threads in [1, cpus.max(2)] {
+        let scanner = IndexScanner::new(Arc::clone(&root), threads);
+        assert_eq!(scanner.scan().len(), CRATES);
+        group.bench_function(BenchmarkId::new("scan_parallelism", threads), |b| {
+            b.iter(|| black_box(scanner.scan()))
+        });
+    }
+    group.finish();
+}
+
+criterion_group! {
+    name = benches;
+    config = config();
+    targets = index_scan
+}
+criterion_main!(benches);
@@ -0,0 +1,226 @@
+// pull 路径上的三段热点：拉取并检出上游变更、文件变化后的缓存失效、逐行解析索引文件（和 serde_json 对比）
+mod common;
+
+use common::{add_versions, commit_all, config, crate_names, index_line, write_index, Rng, SEED};
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use git2::{build::RepoBuilder, Repository, RepositoryInitOptions};
+use local_crates_io_index::{
+    api::VersionCache,
+    git::{self, GitAuth, PullOutcome},
+    health::HealthState,
+    index_parser::fast_parse_index_line,
+    snapshot::{IndexVersion, ServingRoot},
+};
+use serde::Deserialize;
+use std::{
+    collections::BTreeMap,
+    fs,
+    hint::black_box,
+    path::{Path, PathBuf},
+};
+use tempfile::TempDir;
+
+// 上游仓库里的 crate 数量，变更的文件从里面挑
+const BASE_CRATES: usize = 2000;
+const CHANGED_FILES: [usize; 3] = [10, 100, 1000];
+
+fn copy_dir(from: &Path, to: &Path) {
+    fs::create_dir_all(to).unwrap();
+    for entry in fs::read_dir(from).unwrap() {
+        let entry = entry.unwrap();
+        let target = to.join(entry.file_name());
+        if entry.file_type().unwrap().is_dir() {
+            copy_dir(&entry.path(), &target);
+        } else {
+            fs::copy(entry.path(), target).unwrap();
+        }
+    }
+}
+
+// 裸仓库作为上游，master 比镜像模板多一个改了 changed 个文件的提交
+struct PullFixture {
+    _dir: TempDir,
+    upstream: String,
+    template: PathBuf,
+}
+
+impl PullFixture {
+    fn new(changed: usize) -> Self {
+        let dir = TempDir::new().unwrap();
+        let work_path = dir.path().join("work");
+        let work = Repository::init_opts(&work_path, RepositoryInitOptions::new().initial_head("master"))
+            .unwrap();
+
+        let mut rng = Rng::new(SEED);
+        let names = crate_names(&mut rng, BASE_CRATES);
+        let mut files = write_index(&work_path, &names, &mut rng);
+        commit_all(&work, "base");
+
+        // 镜像模板停在 base，之后每次迭代复制一份
+        let template = dir.path().join("template");
+        Repository::clone(work_path.to_str().unwrap(), &template).unwrap();
+
+        add_versions(&work_path, &names, &mut files, changed, &mut rng);
+        commit_all(&work, &format!("update {} crates", changed));
+
+        let bare = dir.path().join("upstream.git");
+        RepoBuilder::new()
+            .bare(true)
+            .clone(work_path.to_str().unwrap(), &bare)
+            .unwrap();
+        let upstream = bare.to_str().unwrap().to_string();
+        Repository::open(&template)
+            .unwrap()
+            .remote_set_url("origin", &upstream)
+            .unwrap();
+
+        PullFixture {
+            _dir: dir,
+            upstream,
+            template,
+        }
+    }
+}
+
+fn auth() -> GitAuth {
+    GitAuth {
+        use_credential_helper: false,
+        ssh_use_agent: false,
+        ssl_verify: true,
+    }
+}
+
+fn pull_repo(c: &mut Criterion) {
+    let mut group = c.benchmark_group("pull_repo");
+    for changed in CHANGED_FILES {
+        let fixture = PullFixture::new(changed);
+        group.bench_with_input(BenchmarkId::new("changed_files", changed), &fixture, |b, fixture| {
+            b.iter_batched(
+                || {
+                    let dir = TempDir::new().unwrap();
+                    copy_dir(&fixture.template, dir.path());
+                    let repo = Repository::open(dir.path()).unwrap();
+                    (dir, repo)
+                },
+                |(dir, repo)| {
+                    let outcome = git::pull_repo(&repo, &fixture.upstream, &auth(), false).unwrap();
+                    assert!(matches!(outcome, PullOutcome::FastForward { .. }));
+                    (dir, repo)
+                },
+                BatchSize::PerIteration,
+            )
+        });
+    }
+    group.finish();
+}
+
+// 索引文件变化后按 crate 删除缓存，缓存里有 BASE_CRATES 个 crate，每个若干版本
+fn cache_invalidation(c: &mut Criterion) {
+    let root = TempDir::new().unwrap();
+    let health = HealthState::new();
+    let updated = IndexVersion::current(&health, &ServingRoot::new(root.path(), false, false));
+    let mut rng = Rng::new(SEED);
+    let names = crate_names(&mut rng, BASE_CRATES);
+    let keys: Vec<(String, String)> = names
+        .iter()
+        .flat_map(|name| {
+            let versions = 1 + rng.below(8);
+            (0..versions).map(move |v| (name.clone(), format!("0.{}.{}", v / 10, v % 10)))
+        })
+        .collect();
+
+    let mut group = c.benchmark_group("cache_invalidation");
+    for changed in CHANGED_FILES {
+        let changed_names: Vec<&String> = (0..changed)
+            .map(|i| &names[(rng.below(BASE_CRATES as u64) as usize + i) % BASE_CRATES])
+            .collect();
+        group.throughput(Throughput::Elements(changed as u64));
+        group.bench_with_input(BenchmarkId::new("changed_crates", changed), &changed_names, |b, changed_names| {
+            b.iter_batched(
+                || {
+                    let cache = VersionCache::<u64>::default();
+                    // get 记录当前的索引版本，之后 insert 才会写入
+                    cache.get(updated, &keys[0]);
+                    for (i, key) in keys.iter().enumerate() {
+                        cache.insert(updated, key.clone(), i as u64);
+                    }
+                    cache
+                },
+                |cache| {
+                    let removed: usize = changed_names.iter().map(|name| cache.invalidate_crate(name)).sum();
+                    black_box(removed);
+                    cache
+                },
+                BatchSize::LargeInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+// 索引里一行的全部字段，serde_json 完整反序列化的对照组
+#[derive(Deserialize)]
+#[allow(dead_code)]
+struct FullIndexLine {
+    name: String,
+    vers: String,
+    deps: Vec<FullDependency>,
+    cksum: String,
+    features: BTreeMap<String, Vec<String>>,
+    yanked: bool,
+    rust_version: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[allow(dead_code)]
+struct FullDependency {
+    name: String,
+    req: String,
+    features: Vec<String>,
+    optional: bool,
+    default_features: bool,
+    target: Option<String>,
+    kind: Option<String>,
+}
+
+fn lines(content: &[u8]) -> impl Iterator<Item = &[u8]> {
+    content.split(|&b| b == b'\n').filter(|l| !l.trim_ascii().is_empty())
+}
+
+// 一个索引文件从几行到一万行，按字节计吞吐量；winnow 只取需要的字段，serde_json 反序列化整行
+fn index_line_parsing(c: &mut Criterion) {
+    let mut rng = Rng::new(SEED);
+    let mut group = c.benchmark_group("index_line_parsing");
+    for count in [1, 10, 100, 1000, 10_000] {
+        let content: String = (0..count).map(|v| index_line(&mut rng, "mirror-bench", v) + "\n").collect();
+        group.throughput(Throughput::Bytes(content.len() as u64));
+        group.bench_with_input(BenchmarkId::new("winnow", count), content.as_bytes(), |b, content| {
+            b.iter(|| {
+                let mut yanked = 0;
+                for line in lines(content) {
+                    let entry = fast_parse_index_line(line).unwrap();
+                    yanked += entry.yanked as usize;
+                }
+                black_box(yanked)
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("serde_json", count), content.as_bytes(), |b, content| {
+            b.iter(|| {
+                let mut yanked = 0;
+                for line in lines(content) {
+                    let entry: FullIndexLine = serde_json::from_slice(line).unwrap();
+                    yanked += entry.yanked as usize;
+                }
+                black_box(yanked)
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group! {
+    name = benches;
+    config = config();
+    targets = pull_repo, cache_invalidation, index_line_parsing
+}
+criterion_main!(benches);
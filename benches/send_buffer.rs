@@ -0,0 +1,59 @@
+// 本机客户端下载 50 MB 的 .crate 文件：系统默认的发送缓冲区和 web.tcp_send_buffer_kb 设置的大缓冲区
+mod common;
+
+use common::{config, web_config, Rng, SEED};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use local_crates_io_index::listen::bind_listener;
+use std::{
+    fs::{self, File},
+    io,
+    net::{SocketAddr, TcpStream},
+    path::PathBuf,
+    thread,
+};
+use tempfile::TempDir;
+
+const FILE_SIZE: usize = 50 * 1024 * 1024;
+
+// 每个连接发送一次整个文件然后关闭；Linux 上 io::copy 从文件到 socket 使用 sendfile
+fn serve(send_buffer_kb: u32, path: PathBuf) -> SocketAddr {
+    let config = web_config(&format!("tcp_send_buffer_kb = {}", send_buffer_kb));
+    let listener = bind_listener("127.0.0.1:0".parse().unwrap(), &config).unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut file = File::open(&path).unwrap();
+            io::copy(&mut file, &mut stream).unwrap();
+        }
+    });
+    addr
+}
+
+fn send_buffer(c: &mut Criterion) {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("demo-1.0.0.crate");
+    fs::write(&path, Rng::new(SEED).bytes(FILE_SIZE)).unwrap();
+
+    let mut group = c.benchmark_group("send_buffer");
+    group.throughput(Throughput::Bytes(FILE_SIZE as u64));
+    // 0 表示系统默认值
+    for send_buffer_kb in [0, 4096] {
+        let addr = serve(send_buffer_kb, path.clone());
+        group.bench_function(BenchmarkId::new("tcp_send_buffer_kb", send_buffer_kb), |b| {
+            b.iter(|| {
+                let mut stream = TcpStream::connect(addr).unwrap();
+                let received = io::copy(&mut stream, &mut io::sink()).unwrap();
+                assert_eq!(received, FILE_SIZE as u64);
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group! {
+    name = benches;
+    config = config();
+    targets = send_buffer
+}
+criterion_main!(benches);
@@ -0,0 +1,60 @@
+// 导出快照时保留未变化文件的 mtime（preserve_mtime）和只做完整导出的对比
+mod common;
+
+use common::{add_versions, commit_all, config, crate_names, write_index, Rng, SEED};
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use git2::{Oid, Repository, RepositoryInitOptions};
+use local_crates_io_index::snapshot::ServingRoot;
+use tempfile::TempDir;
+
+const CRATES: usize = 10_000;
+// 一次 pull 通常只改动很少的文件，其余文件都要恢复 mtime
+const CHANGED: usize = 100;
+
+fn set_master(repo: &Repository, oid: Oid) {
+    repo.reference("refs/heads/master", oid, true, "bench").unwrap();
+}
+
+fn snapshot_mtime(c: &mut Criterion) {
+    let dir = TempDir::new().unwrap();
+    let repo = Repository::init_opts(dir.path().join("index"), RepositoryInitOptions::new().initial_head("master"))
+        .unwrap();
+    let work = repo.workdir().unwrap().to_path_buf();
+    let mut rng = Rng::new(SEED);
+    let names = crate_names(&mut rng, CRATES);
+    let mut files = write_index(&work, &names, &mut rng);
+    let base = commit_all(&repo, "base");
+    add_versions(&work, &names, &mut files, CHANGED, &mut rng);
+    let head = commit_all(&repo, "update");
+
+    let mut group = c.benchmark_group("snapshot_publish");
+    group.throughput(Throughput::Elements(CRATES as u64));
+    for (name, preserve_mtime) in [("full_copy", false), ("preserve_mtime", true)] {
+        group.bench_function(BenchmarkId::new(name, CRATES), |b| {
+            b.iter_batched(
+                || {
+                    // 先发布 base 作为上一个快照，计时的是发布 head
+                    let snapshots = TempDir::new().unwrap();
+                    let root = ServingRoot::new(snapshots.path().join("index"), true, preserve_mtime);
+                    set_master(&repo, base);
+                    root.publish(&repo).unwrap();
+                    set_master(&repo, head);
+                    (snapshots, root)
+                },
+                |(snapshots, root)| {
+                    root.publish(&repo).unwrap();
+                    (snapshots, root)
+                },
+                BatchSize::PerIteration,
+            )
+        });
+    }
+    group.finish();
+}
+
+criterion_group! {
+    name = benches;
+    config = config();
+    targets = snapshot_mtime
+}
+criterion_main!(benches);
@@ -0,0 +1,59 @@
+// /api/v1/suggest 的两种查找：排序后的 Vec 二分查找（trie_enabled = false）和前缀树
+mod common;
+
+use common::{config, crate_names, Rng, SEED};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use local_crates_io_index::{api::sorted_prefix, trie::NameTrie};
+use std::hint::black_box;
+
+// crates.io 的规模
+const CRATES: usize = 500_000;
+const LIMIT: usize = 10;
+
+fn suggest(c: &mut Criterion) {
+    let mut rng = Rng::new(SEED);
+    let names = crate_names(&mut rng, CRATES);
+    let mut sorted: Vec<(String, String)> = names.iter().map(|name| (name.to_lowercase(), name.clone())).collect();
+    sorted.sort();
+    let mut trie = NameTrie::default();
+    for name in &names {
+        trie.insert(name);
+    }
+    // 补全时用户通常只输入了一两个字符，也包括查不到的前缀
+    let prefixes: Vec<String> = (0..256)
+        .map(|i| {
+            let name = &names[rng.below(CRATES as u64) as usize];
+            match i % 4 {
+                3 => format!("{}zzz", &name[..1]),
+                n => name[..(n + 1).min(name.len())].to_string(),
+            }
+        })
+        .collect();
+    for prefix in &prefixes {
+        assert_eq!(sorted_prefix(&sorted, prefix, LIMIT), trie.prefix(prefix, LIMIT));
+    }
+
+    let mut group = c.benchmark_group("suggest");
+    group.bench_function(BenchmarkId::new("sorted_vec", CRATES), |b| {
+        let mut i = 0;
+        b.iter(|| {
+            i = (i + 1) % prefixes.len();
+            black_box(sorted_prefix(&sorted, &prefixes[i], LIMIT))
+        })
+    });
+    group.bench_function(BenchmarkId::new("trie", CRATES), |b| {
+        let mut i = 0;
+        b.iter(|| {
+            i = (i + 1) % prefixes.len();
+            black_box(trie.prefix(&prefixes[i], LIMIT))
+        })
+    });
+    group.finish();
+}
+
+criterion_group! {
+    name = benches;
+    config = config();
+    targets = suggest
+}
+criterion_main!(benches);
@@ -0,0 +1,9 @@
+// tokio-console 只能看到用 --cfg tokio_unstable 编译的 tokio 产生的任务信息
+fn main() {
+    println!("cargo::rerun-if-changed=build.rs");
+    if std::env::var_os("CARGO_FEATURE_TOKIO_CONSOLE").is_some() && std::env::var_os("CARGO_CFG_TOKIO_UNSTABLE").is_none() {
+        println!(
+            "cargo::warning=the tokio-console feature needs RUSTFLAGS=\"--cfg tokio_unstable\", tokio-console will not see any tasks"
+        );
+    }
+}
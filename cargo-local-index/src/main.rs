@@ -0,0 +1,193 @@
+use clap::{Args, Parser, Subcommand};
+use serde_json::Value;
+use std::{fs, path::PathBuf, process::ExitCode, time::Duration};
+use toml_edit::{value, DocumentMut, Item, Table};
+
+// cargo 调用子命令时第一个参数是 local-index
+#[derive(Parser)]
+#[command(name = "cargo", bin_name = "cargo")]
+enum Cargo {
+    LocalIndex(LocalIndex),
+}
+
+#[derive(Args)]
+#[command(version, about = "Use a local-crates-io-index mirror from cargo")]
+struct LocalIndex {
+    /// Cargo config to edit and read the mirror from [default: ~/.cargo/config.toml]
+    #[arg(long, global = true)]
+    cargo_config: Option<PathBuf>,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Replace crates-io with the mirror in the cargo config
+    Configure {
+        /// Mirror address, e.g. http://mirror:8080
+        #[arg(long)]
+        url: String,
+        /// Name of the [source.<name>] table
+        #[arg(long, default_value = "local")]
+        name: String,
+    },
+    /// Show whether the mirror is ready and how stale its index is
+    Status {
+        /// Defaults to the source crates-io is replaced with
+        #[arg(long)]
+        url: Option<String>,
+    },
+    /// Search crate names on the mirror
+    Search {
+        query: String,
+        #[arg(long)]
+        url: Option<String>,
+        #[arg(long, default_value_t = 10)]
+        limit: usize,
+    },
+}
+
+fn cargo_config_path(path: Option<PathBuf>) -> Result<PathBuf, String> {
+    match path {
+        Some(path) => Ok(path),
+        None => dirs::home_dir()
+            .map(|home| home.join(".cargo").join("config.toml"))
+            .ok_or_else(|| "Cannot determine the home directory, use --cargo-config".to_string()),
+    }
+}
+
+// 文件不存在时当作空配置
+fn read_cargo_config(path: &PathBuf) -> Result<DocumentMut, String> {
+    match fs::read_to_string(path) {
+        Ok(content) => content
+            .parse()
+            .map_err(|e| format!("Failed to parse {}: {}", path.display(), e)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(DocumentMut::new()),
+        Err(e) => Err(format!("Failed to read {}: {}", path.display(), e)),
+    }
+}
+
+// 只修改 [source.crates-io] 和 [source.<name>]，保留配置里的其他内容和注释
+fn configure(path: &PathBuf, url: &str, name: &str) -> Result<(), String> {
+    if name == "crates-io" {
+        return Err("--name must not be crates-io".to_string());
+    }
+    let mut doc = read_cargo_config(path)?;
+    // 和 README 一样写成 [source.crates-io] 和 [source.<name>] 两个表
+    let table = |doc: &mut DocumentMut, key: &str| -> Result<(), String> {
+        let source = doc
+            .entry("source")
+            .or_insert_with(|| {
+                let mut table = Table::new();
+                table.set_implicit(true);
+                Item::Table(table)
+            })
+            .as_table_mut()
+            .ok_or_else(|| format!("source in {} is not a table", path.display()))?;
+        if !source.get(key).is_some_and(Item::is_table_like) {
+            source.insert(key, Item::Table(Table::new()));
+        }
+        Ok(())
+    };
+    table(&mut doc, "crates-io")?;
+    table(&mut doc, name)?;
+    doc["source"]["crates-io"]["replace-with"] = value(name);
+    doc["source"][name]["registry"] = value(format!("sparse+{}/", url.trim_end_matches('/')));
+
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    }
+    fs::write(path, doc.to_string()).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    println!("Configured crates-io to use {} ({}) in {}", name, url, path.display());
+    Ok(())
+}
+
+// 从 cargo 配置里找到 crates-io 被替换成的 sparse registry 地址
+fn configured_url(path: &PathBuf) -> Result<String, String> {
+    let doc = read_cargo_config(path)?;
+    let source = &doc["source"];
+    let name = source["crates-io"]["replace-with"]
+        .as_str()
+        .ok_or_else(|| format!("crates-io is not replaced in {}, use --url or run configure", path.display()))?;
+    let registry = source[name]["registry"]
+        .as_str()
+        .ok_or_else(|| format!("source.{}.registry is not set in {}", name, path.display()))?;
+    Ok(registry.trim_start_matches("sparse+").trim_end_matches('/').to_string())
+}
+
+fn mirror_url(url: Option<String>, config: &PathBuf) -> Result<String, String> {
+    match url {
+        Some(url) => Ok(url.trim_end_matches('/').to_string()),
+        None => configured_url(config),
+    }
+}
+
+fn get_json(request: reqwest::blocking::RequestBuilder) -> Result<(reqwest::StatusCode, Value), String> {
+    let res = request.send().map_err(|e| e.to_string())?;
+    let status = res.status();
+    let body = res.text().map_err(|e| e.to_string())?;
+    let json = serde_json::from_str(&body).map_err(|e| format!("Unexpected response ({}): {}", status, e))?;
+    Ok((status, json))
+}
+
+fn client() -> Result<reqwest::blocking::Client, String> {
+    reqwest::blocking::Client::builder()
+        .timeout(Duration::from_secs(30))
+        .build()
+        .map_err(|e| e.to_string())
+}
+
+// /health/ready 在索引不可用时返回 503，响应体格式相同
+fn status(url: &str) -> Result<bool, String> {
+    let (code, health) = get_json(client()?.get(format!("{}/health/ready", url)))?;
+    println!("Mirror:      {}", url);
+    println!("Name:        {}", health["name"].as_str().unwrap_or_default());
+    println!("Status:      {}", health["status"].as_str().unwrap_or_default());
+    println!("Last update: {}", health["last_update"].as_str().unwrap_or_default());
+    println!("Staleness:   {}s", health["staleness_secs"].as_i64().unwrap_or_default());
+    Ok(code.is_success())
+}
+
+fn search(url: &str, query: &str, limit: usize) -> Result<(), String> {
+    let request = client()?
+        .get(format!("{}/api/v1/crates", url))
+        .query(&[("q", query), ("per_page", &limit.to_string())]);
+    let (code, result) = get_json(request)?;
+    if !code.is_success() {
+        return Err(format!("Search failed: {}", code));
+    }
+    let crates = result["crates"].as_array().cloned().unwrap_or_default();
+    for krate in &crates {
+        println!(
+            "{} = \"{}\"",
+            krate["name"].as_str().unwrap_or_default(),
+            krate["max_version"].as_str().unwrap_or_default()
+        );
+    }
+    let total = result["meta"]["total"].as_u64().unwrap_or(crates.len() as u64);
+    if total > crates.len() as u64 {
+        println!("... and {} more", total - crates.len() as u64);
+    }
+    Ok(())
+}
+
+fn run(args: LocalIndex) -> Result<bool, String> {
+    let config = cargo_config_path(args.cargo_config)?;
+    match args.command {
+        Command::Configure { url, name } => configure(&config, &url, &name).map(|_| true),
+        Command::Status { url } => status(&mirror_url(url, &config)?),
+        Command::Search { query, url, limit } => search(&mirror_url(url, &config)?, &query, limit).map(|_| true),
+    }
+}
+
+fn main() -> ExitCode {
+    let Cargo::LocalIndex(args) = Cargo::parse();
+    match run(args) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,737 @@
+use actix_web::{web, HttpResponse};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{
+    config::{RegistryConfig, WebConfig},
+    health::HealthState,
+    index::IndexScanner,
+    panic_recovery,
+    problem::{self, ProblemDetails, ProblemType},
+    protocol::{Negotiate, ProtocolVersion, V2Field},
+    reverse_deps::{ReverseDependency, ReverseDependencyIndex},
+    snapshot::{IndexVersion, ServingRoot},
+    sparse,
+    trie::{self, TrieState},
+};
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct IndexStats {
+    crates: usize,
+    versions: usize,
+    yanked_versions: usize,
+    scan_duration_ms: u64,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/index/stats",
+    tag = "index",
+    responses((status = 200, description = "Totals from a full scan of the index", body = IndexStats))
+)]
+pub async fn index_stats(scanner: web::Data<IndexScanner>) -> actix_web::Result<HttpResponse> {
+    let scanner = scanner.into_inner();
+    let (crates, elapsed) = panic_recovery::block(move || {
+        let start = Instant::now();
+        let crates = scanner.scan();
+        (crates, start.elapsed())
+    })
+    .await?;
+
+    let versions: usize = crates.iter().map(|c| c.versions).sum();
+    let yanked: usize = crates.iter().map(|c| c.yanked).sum();
+    Ok(HttpResponse::Ok().json(IndexStats {
+        crates: crates.len(),
+        versions,
+        yanked_versions: yanked,
+        scan_duration_ms: elapsed.as_millis() as u64,
+    }))
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SearchQuery {
+    // 名称子串，不区分大小写
+    #[serde(default)]
+    q: String,
+    // 默认 10，最多 100
+    per_page: Option<usize>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SearchResult {
+    name: String,
+    max_version: String,
+    // 索引里没有描述，总是 null
+    description: Option<String>,
+    // max_version 的 rust_version
+    #[serde(skip_serializing_if = "V2Field::is_omitted")]
+    #[schema(value_type = Option<String>)]
+    rust_version: V2Field<Option<String>>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SearchMeta {
+    total: usize,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SearchResponse {
+    crates: Vec<SearchResult>,
+    meta: SearchMeta,
+}
+
+impl Negotiate for SearchResponse {
+    fn negotiate(&mut self, version: ProtocolVersion) {
+        for result in &mut self.crates {
+            result.rust_version.negotiate(version);
+        }
+    }
+}
+
+// cargo search 使用的 /api/v1/crates?q= 接口，按名称子串匹配
+#[utoipa::path(
+    get,
+    path = "/api/v1/crates",
+    tag = "search",
+    params(SearchQuery),
+    responses((status = 200, description = "Crates whose name contains q", body = SearchResponse))
+)]
+pub async fn search(
+    scanner: web::Data<IndexScanner>,
+    registry: web::Data<RegistryConfig>,
+    query: web::Query<SearchQuery>,
+    protocol: ProtocolVersion,
+) -> actix_web::Result<HttpResponse> {
+    let scanner = scanner.into_inner();
+    let crates = panic_recovery::block(move || scanner.scan()).await?;
+
+    let normalize = |name: &str| {
+        if registry.strict_name_matching {
+            name.to_lowercase()
+        } else {
+            sparse::normalize_name(name)
+        }
+    };
+    let q = normalize(&query.q);
+    let per_page = query.per_page.unwrap_or(10).min(100);
+    let matched: Vec<_> = crates
+        .iter()
+        .filter(|c| normalize(&c.name).contains(&q))
+        .collect();
+    let results: Vec<_> = matched
+        .iter()
+        .take(per_page)
+        .map(|c| SearchResult {
+            name: c.name.clone(),
+            max_version: c.max_version.clone(),
+            description: None,
+            rust_version: V2Field::new(c.rust_version.clone()),
+        })
+        .collect();
+    let mut response = SearchResponse {
+        crates: results,
+        meta: SearchMeta {
+            total: matched.len(),
+        },
+    };
+    response.negotiate(protocol);
+    Ok(HttpResponse::Ok().json(response))
+}
+
+struct CachedVersions<T> {
+    // 缓存的结果属于哪一次索引更新，变化后整体清空
+    updated: Option<IndexVersion>,
+    versions: HashMap<(String, String), T>,
+}
+
+// 以 (name, version) 为键，单个版本的元数据和 features 各用一个
+pub struct VersionCache<T = VersionResponse> {
+    entries: Mutex<CachedVersions<T>>,
+}
+
+impl<T> Default for VersionCache<T> {
+    fn default() -> Self {
+        VersionCache {
+            entries: Mutex::new(CachedVersions {
+                updated: None,
+                versions: HashMap::new(),
+            }),
+        }
+    }
+}
+
+impl<T: Clone> VersionCache<T> {
+    pub fn get(&self, updated: IndexVersion, key: &(String, String)) -> Option<T> {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        if entries.updated != Some(updated) {
+            *entries = CachedVersions {
+                updated: Some(updated),
+                versions: HashMap::new(),
+            };
+        }
+        entries.versions.get(key).cloned()
+    }
+
+    pub fn insert(&self, updated: IndexVersion, key: (String, String), value: T) {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        if entries.updated == Some(updated) {
+            entries.versions.insert(key, value);
+        }
+    }
+
+    // 索引文件被直接修改时删除这个 crate 的所有版本，返回删除的条目数
+    pub fn invalidate_crate(&self, name: &str) -> usize {
+        let name = sparse::normalize_name(name);
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        let before = entries.versions.len();
+        entries.versions.retain(|(key, _), _| sparse::normalize_name(key) != name);
+        before - entries.versions.len()
+    }
+}
+
+// 索引文件里的一行，只包含这个接口返回的字段
+#[derive(Deserialize)]
+struct IndexLine {
+    name: String,
+    vers: String,
+    cksum: String,
+    #[serde(default)]
+    yanked: bool,
+    #[serde(default)]
+    features: serde_json::Map<String, Value>,
+    #[serde(default)]
+    features2: serde_json::Map<String, Value>,
+    #[serde(default)]
+    deps: Vec<Value>,
+    links: Option<String>,
+    rust_version: Option<String>,
+}
+
+// 字段名和 crates.io API 一致；features2 合并到 features
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct VersionMetadata {
+    #[serde(rename = "crate")]
+    krate: String,
+    num: String,
+    checksum: String,
+    yanked: bool,
+    #[schema(value_type = Object)]
+    features: serde_json::Map<String, Value>,
+    #[schema(value_type = Vec<Object>)]
+    dependencies: Vec<Value>,
+    links: Option<String>,
+    #[serde(skip_serializing_if = "V2Field::is_omitted")]
+    #[schema(value_type = Option<String>)]
+    rust_version: V2Field<Option<String>>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct VersionResponse {
+    version: VersionMetadata,
+}
+
+impl Negotiate for VersionResponse {
+    fn negotiate(&mut self, version: ProtocolVersion) {
+        self.version.rust_version.negotiate(version);
+    }
+}
+
+impl From<IndexLine> for VersionResponse {
+    fn from(line: IndexLine) -> Self {
+        let mut features = line.features;
+        features.extend(line.features2);
+        VersionResponse {
+            version: VersionMetadata {
+                krate: line.name,
+                num: line.vers,
+                checksum: line.cksum,
+                yanked: line.yanked,
+                features,
+                dependencies: line.deps,
+                links: line.links,
+                rust_version: V2Field::new(line.rust_version),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct FeaturesResponse {
+    // 索引里的 features 原样返回
+    #[schema(value_type = Object)]
+    features: serde_json::Map<String, Value>,
+    // 使用 dep: 或 ?/ 语法的 features 在索引里单独存放
+    #[schema(value_type = Object)]
+    features2: serde_json::Map<String, Value>,
+    // 可选依赖隐式生成的同名 feature，依赖被 dep: 引用过或者已有同名 feature 时不会生成
+    implicit: Vec<String>,
+}
+
+// 两个版本的格式相同
+impl Negotiate for FeaturesResponse {
+    fn negotiate(&mut self, _: ProtocolVersion) {}
+}
+
+impl From<IndexLine> for FeaturesResponse {
+    fn from(line: IndexLine) -> Self {
+        let explicit = |name: &str| line.features.contains_key(name) || line.features2.contains_key(name);
+        let dep_prefixed: HashSet<&str> = line
+            .features
+            .values()
+            .chain(line.features2.values())
+            .filter_map(Value::as_array)
+            .flatten()
+            .filter_map(|value| value.as_str()?.strip_prefix("dep:"))
+            .collect();
+        let mut implicit: Vec<String> = line
+            .deps
+            .iter()
+            .filter(|dep| dep["optional"].as_bool().unwrap_or(false))
+            // 重命名的依赖在索引里 name 是新名字，feature 也用这个名字
+            .filter_map(|dep| dep["name"].as_str())
+            .filter(|name| !dep_prefixed.contains(name) && !explicit(name))
+            .map(str::to_string)
+            .collect();
+        implicit.sort();
+        implicit.dedup();
+        FeaturesResponse {
+            features: line.features,
+            features2: line.features2,
+            implicit,
+        }
+    }
+}
+
+// crate 不存在时返回 ProblemDetails 的 detail
+fn read_index_file(root: &ServingRoot, name: &str, strict: bool) -> Result<Vec<u8>, String> {
+    let not_exist = || format!("crate `{}` does not exist", name);
+    if !sparse::valid_crate_name(name) {
+        return Err(not_exist());
+    }
+    let root = root.get();
+    let index_path = sparse::resolve_crate_path(&root, name, strict).ok_or_else(not_exist)?;
+    std::fs::read(root.join(index_path)).map_err(|_| not_exist())
+}
+
+fn parse_lines<T: DeserializeOwned>(content: &[u8]) -> impl Iterator<Item = T> + '_ {
+    content
+        .split(|&b| b == b'\n')
+        .filter(|line| !line.trim_ascii().is_empty())
+        .filter_map(|line| serde_json::from_slice::<T>(line).ok())
+}
+
+fn find_version<T: From<IndexLine>>(root: &ServingRoot, name: &str, version: &str, strict: bool) -> Result<T, String> {
+    let content = read_index_file(root, name, strict)?;
+    let line = parse_lines::<IndexLine>(&content).find(|line| line.vers == version);
+    line.map(T::from)
+        .ok_or_else(|| format!("crate `{}` does not have a version `{}`", name, version))
+}
+
+// 单个版本的元数据，不需要客户端下载整个索引文件
+#[utoipa::path(
+    get,
+    path = "/api/v1/crates/{name}/{version}",
+    tag = "index",
+    params(
+        ("name" = String, Path, description = "Crate name, case-insensitive; - and _ match each other unless registry.strict_name_matching is set"),
+        ("version" = String, Path, description = "Exact version number"),
+    ),
+    responses(
+        (status = 200, description = "Metadata of the version", body = VersionResponse),
+        (status = 404, description = "Crate or version not found", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
+pub async fn crate_version(
+    path: web::Path<(String, String)>,
+    root: web::Data<ServingRoot>,
+    health: web::Data<HealthState>,
+    web_config: web::Data<WebConfig>,
+    registry: web::Data<RegistryConfig>,
+    cache: web::Data<VersionCache>,
+    protocol: ProtocolVersion,
+) -> actix_web::Result<HttpResponse> {
+    cached_version(path.into_inner(), root, health, web_config, registry, cache, protocol).await
+}
+
+// features 列表，供 IDE 等工具展示可选的 feature
+#[utoipa::path(
+    get,
+    path = "/api/v1/crates/{name}/{version}/features",
+    tag = "index",
+    params(
+        ("name" = String, Path, description = "Crate name, case-insensitive; - and _ match each other unless registry.strict_name_matching is set"),
+        ("version" = String, Path, description = "Exact version number"),
+    ),
+    responses(
+        (status = 200, description = "Features of the version", body = FeaturesResponse),
+        (status = 404, description = "Crate or version not found", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
+pub async fn crate_features(
+    path: web::Path<(String, String)>,
+    root: web::Data<ServingRoot>,
+    health: web::Data<HealthState>,
+    web_config: web::Data<WebConfig>,
+    registry: web::Data<RegistryConfig>,
+    cache: web::Data<VersionCache<FeaturesResponse>>,
+    protocol: ProtocolVersion,
+) -> actix_web::Result<HttpResponse> {
+    cached_version(path.into_inner(), root, health, web_config, registry, cache, protocol).await
+}
+
+async fn cached_version<T>(
+    key: (String, String),
+    root: web::Data<ServingRoot>,
+    health: web::Data<HealthState>,
+    web_config: web::Data<WebConfig>,
+    registry: web::Data<RegistryConfig>,
+    cache: web::Data<VersionCache<T>>,
+    protocol: ProtocolVersion,
+) -> actix_web::Result<HttpResponse>
+where
+    T: From<IndexLine> + Negotiate + Serialize + Clone + Send + 'static,
+{
+    // 缓存里保存的是完整的格式
+    let respond = |mut value: T| {
+        value.negotiate(protocol);
+        HttpResponse::Ok().json(value)
+    };
+    let updated = IndexVersion::current(&health, &root);
+    if web_config.cache_single_version {
+        if let Some(value) = cache.get(updated, &key) {
+            return Ok(respond(value));
+        }
+    }
+    let root = root.into_inner();
+    let (name, version) = key.clone();
+    let strict = registry.strict_name_matching;
+    let result = panic_recovery::block(move || find_version::<T>(&root, &name, &version, strict)).await?;
+    Ok(match result {
+        Ok(value) => {
+            if web_config.cache_single_version {
+                cache.insert(updated, key, value.clone());
+            }
+            respond(value)
+        }
+        Err(detail) => problem::not_found(detail).into(),
+    })
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum YankedFilter {
+    #[default]
+    Include,
+    Exclude,
+    Only,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct VersionsQuery {
+    // 默认 include
+    yanked: Option<YankedFilter>,
+}
+
+#[derive(Deserialize)]
+struct VersionLine {
+    vers: String,
+    cksum: String,
+    #[serde(default)]
+    yanked: bool,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct VersionEntry {
+    version: String,
+    yanked: bool,
+    cksum: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct VersionsResponse {
+    versions: Vec<VersionEntry>,
+}
+
+pub const LATEST_VERSION_HEADER: &str = "X-Latest-Version";
+
+// 所有版本按 semver 从小到大排序（预发布版本排在对应的正式版本之前），不需要下载完整的元数据
+// X-Latest-Version 和 cargo 默认选择的一样：最大的未 yank 的正式版本，没有正式版本时才是预发布版本；不受 yanked 参数影响
+#[utoipa::path(
+    get,
+    path = "/api/v1/crates/{name}/versions",
+    tag = "index",
+    params(
+        ("name" = String, Path, description = "Crate name, case-insensitive; - and _ match each other unless registry.strict_name_matching is set"),
+        VersionsQuery,
+    ),
+    responses(
+        (status = 200, description = "Versions sorted by semver, oldest first", body = VersionsResponse,
+            headers(("X-Latest-Version" = String, description = "Latest non-yanked version, absent when every version is yanked"))),
+        (status = 404, description = "Crate not found", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
+pub async fn crate_versions(
+    name: web::Path<String>,
+    query: web::Query<VersionsQuery>,
+    root: web::Data<ServingRoot>,
+    registry: web::Data<RegistryConfig>,
+) -> actix_web::Result<HttpResponse> {
+    let root = root.into_inner();
+    let name = name.into_inner();
+    let strict = registry.strict_name_matching;
+    let content = match panic_recovery::block(move || read_index_file(&root, &name, strict)).await? {
+        Ok(content) => content,
+        Err(detail) => return Ok(problem::not_found(detail).into()),
+    };
+    // 版本号不合法的行跳过
+    let mut versions: Vec<(semver::Version, VersionLine)> = parse_lines::<VersionLine>(&content)
+        .filter_map(|line| semver::Version::parse(&line.vers).ok().map(|parsed| (parsed, line)))
+        .collect();
+    versions.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let latest = versions
+        .iter()
+        .filter(|(_, line)| !line.yanked)
+        .max_by_key(|(parsed, _)| (parsed.pre.is_empty(), parsed))
+        .map(|(_, line)| line.vers.clone());
+    let filter = query.yanked.unwrap_or_default();
+    let versions = versions
+        .into_iter()
+        .map(|(_, line)| line)
+        .filter(|line| match filter {
+            YankedFilter::Include => true,
+            YankedFilter::Exclude => !line.yanked,
+            YankedFilter::Only => line.yanked,
+        })
+        .map(|line| VersionEntry {
+            version: line.vers,
+            yanked: line.yanked,
+            cksum: line.cksum,
+        })
+        .collect();
+    let mut res = HttpResponse::Ok();
+    if let Some(latest) = latest {
+        res.insert_header((LATEST_VERSION_HEADER, latest));
+    }
+    Ok(res.json(VersionsResponse { versions }))
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ReverseDependenciesQuery {
+    // 只返回版本要求包含这个版本的 crate
+    version: Option<String>,
+    // 从 1 开始
+    page: Option<usize>,
+    // 默认 10，最多 100
+    per_page: Option<usize>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ReverseDependenciesResponse {
+    dependents: Vec<ReverseDependency>,
+    meta: SearchMeta,
+}
+
+// 依赖这个 crate 的 crate，升级库之前查看影响范围
+#[utoipa::path(
+    get,
+    path = "/api/v1/crates/{name}/reverse-dependencies",
+    tag = "index",
+    params(
+        ("name" = String, Path, description = "Crate name, case-insensitive; - and _ match each other unless registry.strict_name_matching is set"),
+        ReverseDependenciesQuery,
+    ),
+    responses(
+        (status = 200, description = "Crates whose latest version depends on this crate, sorted by name", body = ReverseDependenciesResponse),
+        (status = 400, description = "version is not a valid semver version", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 404, description = "Crate not found or web.reverse_deps_enabled is false", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
+pub async fn reverse_dependencies(
+    name: web::Path<String>,
+    query: web::Query<ReverseDependenciesQuery>,
+    root: web::Data<ServingRoot>,
+    health: web::Data<HealthState>,
+    web_config: web::Data<WebConfig>,
+    registry: web::Data<RegistryConfig>,
+    index: web::Data<ReverseDependencyIndex>,
+) -> actix_web::Result<HttpResponse> {
+    if !web_config.reverse_deps_enabled {
+        return Ok(problem::not_found("reverse dependencies are disabled").into());
+    }
+    let version = match query.version.as_deref().map(semver::Version::parse).transpose() {
+        Ok(version) => version,
+        Err(e) => {
+            return Ok(ProblemDetails::new(ProblemType::BadRequest)
+                .with_detail(format!("invalid version: {}", e))
+                .into())
+        }
+    };
+    let name = name.into_inner();
+    let exists = {
+        let (root, name) = (root.get(), name.clone());
+        let strict = registry.strict_name_matching;
+        sparse::valid_crate_name(&name)
+            && panic_recovery::block(move || sparse::resolve_crate_path(&root, &name, strict)).await?.is_some()
+    };
+    if !exists {
+        return Ok(problem::not_found(format!("crate `{}` does not exist", name)).into());
+    }
+
+    let graph = index.graph(IndexVersion::current(&health, &root)).await?;
+    let matched: Vec<&ReverseDependency> = graph
+        .get(&sparse::normalize_name(&name))
+        .into_iter()
+        .flatten()
+        .filter(|dep| version.as_ref().is_none_or(|version| dep.matches(version)))
+        .collect();
+    let per_page = query.per_page.unwrap_or(10).clamp(1, 100);
+    let page = query.page.unwrap_or(1).max(1);
+    let dependents = matched
+        .iter()
+        .skip((page - 1).saturating_mul(per_page))
+        .take(per_page)
+        .map(|dep| (*dep).clone())
+        .collect();
+    Ok(HttpResponse::Ok().json(ReverseDependenciesResponse {
+        dependents,
+        meta: SearchMeta {
+            total: matched.len(),
+        },
+    }))
+}
+
+const MAX_SUGGESTIONS: usize = 20;
+
+#[derive(Default)]
+struct SortedNames {
+    updated: Option<IndexVersion>,
+    // (小写名称, 原始名称)，按小写名称排序
+    names: Arc<Vec<(String, String)>>,
+}
+
+// 索引更新后第一次请求时重新扫描或者更新前缀树；多个请求同时发现过期时只做一次
+pub struct SuggestionIndex {
+    trie_enabled: bool,
+    repo_path: PathBuf,
+    names: tokio::sync::Mutex<SortedNames>,
+    trie: tokio::sync::Mutex<Option<(IndexVersion, TrieState)>>,
+}
+
+impl SuggestionIndex {
+    pub fn new(trie_enabled: bool, repo_path: impl Into<PathBuf>) -> Self {
+        SuggestionIndex {
+            trie_enabled,
+            repo_path: repo_path.into(),
+            names: Default::default(),
+            trie: Default::default(),
+        }
+    }
+
+    async fn trie_prefix(
+        &self,
+        scanner: web::Data<IndexScanner>,
+        updated: IndexVersion,
+        prefix: &str,
+        limit: usize,
+    ) -> actix_web::Result<Vec<String>> {
+        let mut trie = self.trie.lock().await;
+        let state = match trie.take() {
+            Some((checked, state)) if checked == updated => state,
+            previous => {
+                let repo_path = self.repo_path.clone();
+                let scanner = scanner.into_inner();
+                let previous = previous.map(|(_, state)| state);
+                panic_recovery::block(move || trie::refresh(&repo_path, &scanner, previous)).await?
+            }
+        };
+        let matched = state.trie.prefix(prefix, limit).into_iter().map(String::from).collect();
+        *trie = Some((updated, state));
+        Ok(matched)
+    }
+
+    async fn names(
+        &self,
+        scanner: web::Data<IndexScanner>,
+        updated: IndexVersion,
+    ) -> actix_web::Result<Arc<Vec<(String, String)>>> {
+        let mut cached = self.names.lock().await;
+        if cached.updated != Some(updated) {
+            let scanner = scanner.into_inner();
+            let mut names: Vec<(String, String)> = panic_recovery::block(move || scanner.scan())
+                .await?
+                .into_iter()
+                .map(|c| (c.name.to_lowercase(), c.name))
+                .collect();
+            names.sort();
+            *cached = SortedNames {
+                updated: Some(updated),
+                names: Arc::new(names),
+            };
+        }
+        Ok(Arc::clone(&cached.names))
+    }
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SuggestQuery {
+    // 名称前缀，不区分大小写
+    #[serde(default)]
+    q: String,
+    // 默认 10，最多 20
+    limit: Option<usize>,
+}
+
+// 编辑器插件、shell 补全使用的名称前缀补全，不区分大小写
+#[utoipa::path(
+    get,
+    path = "/api/v1/suggest",
+    tag = "search",
+    params(SuggestQuery),
+    responses(
+        (status = 200, description = "Crate names starting with q, sorted", body = Vec<String>),
+        (status = 404, description = "web.suggestions_enabled is false", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
+pub async fn suggest(
+    query: web::Query<SuggestQuery>,
+    scanner: web::Data<IndexScanner>,
+    health: web::Data<HealthState>,
+    root: web::Data<ServingRoot>,
+    web_config: web::Data<WebConfig>,
+    index: web::Data<SuggestionIndex>,
+) -> actix_web::Result<HttpResponse> {
+    if !web_config.suggestions_enabled {
+        return Ok(problem::not_found("suggestions are disabled").into());
+    }
+    let prefix = query.q.to_lowercase();
+    let limit = query.limit.unwrap_or(10).min(MAX_SUGGESTIONS);
+    let updated = IndexVersion::current(&health, &root);
+    if index.trie_enabled {
+        return Ok(HttpResponse::Ok().json(index.trie_prefix(scanner, updated, &prefix, limit).await?));
+    }
+    let names = index.names(scanner, updated).await?;
+    Ok(HttpResponse::Ok().json(sorted_prefix(&names, &prefix, limit)))
+}
+
+// trie_enabled = false 时的查找：在按小写名称排序的 (小写名称, 原始名称) 里二分查找 prefix
+pub fn sorted_prefix<'a>(names: &'a [(String, String)], prefix: &str, limit: usize) -> Vec<&'a str> {
+    let start = names.partition_point(|(lower, _)| lower.as_str() < prefix);
+    names[start..]
+        .iter()
+        .take_while(|(lower, _)| lower.starts_with(prefix))
+        .take(limit)
+        .map(|(_, name)| name.as_str())
+        .collect()
+}
@@ -0,0 +1,270 @@
+use actix_web::{
+    dev::Payload,
+    error::InternalError,
+    http::header::{HeaderValue, WWW_AUTHENTICATE},
+    web, Error, FromRequest, HttpRequest, HttpResponse,
+};
+use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+use serde::{Deserialize, Serialize};
+use std::future::{ready, Ready};
+use tracing::debug;
+use utoipa::ToSchema;
+
+use crate::{
+    config::{AuthConfig, JwtConfig},
+    problem::{ProblemDetails, ProblemType},
+};
+
+// 未配置 [auth.jwt] 时 key 为 None，所有需要认证的接口都返回401
+pub struct JwtAuth {
+    key: Option<DecodingKey>,
+    validation: Validation,
+}
+
+impl JwtAuth {
+    pub fn from_config(config: &AuthConfig) -> Result<Self, String> {
+        let Some(jwt) = &config.jwt else {
+            return Ok(JwtAuth {
+                key: None,
+                validation: Validation::default(),
+            });
+        };
+        let (algorithm, key) = decoding_key(jwt)?;
+        let mut validation = Validation::new(algorithm);
+        // 默认已经校验 exp；没有配置 audience 时不校验 aud
+        // 配置了 issuer 或 audience 时 token 必须带有对应的声明，缺少时 jsonwebtoken 默认不报错
+        let mut required = vec!["exp"];
+        if jwt.audience.is_empty() {
+            validation.validate_aud = false;
+        } else {
+            validation.set_audience(&[&jwt.audience]);
+            required.push("aud");
+        }
+        if !jwt.issuer.is_empty() {
+            validation.set_issuer(&[&jwt.issuer]);
+            required.push("iss");
+        }
+        validation.set_required_spec_claims(&required);
+        Ok(JwtAuth {
+            key: Some(key),
+            validation,
+        })
+    }
+}
+
+fn decoding_key(jwt: &JwtConfig) -> Result<(Algorithm, DecodingKey), String> {
+    match jwt.algorithm.as_str() {
+        "HS256" => Ok((Algorithm::HS256, DecodingKey::from_secret(jwt.secret.as_bytes()))),
+        "RS256" => {
+            let path = jwt.public_key_file.as_deref().unwrap_or_default();
+            let pem = std::fs::read(path)
+                .map_err(|e| format!("Failed to read auth.jwt.public_key_file {}: {}", path, e))?;
+            let key = DecodingKey::from_rsa_pem(&pem)
+                .map_err(|e| format!("Invalid auth.jwt.public_key_file {}: {}", path, e))?;
+            Ok((Algorithm::RS256, key))
+        }
+        other => Err(format!("Unsupported auth.jwt.algorithm {:?}", other)),
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct AuthenticatedUser {
+    pub sub: String,
+    #[serde(default)]
+    pub scopes: Vec<String>,
+}
+
+fn unauthorized(detail: &str) -> Error {
+    let mut res: HttpResponse = ProblemDetails::new(ProblemType::Unauthorized).with_detail(detail).into();
+    res.headers_mut()
+        .insert(WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+    InternalError::from_response(detail.to_string(), res).into()
+}
+
+fn authenticate(req: &HttpRequest) -> Result<AuthenticatedUser, Error> {
+    let auth = req
+        .app_data::<web::Data<JwtAuth>>()
+        .expect("JwtAuth not registered");
+    let Some(key) = &auth.key else {
+        return Err(unauthorized("JWT authentication is not configured"));
+    };
+    let Some(token) = req
+        .headers()
+        .get("Authorization")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+    else {
+        return Err(unauthorized("Missing bearer token"));
+    };
+    jsonwebtoken::decode::<AuthenticatedUser>(token, key, &auth.validation)
+        .map(|data| data.claims)
+        .map_err(|e| {
+            debug!("Rejected JWT: {}", e);
+            unauthorized("Invalid token")
+        })
+}
+
+impl FromRequest for AuthenticatedUser {
+    type Error = Error;
+    type Future = Ready<Result<Self, Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(authenticate(req))
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/me",
+    tag = "auth",
+    security(("bearer" = [])),
+    responses(
+        (status = 200, description = "Claims of the bearer token", body = AuthenticatedUser),
+        (status = 401, description = "Missing or invalid token", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
+pub async fn me(user: AuthenticatedUser) -> HttpResponse {
+    HttpResponse::Ok().json(user)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{http::StatusCode, test as actix_test, App};
+    use jsonwebtoken::{EncodingKey, Header};
+    use serde_json::json;
+
+    const SECRET: &str = "test-secret";
+
+    fn config(issuer: &str, audience: &str) -> AuthConfig {
+        AuthConfig {
+            jwt: Some(JwtConfig {
+                secret: SECRET.to_string(),
+                algorithm: "HS256".to_string(),
+                issuer: issuer.to_string(),
+                audience: audience.to_string(),
+                public_key_file: None,
+            }),
+        }
+    }
+
+    fn token(claims: serde_json::Value, secret: &str) -> String {
+        jsonwebtoken::encode(&Header::default(), &claims, &EncodingKey::from_secret(secret.as_bytes())).unwrap()
+    }
+
+    fn exp(offset_secs: i64) -> i64 {
+        chrono::Utc::now().timestamp() + offset_secs
+    }
+
+    async fn get_me(config: &AuthConfig, authorization: Option<String>) -> (StatusCode, String) {
+        let app = actix_test::init_service(
+            App::new()
+                .app_data(web::Data::new(JwtAuth::from_config(config).unwrap()))
+                .route("/api/v1/me", web::get().to(me)),
+        )
+        .await;
+        let mut req = actix_test::TestRequest::get().uri("/api/v1/me");
+        if let Some(value) = authorization {
+            req = req.insert_header(("Authorization", value));
+        }
+        let res = actix_test::call_service(&app, req.to_request()).await;
+        let status = res.status();
+        let body = actix_test::read_body(res).await;
+        (status, String::from_utf8_lossy(&body).into_owned())
+    }
+
+    fn bearer(token: String) -> Option<String> {
+        Some(format!("Bearer {}", token))
+    }
+
+    #[actix_web::test]
+    async fn valid_token() {
+        let claims = json!({"sub": "alice", "scopes": ["read"], "iss": "mirror", "exp": exp(3600)});
+        let (status, body) = get_me(&config("mirror", ""), bearer(token(claims, SECRET))).await;
+        assert_eq!(status, StatusCode::OK);
+        let user: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(user, json!({"sub": "alice", "scopes": ["read"]}));
+    }
+
+    #[actix_web::test]
+    async fn expired_token() {
+        // 超过默认 60 秒的 leeway
+        let claims = json!({"sub": "alice", "exp": exp(-3600)});
+        let (status, _) = get_me(&config("", ""), bearer(token(claims, SECRET))).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+
+    #[actix_web::test]
+    async fn token_without_expiry() {
+        let (status, _) = get_me(&config("", ""), bearer(token(json!({"sub": "alice"}), SECRET))).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+
+    #[actix_web::test]
+    async fn wrong_issuer() {
+        let claims = json!({"sub": "alice", "iss": "someone-else", "exp": exp(3600)});
+        let (status, _) = get_me(&config("mirror", ""), bearer(token(claims, SECRET))).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        let no_iss = json!({"sub": "alice", "exp": exp(3600)});
+        let (status, _) = get_me(&config("mirror", ""), bearer(token(no_iss, SECRET))).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+
+    #[actix_web::test]
+    async fn audience() {
+        let config = config("", "mirror");
+        let claims = |aud: &str| json!({"sub": "alice", "aud": aud, "exp": exp(3600)});
+        let (status, _) = get_me(&config, bearer(token(claims("mirror"), SECRET))).await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, _) = get_me(&config, bearer(token(claims("other"), SECRET))).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        let no_aud = json!({"sub": "alice", "exp": exp(3600)});
+        let (status, _) = get_me(&config, bearer(token(no_aud, SECRET))).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+
+    #[actix_web::test]
+    async fn bad_signature() {
+        let claims = json!({"sub": "alice", "exp": exp(3600)});
+        let (status, _) = get_me(&config("", ""), bearer(token(claims.clone(), "other-secret"))).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        // 把 alg 改成 none 的 token
+        let signed = token(claims, SECRET);
+        let payload = signed.split('.').nth(1).unwrap();
+        let unsigned = format!("eyJhbGciOiJub25lIiwidHlwIjoiSldUIn0.{}.", payload);
+        let (status, _) = get_me(&config("", ""), bearer(unsigned)).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+
+    #[actix_web::test]
+    async fn missing_header() {
+        let (status, body) = get_me(&config("", ""), None).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert!(body.contains("Missing bearer token"));
+        let (status, _) = get_me(&config("", ""), Some("Basic YWxpY2U6c2VjcmV0".to_string())).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+
+    #[actix_web::test]
+    async fn not_configured() {
+        let claims = json!({"sub": "alice", "exp": exp(3600)});
+        let (status, body) = get_me(&AuthConfig::default(), bearer(token(claims, SECRET))).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert!(body.contains("not configured"));
+    }
+
+    #[test]
+    fn rs256_requires_readable_key() {
+        let config = AuthConfig {
+            jwt: Some(JwtConfig {
+                secret: String::new(),
+                algorithm: "RS256".to_string(),
+                issuer: String::new(),
+                audience: String::new(),
+                public_key_file: Some("/nonexistent/key.pem".to_string()),
+            }),
+        };
+        assert!(JwtAuth::from_config(&config).is_err());
+    }
+}
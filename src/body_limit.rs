@@ -0,0 +1,155 @@
+use actix_web::{
+    body::{BodySize, EitherBody, MessageBody},
+    dev::{ServiceRequest, ServiceResponse},
+    middleware::Next,
+    web, Error, ResponseError,
+};
+use bytes::Bytes;
+use std::{
+    error::Error as StdError,
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
+use tracing::error;
+
+use crate::{
+    config::WebConfig,
+    metrics,
+    problem::{ProblemDetails, ProblemType},
+};
+
+// pack 的大小和索引的历史长度有关，git smart HTTP 和 git bundle 不受 web.max_response_body_mb 限制
+const UNLIMITED_PATHS: &[&str] = &["/git-upload-pack", "/api/v1/git-bundle"];
+
+fn limit_exceeded(path: &str, limit: u64) {
+    metrics::RESPONSE_BODY_LIMIT_EXCEEDED_TOTAL.inc();
+    error!(
+        %path,
+        "Response body exceeds web.max_response_body_mb ({} bytes), the checkout may be corrupted",
+        limit
+    );
+}
+
+// 记录已经发送的字节数，超过上限后返回错误，actix-web 随即中断连接
+pub struct LimitedBody<B> {
+    inner: Pin<Box<B>>,
+    path: String,
+    limit: u64,
+    sent: u64,
+}
+
+impl<B: MessageBody> MessageBody for LimitedBody<B> {
+    type Error = Box<dyn StdError>;
+
+    fn size(&self) -> BodySize {
+        self.inner.size()
+    }
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Bytes, Self::Error>>> {
+        let this = self.as_mut().get_mut();
+        match ready!(this.inner.as_mut().poll_next(cx)) {
+            Some(Ok(chunk)) => {
+                this.sent += chunk.len() as u64;
+                if this.sent > this.limit {
+                    limit_exceeded(&this.path, this.limit);
+                    return Poll::Ready(Some(Err("response body limit exceeded".into())));
+                }
+                Poll::Ready(Some(Ok(chunk)))
+            }
+            Some(Err(e)) => Poll::Ready(Some(Err(e.into()))),
+            None => Poll::Ready(None),
+        }
+    }
+}
+
+// 大小已知时直接返回 500，否则在发送过程中计数
+pub async fn limit_response_body(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<LimitedBody<impl MessageBody>>>, Error> {
+    let config = req
+        .app_data::<web::Data<WebConfig>>()
+        .cloned()
+        .expect("WebConfig not registered");
+    let path = req.path().to_string();
+    let limit = match config.max_response_body_mb {
+        0 => u64::MAX,
+        _ if UNLIMITED_PATHS.contains(&path.as_str()) => u64::MAX,
+        mb => mb * 1024 * 1024,
+    };
+    let res = next.call(req).await?;
+    if let BodySize::Sized(size) = res.response().body().size() {
+        if size > limit {
+            limit_exceeded(&path, limit);
+            let (req, _) = res.into_parts();
+            let res = ProblemDetails::new(ProblemType::ResponseTooLarge).error_response();
+            return Ok(ServiceResponse::new(req, res).map_into_right_body());
+        }
+    }
+    Ok(res
+        .map_body(|_, body| LimitedBody {
+            inner: Box::pin(body),
+            path,
+            limit,
+            sent: 0,
+        })
+        .map_into_left_body())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::web_config;
+    use actix_web::{body, middleware::from_fn, test as actix_test, App, HttpResponse};
+    use futures_util::stream;
+
+    const MB: usize = 1024 * 1024;
+
+    // 每块 64 KiB，一共 size 字节，大小未知
+    fn streamed(size: usize) -> HttpResponse {
+        let chunks = (0..size / (64 * 1024)).map(|_| Ok::<_, Error>(Bytes::from(vec![b'x'; 64 * 1024])));
+        HttpResponse::Ok().streaming(stream::iter(chunks))
+    }
+
+    async fn call(uri: &str) -> ServiceResponse {
+        let app = actix_test::init_service(
+            App::new()
+                .app_data(web::Data::new(web_config("max_response_body_mb = 1")))
+                .wrap(from_fn(limit_response_body))
+                .route("/small", web::get().to(|| async { HttpResponse::Ok().body(vec![b'x'; MB]) }))
+                .route("/large", web::get().to(|| async { HttpResponse::Ok().body(vec![b'x'; MB + 1]) }))
+                .route("/streamed", web::get().to(|| async { streamed(2 * MB) }))
+                .route("/api/v1/git-bundle", web::get().to(|| async { streamed(2 * MB) })),
+        )
+        .await;
+        actix_test::call_service(&app, actix_test::TestRequest::get().uri(uri).to_request())
+            .await
+            .map_into_boxed_body()
+    }
+
+    #[actix_web::test]
+    async fn limits_response_bodies() {
+        let res = call("/small").await;
+        assert_eq!(res.status(), 200);
+        assert_eq!(body::to_bytes(res.into_body()).await.unwrap().len(), MB);
+        // 大小已知时直接返回 500
+        assert_eq!(call("/large").await.status(), 500);
+        // 流式的响应在超过上限的那一块中断
+        let res = call("/streamed").await;
+        assert_eq!(res.status(), 200);
+        let mut body = std::pin::pin!(res.into_body());
+        let mut sent = 0;
+        let error = loop {
+            match std::future::poll_fn(|cx| body.as_mut().poll_next(cx)).await {
+                Some(Ok(chunk)) => sent += chunk.len(),
+                Some(Err(e)) => break e,
+                None => panic!("body was not truncated"),
+            }
+        };
+        assert_eq!(sent, MB);
+        assert_eq!(error.to_string(), "response body limit exceeded");
+        // git bundle 不受限制
+        let res = call("/api/v1/git-bundle").await;
+        assert_eq!(body::to_bytes(res.into_body()).await.unwrap().len(), 2 * MB);
+    }
+}
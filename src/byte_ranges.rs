@@ -0,0 +1,202 @@
+use actix_web::{
+    http::header::{
+        ByteRangeSpec, ContentRangeSpec, EntityTag, HttpDate, IfRange, Range, CONTENT_RANGE, CONTENT_TYPE,
+    },
+    http::StatusCode,
+    HttpMessage, HttpRequest, HttpResponse, HttpResponseBuilder,
+};
+use bytes::{BufMut, Bytes, BytesMut};
+
+use crate::util;
+
+// 一个请求最多的区间数，超过时返回整个文件，避免用大量很小的区间放大响应
+const MAX_RANGES: usize = 32;
+
+#[derive(Debug, PartialEq, Eq)]
+enum Ranges {
+    Full,
+    Multipart(Vec<(u64, u64)>),
+    Unsatisfiable,
+}
+
+// 只有多个区间时返回 206；单个区间直接返回整个文件，索引文件很小，cargo 也不会请求单个区间
+fn select(req: &HttpRequest, etag: &EntityTag, last_modified: HttpDate, len: u64) -> Ranges {
+    // 无法解析的 Range 按 RFC 9110 忽略
+    let Some(Range::Bytes(specs)) = req.get_header::<Range>() else {
+        return Ranges::Full;
+    };
+    let unchanged = match req.get_header::<IfRange>() {
+        None => true,
+        Some(IfRange::EntityTag(tag)) => tag.strong_eq(etag),
+        Some(IfRange::Date(date)) => date == last_modified,
+    };
+    if !unchanged || specs.len() > MAX_RANGES {
+        return Ranges::Full;
+    }
+    let ranges: Vec<(u64, u64)> = specs
+        .iter()
+        .filter_map(|spec: &ByteRangeSpec| spec.to_satisfiable_range(len))
+        .collect();
+    match ranges.len() {
+        0 => Ranges::Unsatisfiable,
+        1 => Ranges::Full,
+        _ => Ranges::Multipart(ranges),
+    }
+}
+
+fn multipart_body(body: &Bytes, ranges: &[(u64, u64)], content_type: &str, boundary: &str) -> Bytes {
+    let len = body.len() as u64;
+    let mut out = BytesMut::new();
+    for &(start, end) in ranges {
+        let range = ContentRangeSpec::Bytes {
+            range: Some((start, end)),
+            instance_length: Some(len),
+        };
+        out.put_slice(
+            format!(
+                "--{}\r\n{}: {}\r\n{}: {}\r\n\r\n",
+                boundary, CONTENT_TYPE, content_type, CONTENT_RANGE, range
+            )
+            .as_bytes(),
+        );
+        out.put_slice(&body[start as usize..=end as usize]);
+        out.put_slice(b"\r\n");
+    }
+    out.put_slice(format!("--{}--\r\n", boundary).as_bytes());
+    out.freeze()
+}
+
+// 返回 None 时调用方按 200 返回整个文件；res 上已经设置了 ETag 等响应头，这里只修改状态码和 Content-Type
+pub fn partial_response(
+    req: &HttpRequest,
+    res: &mut HttpResponseBuilder,
+    body: &Bytes,
+    etag: &EntityTag,
+    last_modified: HttpDate,
+    content_type: &str,
+) -> Option<HttpResponse> {
+    let len = body.len() as u64;
+    match select(req, etag, last_modified, len) {
+        Ranges::Full => None,
+        Ranges::Unsatisfiable => {
+            let range = ContentRangeSpec::Bytes {
+                range: None,
+                instance_length: Some(len),
+            };
+            Some(
+                res.status(StatusCode::RANGE_NOT_SATISFIABLE)
+                    .insert_header((CONTENT_RANGE, range.to_string()))
+                    .finish(),
+            )
+        }
+        Ranges::Multipart(ranges) => {
+            let boundary = util::random_hex(16)?;
+            Some(
+                res.status(StatusCode::PARTIAL_CONTENT)
+                    .insert_header((CONTENT_TYPE, format!("multipart/byteranges; boundary={}", boundary)))
+                    .body(multipart_body(body, &ranges, content_type, &boundary)),
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{body::MessageBody, test::TestRequest};
+    use std::time::{Duration, UNIX_EPOCH};
+
+    const BODY: &[u8] = b"0123456789abcdefghij";
+
+    fn etag() -> EntityTag {
+        EntityTag::new_strong("abc".to_string())
+    }
+
+    fn last_modified() -> HttpDate {
+        HttpDate::from(UNIX_EPOCH + Duration::from_secs(1_700_000_000))
+    }
+
+    fn select_for(headers: &[(&str, &str)]) -> Ranges {
+        let mut req = TestRequest::default();
+        for &header in headers {
+            req = req.insert_header(header);
+        }
+        select(&req.to_http_request(), &etag(), last_modified(), BODY.len() as u64)
+    }
+
+    #[test]
+    fn selects_ranges() {
+        assert_eq!(select_for(&[]), Ranges::Full);
+        assert_eq!(select_for(&[("Range", "bytes=0-4")]), Ranges::Full);
+        assert_eq!(
+            select_for(&[("Range", "bytes=0-4, 10-14")]),
+            Ranges::Multipart(vec![(0, 4), (10, 14)])
+        );
+        // 后缀区间和超出文件末尾的区间
+        assert_eq!(
+            select_for(&[("Range", "bytes=-3, 15-100")]),
+            Ranges::Multipart(vec![(17, 19), (15, 19)])
+        );
+        // 不满足的区间被丢弃
+        assert_eq!(select_for(&[("Range", "bytes=0-1, 50-60")]), Ranges::Full);
+        assert_eq!(select_for(&[("Range", "bytes=50-60, 70-80")]), Ranges::Unsatisfiable);
+        // 无法解析的 Range 被忽略
+        assert_eq!(select_for(&[("Range", "items=0-1")]), Ranges::Full);
+    }
+
+    #[test]
+    fn too_many_ranges() {
+        let specs: Vec<String> = (0..=MAX_RANGES).map(|i| format!("{}-{}", i % 20, i % 20)).collect();
+        let header = format!("bytes={}", specs.join(","));
+        assert_eq!(select_for(&[("Range", &header)]), Ranges::Full);
+    }
+
+    #[test]
+    fn if_range() {
+        let range = ("Range", "bytes=0-1, 5-6");
+        assert!(matches!(select_for(&[range, ("If-Range", "\"abc\"")]), Ranges::Multipart(_)));
+        assert_eq!(select_for(&[range, ("If-Range", "\"other\"")]), Ranges::Full);
+        assert_eq!(select_for(&[range, ("If-Range", "W/\"abc\"")]), Ranges::Full);
+        let date = last_modified().to_string();
+        assert!(matches!(select_for(&[range, ("If-Range", &date)]), Ranges::Multipart(_)));
+        assert_eq!(
+            select_for(&[range, ("If-Range", "Thu, 01 Jan 1970 00:00:00 GMT")]),
+            Ranges::Full
+        );
+    }
+
+    fn respond(range: &str) -> Option<HttpResponse> {
+        let req = TestRequest::default().insert_header(("Range", range)).to_http_request();
+        let mut res = HttpResponse::Ok();
+        partial_response(&req, &mut res, &Bytes::from_static(BODY), &etag(), last_modified(), "text/plain")
+    }
+
+    #[test]
+    fn two_part_response() {
+        let res = respond("bytes=0-3, 10-12").unwrap();
+        assert_eq!(res.status(), StatusCode::PARTIAL_CONTENT);
+        let content_type = res.headers().get(CONTENT_TYPE).unwrap().to_str().unwrap().to_string();
+        let boundary = content_type.strip_prefix("multipart/byteranges; boundary=").unwrap().to_string();
+        assert_eq!(boundary.len(), 32);
+        let body = res.into_body().try_into_bytes().unwrap();
+        let expected = format!(
+            "--{b}\r\ncontent-type: text/plain\r\ncontent-range: bytes 0-3/20\r\n\r\n0123\r\n\
+             --{b}\r\ncontent-type: text/plain\r\ncontent-range: bytes 10-12/20\r\n\r\nabc\r\n\
+             --{b}--\r\n",
+            b = boundary
+        );
+        assert_eq!(String::from_utf8(body.to_vec()).unwrap(), expected);
+
+        // 每个响应的 boundary 都不同
+        let other = respond("bytes=0-3, 10-12").unwrap();
+        assert_ne!(other.headers().get(CONTENT_TYPE).unwrap().to_str().unwrap(), content_type);
+    }
+
+    #[test]
+    fn unsatisfiable_response() {
+        let res = respond("bytes=100-200").unwrap();
+        assert_eq!(res.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+        assert_eq!(res.headers().get(CONTENT_RANGE).unwrap(), "bytes */20");
+        assert!(respond("bytes=0-3").is_none());
+    }
+}
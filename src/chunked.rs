@@ -0,0 +1,159 @@
+use actix_web::{
+    body::{BodySize, EitherBody, MessageBody},
+    dev::{ServiceRequest, ServiceResponse},
+    http::Method,
+    middleware::Next,
+    web, Error,
+};
+use bytes::{Bytes, BytesMut};
+use std::{
+    error::Error as StdError,
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
+
+use crate::config::WebConfig;
+
+// 大小超过 web.chunked_threshold_kb 时按流发送，index_file_head 用它决定是否返回 Content-Length
+pub fn applies(config: &WebConfig, size: u64) -> bool {
+    config.use_chunked_transfer && size > config.chunked_threshold_kb * 1024
+}
+
+// 报告未知大小，HTTP/1.1 下 actix-web 因此使用 Transfer-Encoding: chunked
+// 内层的响应体（actix-files 的文件）按需读取，这里只把读到的数据整理成 chunk_size 大小的块
+// 大块（例如内存里的整个索引文件）直接切分不复制，小块攒够 chunk_size 再发送
+pub struct ChunkedBody<B> {
+    inner: Pin<Box<B>>,
+    chunk_size: usize,
+    pending: Bytes,
+    buf: BytesMut,
+    done: bool,
+}
+
+impl<B: MessageBody> MessageBody for ChunkedBody<B> {
+    type Error = Box<dyn StdError>;
+
+    fn size(&self) -> BodySize {
+        BodySize::Stream
+    }
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Bytes, Self::Error>>> {
+        let this = self.as_mut().get_mut();
+        loop {
+            if !this.pending.is_empty() {
+                if this.buf.is_empty() && this.pending.len() >= this.chunk_size {
+                    return Poll::Ready(Some(Ok(this.pending.split_to(this.chunk_size))));
+                }
+                let take = (this.chunk_size - this.buf.len()).min(this.pending.len());
+                this.buf.extend_from_slice(&this.pending.split_to(take));
+                if this.buf.len() == this.chunk_size {
+                    return Poll::Ready(Some(Ok(this.buf.split().freeze())));
+                }
+                continue;
+            }
+            if this.done {
+                return Poll::Ready((!this.buf.is_empty()).then(|| Ok(this.buf.split().freeze())));
+            }
+            match ready!(this.inner.as_mut().poll_next(cx)) {
+                Some(Ok(chunk)) => this.pending = chunk,
+                Some(Err(e)) => return Poll::Ready(Some(Err(e.into()))),
+                None => this.done = true,
+            }
+        }
+    }
+}
+
+// 只包在索引文件和静态文件外面；HEAD 没有响应体，不处理
+pub async fn chunk_large_responses(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<ChunkedBody<impl MessageBody>, impl MessageBody>>, Error> {
+    let config = req
+        .app_data::<web::Data<WebConfig>>()
+        .cloned()
+        .expect("WebConfig not registered");
+    let is_head = req.method() == Method::HEAD;
+    let res = next.call(req).await?;
+    let large = matches!(res.response().body().size(), BodySize::Sized(size) if applies(&config, size));
+    if is_head || !large {
+        return Ok(res.map_into_right_body());
+    }
+    let chunk_size = (config.chunk_size_kb * 1024) as usize;
+    Ok(res
+        .map_body(|_, body| ChunkedBody {
+            inner: Box::pin(body),
+            chunk_size,
+            pending: Bytes::new(),
+            buf: BytesMut::new(),
+            done: false,
+        })
+        .map_into_left_body())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::web_config;
+    use actix_web::{middleware::from_fn, test as actix_test, App, HttpResponse};
+    use futures_util::stream;
+
+    const KB: usize = 1024;
+
+    async fn chunks(body: impl MessageBody) -> Vec<usize> {
+        let mut body = std::pin::pin!(body);
+        let mut sizes = Vec::new();
+        while let Some(chunk) = std::future::poll_fn(|cx| body.as_mut().poll_next(cx)).await {
+            sizes.push(chunk.map_err(|_| "body error").unwrap().len());
+        }
+        sizes
+    }
+
+    fn chunked(pieces: &[usize], chunk_size: usize) -> ChunkedBody<impl MessageBody> {
+        let pieces: Vec<_> = pieces.iter().map(|&len| Ok::<_, Error>(Bytes::from(vec![b'x'; len]))).collect();
+        ChunkedBody {
+            inner: Box::pin(actix_web::body::BodyStream::new(stream::iter(pieces))),
+            chunk_size,
+            pending: Bytes::new(),
+            buf: BytesMut::new(),
+            done: false,
+        }
+    }
+
+    #[actix_web::test]
+    async fn rechunks_inner_body() {
+        // 大块直接切分，小块攒够再发，最后一块可以更小
+        assert_eq!(chunks(chunked(&[40 * KB], 16 * KB)).await, [16 * KB, 16 * KB, 8 * KB]);
+        assert_eq!(chunks(chunked(&[KB; 20], 16 * KB)).await, [16 * KB, 4 * KB]);
+        assert_eq!(chunks(chunked(&[10 * KB, 10 * KB, 30 * KB], 16 * KB)).await, [16 * KB, 16 * KB, 16 * KB, 2 * KB]);
+        assert!(chunks(chunked(&[], 16 * KB)).await.is_empty());
+        assert_eq!(chunked(&[KB], 16 * KB).size(), BodySize::Stream);
+    }
+
+    async fn call(extra: &str, method: Method, uri: &str) -> ServiceResponse {
+        let app = actix_test::init_service(
+            App::new()
+                .app_data(web::Data::new(web_config(&format!("chunked_threshold_kb = 64\nchunk_size_kb = 16\n{}", extra))))
+                .wrap(from_fn(chunk_large_responses))
+                .route("/large", web::route().to(|| async { HttpResponse::Ok().body(vec![b'x'; 100 * KB]) }))
+                .route("/small", web::route().to(|| async { HttpResponse::Ok().body(vec![b'x'; 64 * KB]) })),
+        )
+        .await;
+        let req = actix_test::TestRequest::default().method(method).uri(uri).to_request();
+        actix_test::call_service(&app, req).await.map_into_boxed_body()
+    }
+
+    #[actix_web::test]
+    async fn streams_responses_over_the_threshold() {
+        let res = call("", Method::GET, "/large").await;
+        assert_eq!(res.response().body().size(), BodySize::Stream);
+        let sizes = chunks(res.into_body()).await;
+        assert_eq!(sizes[..6], [16 * KB; 6]);
+        assert_eq!(sizes[6..], [4 * KB]);
+        // 等于阈值时仍然带 Content-Length
+        let res = call("", Method::GET, "/small").await;
+        assert_eq!(res.response().body().size(), BodySize::Sized(64 * KB as u64));
+        assert_eq!(call("", Method::HEAD, "/large").await.response().body().size(), BodySize::Sized(100 * KB as u64));
+        let res = call("use_chunked_transfer = false", Method::GET, "/large").await;
+        assert_eq!(res.response().body().size(), BodySize::Sized(100 * KB as u64));
+    }
+}
@@ -0,0 +1,167 @@
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+use crate::metrics;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BreakerState {
+    Closed,
+    Open,
+    HalfOpen,
+}
+
+impl BreakerState {
+    const ALL: [BreakerState; 3] = [BreakerState::Closed, BreakerState::Open, BreakerState::HalfOpen];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BreakerState::Closed => "closed",
+            BreakerState::Open => "open",
+            BreakerState::HalfOpen => "half_open",
+        }
+    }
+}
+
+// 上游连续失败 failure_threshold 次后，open_duration 内不再尝试 pull
+// 之后进入 HalfOpen 允许一次尝试，成功则恢复，失败则重新打开
+// 只在定时 pull 的任务里使用，不需要加锁
+pub struct CircuitBreaker {
+    failure_threshold: u32,
+    open_duration: Duration,
+    state: BreakerState,
+    failures: u32,
+    opened_at: Option<Instant>,
+}
+
+impl CircuitBreaker {
+    // failure_threshold 为 0 时不会打开
+    pub fn new(failure_threshold: u32, open_duration: Duration) -> Self {
+        let breaker = CircuitBreaker {
+            failure_threshold,
+            open_duration,
+            state: BreakerState::Closed,
+            failures: 0,
+            opened_at: None,
+        };
+        breaker.export_state();
+        breaker
+    }
+
+    fn export_state(&self) {
+        for state in BreakerState::ALL {
+            metrics::CIRCUIT_BREAKER_STATE.set(state.as_str(), (state == self.state) as i64);
+        }
+    }
+
+    fn transition(&mut self, state: BreakerState) {
+        if self.state != state {
+            self.state = state;
+            self.export_state();
+        }
+    }
+
+    // 返回 false 时跳过这一次 pull
+    pub fn allow(&mut self) -> bool {
+        if self.state != BreakerState::Open {
+            return true;
+        }
+        let now = Instant::now();
+        let elapsed = self.opened_at.map_or(self.open_duration, |at| now.duration_since(at));
+        if elapsed < self.open_duration {
+            warn!(
+                "Skipping update: circuit breaker is open for another {}s",
+                (self.open_duration - elapsed).as_secs()
+            );
+            return false;
+        }
+        info!("Circuit breaker half-open, trying one update");
+        self.transition(BreakerState::HalfOpen);
+        true
+    }
+
+    pub fn record_success(&mut self) {
+        if self.state != BreakerState::Closed {
+            info!("Circuit breaker closed, upstream is reachable again");
+        }
+        self.failures = 0;
+        self.opened_at = None;
+        self.transition(BreakerState::Closed);
+    }
+
+    pub fn record_failure(&mut self) {
+        self.failures = self.failures.saturating_add(1);
+        let trip = match self.state {
+            BreakerState::HalfOpen => true,
+            BreakerState::Closed => self.failure_threshold > 0 && self.failures >= self.failure_threshold,
+            BreakerState::Open => false,
+        };
+        if trip {
+            warn!(
+                "Circuit breaker opened after {} consecutive failures, skipping updates for {}s",
+                self.failures,
+                self.open_duration.as_secs()
+            );
+            metrics::CIRCUIT_BREAKER_TRIPS_TOTAL.inc();
+            self.opened_at = Some(Instant::now());
+            self.transition(BreakerState::Open);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn trips_after_threshold_and_recovers() {
+        let mut breaker = CircuitBreaker::new(3, Duration::from_millis(200));
+        for _ in 0..2 {
+            assert!(breaker.allow());
+            breaker.record_failure();
+        }
+        assert_eq!(breaker.state, BreakerState::Closed);
+        assert!(breaker.allow());
+        breaker.record_failure();
+        assert_eq!(breaker.state, BreakerState::Open);
+
+        // 打开期间跳过所有 pull
+        assert!(!breaker.allow());
+        assert!(!breaker.allow());
+
+        // HalfOpen 时失败一次就重新打开
+        thread::sleep(Duration::from_millis(250));
+        assert!(breaker.allow());
+        assert_eq!(breaker.state, BreakerState::HalfOpen);
+        breaker.record_failure();
+        assert_eq!(breaker.state, BreakerState::Open);
+        assert!(!breaker.allow());
+
+        thread::sleep(Duration::from_millis(250));
+        assert!(breaker.allow());
+        breaker.record_success();
+        assert_eq!(breaker.state, BreakerState::Closed);
+        assert_eq!(breaker.failures, 0);
+    }
+
+    #[test]
+    fn success_resets_the_failure_count() {
+        let mut breaker = CircuitBreaker::new(2, Duration::from_secs(3600));
+        breaker.record_failure();
+        breaker.record_success();
+        breaker.record_failure();
+        assert_eq!(breaker.state, BreakerState::Closed);
+        breaker.record_failure();
+        assert_eq!(breaker.state, BreakerState::Open);
+    }
+
+    #[test]
+    fn zero_threshold_never_opens() {
+        let mut breaker = CircuitBreaker::new(0, Duration::from_secs(3600));
+        for _ in 0..100 {
+            breaker.record_failure();
+            assert!(breaker.allow());
+        }
+        assert_eq!(breaker.state, BreakerState::Closed);
+    }
+}
@@ -0,0 +1,122 @@
+use std::path::Path;
+
+use crate::{config::ConfigFormat, init::InitArgs};
+
+pub const USAGE: &str = "Usage:
+  local_crates_io_index [--config <path>] [--config-format toml|yaml|json]
+  local_crates_io_index check [--config <path>] [--config-format toml|yaml|json]
+  local_crates_io_index convert-config --from <path> --to <path>
+  local_crates_io_index init [--config <path>] [--non-interactive] [--force] [--git-url <url>] [--path <dir>]
+                             [--update-interval <secs>] [--address <addr>] [--port <port>] [--https-only]
+                             [--canonical-host <host>] [--jwt-secret <secret>]";
+
+// 未指定 --config 时按顺序查找，都不存在时使用 config.toml
+const DEFAULT_CONFIG_PATHS: &[&str] = &["config.toml", "config.yaml", "config.yml", "config.json"];
+
+pub enum Command {
+    Serve {
+        config_path: String,
+        config_format: ConfigFormat,
+    },
+    // 检查能否连接上游，不启动服务
+    Check {
+        config_path: String,
+        config_format: ConfigFormat,
+    },
+    ConvertConfig {
+        from: String,
+        to: String,
+    },
+    // 交互式生成配置文件
+    Init(InitArgs),
+    Help,
+}
+
+fn value(args: &mut impl Iterator<Item = String>, flag: &str) -> Result<String, String> {
+    args.next().ok_or_else(|| format!("Missing value for {}", flag))
+}
+
+fn parsed<T: std::str::FromStr>(args: &mut impl Iterator<Item = String>, flag: &str) -> Result<T, String> {
+    let raw = value(args, flag)?;
+    raw.parse().map_err(|_| format!("Invalid value {:?} for {}", raw, flag))
+}
+
+// init 的参数和其他子命令不同，单独解析
+fn parse_init_args(mut args: impl Iterator<Item = String>) -> Result<Command, String> {
+    let mut init = InitArgs::default();
+    let mut config_path = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--config" => config_path = Some(value(&mut args, &arg)?),
+            "--non-interactive" => init.non_interactive = true,
+            "--force" => init.force = true,
+            "--git-url" => init.git_url = Some(value(&mut args, &arg)?),
+            "--path" => init.path = Some(value(&mut args, &arg)?),
+            "--update-interval" => init.update_interval = Some(parsed(&mut args, &arg)?),
+            "--address" => init.address = Some(value(&mut args, &arg)?),
+            "--port" => init.port = Some(parsed(&mut args, &arg)?),
+            "--https-only" => init.https_only = Some(true),
+            "--canonical-host" => init.canonical_host = Some(value(&mut args, &arg)?),
+            "--jwt-secret" => init.jwt_secret = Some(value(&mut args, &arg)?),
+            "-h" | "--help" => return Ok(Command::Help),
+            _ => return Err(format!("Unexpected argument {:?}", arg)),
+        }
+    }
+    init.config_path = config_path.unwrap_or_else(|| DEFAULT_CONFIG_PATHS[0].to_string());
+    Ok(Command::Init(init))
+}
+
+pub fn parse_args(args: impl Iterator<Item = String>) -> Result<Command, String> {
+    let mut config_path = None;
+    let mut config_format = None;
+    let mut convert = false;
+    let mut check = false;
+    let mut from = None;
+    let mut to = None;
+    let mut args = args.peekable();
+    if args.next_if_eq("init").is_some() {
+        return parse_init_args(args);
+    }
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "convert-config" if !convert && !check => convert = true,
+            "check" if !convert && !check => check = true,
+            "--from" if convert => from = Some(value(&mut args, &arg)?),
+            "--to" if convert => to = Some(value(&mut args, &arg)?),
+            "--config" if !convert => config_path = Some(value(&mut args, &arg)?),
+            "--config-format" if !convert => {
+                config_format = Some(ConfigFormat::from_name(&value(&mut args, &arg)?)?)
+            }
+            "-h" | "--help" => return Ok(Command::Help),
+            _ => return Err(format!("Unexpected argument {:?}", arg)),
+        }
+    }
+
+    if convert {
+        return match (from, to) {
+            (Some(from), Some(to)) => Ok(Command::ConvertConfig { from, to }),
+            _ => Err("convert-config requires --from and --to".to_string()),
+        };
+    }
+    let config_path = config_path.unwrap_or_else(|| {
+        DEFAULT_CONFIG_PATHS
+            .iter()
+            .find(|path| Path::new(path).exists())
+            .unwrap_or(&DEFAULT_CONFIG_PATHS[0])
+            .to_string()
+    });
+    let config_format = match config_format {
+        Some(format) => format,
+        None => ConfigFormat::from_path(&config_path)?,
+    };
+    if check {
+        return Ok(Command::Check {
+            config_path,
+            config_format,
+        });
+    }
+    Ok(Command::Serve {
+        config_path,
+        config_format,
+    })
+}
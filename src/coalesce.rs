@@ -0,0 +1,200 @@
+use actix_web::http::header::{EntityTag, HttpDate};
+use bytes::Bytes;
+use dashmap::DashMap;
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, OnceLock,
+    },
+    time::UNIX_EPOCH,
+};
+use tokio::sync::Notify;
+
+use crate::{delta, metrics, panic_recovery, sparse};
+
+// 读取一次索引文件的结果，同一时刻请求这个文件的所有请求共用
+pub struct LoadedFile {
+    pub body: Bytes,
+    pub etag: EntityTag,
+    pub last_modified: HttpDate,
+}
+
+struct Flight {
+    // 第一个请求正在读取文件
+    in_progress: AtomicBool,
+    notify: Notify,
+    // 读取失败（文件消失）时是 None
+    result: OnceLock<Option<Arc<LoadedFile>>>,
+}
+
+// 最多保留的最近文件数，超过后不再加入
+const MAX_RECENT_FILES: usize = 4096;
+
+// 同一个索引文件的并发请求只读一次磁盘，cargo 同时启动很多实例时常见
+// 读取完成后立即移除，不会返回过期内容
+pub struct IndexFileCoalescer {
+    // web.delta_encoding 开启时 ETag 是内容的 git blob OID，否则和 actix-files 相同
+    blob_etags: bool,
+    in_flight: DashMap<String, Arc<Flight>>,
+    // 按请求路径保存最近一次返回的文件，只在 sparse_index 因为 [slo] 降级时使用
+    // 降级期间可能返回索引更新之前的内容，恢复后客户端会用 ETag 重新验证
+    recent: Mutex<HashMap<String, Arc<LoadedFile>>>,
+}
+
+// 第一个请求的 future 被取消时（客户端断开）也要唤醒等待的请求
+struct FlightGuard<'a> {
+    coalescer: &'a IndexFileCoalescer,
+    key: String,
+    flight: Arc<Flight>,
+}
+
+impl Drop for FlightGuard<'_> {
+    fn drop(&mut self) {
+        self.coalescer.in_flight.remove(&self.key);
+        self.flight.in_progress.store(false, Ordering::Release);
+        self.flight.notify.notify_waiters();
+    }
+}
+
+fn read_file(path: &Path, blob_etags: bool) -> Option<LoadedFile> {
+    let metadata = fs::metadata(path).ok()?;
+    let body = fs::read(path).ok()?;
+    let modified = metadata.modified().unwrap_or(UNIX_EPOCH);
+    let etag = if blob_etags {
+        delta::blob_etag(&body)
+    } else {
+        sparse::file_etag(&metadata, modified)
+    };
+    Some(LoadedFile {
+        body: Bytes::from(body),
+        etag,
+        last_modified: HttpDate::from(modified),
+    })
+}
+
+async fn read_blocking(path: PathBuf, blob_etags: bool) -> actix_web::Result<Option<Arc<LoadedFile>>> {
+    Ok(panic_recovery::block(move || read_file(&path, blob_etags)).await?.map(Arc::new))
+}
+
+impl IndexFileCoalescer {
+    pub fn new(blob_etags: bool) -> Self {
+        IndexFileCoalescer {
+            blob_etags,
+            in_flight: DashMap::new(),
+            recent: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn remember(&self, key: &str, file: &Arc<LoadedFile>) {
+        let mut recent = self.recent.lock().unwrap_or_else(|e| e.into_inner());
+        if recent.len() < MAX_RECENT_FILES || recent.contains_key(key) {
+            recent.insert(key.to_string(), Arc::clone(file));
+        }
+    }
+
+    pub fn recent(&self, key: &str) -> Option<Arc<LoadedFile>> {
+        self.recent.lock().unwrap_or_else(|e| e.into_inner()).get(key).cloned()
+    }
+
+    pub async fn load(&self, path: PathBuf) -> actix_web::Result<Option<Arc<LoadedFile>>> {
+        let key = path.to_string_lossy().into_owned();
+        let (flight, leader) = match self.in_flight.entry(key.clone()) {
+            dashmap::Entry::Occupied(entry) => (Arc::clone(entry.get()), false),
+            dashmap::Entry::Vacant(entry) => {
+                let flight = Arc::new(Flight {
+                    in_progress: AtomicBool::new(true),
+                    notify: Notify::new(),
+                    result: OnceLock::new(),
+                });
+                entry.insert(Arc::clone(&flight));
+                (flight, true)
+            }
+        };
+
+        if leader {
+            let guard = FlightGuard {
+                coalescer: self,
+                key,
+                flight,
+            };
+            let result = read_blocking(path, self.blob_etags).await;
+            if let Ok(loaded) = &result {
+                let _ = guard.flight.result.set(loaded.clone());
+            }
+            return result;
+        }
+
+        metrics::CACHE_COALESCED_REQUESTS_TOTAL.inc();
+        loop {
+            // 先注册再检查标志，避免错过 notify_waiters
+            let notified = flight.notify.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+            if !flight.in_progress.load(Ordering::Acquire) {
+                break;
+            }
+            notified.await;
+        }
+        match flight.result.get() {
+            Some(loaded) => Ok(loaded.clone()),
+            // 第一个请求被取消或读取出错，自己读
+            None => read_blocking(path, self.blob_etags).await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::future::join_all;
+
+    // 所有请求在第一个请求的读取完成之前都已经开始，共用同一次读取的结果
+    #[actix_web::test]
+    async fn concurrent_requests_read_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("serde");
+        fs::write(&path, "v1").unwrap();
+        let coalescer = IndexFileCoalescer::new(false);
+
+        let results = join_all((0..20).map(|_| coalescer.load(path.clone()))).await;
+        let files: Vec<_> = results.into_iter().map(|r| r.unwrap().unwrap()).collect();
+        assert!(files.iter().all(|file| Arc::ptr_eq(file, &files[0])));
+        assert_eq!(files[0].body, "v1");
+        assert!(coalescer.in_flight.is_empty());
+
+        // 读取完成后不再复用
+        fs::write(&path, "v2").unwrap();
+        let file = coalescer.load(path.clone()).await.unwrap().unwrap();
+        assert!(!Arc::ptr_eq(&file, &files[0]));
+        assert_eq!(file.body, "v2");
+    }
+
+    #[actix_web::test]
+    async fn missing_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let coalescer = IndexFileCoalescer::new(true);
+        let results = join_all((0..5).map(|_| coalescer.load(dir.path().join("nope")))).await;
+        assert!(results.into_iter().all(|r| r.unwrap().is_none()));
+        assert!(coalescer.in_flight.is_empty());
+    }
+
+    // 第一个请求被取消时，等待的请求自己读取
+    #[actix_web::test]
+    async fn cancelled_leader() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("serde");
+        fs::write(&path, "v1").unwrap();
+        let coalescer = IndexFileCoalescer::new(false);
+
+        let mut leader = Box::pin(coalescer.load(path.clone()));
+        assert!(futures_util::poll!(leader.as_mut()).is_pending());
+        let mut follower = Box::pin(coalescer.load(path.clone()));
+        assert!(futures_util::poll!(follower.as_mut()).is_pending());
+        drop(leader);
+        assert_eq!(follower.await.unwrap().unwrap().body, "v1");
+        assert!(coalescer.in_flight.is_empty());
+    }
+}
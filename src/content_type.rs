@@ -0,0 +1,104 @@
+use actix_web::{
+    body::MessageBody,
+    dev::{ServiceRequest, ServiceResponse},
+    http::header::{HeaderValue, CONTENT_TYPE},
+    middleware::Next,
+    web, Error,
+};
+
+use crate::config::WebConfig;
+
+// 索引目录里没有扩展名的文件都是文本（索引文件、README 等），actix-files 会当作 application/octet-stream
+const EXTENSIONLESS_CONTENT_TYPE: HeaderValue = HeaderValue::from_static("text/plain; charset=utf-8");
+
+// actix-files 的 mime_override 只能决定 Content-Disposition，这里按 web.mime_types 改写静态文件的 Content-Type
+pub async fn override_content_type(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let config = req
+        .app_data::<web::Data<WebConfig>>()
+        .cloned()
+        .expect("WebConfig not registered");
+    let file_name = req.path().rsplit('/').next().unwrap_or_default().to_string();
+    let mut res = next.call(req).await?;
+    if res.status().is_success() {
+        if let Some(value) = mime_type_for(&config, &file_name) {
+            res.headers_mut().insert(CONTENT_TYPE, value);
+        } else if is_extensionless(&file_name) && is_octet_stream(res.headers().get(CONTENT_TYPE)) {
+            // 目录列表等已经有具体类型的响应不改写
+            res.headers_mut().insert(CONTENT_TYPE, EXTENSIONLESS_CONTENT_TYPE);
+        }
+    }
+    Ok(res)
+}
+
+// 键可以是完整的文件名（"config.json"）、扩展名（"crate"）或通配的扩展名（"*.crate"），完整的文件名优先
+fn mime_type_for(config: &WebConfig, file_name: &str) -> Option<HeaderValue> {
+    let extension = file_name.rsplit_once('.').map(|(_, ext)| ext);
+    config
+        .mime_types
+        .get(file_name)
+        .or_else(|| {
+            let extension = extension?;
+            config
+                .mime_types
+                .iter()
+                .find(|(key, _)| key.strip_prefix("*.").unwrap_or(key) == extension)
+                .map(|(_, value)| value)
+        })
+        .and_then(|value| HeaderValue::from_str(value).ok())
+}
+
+fn is_extensionless(file_name: &str) -> bool {
+    !file_name.is_empty() && !file_name.contains('.')
+}
+
+fn is_octet_stream(value: Option<&HeaderValue>) -> bool {
+    value.is_some_and(|value| value.as_bytes().starts_with(b"application/octet-stream"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+    use actix_web::{middleware::from_fn, test as actix_test, App};
+    use std::fs;
+
+    #[actix_web::test]
+    async fn serves_configured_content_types() {
+        let dir = tempfile::tempdir().unwrap();
+        for file in ["config.json", "other.json", "notes.txt", "README", "serde-1.0.0.crate", "page.html", "se/rd/serde"] {
+            let path = dir.path().join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "content").unwrap();
+        }
+        let config = test_support::web_config(
+            r#"mime_types = { json = "application/x-json", "*.txt" = "text/x-notes", README = "text/markdown", "config.json" = "application/x-registry" }"#,
+        );
+        let app = actix_test::init_service(
+            App::new()
+                .app_data(web::Data::new(config))
+                .wrap(from_fn(override_content_type))
+                .service(actix_files::Files::new("/", dir.path())),
+        )
+        .await;
+        for (path, content_type) in [
+            ("/config.json", "application/x-registry"),
+            ("/other.json", "application/x-json"),
+            ("/notes.txt", "text/x-notes"),
+            ("/README", "text/markdown"),
+            // 默认的映射和配置的合并
+            ("/serde-1.0.0.crate", "application/gzip"),
+            ("/page.html", "text/html; charset=utf-8"),
+            ("/se/rd/serde", "text/plain; charset=utf-8"),
+        ] {
+            let res = actix_test::call_service(&app, actix_test::TestRequest::get().uri(path).to_request()).await;
+            assert_eq!(res.status(), 200, "{}", path);
+            assert_eq!(res.headers().get(CONTENT_TYPE).unwrap(), content_type, "{}", path);
+        }
+        let res = actix_test::call_service(&app, actix_test::TestRequest::get().uri("/missing.json").to_request()).await;
+        assert_eq!(res.status(), 404);
+        assert_ne!(res.headers().get(CONTENT_TYPE).map(|v| v.as_bytes()), Some(&b"application/x-json"[..]));
+    }
+}
@@ -0,0 +1,113 @@
+use std::{
+    io::Write,
+    process::{Command, Stdio},
+};
+
+// git credential 协议的一组 key=value，见 git-credential(1)
+#[derive(Debug, Clone, Default)]
+pub struct Credential {
+    pub protocol: String,
+    pub host: String,
+    pub path: String,
+    pub username: String,
+    pub password: String,
+}
+
+impl Credential {
+    fn for_url(url: &str) -> Result<Self, String> {
+        let (protocol, rest) = url
+            .split_once("://")
+            .ok_or_else(|| format!("Unsupported url for credential helper: {}", url))?;
+        let (authority, path) = rest.split_once('/').unwrap_or((rest, ""));
+        let (username, host) = match authority.rsplit_once('@') {
+            Some((username, host)) => (username.to_string(), host.to_string()),
+            None => (String::new(), authority.to_string()),
+        };
+        Ok(Credential {
+            protocol: protocol.to_string(),
+            host,
+            path: path.to_string(),
+            username,
+            password: String::new(),
+        })
+    }
+
+    fn to_input(&self) -> String {
+        let mut input = String::new();
+        for (key, value) in [
+            ("protocol", &self.protocol),
+            ("host", &self.host),
+            ("path", &self.path),
+            ("username", &self.username),
+            ("password", &self.password),
+        ] {
+            if !value.is_empty() {
+                input.push_str(&format!("{}={}\n", key, value));
+            }
+        }
+        input.push('\n');
+        input
+    }
+}
+
+fn run(action: &str, input: &str) -> Result<String, String> {
+    let mut child = Command::new("git")
+        .args(["credential", action])
+        // 服务端进程没有终端，禁止 git 回退到交互式输入
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Failed to run git credential {}: {}", action, e))?;
+    child
+        .stdin
+        .take()
+        .expect("stdin is piped")
+        .write_all(input.as_bytes())
+        .map_err(|e| format!("Failed to write to git credential {}: {}", action, e))?;
+    let output = child
+        .wait_with_output()
+        .map_err(|e| format!("Failed to run git credential {}: {}", action, e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "git credential {} failed: {}",
+            action,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+pub fn fill(url: &str, username: Option<&str>) -> Result<Credential, String> {
+    let mut credential = Credential::for_url(url)?;
+    if let Some(username) = username {
+        credential.username = username.to_string();
+    }
+    let output = run("fill", &credential.to_input())?;
+    for line in output.lines() {
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        match key {
+            "protocol" => credential.protocol = value.to_string(),
+            "host" => credential.host = value.to_string(),
+            "path" => credential.path = value.to_string(),
+            "username" => credential.username = value.to_string(),
+            "password" => credential.password = value.to_string(),
+            _ => {}
+        }
+    }
+    if credential.password.is_empty() {
+        return Err(format!("Credential helper returned no password for {}", credential.host));
+    }
+    Ok(credential)
+}
+
+pub fn approve(credential: &Credential) -> Result<(), String> {
+    run("approve", &credential.to_input()).map(|_| ())
+}
+
+pub fn reject(credential: &Credential) -> Result<(), String> {
+    run("reject", &credential.to_input()).map(|_| ())
+}
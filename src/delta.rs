@@ -0,0 +1,269 @@
+use actix_web::{
+    http::header::{EntityTag, ETag, IfNoneMatch, LastModified, CONTENT_ENCODING, CONTENT_TYPE, VARY},
+    HttpMessage, HttpRequest, HttpResponse,
+};
+use git2::{Oid, Repository};
+use std::path::{Path, PathBuf};
+
+use crate::{coalesce::LoadedFile, panic_recovery};
+
+pub const DELTA_CONTENT_TYPE: &str = "application/x-index-delta";
+pub const DELTA_HEADER: &str = "X-Delta-Encoding";
+pub const DELTA_VERSION: &str = "v1";
+// 旧版本只在同一个文件最近的这么多个版本里找，最多回溯这么多个提交
+const MAX_BASE_VERSIONS: usize = 32;
+const MAX_BASE_COMMITS: usize = 10_000;
+// 旧文件或新文件超过这个大小时不计算差异，避免客户端让服务器读取和比较很大的 blob
+const MAX_DELTA_FILE_SIZE: usize = 4 * 1024 * 1024;
+
+// web.delta_encoding 开启时索引文件的 ETag 是文件内容的 git blob OID（见 coalesce.rs），
+// 客户端带着旧的 ETag 和 X-Delta-Encoding: v1 请求时，从对象库里取出旧的 blob，只返回差异
+pub struct DeltaEncoding {
+    pub enabled: bool,
+    repo_path: PathBuf,
+}
+
+impl DeltaEncoding {
+    pub fn new(enabled: bool, repo_path: impl Into<PathBuf>) -> Self {
+        DeltaEncoding {
+            enabled,
+            repo_path: repo_path.into(),
+        }
+    }
+}
+
+pub fn blob_etag(content: &[u8]) -> EntityTag {
+    let oid = Oid::hash_object(git2::ObjectType::Blob, content).expect("hashing a blob in memory cannot fail");
+    EntityTag::new_strong(oid.to_string())
+}
+
+// 客户端声明支持 v1，并且 If-None-Match 里有一个是 blob OID 的强 ETag
+fn base_oid(req: &HttpRequest) -> Option<Oid> {
+    let accepts = req
+        .headers()
+        .get(DELTA_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.split(',').any(|version| version.trim() == DELTA_VERSION));
+    if !accepts {
+        return None;
+    }
+    match req.get_header::<IfNoneMatch>()? {
+        IfNoneMatch::Items(tags) => tags.iter().filter(|tag| !tag.weak).find_map(|tag| Oid::from_str(tag.tag()).ok()),
+        IfNoneMatch::Any => None,
+    }
+}
+
+// v1 格式按行描述怎样从旧文件得到新文件，依次执行：
+//   copy <n>\n    复制旧文件接下来的 n 行
+//   skip <n>\n    跳过旧文件接下来的 n 行
+//   insert <n>\n  后面紧跟 n 个字节的新内容
+// 行包括结尾的 \n。索引文件通常只在末尾追加版本，yank 只修改个别行，只比较相同的开头和结尾就够了
+pub fn encode(old: &[u8], new: &[u8]) -> Vec<u8> {
+    let old_lines: Vec<&[u8]> = old.split_inclusive(|&b| b == b'\n').collect();
+    let new_lines: Vec<&[u8]> = new.split_inclusive(|&b| b == b'\n').collect();
+    let prefix = old_lines.iter().zip(&new_lines).take_while(|(a, b)| a == b).count();
+    let suffix = old_lines[prefix..]
+        .iter()
+        .rev()
+        .zip(new_lines[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let inserted = new_lines[prefix..new_lines.len() - suffix].concat();
+
+    let mut delta = Vec::new();
+    if prefix > 0 {
+        delta.extend_from_slice(format!("copy {}\n", prefix).as_bytes());
+    }
+    let skipped = old_lines.len() - prefix - suffix;
+    if skipped > 0 {
+        delta.extend_from_slice(format!("skip {}\n", skipped).as_bytes());
+    }
+    if !inserted.is_empty() {
+        delta.extend_from_slice(format!("insert {}\n", inserted.len()).as_bytes());
+        delta.extend_from_slice(&inserted);
+    }
+    if suffix > 0 {
+        delta.extend_from_slice(format!("copy {}\n", suffix).as_bytes());
+    }
+    delta
+}
+
+// 沿着 master 的第一父提交往回找 path 的历史版本，base 不是其中之一时返回 None，
+// 不能把对象库里任意一个 blob（例如别的 crate 的文件）当作旧版本
+fn historical_blob(repo: &Repository, path: &Path, base: Oid) -> Result<Option<Vec<u8>>, git2::Error> {
+    let mut walk = repo.revwalk()?;
+    walk.push_head()?;
+    walk.simplify_first_parent()?;
+    let mut versions = 0;
+    let mut last = None;
+    for oid in walk.take(MAX_BASE_COMMITS) {
+        let tree = repo.find_commit(oid?)?.tree()?;
+        // 再往前这个文件还不存在
+        let Ok(entry) = tree.get_path(path) else {
+            break;
+        };
+        if last != Some(entry.id()) {
+            versions += 1;
+            if versions > MAX_BASE_VERSIONS {
+                break;
+            }
+            last = Some(entry.id());
+        }
+        if entry.id() == base {
+            let blob = repo.find_blob(base)?;
+            return Ok((blob.size() <= MAX_DELTA_FILE_SIZE).then(|| blob.content().to_vec()));
+        }
+    }
+    Ok(None)
+}
+
+// 旧的 blob 不是这个文件最近的版本（例如不是这个镜像返回的 ETag）、文件太大或差异不比整个文件小时返回 None，照常返回整个文件
+pub async fn delta_response(
+    req: &HttpRequest,
+    delta: &DeltaEncoding,
+    relative: PathBuf,
+    file: &LoadedFile,
+) -> Option<HttpResponse> {
+    if !delta.enabled || file.body.len() > MAX_DELTA_FILE_SIZE {
+        return None;
+    }
+    let base = base_oid(req)?;
+    let repo_path = delta.repo_path.clone();
+    let old = panic_recovery::block(move || -> Result<Option<Vec<u8>>, git2::Error> {
+        let repo = Repository::open(repo_path)?;
+        historical_blob(&repo, &relative, base)
+    })
+    .await
+    .ok()?
+    .ok()??;
+    let body = encode(&old, &file.body);
+    if body.len() >= file.body.len() {
+        return None;
+    }
+    Some(
+        HttpResponse::Ok()
+            .insert_header(ETag(file.etag.clone()))
+            .insert_header(LastModified(file.last_modified))
+            .insert_header((CONTENT_TYPE, DELTA_CONTENT_TYPE))
+            .insert_header((CONTENT_ENCODING, "identity"))
+            .insert_header((DELTA_HEADER, DELTA_VERSION))
+            .insert_header((VARY, "X-Delta-Encoding, If-None-Match"))
+            .body(body),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{index_line, Upstream};
+    use actix_web::test::TestRequest;
+
+    // 客户端按 v1 格式还原新文件
+    fn apply(old: &[u8], delta: &[u8]) -> Vec<u8> {
+        let mut old_lines = old.split_inclusive(|&b| b == b'\n');
+        let mut out = Vec::new();
+        let mut rest = delta;
+        while !rest.is_empty() {
+            let end = rest.iter().position(|&b| b == b'\n').unwrap();
+            let command = std::str::from_utf8(&rest[..end]).unwrap();
+            rest = &rest[end + 1..];
+            let (op, n) = command.split_once(' ').unwrap();
+            let n: usize = n.parse().unwrap();
+            match op {
+                "copy" => (0..n).for_each(|_| out.extend_from_slice(old_lines.next().unwrap())),
+                "skip" => {
+                    old_lines.nth(n - 1).unwrap();
+                }
+                "insert" => {
+                    out.extend_from_slice(&rest[..n]);
+                    rest = &rest[n..];
+                }
+                other => panic!("unknown delta command {}", other),
+            }
+        }
+        assert!(old_lines.next().is_none(), "delta did not consume the old file");
+        out
+    }
+
+    fn lines(versions: &[&str]) -> Vec<u8> {
+        versions.iter().map(|v| index_line("serde", v)).collect::<String>().into_bytes()
+    }
+
+    #[test]
+    fn round_trip() {
+        let cases = [
+            (lines(&[]), lines(&["1.0.0"])),
+            (lines(&["1.0.0"]), lines(&["1.0.0", "1.0.1"])),
+            (lines(&["1.0.0", "1.0.1", "1.0.2"]), lines(&["1.0.0", "1.0.9", "1.0.2"])),
+            (lines(&["1.0.0", "1.0.1"]), lines(&["1.0.1"])),
+            (lines(&["1.0.0"]), lines(&["1.0.0"])),
+            (lines(&["1.0.0", "1.0.1"]), lines(&[])),
+            // 没有结尾换行的文件
+            (b"a\nb".to_vec(), b"a\nb\nc".to_vec()),
+        ];
+        for (old, new) in cases {
+            assert_eq!(apply(&old, &encode(&old, &new)), new);
+        }
+        let old = lines(&["1.0.0", "1.0.1"]);
+        let new = lines(&["1.0.0", "1.0.1", "1.0.2"]);
+        let appended = index_line("serde", "1.0.2");
+        assert_eq!(
+            String::from_utf8(encode(&old, &new)).unwrap(),
+            format!("copy 2\ninsert {}\n{}", appended.len(), appended)
+        );
+    }
+
+    #[test]
+    fn base_from_if_none_match() {
+        let oid = "0123456789abcdef0123456789abcdef01234567";
+        let req = |headers: &[(&str, &str)]| {
+            let mut req = TestRequest::default();
+            for &header in headers {
+                req = req.insert_header(header);
+            }
+            base_oid(&req.to_http_request())
+        };
+        let strong = format!("\"{}\"", oid);
+        assert_eq!(req(&[(DELTA_HEADER, "v1"), ("If-None-Match", &strong)]), Some(Oid::from_str(oid).unwrap()));
+        assert_eq!(req(&[(DELTA_HEADER, "v0, v1"), ("If-None-Match", &strong)]), Some(Oid::from_str(oid).unwrap()));
+        assert_eq!(req(&[("If-None-Match", &strong)]), None);
+        assert_eq!(req(&[(DELTA_HEADER, "v2"), ("If-None-Match", &strong)]), None);
+        assert_eq!(req(&[(DELTA_HEADER, "v1"), ("If-None-Match", &format!("W/{}", strong))]), None);
+        assert_eq!(req(&[(DELTA_HEADER, "v1"), ("If-None-Match", "*")]), None);
+        assert_eq!(req(&[(DELTA_HEADER, "v1"), ("If-None-Match", "\"1:2:3:4\"")]), None);
+    }
+
+    #[test]
+    fn base_must_be_an_earlier_version_of_the_same_file() {
+        let upstream = Upstream::new();
+        let v1 = lines(&["1.0.0"]);
+        let v2 = lines(&["1.0.0", "1.0.1"]);
+        let other = index_line("tokio", "1.0.0");
+        upstream.commit("serde 1.0.0", &[("se/rd/serde", Some(std::str::from_utf8(&v1).unwrap())), ("to/ki/tokio", Some(&other))]);
+        upstream.commit("serde 1.0.1", &[("se/rd/serde", Some(std::str::from_utf8(&v2).unwrap()))]);
+        let repo = &upstream.repo;
+        let path = Path::new("se/rd/serde");
+        let oid = |content: &[u8]| Oid::hash_object(git2::ObjectType::Blob, content).unwrap();
+
+        assert_eq!(historical_blob(repo, path, oid(&v1)).unwrap(), Some(v1.clone()));
+        assert_eq!(historical_blob(repo, path, oid(&v2)).unwrap(), Some(v2.clone()));
+        // 对象库里有，但属于别的文件
+        assert_eq!(historical_blob(repo, path, oid(other.as_bytes())).unwrap(), None);
+        assert_eq!(historical_blob(repo, path, Oid::zero()).unwrap(), None);
+        assert_eq!(apply(&v1, &encode(&v1, &v2)), v2);
+    }
+
+    #[test]
+    fn base_history_is_bounded() {
+        let upstream = Upstream::new();
+        let first = lines(&["0.0.0"]);
+        let mut content = String::from_utf8(first.clone()).unwrap();
+        upstream.commit("first", &[("se/rd/serde", Some(&content))]);
+        for i in 1..=MAX_BASE_VERSIONS {
+            content.push_str(&index_line("serde", &format!("0.0.{}", i)));
+            upstream.commit("bump", &[("se/rd/serde", Some(&content))]);
+        }
+        let oid = Oid::hash_object(git2::ObjectType::Blob, &first).unwrap();
+        assert_eq!(historical_blob(&upstream.repo, Path::new("se/rd/serde"), oid).unwrap(), None);
+    }
+}
@@ -0,0 +1,404 @@
+use actix_web::{
+    http::header::{ETAG, IF_NONE_MATCH},
+    web, HttpRequest, HttpResponse,
+};
+use chrono::{SecondsFormat, Utc};
+use git2::{Oid, Repository};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, VecDeque},
+    fs::{self, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::Duration,
+};
+use tokio::sync::watch;
+use tracing::{info, warn};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{
+    index_parser::fast_parse_index_line,
+    problem::{self, ProblemDetails},
+    server::SharedState,
+    sparse,
+};
+
+const MAX_EVENTS_PER_PAGE: usize = 1000;
+const MAX_WAIT_SECS: u64 = 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub enum EventKind {
+    #[serde(rename = "crate_added")]
+    Added,
+    #[serde(rename = "crate_updated")]
+    Updated,
+    #[serde(rename = "crate_yanked")]
+    Yanked,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Event {
+    pub seq: u64,
+    pub ts: String,
+    pub event: EventKind,
+    pub name: String,
+    pub version: String,
+    pub cksum: String,
+}
+
+struct EventLogState {
+    events: VecDeque<Event>,
+    // 文件里的行数，超过 max_events 的两倍时重写文件
+    file_events: usize,
+    // 上一次记录到的提交，下次从这里开始 diff
+    last_head: Option<Oid>,
+}
+
+// [events] log_path 指向的 NDJSON 文件，只追加；每次索引更新后根据 git diff 生成事件
+pub struct EventLog {
+    path: PathBuf,
+    max_events: usize,
+    state: Mutex<EventLogState>,
+    last_seq: watch::Sender<u64>,
+}
+
+// 索引文件每行一个版本，按版本号对应 (yanked, cksum)
+fn versions(repo: &Repository, blob: Oid) -> HashMap<String, (bool, String)> {
+    let Ok(blob) = repo.find_blob(blob) else {
+        return HashMap::new();
+    };
+    blob.content()
+        .split(|&b| b == b'\n')
+        .filter_map(|line| fast_parse_index_line(line).ok())
+        .map(|entry| (entry.vers.into_owned(), (entry.yanked, entry.cksum.into_owned())))
+        .collect()
+}
+
+// 新增的版本是 crate_added，已有版本被 yank 是 crate_yanked，其余变化（例如取消 yank）是 crate_updated
+fn diff_events(repo: &Repository, old: Oid, new: Oid) -> Result<Vec<(EventKind, String, String, String)>, git2::Error> {
+    let old_tree = repo.find_commit(old)?.tree()?;
+    let new_tree = repo.find_commit(new)?.tree()?;
+    let diff = repo.diff_tree_to_tree(Some(&old_tree), Some(&new_tree), None)?;
+    let mut events = Vec::new();
+    for delta in diff.deltas() {
+        let new_file = delta.new_file();
+        let is_crate_file = new_file
+            .path()
+            .and_then(|path| path.to_str())
+            .is_some_and(sparse::is_crate_request);
+        // 删除的文件没有对应的事件
+        if !is_crate_file || new_file.id().is_zero() {
+            continue;
+        }
+        let old_versions = versions(repo, delta.old_file().id());
+        let Ok(blob) = repo.find_blob(new_file.id()) else {
+            continue;
+        };
+        // 按文件中的顺序，也就是发布的顺序
+        for line in blob.content().split(|&b| b == b'\n') {
+            let Ok(entry) = fast_parse_index_line(line) else {
+                continue;
+            };
+            let kind = match old_versions.get(entry.vers.as_ref()) {
+                None => EventKind::Added,
+                Some((yanked, cksum)) if *yanked == entry.yanked && *cksum == entry.cksum => continue,
+                Some((false, _)) if entry.yanked => EventKind::Yanked,
+                Some(_) => EventKind::Updated,
+            };
+            events.push((
+                kind,
+                entry.name.into_owned(),
+                entry.vers.into_owned(),
+                entry.cksum.into_owned(),
+            ));
+        }
+    }
+    Ok(events)
+}
+
+fn head_commit(repo: &Repository) -> Option<Oid> {
+    repo.head().ok().and_then(|head| head.target())
+}
+
+impl EventLog {
+    // 读取已有的事件；无法解析的行（例如写了一半的最后一行）跳过
+    pub fn open(path: impl Into<PathBuf>, repo_path: &Path, max_events: usize) -> Result<Self, String> {
+        let path = path.into();
+        let mut events: VecDeque<Event> = match fs::read_to_string(&path) {
+            Ok(content) => content
+                .lines()
+                .filter_map(|line| serde_json::from_str(line).ok())
+                .collect(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => VecDeque::new(),
+            Err(e) => return Err(format!("Failed to read {}: {}", path.display(), e)),
+        };
+        let file_events = events.len();
+        if events.len() > max_events {
+            events.drain(..events.len() - max_events);
+        }
+        let last_seq = events.back().map_or(0, |event| event.seq);
+        info!("Loaded {} events from {}", events.len(), path.display());
+        // 从当前的 HEAD 开始记录；还没有 clone 时在第一次 record 时确定起点
+        let last_head = Repository::open(repo_path).ok().and_then(|repo| head_commit(&repo));
+        Ok(EventLog {
+            path,
+            max_events,
+            state: Mutex::new(EventLogState {
+                events,
+                file_events,
+                last_head,
+            }),
+            last_seq: watch::Sender::new(last_seq),
+        })
+    }
+
+    // 在 HEAD 变化之后调用（pull、hard reset、replication），调用方持有 RepoLock
+    // 第一次调用只记录起点，不把整个索引当作新增
+    pub fn record(&self, repo: &Repository) {
+        let Some(head) = head_commit(repo) else {
+            return;
+        };
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let old = match state.last_head {
+            Some(old) if old != head => old,
+            Some(_) => return,
+            None => {
+                state.last_head = Some(head);
+                return;
+            }
+        };
+        let changes = match diff_events(repo, old, head) {
+            Ok(changes) => changes,
+            Err(e) => {
+                warn!("Failed to diff {}..{} for the event log: {}", old, head, e);
+                return;
+            }
+        };
+        let ts = Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true);
+        let first_seq = state.events.back().map_or(0, |event| event.seq) + 1;
+        let events: Vec<Event> = changes
+            .into_iter()
+            .zip(first_seq..)
+            .map(|((event, name, version, cksum), seq)| Event {
+                seq,
+                ts: ts.clone(),
+                event,
+                name,
+                version,
+                cksum,
+            })
+            .collect();
+        let mut lines = String::new();
+        for event in &events {
+            lines.push_str(&serde_json::to_string(event).expect("Event is always serializable"));
+            lines.push('\n');
+        }
+        // 写入失败时不移动起点，下次更新时重新生成这一段的事件
+        let written = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut file| file.write_all(lines.as_bytes()));
+        if let Err(e) = written {
+            warn!("Failed to append to event log {}: {}", self.path.display(), e);
+            return;
+        }
+        state.last_head = Some(head);
+        if let Some(last) = events.last() {
+            info!("Recorded {} index events up to seq {}", events.len(), last.seq);
+            self.last_seq.send_replace(last.seq);
+        }
+        state.file_events += events.len();
+        state.events.extend(events);
+        if state.events.len() > self.max_events {
+            let excess = state.events.len() - self.max_events;
+            state.events.drain(..excess);
+        }
+        if state.file_events > self.max_events * 2 {
+            self.compact(&mut state);
+        }
+    }
+
+    // 只保留内存里的事件；先写临时文件再替换，中途失败时原文件不变
+    fn compact(&self, state: &mut EventLogState) {
+        let mut lines = String::new();
+        for event in &state.events {
+            lines.push_str(&serde_json::to_string(event).expect("Event is always serializable"));
+            lines.push('\n');
+        }
+        let tmp = self.path.with_extension("compacting");
+        match fs::write(&tmp, lines).and_then(|()| fs::rename(&tmp, &self.path)) {
+            Ok(()) => {
+                info!("Compacted event log {} to {} events", self.path.display(), state.events.len());
+                state.file_events = state.events.len();
+            }
+            Err(e) => warn!("Failed to compact event log {}: {}", self.path.display(), e),
+        }
+    }
+
+    fn since(&self, since_seq: u64, limit: usize) -> Vec<Event> {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let start = state.events.partition_point(|event| event.seq <= since_seq);
+        state.events.range(start..).take(limit).cloned().collect()
+    }
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct EventsQuery {
+    #[serde(default)]
+    since_seq: u64,
+    limit: Option<usize>,
+    // 和 If-None-Match 一起使用：没有新事件时最多等待这么多秒，再返回 304
+    #[serde(default)]
+    wait: u64,
+}
+
+// 同一个 since_seq 的结果只会在有新事件时变化
+fn etag(since_seq: u64, last_seq: u64) -> String {
+    format!("\"{}-{}\"", since_seq, last_seq)
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct EventsResponse {
+    events: Vec<Event>,
+    last_seq: u64,
+}
+
+// GET /api/v1/events?since_seq=N，返回 seq 大于 N 的事件
+// ETag 包含最新的 seq，客户端带上 If-None-Match 轮询，没有新事件时返回 304
+#[utoipa::path(
+    get,
+    path = "/api/v1/events",
+    tag = "index",
+    params(
+        EventsQuery,
+        ("If-None-Match" = Option<String>, Header, description = "ETag of the previous response"),
+    ),
+    responses(
+        (status = 200, description = "Events after since_seq, at most 1000", body = EventsResponse),
+        (status = 304, description = "No new events"),
+        (status = 404, description = "events.log_path is not set", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
+pub async fn list_events(
+    req: HttpRequest,
+    state: web::Data<SharedState>,
+    query: web::Query<EventsQuery>,
+) -> HttpResponse {
+    let Some(log) = &state.events else {
+        return problem::not_found("event log is not enabled").into();
+    };
+    let if_none_match = req
+        .headers()
+        .get(IF_NONE_MATCH)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.to_string());
+    let mut last_seq = log.last_seq.subscribe();
+    if if_none_match.as_deref() == Some(etag(query.since_seq, *last_seq.borrow()).as_str()) {
+        let wait = Duration::from_secs(query.wait.min(MAX_WAIT_SECS));
+        let changed = !wait.is_zero()
+            && tokio::time::timeout(wait, last_seq.changed()).await.is_ok_and(|r| r.is_ok());
+        if !changed {
+            return HttpResponse::NotModified()
+                .insert_header((ETAG, etag(query.since_seq, *last_seq.borrow())))
+                .finish();
+        }
+    }
+
+    let current = *last_seq.borrow();
+    let limit = query.limit.unwrap_or(MAX_EVENTS_PER_PAGE).clamp(1, MAX_EVENTS_PER_PAGE);
+    let events = log.since(query.since_seq, limit);
+    HttpResponse::Ok()
+        .insert_header((ETAG, etag(query.since_seq, current)))
+        .json(EventsResponse {
+            events,
+            last_seq: current,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{index_line, Upstream};
+
+    const CRATES: [(&str, &str); 4] = [
+        ("3/l/log", "log"),
+        ("ra/nd/rand", "rand"),
+        ("se/rd/serde", "serde"),
+        ("to/ki/tokio", "tokio"),
+    ];
+
+    fn seqs(events: &[Event]) -> Vec<u64> {
+        events.iter().map(|event| event.seq).collect()
+    }
+
+    // 4 个 crate 各发布 25 个版本，一共 100 个事件
+    fn populate(upstream: &Upstream, log: &EventLog) {
+        let files: Vec<(&str, String)> = CRATES
+            .iter()
+            .map(|(path, name)| (*path, (0..25).map(|i| index_line(name, &format!("1.0.{}", i))).collect()))
+            .collect();
+        let files: Vec<_> = files.iter().map(|(path, content)| (*path, Some(content.as_str()))).collect();
+        upstream.commit("publish", &files);
+        log.record(&upstream.repo);
+    }
+
+    #[test]
+    fn since_seq_filters_events() {
+        let upstream = Upstream::new();
+        upstream.commit("initial", &[("config.json", Some("{}"))]);
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("events.ndjson");
+        let log = EventLog::open(&path, upstream.dir.path(), 1000).unwrap();
+        populate(&upstream, &log);
+
+        let all = log.since(0, MAX_EVENTS_PER_PAGE);
+        assert_eq!(seqs(&all), (1..=100).collect::<Vec<_>>());
+        assert!(all.iter().all(|event| event.event == EventKind::Added));
+        // 同一个文件里按发布顺序
+        assert_eq!((all[0].name.as_str(), all[0].version.as_str()), ("log", "1.0.0"));
+        assert_eq!((all[99].name.as_str(), all[99].version.as_str()), ("tokio", "1.0.24"));
+        assert_eq!(seqs(&log.since(50, MAX_EVENTS_PER_PAGE)), (51..=100).collect::<Vec<_>>());
+        assert_eq!(seqs(&log.since(50, 10)), (51..=60).collect::<Vec<_>>());
+        assert_eq!(seqs(&log.since(99, MAX_EVENTS_PER_PAGE)), [100]);
+        assert!(log.since(100, MAX_EVENTS_PER_PAGE).is_empty());
+        assert!(log.since(1000, MAX_EVENTS_PER_PAGE).is_empty());
+        assert_eq!(*log.last_seq.borrow(), 100);
+
+        // HEAD 没变时不产生新事件；yank 接着之前的 seq
+        log.record(&upstream.repo);
+        let serde: String = (0..25)
+            .map(|i| index_line("serde", &format!("1.0.{}", i)))
+            .collect::<String>()
+            .replacen("\"yanked\":false", "\"yanked\":true", 1);
+        upstream.commit("yank", &[("se/rd/serde", Some(&serde))]);
+        log.record(&upstream.repo);
+        let yanked = log.since(100, MAX_EVENTS_PER_PAGE);
+        assert_eq!(seqs(&yanked), [101]);
+        assert_eq!(yanked[0].event, EventKind::Yanked);
+        assert_eq!((yanked[0].name.as_str(), yanked[0].version.as_str()), ("serde", "1.0.0"));
+
+        // 重新打开时从文件恢复，超出 max_events 的旧事件被丢掉
+        let reopened = EventLog::open(&path, upstream.dir.path(), 1000).unwrap();
+        assert_eq!(seqs(&reopened.since(0, MAX_EVENTS_PER_PAGE)), (1..=101).collect::<Vec<_>>());
+        let truncated = EventLog::open(&path, upstream.dir.path(), 20).unwrap();
+        assert_eq!(seqs(&truncated.since(0, MAX_EVENTS_PER_PAGE)), (82..=101).collect::<Vec<_>>());
+        assert_eq!(seqs(&truncated.since(90, 5)), (91..=95).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn first_record_only_sets_the_start() {
+        let upstream = Upstream::new();
+        let dir = tempfile::tempdir().unwrap();
+        // 还没有提交，第一次 record 时确定起点
+        let log = EventLog::open(dir.path().join("events.ndjson"), upstream.dir.path(), 1000).unwrap();
+        upstream.commit("initial", &[("se/rd/serde", Some(&index_line("serde", "1.0.0")))]);
+        log.record(&upstream.repo);
+        assert!(log.since(0, MAX_EVENTS_PER_PAGE).is_empty());
+        populate(&upstream, &log);
+        // serde 1.0.0 已经存在，不是新增
+        assert_eq!(log.since(0, MAX_EVENTS_PER_PAGE).len(), 99);
+    }
+}
@@ -0,0 +1,164 @@
+use std::{
+    fmt,
+    fs::{File, OpenOptions, TryLockError},
+    io::{self, Read, Seek, Write},
+    path::Path,
+};
+
+#[derive(Debug)]
+pub enum LockError {
+    // 另一个进程持有锁；pid 是最后一次获得锁的进程写入的，可能为空
+    AlreadyLocked(Option<u32>),
+    Io(io::Error),
+}
+
+impl fmt::Display for LockError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LockError::AlreadyLocked(Some(pid)) => write!(f, "repository is locked by process {}", pid),
+            LockError::AlreadyLocked(None) => write!(f, "repository is locked by another process"),
+            LockError::Io(e) => write!(f, "failed to lock repository: {}", e),
+        }
+    }
+}
+
+impl From<io::Error> for LockError {
+    fn from(e: io::Error) -> Self {
+        LockError::Io(e)
+    }
+}
+
+// 进程间的排他锁（Unix 上是 flock，Windows 上是 LockFileEx），进程退出或 panic 后由系统释放
+// 文件内容是持有锁的进程的 pid
+pub struct FileLock {
+    file: File,
+}
+
+impl FileLock {
+    // 不等待，已被占用时直接返回 AlreadyLocked
+    pub fn try_acquire(path: &Path) -> Result<Self, LockError> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => {
+                let mut content = String::new();
+                let pid = file.read_to_string(&mut content).ok().and_then(|_| content.trim().parse().ok());
+                return Err(LockError::AlreadyLocked(pid));
+            }
+            Err(TryLockError::Error(e)) => return Err(e.into()),
+        }
+        file.set_len(0)?;
+        file.rewind()?;
+        write!(file, "{}", std::process::id())?;
+        Ok(FileLock { file })
+    }
+}
+
+impl Drop for FileLock {
+    fn drop(&mut self) {
+        let _ = self.file.unlock();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        process::{Command, Stdio},
+        thread,
+        time::{Duration, Instant},
+    };
+
+    const CHILD_DIR: &str = "FILE_LOCK_TEST_DIR";
+
+    fn wait_for(path: &Path) {
+        let deadline = Instant::now() + Duration::from_secs(30);
+        while !path.exists() {
+            assert!(Instant::now() < deadline, "{} was not created", path.display());
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    // 由 two_processes 作为子进程运行，输出和测试框架的输出在同一行；等 go 出现后同时去拿锁，拿到的进程持有锁直到 release 出现
+    #[test]
+    fn lock_in_child() {
+        let Ok(dir) = std::env::var(CHILD_DIR) else {
+            return;
+        };
+        let dir = Path::new(&dir);
+        wait_for(&dir.join("go"));
+        match FileLock::try_acquire(&dir.join("mirror.lock")) {
+            Ok(_lock) => {
+                println!("lock-result: acquired {}", std::process::id());
+                wait_for(&dir.join("release"));
+            }
+            Err(LockError::AlreadyLocked(pid)) => println!("lock-result: locked {:?}", pid),
+            Err(e) => println!("lock-result: error {}", e),
+        }
+    }
+
+    #[test]
+    fn two_processes() {
+        let dir = tempfile::tempdir().unwrap();
+        let spawn = || {
+            Command::new(std::env::current_exe().unwrap())
+                .args(["--exact", "file_lock::tests::lock_in_child", "--nocapture", "--test-threads=1"])
+                .env(CHILD_DIR, dir.path())
+                .stdout(Stdio::piped())
+                .spawn()
+                .unwrap()
+        };
+        let mut children = [spawn(), spawn()];
+        std::fs::write(dir.path().join("go"), "").unwrap();
+        // 没拿到锁的进程先退出，之后才让另一个释放锁
+        let deadline = Instant::now() + Duration::from_secs(30);
+        while children.iter_mut().all(|child| child.try_wait().unwrap().is_none()) {
+            assert!(Instant::now() < deadline, "neither process finished");
+            thread::sleep(Duration::from_millis(10));
+        }
+        std::fs::write(dir.path().join("release"), "").unwrap();
+
+        let mut results: Vec<String> = children
+            .into_iter()
+            .map(|child| {
+                let output = child.wait_with_output().unwrap();
+                assert!(output.status.success());
+                let stdout = String::from_utf8(output.stdout).unwrap();
+                stdout
+                    .lines()
+                    .find_map(|line| Some(line.split_once("lock-result: ")?.1))
+                    .unwrap_or_else(|| panic!("no result in {:?}", stdout))
+                    .to_string()
+            })
+            .collect();
+        results.sort();
+        let holder = results[0].strip_prefix("acquired ").unwrap_or_else(|| panic!("{:?}", results));
+        assert_eq!(results[1], format!("locked Some({})", holder));
+        // 持有锁的进程退出后可以再次获得
+        FileLock::try_acquire(&dir.path().join("mirror.lock")).unwrap();
+    }
+
+    #[test]
+    fn released_on_drop_and_panic() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("mirror.lock");
+        let lock = FileLock::try_acquire(&path).unwrap();
+        // flock 属于打开的文件，同一个进程里再次打开也会冲突
+        match FileLock::try_acquire(&path) {
+            Err(LockError::AlreadyLocked(pid)) => assert_eq!(pid, Some(std::process::id())),
+            other => panic!("{:?}", other.map(|_| ())),
+        }
+        drop(lock);
+        let result = std::panic::catch_unwind(|| {
+            let _lock = FileLock::try_acquire(&path).unwrap();
+            panic!("git operation failed");
+        });
+        assert!(result.is_err());
+        FileLock::try_acquire(&path).unwrap();
+    }
+}
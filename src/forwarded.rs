@@ -0,0 +1,80 @@
+use actix_web::{http::header, HttpRequest};
+use ipnet::IpNet;
+use std::net::IpAddr;
+
+const X_FORWARDED_FOR: &str = "x-forwarded-for";
+
+use crate::config::WebConfig;
+
+// 单个地址按 /32 或 /128 处理
+pub fn parse_proxy(proxy: &str) -> Option<IpNet> {
+    proxy
+        .parse::<IpNet>()
+        .ok()
+        .or_else(|| proxy.parse::<IpAddr>().ok().map(IpNet::from))
+}
+
+// web.trusted_proxies；其他地址发来的 X-Forwarded-* 和 Forwarded 请求头可以随意伪造，一律忽略
+pub struct TrustedProxies(Vec<IpNet>);
+
+impl TrustedProxies {
+    // 配置已经在 Config::validate 里检查过
+    pub fn from_config(config: &WebConfig) -> Self {
+        TrustedProxies(config.trusted_proxies.iter().filter_map(|proxy| parse_proxy(proxy)).collect())
+    }
+
+    fn contains(&self, ip: IpAddr) -> bool {
+        self.0.iter().any(|net| net.contains(&ip))
+    }
+
+    fn is_trusted(&self, req: &HttpRequest) -> bool {
+        req.peer_addr().is_some_and(|addr| self.contains(addr.ip()))
+    }
+
+    // 从 X-Forwarded-For 的右边开始跳过可信代理，第一个不可信的地址就是客户端；
+    // 最左边的值由客户端自己填写，不能直接使用。无法解析的值之前的部分同样不可信
+    pub fn client_ip(&self, req: &HttpRequest) -> Option<IpAddr> {
+        let mut client = req.peer_addr()?.ip();
+        if !self.contains(client) {
+            return Some(client);
+        }
+        let hops: Vec<&str> = req
+            .headers()
+            .get_all(X_FORWARDED_FOR)
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .collect();
+        for hop in hops.into_iter().rev() {
+            let Ok(ip) = hop.trim().parse::<IpAddr>() else {
+                break;
+            };
+            client = ip;
+            if !self.contains(ip) {
+                break;
+            }
+        }
+        Some(client)
+    }
+
+    // 经过可信代理时以代理转发的协议为准，否则看连接本身
+    pub fn is_https(&self, req: &HttpRequest) -> bool {
+        if self.is_trusted(req) {
+            req.connection_info().scheme() == "https"
+        } else {
+            req.app_config().secure()
+        }
+    }
+
+    // 只有可信代理才能通过 X-Forwarded-Host 改变主机名
+    pub fn host(&self, req: &HttpRequest) -> String {
+        if self.is_trusted(req) {
+            return req.connection_info().host().to_string();
+        }
+        req.headers()
+            .get(header::HOST)
+            .and_then(|host| host.to_str().ok())
+            .or_else(|| req.uri().authority().map(|authority| authority.as_str()))
+            .unwrap_or_else(|| req.app_config().host())
+            .to_string()
+    }
+}
@@ -0,0 +1,224 @@
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::time;
+use tracing::{error, info, warn};
+
+use crate::{git::RepoLock, metrics, repo_stats};
+
+// git gc --auto 在 loose 对象或 pack 文件超过阈值时打包并清理不可达的对象，否则什么都不做
+fn run_gc(repo_path: &Path, repo_lock: &RepoLock) -> Result<(), String> {
+    // 和 pull、replication 互斥，避免 fetch 写入对象时被清理
+    let _guard = repo_lock.lock().map_err(|e| e.to_string())?;
+    let git_dir = repo_path.join(".git");
+    let before = repo_stats::dir_size(&git_dir, None);
+    let start = Instant::now();
+    // gc --auto 默认转到后台运行，会在释放锁之后继续改写对象目录
+    let output = Command::new("git")
+        .current_dir(repo_path)
+        .args(["-c", "gc.autoDetach=false", "gc", "--auto", "--quiet"])
+        .output()
+        .map_err(|e| format!("Failed to run git gc: {}", e))?;
+    metrics::GC_DURATION_SECONDS.observe(start.elapsed());
+    metrics::GC_RUNS_TOTAL.inc();
+    if !output.status.success() {
+        return Err(format!(
+            "git gc failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    let after = repo_stats::dir_size(&git_dir, None);
+    info!(
+        "Repository gc finished in {:?}, .git size {} -> {} bytes",
+        start.elapsed(),
+        before,
+        after
+    );
+    Ok(())
+}
+
+pub async fn run(repo_path: PathBuf, repo_lock: Arc<RepoLock>, interval: Duration) {
+    let mut interval = time::interval(interval);
+    // 第一次 tick 立即返回，跳过它，不在启动时就执行 gc
+    interval.tick().await;
+    loop {
+        interval.tick().await;
+        let path = repo_path.clone();
+        let repo_lock = Arc::clone(&repo_lock);
+        match tokio::task::spawn_blocking(move || run_gc(&path, &repo_lock)).await {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => warn!("{}", e),
+            Err(e) => error!("Repository gc task failed: {}", e),
+        }
+    }
+}
+
+// 很多次增量 fetch 之后会积累大量小的 pack 文件，查找对象时要逐个检查
+fn repack_if_fragmented(repo_path: &Path, repo_lock: &RepoLock, max_pack_files: u32) -> Result<(), String> {
+    let git_dir = repo_path.join(".git");
+    let count = repo_stats::count_pack_files(&git_dir);
+    metrics::PACK_FILE_COUNT.set(count);
+    if count <= i64::from(max_pack_files) {
+        return Ok(());
+    }
+    let _guard = repo_lock.lock().map_err(|e| e.to_string())?;
+    // 等锁期间可能已经有 gc 或者 repack 完成
+    let before = repo_stats::count_pack_files(&git_dir);
+    if before <= i64::from(max_pack_files) {
+        metrics::PACK_FILE_COUNT.set(before);
+        return Ok(());
+    }
+    info!(
+        "Repository has {} pack files (repo.max_pack_files = {}), repacking",
+        before, max_pack_files
+    );
+    metrics::REPACKS_TRIGGERED_TOTAL.inc();
+    let start = Instant::now();
+    // 不加 -f，复用已有的 delta，不重新压缩所有对象；-a 把所有 pack 合并成一个
+    let output = Command::new("git")
+        .current_dir(repo_path)
+        .args(["repack", "-a", "-d", "-q"])
+        .output()
+        .map_err(|e| format!("Failed to run git repack: {}", e))?;
+    let after = repo_stats::count_pack_files(&git_dir);
+    metrics::PACK_FILE_COUNT.set(after);
+    if !output.status.success() {
+        return Err(format!(
+            "git repack failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    info!(
+        "Repository repack finished in {:?}, pack files {} -> {}",
+        start.elapsed(),
+        before,
+        after
+    );
+    Ok(())
+}
+
+pub async fn monitor_packs(repo_path: PathBuf, repo_lock: Arc<RepoLock>, max_pack_files: u32, interval: Duration) {
+    let mut interval = time::interval(interval);
+    loop {
+        interval.tick().await;
+        let path = repo_path.clone();
+        let repo_lock = Arc::clone(&repo_lock);
+        match tokio::task::spawn_blocking(move || repack_if_fragmented(&path, &repo_lock, max_pack_files)).await {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => warn!("{}", e),
+            Err(e) => error!("Pack file monitor task failed: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{self, index_line, Upstream};
+
+    // 每个提交之后增量打包一次，每次生成一个新的 pack 文件
+    fn fragmented(packs: usize) -> Upstream {
+        let upstream = Upstream::new();
+        for i in 0..packs {
+            let name = format!("crate{}", i);
+            upstream.commit(&name, &[(&format!("cr/at/{}", name), Some(&index_line(&name, "1.0.0")))]);
+            let status = Command::new("git")
+                .current_dir(upstream.dir.path())
+                .args(["repack", "-q"])
+                .status()
+                .unwrap();
+            assert!(status.success());
+        }
+        upstream
+    }
+
+    fn packs(upstream: &Upstream) -> i64 {
+        repo_stats::count_pack_files(&upstream.dir.path().join(".git"))
+    }
+
+    #[test]
+    fn repacks_when_over_limit() {
+        let upstream = fragmented(6);
+        let lock_dir = tempfile::tempdir().unwrap();
+        let lock = RepoLock::new(lock_dir.path().join("lock"));
+        assert_eq!(packs(&upstream), 6);
+
+        repack_if_fragmented(upstream.dir.path(), &lock, 6).unwrap();
+        assert_eq!(packs(&upstream), 6);
+
+        repack_if_fragmented(upstream.dir.path(), &lock, 5).unwrap();
+        assert_eq!(packs(&upstream), 1);
+        // 合并之后对象都还在
+        let head = upstream.repo.head().unwrap().peel_to_tree().unwrap();
+        assert!(head.get_path(Path::new("cr/at/crate0")).is_ok());
+        assert!(head.get_path(Path::new("cr/at/crate5")).is_ok());
+    }
+
+    #[test]
+    fn skips_while_repo_is_locked() {
+        let upstream = fragmented(3);
+        let lock_dir = tempfile::tempdir().unwrap();
+        // 另一个进程（这里是另一个 RepoLock）正在使用仓库
+        let other = RepoLock::new(lock_dir.path().join("lock"));
+        let guard = other.lock().unwrap();
+        let lock = RepoLock::new(lock_dir.path().join("lock"));
+        assert!(repack_if_fragmented(upstream.dir.path(), &lock, 1).is_err());
+        assert_eq!(packs(&upstream), 3);
+        drop(guard);
+        repack_if_fragmented(upstream.dir.path(), &lock, 1).unwrap();
+        assert_eq!(packs(&upstream), 1);
+    }
+
+    // .git/objects/xx/ 下的 loose 对象
+    fn loose_objects(upstream: &Upstream) -> usize {
+        std::fs::read_dir(upstream.dir.path().join(".git/objects"))
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.file_name().unwrap().len() == 2)
+            .map(|dir| std::fs::read_dir(dir).unwrap().count())
+            .sum()
+    }
+
+    #[test]
+    fn gc_packs_loose_objects() {
+        let upstream = Upstream::new();
+        upstream.commit("add a", &[("1/a", Some(&index_line("a", "0.1.0")))]);
+        // git gc --auto 只抽样 objects/17 估算 loose 对象数，需要足够多的对象才能稳定触发
+        let repo = &upstream.repo;
+        let mut tree = repo.treebuilder(None).unwrap();
+        for i in 0..5000 {
+            let name = format!("crate{}", i);
+            let blob = repo.blob(index_line(&name, "1.0.0").as_bytes()).unwrap();
+            tree.insert(&name, blob, 0o100644).unwrap();
+        }
+        let tree = repo.find_tree(tree.write().unwrap()).unwrap();
+        let parent = repo.head().unwrap().peel_to_commit().unwrap();
+        let signature = test_support::signature();
+        repo.commit(Some("HEAD"), &signature, &signature, "add crates", &tree, &[&parent]).unwrap();
+        repo.config().unwrap().set_i32("gc.auto", 256).unwrap();
+        let before = loose_objects(&upstream);
+        assert!(before > 5000, "{}", before);
+        let lock_dir = tempfile::tempdir().unwrap();
+        let lock = RepoLock::new(lock_dir.path().join("lock"));
+
+        run_gc(upstream.dir.path(), &lock).unwrap();
+        assert!(loose_objects(&upstream) < before / 10, "{} -> {}", before, loose_objects(&upstream));
+        assert!(packs(&upstream) >= 1);
+        let head = repo.head().unwrap().peel_to_tree().unwrap();
+        assert!(head.get_path(Path::new("crate0")).is_ok());
+        assert!(head.get_path(Path::new("crate4999")).is_ok());
+    }
+
+    #[test]
+    fn gc_waits_for_repo_lock() {
+        let upstream = fragmented(1);
+        let lock_dir = tempfile::tempdir().unwrap();
+        let other = RepoLock::new(lock_dir.path().join("lock"));
+        let _guard = other.lock().unwrap();
+        let lock = RepoLock::new(lock_dir.path().join("lock"));
+        assert!(run_gc(upstream.dir.path(), &lock).is_err());
+    }
+}
@@ -0,0 +1,766 @@
+use git2::{
+    build::CheckoutBuilder, CertificateCheckStatus, Direction, FetchOptions, ObjectType, Oid, RemoteCallbacks,
+    Repository, Tree,
+};
+use std::{
+    cell::{Cell, RefCell},
+    ffi::{c_int, CStr, CString},
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+    sync::{Mutex, MutexGuard, OnceLock},
+};
+use tracing::{debug, error, info, trace, warn};
+
+use crate::{
+    config::{redact_url, CratesIoIndexRepo, Git2TraceLevel},
+    credential,
+    file_lock::{FileLock, LockError},
+};
+
+// 串行化所有会修改仓库的操作（定时pull、replication）；同时持有锁文件，和使用同一个仓库的其他进程互斥
+pub struct RepoLock {
+    mutex: Mutex<()>,
+    lock_file: PathBuf,
+}
+
+// 先释放锁文件，再释放进程内的锁
+pub struct RepoGuard<'a> {
+    _file: FileLock,
+    _guard: MutexGuard<'a, ()>,
+}
+
+impl RepoLock {
+    pub fn new(lock_file: impl Into<PathBuf>) -> Self {
+        RepoLock {
+            mutex: Mutex::new(()),
+            lock_file: lock_file.into(),
+        }
+    }
+
+    pub fn lock(&self) -> Result<RepoGuard<'_>, LockError> {
+        let guard = self.mutex.lock().unwrap_or_else(|e| e.into_inner());
+        Ok(RepoGuard {
+            _file: FileLock::try_acquire(&self.lock_file)?,
+            _guard: guard,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PullOutcome {
+    UpToDate,
+    FastForward { old: Oid, new: Oid },
+    // 本地历史和上游分叉，例如上游 squash 了历史
+    NonFastForward { upstream: Oid },
+    // repo.require_signed_commits 开启时，新提交中有没有签名或签名无法验证的
+    Unverified { upstream: Oid },
+}
+
+// libgit2 在任意线程上调用，只能是不捕获状态的函数
+fn forward_git2_trace(level: git2::TraceLevel, message: &[u8]) {
+    let message = String::from_utf8_lossy(message);
+    match level {
+        git2::TraceLevel::None => {}
+        git2::TraceLevel::Fatal | git2::TraceLevel::Error => error!(target: "git2", "libgit2: {}", message),
+        git2::TraceLevel::Warn => warn!(target: "git2", "libgit2: {}", message),
+        git2::TraceLevel::Info => info!(target: "git2", "libgit2: {}", message),
+        git2::TraceLevel::Debug => debug!(target: "git2", "libgit2: {}", message),
+        git2::TraceLevel::Trace => trace!(target: "git2", "libgit2: {}", message),
+    }
+}
+
+// 按 logging.git2_trace_level 把 libgit2 的 trace 转发到 tracing；启动时、第一次 git 操作之前调用
+// 例如设置为 debug 并使用 RUST_LOG=info,git2=debug
+pub fn set_trace_level(level: Git2TraceLevel) -> Result<(), String> {
+    let level = match level {
+        Git2TraceLevel::None => return Ok(()),
+        Git2TraceLevel::Fatal => git2::TraceLevel::Fatal,
+        Git2TraceLevel::Error => git2::TraceLevel::Error,
+        Git2TraceLevel::Warn => git2::TraceLevel::Warn,
+        Git2TraceLevel::Info => git2::TraceLevel::Info,
+        Git2TraceLevel::Debug => git2::TraceLevel::Debug,
+        Git2TraceLevel::Trace => git2::TraceLevel::Trace,
+    };
+    // libgit2 初始化时会清空 trace 设置，先初始化
+    libgit2_sys::init();
+    git2::trace_set(level, forward_git2_trace).map_err(|e| e.to_string())
+}
+
+// libgit2 的全局选项，对之后所有的 clone/fetch 生效；HTTP 请求里会以 "git/2.0 (<user_agent>)" 的形式发送
+pub fn set_user_agent(user_agent: &str) -> Result<(), String> {
+    let user_agent = CString::new(user_agent).map_err(|e| e.to_string())?;
+    libgit2_sys::init();
+    // 只在启动时、还没有任何 git 操作之前调用，不会和其他线程竞争
+    let ret = unsafe {
+        libgit2_sys::git_libgit2_opts(
+            libgit2_sys::GIT_OPT_SET_USER_AGENT as c_int,
+            user_agent.as_ptr(),
+        )
+    };
+    if ret < 0 {
+        return Err(git2::Error::last_error(ret).to_string());
+    }
+    Ok(())
+}
+
+// 启动时、第一次 git 操作之前调用；libgit2 在系统 CA 之外额外加载这个文件里的证书
+pub fn set_ssl_ca_cert(path: &str) -> Result<(), String> {
+    libgit2_sys::init();
+    unsafe { git2::opts::set_ssl_cert_file(path) }.map_err(|e| e.to_string())
+}
+
+// libgit2 不支持 sparse-checkout，每次检出时用 pathspec 限制写入的路径
+struct SparseCheckout {
+    patterns: Vec<String>,
+    cone_mode: bool,
+}
+
+static SPARSE_CHECKOUT: OnceLock<SparseCheckout> = OnceLock::new();
+
+// 启动时、第一次检出之前调用
+pub fn set_sparse_checkout(repo: &CratesIoIndexRepo) {
+    if repo.sparse_checkout {
+        let _ = SPARSE_CHECKOUT.set(SparseCheckout {
+            patterns: repo.sparse_checkout_patterns.clone(),
+            cone_mode: repo.cone_mode,
+        });
+    }
+}
+
+// cone 模式和 git 一样，只包含列出的目录，以及根目录下的文件
+pub fn checkout_builder(tree: &Tree) -> CheckoutBuilder<'static> {
+    let mut builder = CheckoutBuilder::new();
+    let Some(sparse) = SPARSE_CHECKOUT.get() else {
+        return builder;
+    };
+    for pattern in &sparse.patterns {
+        if sparse.cone_mode {
+            builder.path(format!("{}/", pattern.trim_matches('/')));
+        } else {
+            builder.path(pattern);
+        }
+    }
+    if sparse.cone_mode {
+        for entry in tree.iter().filter(|entry| entry.kind() == Some(ObjectType::Blob)) {
+            builder.path(entry.name_bytes());
+        }
+    }
+    builder
+}
+
+// 让 git 命令行看到同样的 sparse-checkout 设置，例如在工作区里执行 git status
+fn write_sparse_checkout_file(repo: &Repository) -> Result<(), String> {
+    let Some(sparse) = SPARSE_CHECKOUT.get() else {
+        return Ok(());
+    };
+    let info_dir = repo.path().join("info");
+    std::fs::create_dir_all(&info_dir).map_err(|e| e.to_string())?;
+    let content = if sparse.cone_mode {
+        // git 的 cone 模式格式：根目录下的文件，加上每个目录及其父目录
+        let mut lines = vec!["/*".to_string(), "!/*/".to_string()];
+        for pattern in &sparse.patterns {
+            let components: Vec<&str> = pattern.trim_matches('/').split('/').collect();
+            for i in 1..components.len() {
+                let parent = components[..i].join("/");
+                lines.push(format!("/{}/", parent));
+                lines.push(format!("!/{}/*/", parent));
+            }
+            lines.push(format!("/{}/", components.join("/")));
+        }
+        lines.join("\n")
+    } else {
+        sparse.patterns.join("\n")
+    };
+    std::fs::write(info_dir.join("sparse-checkout"), content + "\n").map_err(|e| e.to_string())?;
+    let mut config = repo.config().map_err(|e| e.to_string())?;
+    config
+        .set_bool("core.sparseCheckout", true)
+        .and_then(|_| config.set_bool("core.sparseCheckoutCone", sparse.cone_mode))
+        .map_err(|e| e.to_string())
+}
+
+// repo.signing_key_paths 导入到单独的 GNUPGHOME 和 SSH allowed signers 文件，只信任这些公钥
+struct TrustedKeys {
+    gnupg_home: Option<PathBuf>,
+    allowed_signers: Option<PathBuf>,
+}
+
+static TRUSTED_KEYS: OnceLock<TrustedKeys> = OnceLock::new();
+
+fn is_ssh_public_key(content: &str) -> bool {
+    ["ssh-", "ecdsa-", "sk-"].iter().any(|prefix| content.trim_start().starts_with(prefix))
+}
+
+// 启动时调用；密钥放在 <repo.path>.trusted-keys，每次启动重新导入
+pub fn set_trusted_keys(repo: &CratesIoIndexRepo) -> Result<(), String> {
+    if repo.signing_key_paths.is_empty() {
+        return Ok(());
+    }
+    let mut dir = PathBuf::from(&repo.path).into_os_string();
+    dir.push(".trusted-keys");
+    let dir = PathBuf::from(dir);
+    if dir.exists() {
+        fs::remove_dir_all(&dir).map_err(|e| format!("Failed to remove {}: {}", dir.display(), e))?;
+    }
+    let gnupg_home = dir.join("gnupg");
+    fs::create_dir_all(&gnupg_home).map_err(|e| format!("Failed to create {}: {}", gnupg_home.display(), e))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        // gpg 拒绝使用其他用户可读的 homedir
+        let _ = fs::set_permissions(&gnupg_home, fs::Permissions::from_mode(0o700));
+    }
+
+    let mut allowed_signers = String::new();
+    for path in &repo.signing_key_paths {
+        let content = fs::read_to_string(path).map_err(|e| format!("Failed to read signing key {}: {}", path, e))?;
+        if is_ssh_public_key(&content) {
+            for key in content.lines().filter(|line| !line.trim().is_empty()) {
+                allowed_signers.push_str(&format!("* namespaces=\"git\" {}\n", key.trim()));
+            }
+            continue;
+        }
+        let output = Command::new("gpg")
+            .arg("--homedir")
+            .arg(&gnupg_home)
+            .args(["--batch", "--quiet", "--import", path])
+            .output()
+            .map_err(|e| format!("Failed to run gpg: {}", e))?;
+        if !output.status.success() {
+            return Err(format!(
+                "Failed to import signing key {}: {}",
+                path,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+    }
+    let allowed_signers_path = (!allowed_signers.is_empty()).then(|| dir.join("allowed_signers"));
+    if let Some(path) = &allowed_signers_path {
+        fs::write(path, allowed_signers).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    }
+    info!("Trusting {} signing key file(s) for commit verification", repo.signing_key_paths.len());
+    let _ = TRUSTED_KEYS.set(TrustedKeys {
+        // 没有 GPG 公钥时也使用空的 keyring，不回退到用户自己的 keyring
+        gnupg_home: Some(gnupg_home),
+        allowed_signers: allowed_signers_path,
+    });
+    Ok(())
+}
+
+// 认证方式，来自 [repo] 配置
+#[derive(Debug, Clone)]
+pub struct GitAuth {
+    pub use_credential_helper: bool,
+    pub ssh_use_agent: bool,
+    pub ssl_verify: bool,
+}
+
+impl GitAuth {
+    pub fn from_config(repo: &CratesIoIndexRepo) -> Self {
+        if repo.ssh_use_agent && std::env::var_os("SSH_AUTH_SOCK").is_none() {
+            warn!("repo.ssh_use_agent is set but SSH_AUTH_SOCK is not, falling back to key files");
+        }
+        GitAuth {
+            use_credential_helper: repo.use_credential_helper,
+            ssh_use_agent: repo.ssh_use_agent,
+            ssl_verify: repo.ssl_verify,
+        }
+    }
+}
+
+// 单次 clone/fetch 过程中的认证状态
+#[derive(Default)]
+struct AuthState {
+    // credential helper 给出的凭据，操作结束后根据结果 approve 或 reject
+    helper_credential: RefCell<Option<credential::Credential>>,
+    // 最近一次尝试的 SSH 认证方式
+    ssh_method: Cell<Option<&'static str>>,
+}
+
+fn ssh_key_file(username: &str) -> Result<git2::Cred, git2::Error> {
+    let home_dir = dirs::home_dir().expect("Failed to get home directory");
+    let private_key = home_dir.join(".ssh").join("id_rsa");
+    let public_key = home_dir.join(".ssh").join("id_rsa.pub");
+    git2::Cred::ssh_key(username, Some(&public_key), &private_key, None)
+}
+
+// 先尝试 agent，agent 不可用或被服务端拒绝（libgit2 再次调用回调）时改用 key 文件
+fn ssh_credential(
+    url: &str,
+    username: &str,
+    auth: &GitAuth,
+    state: &AuthState,
+    from_agent: impl FnOnce(&str) -> Result<git2::Cred, git2::Error>,
+    from_key_file: impl FnOnce(&str) -> Result<git2::Cred, git2::Error>,
+) -> Result<git2::Cred, git2::Error> {
+    if auth.ssh_use_agent && state.ssh_method.get().is_none() {
+        match from_agent(username) {
+            Ok(cred) => {
+                state.ssh_method.set(Some("ssh agent"));
+                return Ok(cred);
+            }
+            Err(e) => warn!("[{}] SSH agent unavailable, falling back to key files: {}", redact_url(url), e),
+        }
+    } else if state.ssh_method.get() == Some("ssh agent") {
+        warn!("[{}] SSH agent authentication failed, falling back to key files", redact_url(url));
+    } else if state.ssh_method.get().is_some() {
+        return Err(git2::Error::from_str("SSH key file authentication failed"));
+    }
+    state.ssh_method.set(Some("ssh key file"));
+    from_key_file(username)
+}
+
+fn remote_callbacks<'a>(auth: &'a GitAuth, state: &'a AuthState) -> RemoteCallbacks<'a> {
+    let mut callbacks = RemoteCallbacks::new();
+    callbacks.credentials(move |url, username_from_url, allowed_types| {
+        let helper_credential = &state.helper_credential;
+        if auth.use_credential_helper && allowed_types.contains(git2::CredentialType::USER_PASS_PLAINTEXT) {
+            // libgit2 在认证失败后会再次调用回调，此时说明上一次的凭据无效
+            if let Some(previous) = helper_credential.borrow_mut().take() {
+                warn!("[{}] Credentials from git credential helper were rejected", redact_url(url));
+                if let Err(e) = credential::reject(&previous) {
+                    warn!("{}", e);
+                }
+                return Err(git2::Error::from_str("credential helper credentials were rejected"));
+            }
+            let credential = credential::fill(url, username_from_url).map_err(|e| git2::Error::from_str(&e))?;
+            let cred = git2::Cred::userpass_plaintext(&credential.username, &credential.password);
+            *helper_credential.borrow_mut() = Some(credential);
+            return cred;
+        }
+        if allowed_types.contains(git2::CredentialType::SSH_KEY) {
+            let username = username_from_url.unwrap_or("git");
+            ssh_credential(url, username, auth, state, git2::Cred::ssh_key_from_agent, ssh_key_file)
+        } else {
+            git2::Cred::default()
+        }
+    });
+    if !auth.ssl_verify {
+        // 只放过 TLS 证书，SSH 的 host key 仍然按 known_hosts 检查
+        callbacks.certificate_check(|cert, _| {
+            Ok(if cert.as_x509().is_some() {
+                CertificateCheckStatus::CertificateOk
+            } else {
+                CertificateCheckStatus::CertificatePassthrough
+            })
+        });
+    }
+    callbacks
+}
+
+fn finish_auth<T>(url: &str, result: &Result<T, git2::Error>, state: &AuthState) {
+    if let (Ok(_), Some(method)) = (result, state.ssh_method.get()) {
+        info!("[{}] Authenticated with {}", redact_url(url), method);
+    }
+    let Some(credential) = state.helper_credential.borrow_mut().take() else {
+        return;
+    };
+    let outcome = match result {
+        Ok(_) => credential::approve(&credential),
+        Err(e) if e.code() == git2::ErrorCode::Auth => credential::reject(&credential),
+        Err(_) => Ok(()),
+    };
+    if let Err(e) = outcome {
+        warn!("{}", e);
+    }
+}
+
+pub fn clone_repo(url: &str, path: &Path, auth: &GitAuth) -> Result<Repository, git2::Error> {
+    let auth_state = AuthState::default();
+    let mut fetch_options = FetchOptions::new();
+    fetch_options.remote_callbacks(remote_callbacks(auth, &auth_state));
+
+    let mut builder = git2::build::RepoBuilder::new();
+    builder.fetch_options(fetch_options);
+    // clone 时还不知道根目录有哪些文件，先不检出，之后按 sparse-checkout 设置检出
+    if SPARSE_CHECKOUT.get().is_some() {
+        let mut checkout = CheckoutBuilder::new();
+        checkout.dry_run();
+        builder.with_checkout(checkout);
+    }
+
+    let result = builder.clone(url, path);
+    finish_auth(url, &result, &auth_state);
+    let repo = result?;
+    if SPARSE_CHECKOUT.get().is_some() {
+        if let Err(e) = write_sparse_checkout_file(&repo) {
+            warn!("Failed to write .git/info/sparse-checkout: {}", e);
+        }
+        let tree = repo.head()?.peel_to_tree()?;
+        repo.checkout_tree(tree.as_object(), Some(checkout_builder(&tree).force()))?;
+    }
+    Ok(repo)
+}
+
+// 只连接上游并列出引用，不下载对象；返回引用的数量
+pub fn check_connectivity(url: &str, auth: &GitAuth) -> Result<usize, git2::Error> {
+    let auth_state = AuthState::default();
+    let mut remote = git2::Remote::create_detached(url)?;
+    let result = remote
+        .connect_auth(Direction::Fetch, Some(remote_callbacks(auth, &auth_state)), None)
+        .and_then(|connection| connection.list().map(|refs| refs.len()));
+    finish_auth(url, &result, &auth_state);
+    result
+}
+
+pub fn pull_repo(
+    repo: &Repository,
+    url: &str,
+    auth: &GitAuth,
+    require_signed: bool,
+) -> Result<PullOutcome, git2::Error> {
+    let auth_state = AuthState::default();
+    let mut fetch_options = FetchOptions::new();
+    fetch_options.remote_callbacks(remote_callbacks(auth, &auth_state));
+
+    let mut remote = match repo.find_remote("origin") {
+        Ok(remote) => remote,
+        Err(_) => repo.remote("origin", url)?,
+    };
+
+    let result = remote.fetch(
+        // 拉取到远程跟踪分支，直接更新 refs/heads/* 会让 master 先于工作区移动，导致永远判断为 up-to-date
+        &["+refs/heads/*:refs/remotes/origin/*"],
+        Some(&mut fetch_options),
+        None,
+    );
+    finish_auth(url, &result, &auth_state);
+    result?;
+
+    let fetch_head = repo.find_reference("FETCH_HEAD")?;
+    let fetch_commit = repo.reference_to_annotated_commit(&fetch_head)?;
+    let analysis = repo.merge_analysis(&[&fetch_commit])?;
+
+    if analysis.0.is_up_to_date() {
+        info!("[{}] Already up-to-date", redact_url(url));
+        Ok(PullOutcome::UpToDate)
+    } else if analysis.0.is_fast_forward() {
+        if require_signed {
+            let head = repo.refname_to_id("refs/heads/master")?;
+            if let Err(e) = verify_commit_range(repo, head, fetch_commit.id()) {
+                error!("[{}] Refusing to fast-forward to {}: {}", redact_url(url), fetch_commit.id(), e);
+                return Ok(PullOutcome::Unverified {
+                    upstream: fetch_commit.id(),
+                });
+            }
+        }
+        info!("[{}] Performing fast-forward merge", redact_url(url));
+        let old = move_master(repo, fetch_commit.id(), "Fast-forward")?;
+        Ok(PullOutcome::FastForward {
+            old,
+            new: fetch_commit.id(),
+        })
+    } else {
+        warn!("[{}] Local history has diverged from upstream {}", redact_url(url), fetch_commit.id());
+        Ok(PullOutcome::NonFastForward {
+            upstream: fetch_commit.id(),
+        })
+    }
+}
+
+// 先检出旧的提交（删除写了一半的新文件，腾出磁盘空间），再把 master 指回去
+fn revert_checkout(repo: &Repository, old: Oid) -> Result<(), git2::Error> {
+    let tree = repo.find_commit(old)?.tree()?;
+    repo.checkout_tree(
+        tree.as_object(),
+        Some(checkout_builder(&tree).force().remove_untracked(true)),
+    )?;
+    repo.find_reference("refs/heads/master")?
+        .set_target(old, "Revert failed checkout")?;
+    Ok(())
+}
+
+// 写满之后文件系统剩下的空间不会超过几个块
+const DISK_FULL_THRESHOLD: u64 = 1024 * 1024;
+
+// libgit2 没有单独的错误码，ENOSPC 只以 strerror 文本出现在 OS 错误的消息里，这段文本随 locale 变化；
+// 按当前 locale 的 strerror(ENOSPC) 匹配，匹配不上时再看 path 所在的文件系统是否已经没有空间或 inode
+pub fn is_disk_full(error: &git2::Error, path: &Path) -> bool {
+    error.class() == git2::ErrorClass::Os && (error.message().contains(&enospc_message()) || filesystem_full(path))
+}
+
+#[cfg(unix)]
+fn enospc_message() -> String {
+    // libgit2 在同一个进程里调用 strerror，locale 相同
+    unsafe { CStr::from_ptr(libc::strerror(libc::ENOSPC)) }.to_string_lossy().into_owned()
+}
+
+#[cfg(not(unix))]
+fn enospc_message() -> String {
+    "No space left on device".to_string()
+}
+
+#[cfg(unix)]
+fn filesystem_full(path: &Path) -> bool {
+    use std::os::unix::ffi::OsStrExt;
+    let Ok(path) = CString::new(path.as_os_str().as_bytes()) else {
+        return false;
+    };
+    let mut stat = std::mem::MaybeUninit::<libc::statvfs>::uninit();
+    if unsafe { libc::statvfs(path.as_ptr(), stat.as_mut_ptr()) } != 0 {
+        return false;
+    }
+    let stat = unsafe { stat.assume_init() };
+    #[allow(clippy::unnecessary_cast)]
+    let available = stat.f_bavail as u64 * stat.f_frsize as u64;
+    available < DISK_FULL_THRESHOLD || (stat.f_files > 0 && stat.f_favail == 0)
+}
+
+#[cfg(not(unix))]
+fn filesystem_full(_path: &Path) -> bool {
+    false
+}
+
+// 检出中途失败后，丢弃半写入的文件并恢复到 HEAD
+pub fn cleanup_partial_checkout(repo: &Repository) -> Result<(), git2::Error> {
+    let tree = repo.head()?.peel_to_tree()?;
+    repo.checkout_head(Some(checkout_builder(&tree).force().remove_untracked(true)))
+}
+
+// 只检查 gpgsig 头是否存在不够，签名需要能被本机 git 配置的密钥验证
+pub fn verify_commit_signature(repo: &Repository, oid: Oid) -> Result<(), String> {
+    let commit = repo.find_commit(oid).map_err(|e| e.to_string())?;
+    let signed = ["gpgsig", "gpgsig-sha256"]
+        .iter()
+        .any(|field| commit.header_field_bytes(field).is_ok());
+    if !signed {
+        return Err(format!("Commit {} is not signed", oid));
+    }
+    let mut command = Command::new("git");
+    if let Some(keys) = TRUSTED_KEYS.get() {
+        if let Some(gnupg_home) = &keys.gnupg_home {
+            command.env("GNUPGHOME", gnupg_home);
+        }
+        let allowed_signers = keys.allowed_signers.as_deref().unwrap_or(Path::new("/dev/null"));
+        command
+            .arg("-c")
+            .arg(format!("gpg.ssh.allowedSignersFile={}", allowed_signers.display()));
+    }
+    let output = command
+        .arg("--git-dir")
+        .arg(repo.path())
+        .args(["verify-commit", &oid.to_string()])
+        .output()
+        .map_err(|e| format!("Failed to run git verify-commit: {}", e))?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(format!(
+            "Signature of commit {} could not be verified: {}",
+            oid,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))
+    }
+}
+
+// old（不含）到 new 之间的每个提交都必须通过验证
+pub fn verify_commit_range(repo: &Repository, old: Oid, new: Oid) -> Result<(), String> {
+    let mut revwalk = repo.revwalk().map_err(|e| e.to_string())?;
+    revwalk.push(new).map_err(|e| e.to_string())?;
+    revwalk.hide(old).map_err(|e| e.to_string())?;
+    for oid in revwalk {
+        verify_commit_signature(repo, oid.map_err(|e| e.to_string())?)?;
+    }
+    Ok(())
+}
+
+// 把 master 指向 target 并强制检出，返回原来的 master
+pub fn move_master(repo: &Repository, target: Oid, log_message: &str) -> Result<Oid, git2::Error> {
+    let mut reference = repo.find_reference("refs/heads/master")?;
+    let old = reference
+        .target()
+        .ok_or_else(|| git2::Error::from_str("master is not a direct reference"))?;
+    reference.set_target(target, log_message)?;
+    repo.set_head("refs/heads/master")?;
+    let tree = repo.find_commit(target)?.tree()?;
+    if let Err(e) = repo.checkout_head(Some(checkout_builder(&tree).force())) {
+        // 检出失败时恢复到原来的提交，下次 pull 会重新尝试
+        if let Err(revert_error) = revert_checkout(repo, old) {
+            warn!("Failed to revert master to {}: {}", old, revert_error);
+        }
+        return Err(e);
+    }
+    Ok(old)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{self, index_line, Upstream};
+
+    fn auth() -> GitAuth {
+        GitAuth {
+            use_credential_helper: false,
+            ssh_use_agent: false,
+            ssl_verify: true,
+        }
+    }
+
+    #[test]
+    fn disk_full_needs_an_os_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let os_error = |message: &str| git2::Error::new(git2::ErrorCode::GenericError, git2::ErrorClass::Os, message);
+        let enospc = format!("failed to write file: {}", enospc_message());
+        assert!(is_disk_full(&os_error(&enospc), dir.path()));
+        assert!(!is_disk_full(
+            &git2::Error::new(git2::ErrorCode::GenericError, git2::ErrorClass::Net, enospc.as_str()),
+            dir.path()
+        ));
+        // 其他语言的 strerror 文本只有在文件系统确实满了的时候才算
+        assert!(!is_disk_full(
+            &os_error("failed to write file: Auf dem Gerät ist kein Speicherplatz mehr verfügbar"),
+            dir.path()
+        ));
+    }
+
+    #[test]
+    fn detects_non_fast_forward() {
+        let upstream = Upstream::new();
+        let old = upstream.commit("add a", &[("1/a", Some(&index_line("a", "0.1.0")))]);
+        let dir = tempfile::tempdir().unwrap();
+        let repo = test_support::mirror(&upstream, dir.path());
+        assert_eq!(pull_repo(&repo, &upstream.url(), &auth(), false).unwrap(), PullOutcome::UpToDate);
+        let new = upstream.rewrite("squash", &[("1/a", Some(&index_line("a", "0.2.0")))]);
+        assert_eq!(
+            pull_repo(&repo, &upstream.url(), &auth(), false).unwrap(),
+            PullOutcome::NonFastForward { upstream: new }
+        );
+        assert_eq!(repo.refname_to_id("refs/heads/master").unwrap(), old);
+        assert_eq!(move_master(&repo, new, "Hard reset to upstream").unwrap(), old);
+        assert_eq!(
+            fs::read_to_string(dir.path().join("index/1/a")).unwrap(),
+            index_line("a", "0.2.0")
+        );
+    }
+
+    // TRUSTED_KEYS 在进程里只能设置一次，所有签名相关的情况放在同一个测试里
+    #[test]
+    fn require_signed_commits() {
+        let keys = tempfile::tempdir().unwrap();
+        let trusted = test_support::ssh_key(keys.path(), "trusted");
+        let untrusted = test_support::ssh_key(keys.path(), "untrusted");
+        let upstream = Upstream::new();
+        let first = test_support::signed_commit(&upstream, &trusted, "add a", "1/a", &index_line("a", "0.1.0"));
+        let dir = tempfile::tempdir().unwrap();
+        let repo = test_support::mirror(&upstream, dir.path());
+        let config = test_support::config(
+            &upstream.url(),
+            &dir.path().join("index"),
+            &format!("signing_key_paths = [{:?}]", trusted.with_extension("pub").to_str().unwrap()),
+            "",
+        );
+        set_trusted_keys(&config.repo).unwrap();
+        assert!(verify_commit_signature(&repo, first).is_ok());
+
+        // 没有签名
+        let unsigned = upstream.commit("add b", &[("1/b", Some(&index_line("b", "0.1.0")))]);
+        assert!(verify_commit_signature(&repo, unsigned).is_err());
+        assert_eq!(
+            pull_repo(&repo, &upstream.url(), &auth(), true).unwrap(),
+            PullOutcome::Unverified { upstream: unsigned }
+        );
+        assert_eq!(repo.refname_to_id("refs/heads/master").unwrap(), first);
+
+        // 最新的提交签名有效，但中间的提交没有签名，整个范围都要验证
+        let signed = test_support::signed_commit(&upstream, &trusted, "add c", "1/c", &index_line("c", "0.1.0"));
+        assert_eq!(
+            pull_repo(&repo, &upstream.url(), &auth(), true).unwrap(),
+            PullOutcome::Unverified { upstream: signed }
+        );
+        assert!(verify_commit_range(&repo, unsigned, signed).is_ok());
+        assert!(verify_commit_range(&repo, first, signed).is_err());
+
+        // 不受信任的密钥
+        let upstream = Upstream::new();
+        test_support::signed_commit(&upstream, &trusted, "add a", "1/a", &index_line("a", "0.1.0"));
+        let dir = tempfile::tempdir().unwrap();
+        let repo = test_support::mirror(&upstream, dir.path());
+        let forged = test_support::signed_commit(&upstream, &untrusted, "add b", "1/b", &index_line("b", "0.1.0"));
+        assert_eq!(
+            pull_repo(&repo, &upstream.url(), &auth(), true).unwrap(),
+            PullOutcome::Unverified { upstream: forged }
+        );
+
+        // 全部由受信任的密钥签名
+        let upstream = Upstream::new();
+        let first = test_support::signed_commit(&upstream, &trusted, "add a", "1/a", &index_line("a", "0.1.0"));
+        let dir = tempfile::tempdir().unwrap();
+        let repo = test_support::mirror(&upstream, dir.path());
+        let second = test_support::signed_commit(&upstream, &trusted, "add b", "1/b", &index_line("b", "0.1.0"));
+        assert_eq!(
+            pull_repo(&repo, &upstream.url(), &auth(), true).unwrap(),
+            PullOutcome::FastForward { old: first, new: second }
+        );
+        assert!(dir.path().join("index/1/b").is_file());
+    }
+
+    // 用凭据类型区分来源：agent 返回 USERNAME，key 文件返回 USER_PASS_PLAINTEXT
+    fn mock_agent(_: &str) -> Result<git2::Cred, git2::Error> {
+        git2::Cred::username("agent")
+    }
+
+    fn missing_agent(_: &str) -> Result<git2::Cred, git2::Error> {
+        Err(git2::Error::from_str("Failed connecting with agent"))
+    }
+
+    fn key_file(username: &str) -> Result<git2::Cred, git2::Error> {
+        git2::Cred::userpass_plaintext(username, "key")
+    }
+
+    fn source(cred: Result<git2::Cred, git2::Error>) -> Result<&'static str, String> {
+        match cred.map_err(|e| e.message().to_string())?.credtype() {
+            libgit2_sys::GIT_CREDTYPE_USERNAME => Ok("agent"),
+            _ => Ok("key file"),
+        }
+    }
+
+    #[test]
+    fn ssh_agent_falls_back_to_key_file() {
+        let auth = GitAuth { ssh_use_agent: true, ..auth() };
+        let state = AuthState::default();
+        let (logs, _guard) = test_support::capture_logs();
+        let url = "ssh://git@example.com/index";
+        assert_eq!(source(ssh_credential(url, "git", &auth, &state, mock_agent, key_file)), Ok("agent"));
+        // 服务端拒绝了 agent 的 key，libgit2 再次调用回调
+        assert_eq!(source(ssh_credential(url, "git", &auth, &state, mock_agent, key_file)), Ok("key file"));
+        assert!(logs.contents().contains("SSH agent authentication failed, falling back to key files"));
+        finish_auth(url, &Ok(()), &state);
+        assert!(logs.contents().contains("Authenticated with ssh key file"));
+        // key 文件也被拒绝后不再重试
+        assert_eq!(
+            source(ssh_credential(url, "git", &auth, &state, mock_agent, key_file)),
+            Err("SSH key file authentication failed".to_string())
+        );
+    }
+
+    #[test]
+    fn ssh_agent_success_is_logged() {
+        let auth = GitAuth { ssh_use_agent: true, ..auth() };
+        let state = AuthState::default();
+        let (logs, _guard) = test_support::capture_logs();
+        let url = "ssh://git@example.com/index";
+        assert_eq!(source(ssh_credential(url, "git", &auth, &state, mock_agent, key_file)), Ok("agent"));
+        finish_auth(url, &Ok(()), &state);
+        assert!(logs.contents().contains("Authenticated with ssh agent"));
+    }
+
+    #[test]
+    fn unavailable_ssh_agent_uses_key_file() {
+        let auth = GitAuth { ssh_use_agent: true, ..auth() };
+        let state = AuthState::default();
+        let (logs, _guard) = test_support::capture_logs();
+        let url = "ssh://git@example.com/index";
+        assert_eq!(source(ssh_credential(url, "git", &auth, &state, missing_agent, key_file)), Ok("key file"));
+        assert!(logs.contents().contains("SSH agent unavailable, falling back to key files: Failed connecting with agent"));
+    }
+
+    #[test]
+    fn key_file_only_without_ssh_agent() {
+        let state = AuthState::default();
+        let url = "ssh://git@example.com/index";
+        let agent = |_: &str| -> Result<git2::Cred, git2::Error> { panic!("agent must not be used") };
+        assert_eq!(source(ssh_credential(url, "git", &auth(), &state, agent, key_file)), Ok("key file"));
+        assert!(ssh_credential(url, "git", &auth(), &state, agent, key_file).is_err());
+    }
+}
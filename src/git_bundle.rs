@@ -0,0 +1,124 @@
+use actix_web::{
+    http::header::{CacheControl, CacheDirective},
+    web, HttpResponse,
+};
+use chrono::{Local, TimeZone};
+use serde::Serialize;
+use std::{path::PathBuf, process::Stdio, sync::Arc};
+use tokio::{process::Command, sync::Semaphore};
+use tokio_util::io::ReaderStream;
+use tracing::warn;
+use utoipa::ToSchema;
+
+use crate::{
+    panic_recovery,
+    problem::{self, ProblemDetails, ProblemType},
+};
+
+pub const CONTENT_TYPE_BUNDLE: &str = "application/x-git-bundle";
+
+// web.git_bundle_enabled 开启时提供整个仓库的 git bundle，新的镜像下载后 git clone <bundle> 即可
+// 生成 bundle 要打包所有对象，同一时刻只生成一个，其他请求排队
+pub struct GitBundle {
+    pub enabled: bool,
+    pub repo_path: PathBuf,
+    pub permits: Arc<Semaphore>,
+}
+
+impl GitBundle {
+    pub fn new(enabled: bool, repo_path: impl Into<PathBuf>) -> Self {
+        GitBundle {
+            enabled,
+            repo_path: repo_path.into(),
+            permits: Arc::new(Semaphore::new(1)),
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/git-bundle",
+    tag = "git",
+    responses(
+        (status = 200, description = "git bundle of HEAD and refs/heads/master", content_type = "application/x-git-bundle"),
+        (status = 404, description = "web.git_bundle_enabled is false", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
+pub async fn git_bundle(bundle: web::Data<GitBundle>) -> actix_web::Result<HttpResponse> {
+    if !bundle.enabled {
+        return Ok(problem::not_found("git bundles are not enabled").into());
+    }
+    let permit = Arc::clone(&bundle.permits)
+        .acquire_owned()
+        .await
+        .expect("bundle semaphore is never closed");
+    // 输出直接流式返回，不在内存里缓存整个 bundle
+    let mut child = Command::new("git")
+        .current_dir(&bundle.repo_path)
+        .args(["bundle", "create", "-q", "-", "HEAD", "refs/heads/master"])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()?;
+    let stdout = child.stdout.take().expect("stdout is piped");
+    tokio::spawn(async move {
+        // 进程结束后才允许下一个请求生成
+        let _permit = permit;
+        match child.wait().await {
+            Ok(status) if !status.success() => warn!("git bundle create exited with {}", status),
+            Ok(_) => {}
+            Err(e) => warn!("Failed to wait for git bundle create: {}", e),
+        }
+    });
+    Ok(HttpResponse::Ok()
+        .content_type(CONTENT_TYPE_BUNDLE)
+        .insert_header(CacheControl(vec![CacheDirective::NoCache]))
+        .streaming(ReaderStream::new(stdout)))
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct BundleTimestamp {
+    head: String,
+    // HEAD 提交的 committer 时间
+    timestamp: String,
+}
+
+// 客户端比较本地 bundle 的 HEAD 和时间，决定是否需要重新下载
+#[utoipa::path(
+    get,
+    path = "/api/v1/git-bundle/timestamp",
+    tag = "git",
+    responses(
+        (status = 200, description = "HEAD commit the next bundle will contain", body = BundleTimestamp),
+        (status = 404, description = "web.git_bundle_enabled is false", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
+pub async fn git_bundle_timestamp(bundle: web::Data<GitBundle>) -> actix_web::Result<HttpResponse> {
+    if !bundle.enabled {
+        return Ok(problem::not_found("git bundles are not enabled").into());
+    }
+    let repo_path = bundle.repo_path.clone();
+    let head = panic_recovery::block(move || -> Result<_, git2::Error> {
+        let repo = git2::Repository::open(&repo_path)?;
+        let commit = repo.head()?.peel_to_commit()?;
+        Ok((commit.id(), commit.time().seconds()))
+    })
+    .await?;
+    let (head, seconds) = match head {
+        Ok(head) => head,
+        Err(e) => {
+            return Ok(ProblemDetails::new(ProblemType::Internal)
+                .with_detail(format!("Failed to read HEAD: {}", e))
+                .into())
+        }
+    };
+    Ok(HttpResponse::Ok().json(BundleTimestamp {
+        head: head.to_string(),
+        timestamp: Local
+            .timestamp_opt(seconds, 0)
+            .single()
+            .map(|time| time.to_rfc3339())
+            .unwrap_or_default(),
+    }))
+}
@@ -0,0 +1,154 @@
+use actix_web::{
+    http::header::{CacheControl, CacheDirective, RETRY_AFTER},
+    web, HttpRequest, HttpResponse, ResponseError,
+};
+use bytes::Bytes;
+use futures_util::{stream, StreamExt};
+use serde::Deserialize;
+use std::{path::PathBuf, process::Stdio, sync::Arc};
+use tokio::{
+    io::AsyncWriteExt,
+    process::Command,
+    sync::{OwnedSemaphorePermit, Semaphore},
+};
+
+use crate::problem::{self, ProblemDetails, ProblemType};
+use tokio_util::io::ReaderStream;
+use tracing::warn;
+
+// negotiation 请求里 have 行的数量和本地已有的提交数成正比
+pub const MAX_UPLOAD_PACK_REQUEST_SIZE: usize = 16 * 1024 * 1024;
+const RETRY_AFTER_SECS: u64 = 10;
+
+// web.git_smart_http 开启时通过 git upload-pack 提供 smart HTTP，可以直接 git clone http://<host>/
+// 每个 upload-pack 都要压缩对象，同时运行的进程数由 web.git_smart_http_max_concurrent 限制
+pub struct GitHttp {
+    pub enabled: bool,
+    pub repo_path: PathBuf,
+    pub permits: Arc<Semaphore>,
+}
+
+impl GitHttp {
+    pub fn new(enabled: bool, repo_path: impl Into<PathBuf>, max_concurrent: usize) -> Self {
+        GitHttp {
+            enabled,
+            repo_path: repo_path.into(),
+            permits: Arc::new(Semaphore::new(max_concurrent)),
+        }
+    }
+}
+
+// 不排队，直接让客户端稍后重试
+fn busy() -> HttpResponse {
+    let mut res = ProblemDetails::new(ProblemType::Overloaded)
+        .with_detail(format!("Too many concurrent git fetches, retry in {}s", RETRY_AFTER_SECS))
+        .error_response();
+    res.headers_mut().insert(RETRY_AFTER, RETRY_AFTER_SECS.into());
+    res
+}
+
+#[derive(Debug, Deserialize)]
+pub struct InfoRefsQuery {
+    service: Option<String>,
+}
+
+// 客户端通过 Git-Protocol 请求头协商协议版本（例如 version=2），原样传给 upload-pack
+fn git_protocol(req: &HttpRequest) -> Option<String> {
+    req.headers()
+        .get("Git-Protocol")
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.to_string())
+}
+
+// 输出直接流式返回，不在内存里缓存整个 pack
+fn upload_pack(
+    git: &GitHttp,
+    permit: OwnedSemaphorePermit,
+    protocol: Option<String>,
+    advertise: bool,
+    input: Option<Bytes>,
+) -> std::io::Result<ReaderStream<tokio::process::ChildStdout>> {
+    let mut command = Command::new("git");
+    command.arg("upload-pack").arg("--stateless-rpc");
+    if advertise {
+        command.arg("--advertise-refs");
+    }
+    if let Some(protocol) = protocol {
+        command.env("GIT_PROTOCOL", protocol);
+    }
+    let mut child = command
+        .arg(&git.repo_path)
+        .stdin(if input.is_some() { Stdio::piped() } else { Stdio::null() })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()?;
+    let stdout = child.stdout.take().expect("stdout is piped");
+    let mut stdin = child.stdin.take();
+    tokio::spawn(async move {
+        // 进程结束后才释放
+        let _permit = permit;
+        if let (Some(stdin), Some(input)) = (stdin.as_mut(), input) {
+            if let Err(e) = stdin.write_all(&input).await {
+                warn!("Failed to write to git upload-pack: {}", e);
+            }
+        }
+        // 关闭 stdin，upload-pack 读到 EOF 后才会结束
+        drop(stdin);
+        match child.wait().await {
+            Ok(status) if !status.success() => warn!("git upload-pack exited with {}", status),
+            Ok(_) => {}
+            Err(e) => warn!("Failed to wait for git upload-pack: {}", e),
+        }
+    });
+    Ok(ReaderStream::new(stdout))
+}
+
+// GET /info/refs?service=git-upload-pack；不支持 dumb HTTP 和 git-receive-pack
+pub async fn info_refs(
+    req: HttpRequest,
+    git: web::Data<GitHttp>,
+    query: web::Query<InfoRefsQuery>,
+) -> actix_web::Result<HttpResponse> {
+    if !git.enabled {
+        return Ok(problem::not_found("git smart HTTP is not enabled").into());
+    }
+    if query.service.as_deref() != Some("git-upload-pack") {
+        return Ok(ProblemDetails::new(ProblemType::Forbidden)
+            .with_detail("Only git-upload-pack is supported")
+            .into());
+    }
+    let Ok(permit) = Arc::clone(&git.permits).try_acquire_owned() else {
+        return Ok(busy());
+    };
+    let protocol = git_protocol(&req);
+    // 协议 v2 的响应里没有 service 行，和 git http-backend 一致
+    let header = if protocol.as_deref().is_some_and(|p| p.contains("version=2")) {
+        Bytes::new()
+    } else {
+        Bytes::from_static(b"001e# service=git-upload-pack\n0000")
+    };
+    let output = upload_pack(&git, permit, protocol, true, None)?;
+    Ok(HttpResponse::Ok()
+        .content_type("application/x-git-upload-pack-advertisement")
+        .insert_header(CacheControl(vec![CacheDirective::NoCache]))
+        .streaming(stream::once(async move { Ok(header) }).chain(output)))
+}
+
+// POST /git-upload-pack；git 发送的 gzip 请求体由 actix-web 解压
+pub async fn upload_pack_rpc(
+    req: HttpRequest,
+    git: web::Data<GitHttp>,
+    body: web::Bytes,
+) -> actix_web::Result<HttpResponse> {
+    if !git.enabled {
+        return Ok(problem::not_found("git smart HTTP is not enabled").into());
+    }
+    let Ok(permit) = Arc::clone(&git.permits).try_acquire_owned() else {
+        return Ok(busy());
+    };
+    let output = upload_pack(&git, permit, git_protocol(&req), false, Some(body))?;
+    Ok(HttpResponse::Ok()
+        .content_type("application/x-git-upload-pack-result")
+        .insert_header(CacheControl(vec![CacheDirective::NoCache]))
+        .streaming(output))
+}
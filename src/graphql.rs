@@ -0,0 +1,273 @@
+use actix_web::{http::header, web, HttpResponse};
+use async_graphql::{
+    http::GraphiQLSource, Context, EmptyMutation, EmptySubscription, Object, Result, Schema, SimpleObject,
+};
+use async_graphql_actix_web::{GraphQLRequest, GraphQLResponse};
+use serde::Deserialize;
+use std::sync::Arc;
+
+use crate::{
+    config::{RegistryConfig, WebConfig},
+    index::IndexScanner,
+    panic_recovery, problem,
+    security_headers,
+    snapshot::ServingRoot,
+    sparse,
+};
+
+const MAX_CRATES: usize = 100;
+
+pub type IndexSchema = Schema<QueryRoot, EmptyMutation, EmptySubscription>;
+
+// 只包含索引里有的字段；描述、下载量、安全公告不在索引里
+pub fn build_schema(
+    root: Arc<ServingRoot>,
+    scanner: web::Data<IndexScanner>,
+    registry: RegistryConfig,
+    depth_limit: usize,
+) -> IndexSchema {
+    Schema::build(QueryRoot, EmptyMutation, EmptySubscription)
+        .data(root)
+        .data(scanner)
+        .data(registry)
+        .limit_depth(depth_limit)
+        .finish()
+}
+
+#[derive(Debug, Clone, Deserialize, SimpleObject)]
+pub struct DependencyNode {
+    name: String,
+    req: String,
+    #[serde(default)]
+    optional: bool,
+    // normal、dev 或 build，旧的索引条目可能没有
+    kind: Option<String>,
+    target: Option<String>,
+    // 重命名依赖时的原始 crate 名称
+    package: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize, SimpleObject)]
+pub struct VersionNode {
+    #[serde(rename = "vers")]
+    version: String,
+    #[serde(rename = "cksum")]
+    checksum: String,
+    #[serde(default)]
+    yanked: bool,
+    #[serde(rename = "deps", default)]
+    dependencies: Vec<DependencyNode>,
+    rust_version: Option<String>,
+}
+
+pub struct CrateNode {
+    name: String,
+    versions: Vec<VersionNode>,
+}
+
+#[Object]
+impl CrateNode {
+    async fn name(&self) -> &str {
+        &self.name
+    }
+
+    // 按发布顺序
+    async fn versions(&self) -> &[VersionNode] {
+        &self.versions
+    }
+
+    // 版本号最大的未 yank 版本，和 /api/v1/crates 的 max_version 一致
+    async fn latest_version(&self) -> Option<&VersionNode> {
+        self.versions
+            .iter()
+            .filter(|v| !v.yanked)
+            .filter_map(|v| semver::Version::parse(&v.version).ok().map(|parsed| (parsed, v)))
+            .max_by(|a, b| a.0.cmp(&b.0))
+            .map(|(_, v)| v)
+    }
+}
+
+async fn read_crate(root: &ServingRoot, name: &str, strict: bool) -> Option<CrateNode> {
+    if !sparse::valid_crate_name(name) {
+        return None;
+    }
+    let root = root.get();
+    let index_path = {
+        let (root, name) = (root.clone(), name.to_string());
+        panic_recovery::block(move || sparse::resolve_crate_path(&root, &name, strict)).await.ok()??
+    };
+    let content = tokio::fs::read(root.join(index_path)).await.ok()?;
+    let mut crate_name = None;
+    let versions: Vec<VersionNode> = content
+        .split(|&b| b == b'\n')
+        .filter(|line| !line.trim_ascii().is_empty())
+        .filter_map(|line| {
+            #[derive(Deserialize)]
+            struct Line {
+                name: String,
+                #[serde(flatten)]
+                version: VersionNode,
+            }
+            let line: Line = serde_json::from_slice(line).ok()?;
+            crate_name.get_or_insert(line.name);
+            Some(line.version)
+        })
+        .collect();
+    Some(CrateNode {
+        name: crate_name?,
+        versions,
+    })
+}
+
+pub struct QueryRoot;
+
+#[Object]
+impl QueryRoot {
+    // 和 /api/v1/crates?q= 一样按名称子串匹配，最多返回 100 个
+    async fn crates(&self, ctx: &Context<'_>, query: String, #[graphql(default = 10)] limit: usize) -> Result<Vec<CrateNode>> {
+        let root = ctx.data::<Arc<ServingRoot>>()?;
+        let strict = ctx.data::<RegistryConfig>()?.strict_name_matching;
+        let scanner = ctx.data::<web::Data<IndexScanner>>()?.clone().into_inner();
+        let crates = panic_recovery::block(move || scanner.scan()).await?;
+        let query = query.to_lowercase();
+        let mut nodes = Vec::new();
+        for summary in crates
+            .iter()
+            .filter(|c| c.name.to_lowercase().contains(&query))
+            .take(limit.min(MAX_CRATES))
+        {
+            if let Some(node) = read_crate(root, &summary.name, strict).await {
+                nodes.push(node);
+            }
+        }
+        Ok(nodes)
+    }
+
+    #[graphql(name = "crate")]
+    async fn krate(&self, ctx: &Context<'_>, name: String) -> Result<Option<CrateNode>> {
+        let root = ctx.data::<Arc<ServingRoot>>()?;
+        let strict = ctx.data::<RegistryConfig>()?.strict_name_matching;
+        Ok(read_crate(root, &name, strict).await)
+    }
+}
+
+// GET 和 POST /graphql
+pub async fn graphql(
+    web_config: web::Data<WebConfig>,
+    schema: web::Data<IndexSchema>,
+    request: GraphQLRequest,
+) -> Result<GraphQLResponse, actix_web::Error> {
+    if !web_config.graphql_enabled {
+        return Err(problem::not_found("GraphQL is not enabled").into());
+    }
+    Ok(schema.execute(request.into_inner()).await.into())
+}
+
+pub async fn playground(web_config: web::Data<WebConfig>) -> HttpResponse {
+    if !web_config.graphql_enabled {
+        return problem::not_found("GraphQL is not enabled").into();
+    }
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .insert_header((header::CONTENT_SECURITY_POLICY, security_headers::CDN_PAGE_CSP))
+        .body(GraphiQLSource::build().endpoint("/graphql").finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{index_line, web_config, Upstream};
+    use actix_web::{test as actix_test, App};
+    use async_graphql::{value, Request};
+
+    fn schema(upstream: &Upstream, depth_limit: usize) -> IndexSchema {
+        let serde = index_line("serde", "1.0.0")
+            + &index_line("serde", "1.0.1").replace("\"yanked\":false", "\"yanked\":true")
+            + &index_line("serde", "0.9.0");
+        let serde_json = "{\"name\":\"serde_json\",\"vers\":\"1.0.0\",\"deps\":[{\"name\":\"serde\",\"req\":\"^1.0\",\
+                          \"features\":[],\"optional\":false,\"default_features\":true,\"target\":null,\"kind\":\"normal\"}],\
+                          \"cksum\":\"abc\",\"features\":{},\"yanked\":false}\n";
+        upstream.commit(
+            "initial",
+            &[
+                ("se/rd/serde", Some(&serde)),
+                ("se/rd/serde_json", Some(serde_json)),
+                ("to/ki/tokio", Some(&index_line("tokio", "1.0.0"))),
+            ],
+        );
+        let root = Arc::new(ServingRoot::new(upstream.dir.path(), false, false));
+        root.publish(&upstream.repo).unwrap();
+        let scanner = web::Data::new(IndexScanner::new(Arc::clone(&root), 1));
+        build_schema(root, scanner, RegistryConfig::default(), depth_limit)
+    }
+
+    #[actix_web::test]
+    async fn queries_crates() {
+        let upstream = Upstream::new();
+        let schema = schema(&upstream, 5);
+        let res = schema
+            .execute(r#"{ crates(query: "serde") { name latestVersion { version dependencies { name req kind } } } }"#)
+            .await;
+        assert!(res.errors.is_empty(), "{:?}", res.errors);
+        assert_eq!(
+            res.data,
+            value!({
+                "crates": [
+                    {"name": "serde", "latestVersion": {"version": "1.0.0", "dependencies": []}},
+                    {"name": "serde_json", "latestVersion": {"version": "1.0.0", "dependencies": [
+                        {"name": "serde", "req": "^1.0", "kind": "normal"}
+                    ]}},
+                ]
+            })
+        );
+
+        let res = schema
+            .execute(Request::new(r#"query($name: String!) { crate(name: $name) { name versions { version yanked } } }"#)
+                .variables(async_graphql::Variables::from_json(serde_json::json!({"name": "Serde-JSON"}))))
+            .await;
+        assert!(res.errors.is_empty(), "{:?}", res.errors);
+        assert_eq!(res.data, value!({"crate": {"name": "serde_json", "versions": [{"version": "1.0.0", "yanked": false}]}}));
+
+        let res = schema.execute(r#"{ crate(name: "no-such-crate") { name } }"#).await;
+        assert_eq!(res.data, value!({"crate": null}));
+        let res = schema.execute(r#"{ crates(query: "serde", limit: 1) { name } }"#).await;
+        assert_eq!(res.data, value!({"crates": [{"name": "serde"}]}));
+    }
+
+    #[actix_web::test]
+    async fn rejects_deep_queries() {
+        let upstream = Upstream::new();
+        let schema = schema(&upstream, 3);
+        let ok = schema.execute(r#"{ crate(name: "serde") { versions { version } } }"#).await;
+        assert!(ok.errors.is_empty(), "{:?}", ok.errors);
+        let deep = schema.execute(r#"{ crate(name: "serde") { versions { dependencies { name } } } }"#).await;
+        assert_eq!(deep.errors.len(), 1);
+        assert!(deep.errors[0].message.contains("nested too deep"), "{}", deep.errors[0].message);
+    }
+
+    #[actix_web::test]
+    async fn endpoint_can_be_disabled() {
+        let upstream = Upstream::new();
+        let app = |extra: &str| {
+            App::new()
+                .app_data(web::Data::new(web_config(extra)))
+                .app_data(web::Data::new(schema(&upstream, 5)))
+                .route("/graphql", web::post().to(graphql))
+                .route("/graphql/playground", web::get().to(playground))
+        };
+        let query = || {
+            actix_test::TestRequest::post()
+                .uri("/graphql")
+                .set_json(serde_json::json!({"query": "{ crate(name: \"tokio\") { name } }"}))
+                .to_request()
+        };
+        let enabled = actix_test::init_service(app("graphql_enabled = true")).await;
+        let body: serde_json::Value = actix_test::call_and_read_body_json(&enabled, query()).await;
+        assert_eq!(body, serde_json::json!({"data": {"crate": {"name": "tokio"}}}));
+        let playground = actix_test::TestRequest::get().uri("/graphql/playground").to_request();
+        assert_eq!(actix_test::call_service(&enabled, playground).await.status(), 200);
+
+        let disabled = actix_test::init_service(app("graphql_enabled = false")).await;
+        assert_eq!(actix_test::call_service(&disabled, query()).await.status(), 404);
+    }
+}
@@ -0,0 +1,156 @@
+use actix_web::web;
+use std::pin::Pin;
+use tokio_stream::{wrappers::WatchStream, Stream, StreamExt};
+use tonic::{
+    transport::{server::TcpIncoming, Server},
+    Request, Response, Status,
+};
+use tonic_health::pb::{
+    health_check_response::ServingStatus,
+    health_server::{Health, HealthServer},
+    HealthCheckRequest, HealthCheckResponse,
+};
+use tracing::{error, info};
+
+use crate::{config::WebConfig, health::HealthState, listen, metrics};
+
+// gRPC Health Checking Protocol，供 Kubernetes/Envoy 等使用；只支持整体状态（service 为空）
+struct GrpcHealth {
+    health: web::Data<HealthState>,
+}
+
+fn serving_status(available: bool) -> ServingStatus {
+    if available {
+        ServingStatus::Serving
+    } else {
+        ServingStatus::NotServing
+    }
+}
+
+fn response(status: ServingStatus) -> HealthCheckResponse {
+    HealthCheckResponse {
+        status: status.into(),
+    }
+}
+
+fn check_service(request: &Request<HealthCheckRequest>) -> Result<(), Status> {
+    let service = &request.get_ref().service;
+    if service.is_empty() {
+        Ok(())
+    } else {
+        Err(Status::not_found(format!("Unknown service {:?}", service)))
+    }
+}
+
+#[tonic::async_trait]
+impl Health for GrpcHealth {
+    async fn check(
+        &self,
+        request: Request<HealthCheckRequest>,
+    ) -> Result<Response<HealthCheckResponse>, Status> {
+        check_service(&request)?;
+        let status = serving_status(self.health.is_available());
+        metrics::GRPC_HEALTH_CHECKS_TOTAL.inc(status.as_str_name());
+        Ok(Response::new(response(status)))
+    }
+
+    type WatchStream = Pin<Box<dyn Stream<Item = Result<HealthCheckResponse, Status>> + Send>>;
+
+    // 先返回当前状态，之后每次状态变化推送一次
+    // 很快恢复的不可用状态可能被合并掉，合并后和上一次相同的状态不重复发送
+    async fn watch(
+        &self,
+        request: Request<HealthCheckRequest>,
+    ) -> Result<Response<Self::WatchStream>, Status> {
+        check_service(&request)?;
+        let mut last = None;
+        let stream = WatchStream::new(self.health.subscribe()).filter_map(move |available| {
+            if last == Some(available) {
+                return None;
+            }
+            last = Some(available);
+            Some(Ok(response(serving_status(available))))
+        });
+        Ok(Response::new(Box::pin(stream)))
+    }
+}
+
+// 和 HTTP 服务监听相同的地址，端口换成 grpc_health_port
+pub fn start(config: &WebConfig, port: u16, health: web::Data<HealthState>) -> std::io::Result<()> {
+    let addrs = listen::resolve_listen_addrs(config)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+    for mut addr in addrs {
+        addr.set_port(port);
+        let listener = listen::bind_listener(addr, config)?;
+        listener.set_nonblocking(true)?;
+        let incoming = TcpIncoming::from(tokio::net::TcpListener::from_std(listener)?);
+        let service = HealthServer::new(GrpcHealth {
+            health: health.clone(),
+        });
+        info!("gRPC health service started at {}", addr);
+        tokio::spawn(async move {
+            if let Err(e) = Server::builder()
+                .add_service(service)
+                .serve_with_incoming(incoming)
+                .await
+            {
+                error!("gRPC health service on {} stopped: {}", addr, e);
+            }
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+    use std::{net::TcpListener, time::Duration};
+    use tonic::{transport::Channel, Code};
+    use tonic_health::pb::health_client::HealthClient;
+
+    async fn client(health: &web::Data<HealthState>) -> HealthClient<Channel> {
+        let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        start(&test_support::web_config(""), port, health.clone()).unwrap();
+        let channel = Channel::from_shared(format!("http://127.0.0.1:{}", port)).unwrap().connect().await.unwrap();
+        HealthClient::new(channel)
+    }
+
+    fn request(service: &str) -> HealthCheckRequest {
+        HealthCheckRequest {
+            service: service.to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn check_reports_availability() {
+        let health = web::Data::new(HealthState::new());
+        let mut client = client(&health).await;
+        let status = client.check(request("")).await.unwrap().into_inner().status();
+        assert_eq!(status, ServingStatus::Serving);
+        health.set_available(false);
+        let status = client.check(request("")).await.unwrap().into_inner().status();
+        assert_eq!(status, ServingStatus::NotServing);
+        let err = client.check(request("index")).await.unwrap_err();
+        assert_eq!(err.code(), Code::NotFound);
+    }
+
+    #[tokio::test]
+    async fn watch_streams_transitions() {
+        let health = web::Data::new(HealthState::new());
+        let mut client = client(&health).await;
+        let mut stream = client.watch(request("")).await.unwrap().into_inner();
+        let mut next = async || {
+            let message = tokio::time::timeout(Duration::from_secs(5), stream.message()).await;
+            message.expect("no status change").unwrap().unwrap().status()
+        };
+        assert_eq!(next().await, ServingStatus::Serving);
+        health.set_available(false);
+        assert_eq!(next().await, ServingStatus::NotServing);
+        health.set_available(true);
+        assert_eq!(next().await, ServingStatus::Serving);
+        health.set_available(false);
+        assert_eq!(next().await, ServingStatus::NotServing);
+        assert_eq!(client.watch(request("index")).await.unwrap_err().code(), Code::NotFound);
+    }
+}
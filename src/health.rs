@@ -0,0 +1,351 @@
+use actix_web::{
+    body::{EitherBody, MessageBody},
+    dev::{ServiceRequest, ServiceResponse},
+    middleware::Next,
+    web, Error, HttpRequest, HttpResponse, ResponseError,
+};
+use chrono::{DateTime, Local};
+use serde::Serialize;
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        RwLock,
+    },
+    time::{Duration, Instant},
+};
+use tokio::sync::watch;
+use utoipa::ToSchema;
+
+use crate::{
+    config::{AppConfig, HealthConfig},
+    idle::Activity,
+    metrics,
+    problem::{ProblemDetails, ProblemType},
+    security_headers,
+    util::html_escape,
+};
+
+pub struct HealthState {
+    created: Instant,
+    // 初始 clone 完成后为 true；仓库已存在时启动后立即为 true
+    started: AtomicBool,
+    last_update: RwLock<DateTime<Local>>,
+    // 工作区处于不一致状态（例如磁盘满导致检出失败）时为 false；gRPC Watch 订阅它的变化
+    available: watch::Sender<bool>,
+    // 最近一次上游连通性检查的结果，未检查时为空
+    connectivity: RwLock<Option<ConnectivityCheck>>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ConnectivityCheck {
+    ok: bool,
+    checked_at: String,
+    detail: String,
+}
+
+impl Default for HealthState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HealthState {
+    pub fn new() -> Self {
+        HealthState {
+            created: Instant::now(),
+            started: AtomicBool::new(false),
+            last_update: RwLock::new(Local::now()),
+            available: watch::Sender::new(true),
+            connectivity: RwLock::new(None),
+        }
+    }
+
+    pub fn set_connectivity(&self, ok: bool, detail: String) {
+        *self.connectivity.write().unwrap() = Some(ConnectivityCheck {
+            ok,
+            checked_at: Local::now().to_rfc3339(),
+            detail,
+        });
+    }
+
+    pub fn set_started(&self) {
+        self.started.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_started(&self) -> bool {
+        self.started.load(Ordering::Relaxed)
+    }
+
+    // pull 成功后调用
+    pub fn record_update(&self) {
+        *self.last_update.write().unwrap() = Local::now();
+    }
+
+    pub fn last_update(&self) -> DateTime<Local> {
+        *self.last_update.read().unwrap()
+    }
+
+    pub fn staleness_secs(&self) -> i64 {
+        (Local::now() - self.last_update()).num_seconds()
+    }
+
+    // 只在状态变化时通知订阅者
+    pub fn set_available(&self, available: bool) {
+        self.available.send_if_modified(|current| {
+            let changed = *current != available;
+            *current = available;
+            changed
+        });
+    }
+
+    pub fn is_available(&self) -> bool {
+        *self.available.borrow()
+    }
+
+    pub fn subscribe(&self) -> watch::Receiver<bool> {
+        self.available.subscribe()
+    }
+
+    fn status(&self) -> &'static str {
+        if !self.is_started() {
+            "starting"
+        } else if self.is_available() {
+            "ok"
+        } else {
+            "unavailable"
+        }
+    }
+}
+
+// 工作区不可用时索引文件返回 503，避免客户端读到半检出的内容
+pub async fn require_available(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    let health = req
+        .app_data::<web::Data<HealthState>>()
+        .cloned()
+        .expect("HealthState not registered");
+    if !health.is_available() {
+        let res = ProblemDetails::new(ProblemType::IndexUnavailable).error_response();
+        return Ok(req.into_response(res).map_into_right_body());
+    }
+    next.call(req).await.map(|res| res.map_into_left_body())
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct HealthReady {
+    name: String,
+    description: String,
+    // starting、ok 或 unavailable
+    status: &'static str,
+    last_update: String,
+    staleness_secs: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    upstream: Option<ConnectivityCheck>,
+    // repo.idle_threshold_secs 内没有客户端请求
+    is_idle: bool,
+}
+
+#[utoipa::path(
+    get,
+    path = "/health/ready",
+    tag = "health",
+    responses(
+        (status = 200, description = "Index is available", body = HealthReady),
+        (status = 503, description = "Index is cloning or unavailable", body = HealthReady),
+    )
+)]
+pub async fn health_ready(
+    app: web::Data<AppConfig>,
+    health: web::Data<HealthState>,
+    activity: web::Data<Activity>,
+) -> HttpResponse {
+    let mut res = if health.is_available() {
+        HttpResponse::Ok()
+    } else {
+        HttpResponse::ServiceUnavailable()
+    };
+    res.json(HealthReady {
+        name: app.name.clone(),
+        description: app.description.clone(),
+        status: health.status(),
+        last_update: health.last_update().to_rfc3339(),
+        staleness_secs: health.staleness_secs(),
+        upstream: health.connectivity.read().unwrap().clone(),
+        is_idle: activity.is_idle(),
+    })
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ProbeResult {
+    ok: bool,
+    detail: String,
+}
+
+fn probe(gauge: &metrics::Gauge, ok: bool, detail: String) -> HttpResponse {
+    gauge.set(ok as i64);
+    let mut res = if ok {
+        HttpResponse::Ok()
+    } else {
+        HttpResponse::ServiceUnavailable()
+    };
+    res.json(ProbeResult { ok, detail })
+}
+
+// 进程是否还能响应：阻塞线程池在 live_timeout_ms 内执行完一个空任务
+#[utoipa::path(
+    get,
+    path = "/healthz/live",
+    tag = "health",
+    responses(
+        (status = 200, description = "Liveness probe passed", body = ProbeResult),
+        (status = 503, description = "Liveness probe failed", body = ProbeResult),
+    )
+)]
+pub async fn healthz_live(config: web::Data<HealthConfig>) -> HttpResponse {
+    let timeout = Duration::from_millis(config.live_timeout_ms);
+    let ok = tokio::time::timeout(timeout, web::block(|| ()))
+        .await
+        .is_ok_and(|result| result.is_ok());
+    let detail = if ok {
+        "responsive".to_string()
+    } else {
+        format!("blocking thread pool did not respond within {}ms", config.live_timeout_ms)
+    };
+    probe(&metrics::HEALTH_LIVE, ok, detail)
+}
+
+#[utoipa::path(
+    get,
+    path = "/healthz/ready",
+    tag = "health",
+    responses(
+        (status = 200, description = "Readiness probe passed", body = ProbeResult),
+        (status = 503, description = "Readiness probe failed", body = ProbeResult),
+    )
+)]
+pub async fn healthz_ready(config: web::Data<HealthConfig>, health: web::Data<HealthState>) -> HttpResponse {
+    let staleness = health.staleness_secs();
+    let (ok, detail) = if !health.is_started() {
+        (false, "initial clone has not completed".to_string())
+    } else if !health.is_available() {
+        (false, "index is temporarily unavailable".to_string())
+    } else if config.ready_max_staleness_secs > 0 && staleness > config.ready_max_staleness_secs {
+        (false, format!("index was last updated {}s ago", staleness))
+    } else {
+        (true, "serving".to_string())
+    };
+    probe(&metrics::HEALTH_READY, ok, detail)
+}
+
+// 初始 clone 进行中时在 startup_timeout_secs 之内仍然返回成功，超时后返回失败让 Kubernetes 重启
+#[utoipa::path(
+    get,
+    path = "/healthz/startup",
+    tag = "health",
+    responses(
+        (status = 200, description = "Startup probe passed", body = ProbeResult),
+        (status = 503, description = "Startup probe failed", body = ProbeResult),
+    )
+)]
+pub async fn healthz_startup(config: web::Data<HealthConfig>, health: web::Data<HealthState>) -> HttpResponse {
+    let elapsed = health.created.elapsed().as_secs();
+    let (ok, detail) = if health.is_started() {
+        (true, "initial clone completed".to_string())
+    } else if elapsed < config.startup_timeout_secs {
+        (true, format!("initial clone in progress for {}s", elapsed))
+    } else {
+        (
+            false,
+            format!("initial clone did not complete within {}s", config.startup_timeout_secs),
+        )
+    };
+    probe(&metrics::HEALTH_STARTUP, ok, detail)
+}
+
+// 样式放在 <style> 里而不是 style 属性上，web.csp.use_nonces 时可以用 nonce 放行
+pub async fn status_page(
+    req: HttpRequest,
+    app: web::Data<AppConfig>,
+    health: web::Data<HealthState>,
+) -> HttpResponse {
+    let name = html_escape(&app.name);
+    let description = html_escape(&app.description);
+    let nonce = security_headers::csp_nonce(&req).map_or(String::new(), |nonce| format!(r#" nonce="{}""#, nonce));
+    let body = format!(
+        r#"<!DOCTYPE html>
+<html><head><meta charset="utf-8"><title>{name}</title>
+<style{nonce}>
+body {{ font-family: sans-serif; margin: 2em; }}
+p {{ color: #555; }}
+table {{ border-collapse: collapse; }}
+td:first-child {{ padding: 4px 12px 4px 0; }}
+</style></head>
+<body>
+<h1>{name}</h1>
+<p>{description}</p>
+<table>
+<tr><td>Status</td><td>{status}</td></tr>
+<tr><td>Last update</td><td>{last_update}</td></tr>
+<tr><td>Staleness</td><td>{staleness}s</td></tr>
+</table>
+</body></html>
+"#,
+        last_update = health.last_update().format("%Y-%m-%d %H:%M:%S"),
+        staleness = health.staleness_secs(),
+        status = health.status(),
+    );
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{middleware::from_fn, test as actix_test, App};
+
+    #[actix_web::test]
+    async fn unavailable_index_returns_503() {
+        let health = web::Data::new(HealthState::new());
+        let app = actix_test::init_service(
+            App::new()
+                .app_data(health.clone())
+                .wrap(from_fn(require_available))
+                .default_service(web::to(HttpResponse::Ok)),
+        )
+        .await;
+        let get = || actix_test::TestRequest::get().uri("/se/rd/serde").to_request();
+        assert_eq!(actix_test::call_service(&app, get()).await.status(), 200);
+        health.set_available(false);
+        let res = actix_test::call_service(&app, get()).await;
+        assert_eq!(res.status(), 503);
+        assert_eq!(res.headers().get("Content-Type").unwrap(), "application/problem+json");
+        health.set_available(true);
+        assert_eq!(actix_test::call_service(&app, get()).await.status(), 200);
+    }
+
+    #[actix_web::test]
+    async fn startup_probe_times_out_until_clone_completes() {
+        let health = web::Data::new(HealthState::new());
+        let config = |startup_timeout_secs| {
+            web::Data::new(HealthConfig {
+                startup_timeout_secs,
+                ..HealthConfig::default()
+            })
+        };
+        let res = healthz_startup(config(600), health.clone()).await;
+        assert_eq!(res.status(), 200);
+        let res = healthz_startup(config(0), health.clone()).await;
+        assert_eq!(res.status(), 503);
+        let body = actix_web::body::to_bytes(res.into_body()).await.unwrap();
+        assert_eq!(
+            serde_json::from_slice::<serde_json::Value>(&body).unwrap(),
+            serde_json::json!({"ok": false, "detail": "initial clone did not complete within 0s"})
+        );
+        // clone 完成后超时不再有影响
+        health.set_started();
+        assert_eq!(healthz_startup(config(0), health.clone()).await.status(), 200);
+    }
+}
@@ -0,0 +1,149 @@
+use git2::{Oid, Repository};
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    process::Command,
+    thread,
+    time::{Duration, Instant},
+};
+use tempfile::NamedTempFile;
+use tracing::{error, info, warn};
+
+use crate::{metrics, sparse};
+
+// 这次更新中有变化的 crate 名称（索引文件名就是小写的 crate 名称）
+pub fn changed_crates(repo_path: &Path, old: Oid, new: Oid) -> Result<Vec<String>, git2::Error> {
+    let repo = Repository::open(repo_path)?;
+    let old_tree = repo.find_commit(old)?.tree()?;
+    let new_tree = repo.find_commit(new)?.tree()?;
+    let diff = repo.diff_tree_to_tree(Some(&old_tree), Some(&new_tree), None)?;
+    let mut crates: Vec<String> = diff
+        .deltas()
+        .filter_map(|delta| delta.new_file().path().or(delta.old_file().path()).map(Path::to_path_buf))
+        .filter(|path| path.to_str().is_some_and(sparse::is_crate_request))
+        .filter_map(|path| path.file_name().map(|name| name.to_string_lossy().into_owned()))
+        .collect();
+    crates.sort();
+    crates.dedup();
+    Ok(crates)
+}
+
+// 通过 sh -c 执行，超时后 kill
+fn run_hook(hook: &str, env: &[(&str, String)], timeout: Duration) -> Result<(), String> {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(hook)
+        .envs(env.iter().map(|(key, value)| (key, value)))
+        .spawn()
+        .map_err(|e| format!("cannot start sh: {}", e))?;
+    let deadline = Instant::now() + timeout;
+    loop {
+        match child.try_wait().map_err(|e| e.to_string())? {
+            Some(status) if status.success() => return Ok(()),
+            Some(status) => return Err(status.to_string()),
+            None if Instant::now() >= deadline => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(format!("timed out after {:?}", timeout));
+            }
+            None => thread::sleep(Duration::from_millis(100)),
+        }
+    }
+}
+
+// 每行一个 crate 名称；大的更新里名称列表会超过环境变量的长度限制（Linux 上单个变量 128 KiB），只通过环境变量传文件路径
+fn write_changed_crates(crates: &[String]) -> std::io::Result<NamedTempFile> {
+    let mut file = tempfile::Builder::new().prefix("crates-index-changed-").tempfile()?;
+    for name in crates {
+        writeln!(file, "{}", name)?;
+    }
+    file.flush()?;
+    Ok(file)
+}
+
+fn run_hooks_blocking(hooks: &[String], repo_path: &Path, old: Oid, new: Oid, timeout: Duration) {
+    let changed = changed_crates(repo_path, old, new).unwrap_or_else(|e| {
+        warn!("Failed to list changed crates for post-pull hooks: {}", e);
+        Vec::new()
+    });
+    // 所有 hook 执行完之后才删除
+    let changed_file = match write_changed_crates(&changed) {
+        Ok(file) => file,
+        Err(e) => {
+            error!("Failed to write the changed crates for post-pull hooks: {}", e);
+            return;
+        }
+    };
+    let env = [
+        ("CRATES_INDEX_OLD_HEAD", old.to_string()),
+        ("CRATES_INDEX_NEW_HEAD", new.to_string()),
+        ("CRATES_INDEX_PATH", repo_path.display().to_string()),
+        ("CRATES_INDEX_CHANGED_CRATES_FILE", changed_file.path().display().to_string()),
+        ("CRATES_INDEX_CHANGED_CRATES_COUNT", changed.len().to_string()),
+    ];
+    // 按顺序执行，某个 hook 失败不影响后面的 hook
+    for (index, hook) in hooks.iter().enumerate() {
+        let start = Instant::now();
+        match run_hook(hook, &env, timeout) {
+            Ok(()) => info!("Post-pull hook #{} finished in {:?}", index, start.elapsed()),
+            Err(e) => {
+                error!("Post-pull hook #{} ({:?}) failed: {}", index, hook, e);
+                metrics::HOOK_FAILURES_TOTAL.inc(&index.to_string());
+            }
+        }
+    }
+}
+
+// fast-forward 成功、快照切换之后调用
+pub async fn run_post_pull_hooks(hooks: Vec<String>, repo_path: PathBuf, old: Oid, new: Oid, timeout: Duration) {
+    let result =
+        tokio::task::spawn_blocking(move || run_hooks_blocking(&hooks, &repo_path, old, new, timeout)).await;
+    if let Err(e) = result {
+        error!("Post-pull hooks task failed: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{capture_logs, index_line, Upstream};
+
+    #[test]
+    fn hooks_get_pull_environment() {
+        let upstream = Upstream::new();
+        let old = upstream.commit("initial", &[("se/rd/serde", Some(&index_line("serde", "1.0.0")))]);
+        let new = upstream.commit(
+            "publish",
+            &[
+                ("se/rd/serde", Some(&(index_line("serde", "1.0.0") + &index_line("serde", "1.0.1")))),
+                ("to/ki/tokio", Some(&index_line("tokio", "1.0.0"))),
+                ("config.json", Some("{}")),
+            ],
+        );
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("hook.out");
+        let env = "echo \"$CRATES_INDEX_OLD_HEAD $CRATES_INDEX_NEW_HEAD $CRATES_INDEX_PATH $CRATES_INDEX_CHANGED_CRATES_COUNT\"";
+        let hooks = vec![
+            // 失败的 hook 不影响后面的 hook
+            "exit 3".to_string(),
+            format!("{} > {:?} && cat \"$CRATES_INDEX_CHANGED_CRATES_FILE\" >> {:?}", env, output, output),
+            format!("echo $CRATES_INDEX_CHANGED_CRATES_FILE >> {:?}", output),
+            "sleep 10".to_string(),
+        ];
+        let (logs, _guard) = capture_logs();
+        let start = Instant::now();
+        run_hooks_blocking(&hooks, upstream.dir.path(), old, new, Duration::from_secs(1));
+        assert!(start.elapsed() < Duration::from_secs(5));
+
+        let content = std::fs::read_to_string(&output).unwrap();
+        let lines: Vec<_> = content.lines().collect();
+        assert_eq!(lines[0], format!("{} {} {} 2", old, new, upstream.dir.path().display()));
+        assert_eq!(lines[1..3], ["serde", "tokio"]);
+        // 所有 hook 执行完之后删除列表文件
+        assert!(!Path::new(lines[3]).exists());
+        let logs = logs.contents();
+        assert!(logs.contains("Post-pull hook #0 (\"exit 3\") failed: exit status: 3"), "{}", logs);
+        assert!(logs.contains("Post-pull hook #1 finished"), "{}", logs);
+        assert!(logs.contains("Post-pull hook #3 (\"sleep 10\") failed: timed out after 1s"), "{}", logs);
+    }
+}
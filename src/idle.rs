@@ -0,0 +1,70 @@
+use actix_web::{
+    body::MessageBody,
+    dev::{ServiceRequest, ServiceResponse},
+    middleware::Next,
+    web, Error,
+};
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::sync::Notify;
+
+// 探针和指标的抓取不算客户端流量，否则永远不会空闲
+const IGNORED_PREFIXES: &[&str] = &["/health", "/metrics", "/status"];
+
+fn now_secs() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs()
+}
+
+// 最近一次客户端请求的时间，定时 pull 据此判断是否空闲
+pub struct Activity {
+    last_request: Arc<AtomicU64>,
+    idle_threshold: Duration,
+    // 空闲后的第一个请求唤醒定时 pull
+    resumed: Notify,
+}
+
+impl Activity {
+    pub fn new(idle_threshold: Duration) -> Self {
+        Activity {
+            last_request: Arc::new(AtomicU64::new(now_secs())),
+            idle_threshold,
+            resumed: Notify::new(),
+        }
+    }
+
+    fn idle_since(&self, last_request: u64) -> bool {
+        now_secs().saturating_sub(last_request) >= self.idle_threshold.as_secs()
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.idle_since(self.last_request.load(Ordering::Relaxed))
+    }
+
+    fn record_request(&self) {
+        let previous = self.last_request.swap(now_secs(), Ordering::Relaxed);
+        if self.idle_since(previous) {
+            self.resumed.notify_one();
+        }
+    }
+
+    pub async fn resumed(&self) {
+        self.resumed.notified().await
+    }
+}
+
+pub async fn track_activity(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    if !IGNORED_PREFIXES.iter().any(|prefix| req.path().starts_with(prefix)) {
+        req.app_data::<web::Data<Activity>>()
+            .expect("Activity not registered")
+            .record_request();
+    }
+    next.call(req).await
+}
@@ -0,0 +1,200 @@
+use rayon::prelude::*;
+use rayon::ThreadPool;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use crate::{index_parser::fast_parse_index_line, snapshot::ServingRoot};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CrateSummary {
+    pub name: String,
+    pub versions: usize,
+    pub yanked: usize,
+    pub max_version: String,
+    // max_version 的 rust_version
+    pub rust_version: Option<String>,
+}
+
+pub struct IndexScanner {
+    pool: ThreadPool,
+    root: Arc<ServingRoot>,
+}
+
+impl IndexScanner {
+    // rayon 线程池独立于 tokio/actix 的 worker，避免扫描时抢占请求处理线程
+    pub fn new(root: Arc<ServingRoot>, parallelism: usize) -> Self {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(parallelism)
+            .thread_name(|i| format!("index-scan-{}", i))
+            .build()
+            .expect("Failed to build index scan thread pool");
+        IndexScanner {
+            pool,
+            root,
+        }
+    }
+
+    pub fn scan(&self) -> Vec<CrateSummary> {
+        let mut crates = self.map_files(read_crate_file);
+        crates.sort_by(|a, b| a.name.cmp(&b.name));
+        crates
+    }
+
+    // 在扫描线程池里并行处理每个索引文件，返回 None 的文件跳过，结果没有顺序
+    pub fn map_files<T, F>(&self, f: F) -> Vec<T>
+    where
+        T: Send,
+        F: Fn(&Path) -> Option<T> + Sync + Send,
+    {
+        let dirs = top_level_entries(&self.root.get());
+        self.pool.install(|| {
+            dirs.par_iter()
+                .flat_map_iter(|dir| {
+                    let mut files = Vec::new();
+                    collect_files(dir, &mut files);
+                    files
+                })
+                .filter_map(|file| f(&file))
+                .collect()
+        })
+    }
+}
+
+// 索引根目录下除 .git、config.json 之外的条目
+fn top_level_entries(root: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(root) else {
+        return Vec::new();
+    };
+    entries
+        .filter_map(|e| e.ok())
+        .filter(|e| {
+            let name = e.file_name();
+            let name = name.to_string_lossy();
+            !name.starts_with('.') && name != "config.json"
+        })
+        .map(|e| e.path())
+        .collect()
+}
+
+fn collect_files(path: &Path, files: &mut Vec<PathBuf>) {
+    if path.is_file() {
+        files.push(path.to_path_buf());
+        return;
+    }
+    let Ok(entries) = fs::read_dir(path) else {
+        return;
+    };
+    for entry in entries.filter_map(|e| e.ok()) {
+        if !entry.file_name().to_string_lossy().starts_with('.') {
+            collect_files(&entry.path(), files);
+        }
+    }
+}
+
+pub fn read_crate_file(path: &Path) -> Option<CrateSummary> {
+    let content = fs::read(path).ok()?;
+    let mut summary: Option<CrateSummary> = None;
+    let mut max_version: Option<semver::Version> = None;
+    for line in content
+        .split(|&b| b == b'\n')
+        .filter(|l| !l.trim_ascii().is_empty())
+    {
+        let Ok(entry) = fast_parse_index_line(line) else {
+            continue;
+        };
+        let summary = summary.get_or_insert_with(|| CrateSummary {
+            name: entry.name.to_string(),
+            versions: 0,
+            yanked: 0,
+            max_version: String::new(),
+            rust_version: None,
+        });
+        summary.versions += 1;
+        if entry.yanked {
+            summary.yanked += 1;
+            continue;
+        }
+        if let Ok(version) = semver::Version::parse(&entry.vers) {
+            if max_version.as_ref().is_none_or(|max| version > *max) {
+                summary.max_version = entry.vers.to_string();
+                summary.rust_version = entry.rust_version.map(|v| v.to_string());
+                max_version = Some(version);
+            }
+        }
+    }
+    summary
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{sparse::crate_index_path, test_support::index_line};
+
+    fn write_index(root: &Path, crates: usize) {
+        for i in 0..crates {
+            let name = format!("crate-{}", i);
+            let path = root.join(crate_index_path(&name).unwrap());
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            let lines: String = (0..=i % 5).map(|v| index_line(&name, &format!("0.{}.0", v))).collect();
+            fs::write(path, lines).unwrap();
+        }
+        for name in ["a", "ab", "abc"] {
+            let path = root.join(crate_index_path(name).unwrap());
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, index_line(name, "1.0.0")).unwrap();
+        }
+        fs::write(root.join("config.json"), "{}").unwrap();
+        fs::create_dir_all(root.join(".git")).unwrap();
+        fs::write(root.join(".git/HEAD"), "ref: refs/heads/master\n").unwrap();
+    }
+
+    fn sequential_scan(root: &Path) -> Vec<CrateSummary> {
+        let mut files = Vec::new();
+        for dir in top_level_entries(root) {
+            collect_files(&dir, &mut files);
+        }
+        let mut crates: Vec<CrateSummary> = files.iter().filter_map(|file| read_crate_file(file)).collect();
+        crates.sort_by(|a, b| a.name.cmp(&b.name));
+        crates
+    }
+
+    #[test]
+    fn parallel_scan_matches_sequential_scan() {
+        let dir = tempfile::tempdir().unwrap();
+        write_index(dir.path(), 500);
+        let root = Arc::new(ServingRoot::new(dir.path(), false, false));
+        let expected = sequential_scan(dir.path());
+        assert_eq!(expected.len(), 503);
+        for parallelism in [1, 4, 16] {
+            assert_eq!(IndexScanner::new(Arc::clone(&root), parallelism).scan(), expected);
+        }
+    }
+
+    #[test]
+    fn summarizes_versions() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("serde");
+        let lines = [
+            index_line("serde", "1.0.0"),
+            r#"{"name":"serde","vers":"1.2.0","deps":[],"cksum":"0","features":{},"yanked":true}"#.to_string(),
+            r#"{"name":"serde","vers":"1.1.0","deps":[],"cksum":"0","features":{},"yanked":false,"rust_version":"1.60"}"#.to_string(),
+            "not json".to_string(),
+        ];
+        fs::write(&path, lines.join("\n")).unwrap();
+        assert_eq!(
+            read_crate_file(&path),
+            Some(CrateSummary {
+                name: "serde".to_string(),
+                versions: 3,
+                yanked: 1,
+                max_version: "1.1.0".to_string(),
+                rust_version: Some("1.60".to_string()),
+            })
+        );
+        fs::write(&path, "").unwrap();
+        assert_eq!(read_crate_file(&path), None);
+    }
+}
@@ -0,0 +1,83 @@
+use git2::{Oid, Repository};
+use tracing::warn;
+
+use crate::{metrics, sparse};
+
+// 非 UTF-8 的索引文件 cargo 和所有 JSON 解析器都无法读取，pull 之后只检查这次变化的文件并报告
+pub fn check_changed_files(repo: &Repository, old: Oid, new: Oid) -> Result<(), git2::Error> {
+    let old_tree = repo.find_commit(old)?.tree()?;
+    let new_tree = repo.find_commit(new)?.tree()?;
+    let diff = repo.diff_tree_to_tree(Some(&old_tree), Some(&new_tree), None)?;
+    for delta in diff.deltas() {
+        let file = delta.new_file();
+        let Some(path) = file.path().and_then(|path| path.to_str()) else {
+            continue;
+        };
+        if file.id().is_zero() || !sparse::is_crate_request(path) {
+            continue;
+        }
+        let blob = repo.find_blob(file.id())?;
+        let content = blob.content();
+        if let Err(e) = std::str::from_utf8(content) {
+            let offset = e.valid_up_to();
+            let line = content[..offset].iter().filter(|&&b| b == b'\n').count() + 1;
+            warn!(
+                "Index file {} in {} is not valid UTF-8 at line {} (byte {})",
+                path, new, line, offset
+            );
+            metrics::INDEX_INVALID_UTF8_FILES_TOTAL.inc();
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{capture_logs, index_line, signature, Upstream};
+    use std::path::Path;
+
+    // Upstream::commit 只接受 UTF-8 的内容，这里直接写字节
+    fn commit_bytes(upstream: &Upstream, path: &str, content: &[u8]) -> Oid {
+        std::fs::create_dir_all(upstream.dir.path().join(path).parent().unwrap()).unwrap();
+        std::fs::write(upstream.dir.path().join(path), content).unwrap();
+        let mut index = upstream.repo.index().unwrap();
+        index.add_path(Path::new(path)).unwrap();
+        index.write().unwrap();
+        let tree = upstream.repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let parent = upstream.repo.head().unwrap().peel_to_commit().unwrap();
+        upstream
+            .repo
+            .commit(Some("HEAD"), &signature(), &signature(), "publish", &tree, &[&parent])
+            .unwrap()
+    }
+
+    #[test]
+    fn reports_invalid_utf8_in_changed_files() {
+        let upstream = Upstream::new();
+        let old = upstream.commit("initial", &[("se/rd/serde", Some(&index_line("serde", "1.0.0")))]);
+        // 描述里有一个无效的 UTF-8 字节序列
+        let mut content = index_line("serde", "1.0.0").into_bytes();
+        content.extend_from_slice(b"{\"name\":\"serde\",\"vers\":\"1.0.1\",\"description\":\"caf\xc3\x28\"}\n");
+        let new = commit_bytes(&upstream, "se/rd/serde", &content);
+        // 不是 crate 文件的不检查
+        let other = commit_bytes(&upstream, "notes.txt", b"\xff\xfe");
+
+        let (logs, _guard) = capture_logs();
+        check_changed_files(&upstream.repo, old, new).unwrap();
+        let offset = index_line("serde", "1.0.0").len() + "{\"name\":\"serde\",\"vers\":\"1.0.1\",\"description\":\"caf".len();
+        assert!(
+            logs.contents()
+                .contains(&format!("Index file se/rd/serde in {} is not valid UTF-8 at line 2 (byte {})", new, offset)),
+            "{}",
+            logs.contents()
+        );
+
+        let (logs, _guard) = capture_logs();
+        check_changed_files(&upstream.repo, new, other).unwrap();
+        assert!(!logs.contents().contains("not valid UTF-8"), "{}", logs.contents());
+        let fixed = upstream.commit("fix", &[("se/rd/serde", Some(&index_line("serde", "1.0.0")))]);
+        check_changed_files(&upstream.repo, other, fixed).unwrap();
+        assert!(!logs.contents().contains("not valid UTF-8"), "{}", logs.contents());
+    }
+}
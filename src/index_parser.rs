@@ -0,0 +1,299 @@
+use std::{borrow::Cow, fmt};
+use winnow::{
+    ascii::multispace0,
+    combinator::{alt, cut_err},
+    error::{ContextError, ErrMode, ModalResult},
+    token::{any, literal, take_till, take_while},
+    Parser,
+};
+
+// 只提取热路径需要的字段（搜索、统计），其余字段跳过不反序列化
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParsedIndexEntry<'a> {
+    pub name: Cow<'a, str>,
+    pub vers: Cow<'a, str>,
+    pub yanked: bool,
+    pub cksum: Cow<'a, str>,
+    pub rust_version: Option<Cow<'a, str>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub offset: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at byte {}", self.message, self.offset)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+type Input<'i> = &'i [u8];
+
+fn backtrack() -> ErrMode<ContextError> {
+    ErrMode::Backtrack(ContextError::new())
+}
+
+// 返回包含引号的原始字符串切片
+fn raw_string<'i>(input: &mut Input<'i>) -> ModalResult<&'i [u8]> {
+    let start = *input;
+    b'"'.parse_next(input)?;
+    loop {
+        take_till(0.., (b'"', b'\\')).parse_next(input)?;
+        match any.parse_next(input)? {
+            b'"' => break,
+            _ => {
+                // 转义字符，\uXXXX 的后4位在下一轮按普通字符跳过
+                any.parse_next(input)?;
+            }
+        }
+    }
+    Ok(&start[..start.len() - input.len()])
+}
+
+fn string<'i>(input: &mut Input<'i>) -> ModalResult<Cow<'i, str>> {
+    let raw = raw_string(input)?;
+    let inner = &raw[1..raw.len() - 1];
+    if !inner.contains(&b'\\') {
+        return std::str::from_utf8(inner)
+            .map(Cow::Borrowed)
+            .map_err(|_| backtrack());
+    }
+    // 很少出现转义，交给 serde_json 处理，保证结果和完整反序列化一致
+    serde_json::from_slice::<String>(raw)
+        .map(Cow::Owned)
+        .map_err(|_| backtrack())
+}
+
+fn boolean(input: &mut Input<'_>) -> ModalResult<bool> {
+    alt((literal("true").value(true), literal("false").value(false))).parse_next(input)
+}
+
+fn number(input: &mut Input<'_>) -> ModalResult<()> {
+    take_while(1.., |c: u8| c.is_ascii_digit() || matches!(c, b'-' | b'+' | b'.' | b'e' | b'E'))
+        .void()
+        .parse_next(input)
+}
+
+// 调用方已经确认了开头的 '{' 或 '['
+fn sequence(input: &mut Input<'_>, close: u8, keyed: bool) -> ModalResult<()> {
+    any.parse_next(input)?;
+    multispace0.parse_next(input)?;
+    if input.first() == Some(&close) {
+        any.parse_next(input)?;
+        return Ok(());
+    }
+    loop {
+        if keyed {
+            raw_string(input)?;
+            multispace0.parse_next(input)?;
+            b':'.parse_next(input)?;
+            multispace0.parse_next(input)?;
+        }
+        skip_value(input)?;
+        multispace0.parse_next(input)?;
+        match any.parse_next(input)? {
+            b',' => {
+                multispace0.parse_next(input)?;
+            }
+            c if c == close => return Ok(()),
+            _ => return Err(backtrack()),
+        }
+    }
+}
+
+fn skip_value(input: &mut Input<'_>) -> ModalResult<()> {
+    match input.first() {
+        Some(b'"') => raw_string.void().parse_next(input),
+        Some(b'{') => sequence(input, b'}', true),
+        Some(b'[') => sequence(input, b']', false),
+        Some(b't') | Some(b'f') => boolean.void().parse_next(input),
+        Some(b'n') => literal("null").void().parse_next(input),
+        _ => number(input),
+    }
+}
+
+fn entry<'i>(input: &mut Input<'i>) -> ModalResult<ParsedIndexEntry<'i>> {
+    let mut name = None;
+    let mut vers = None;
+    let mut cksum = None;
+    let mut yanked = false;
+    let mut rust_version = None;
+
+    multispace0.parse_next(input)?;
+    b'{'.parse_next(input)?;
+    multispace0.parse_next(input)?;
+    if input.first() == Some(&b'}') {
+        any.parse_next(input)?;
+    } else {
+        loop {
+            let key = raw_string(input)?;
+            multispace0.parse_next(input)?;
+            cut_err(b':').parse_next(input)?;
+            multispace0.parse_next(input)?;
+            match key {
+                b"\"name\"" => name = Some(string(input)?),
+                b"\"vers\"" => vers = Some(string(input)?),
+                b"\"cksum\"" => cksum = Some(string(input)?),
+                b"\"yanked\"" => yanked = boolean(input)?,
+                b"\"rust_version\"" if input.first() == Some(&b'"') => rust_version = Some(string(input)?),
+                _ => skip_value(input)?,
+            }
+            multispace0.parse_next(input)?;
+            match any.parse_next(input)? {
+                b',' => {
+                    multispace0.parse_next(input)?;
+                }
+                b'}' => break,
+                _ => return Err(backtrack()),
+            }
+        }
+    }
+    multispace0.parse_next(input)?;
+
+    match (name, vers, cksum) {
+        (Some(name), Some(vers), Some(cksum)) => Ok(ParsedIndexEntry {
+            name,
+            vers,
+            yanked,
+            cksum,
+            rust_version,
+        }),
+        _ => Err(ErrMode::Cut(ContextError::new())),
+    }
+}
+
+pub fn fast_parse_index_line(input: &[u8]) -> Result<ParsedIndexEntry<'_>, ParseError> {
+    let mut remaining = input;
+    match entry(&mut remaining) {
+        Ok(_) if !remaining.is_empty() => Err(ParseError {
+            offset: input.len() - remaining.len(),
+            message: "trailing characters".to_string(),
+        }),
+        Ok(parsed) => Ok(parsed),
+        Err(ErrMode::Cut(_)) if remaining.is_empty() => Err(ParseError {
+            offset: input.len(),
+            message: "missing name, vers or cksum".to_string(),
+        }),
+        Err(_) => Err(ParseError {
+            offset: input.len() - remaining.len(),
+            message: "invalid index entry".to_string(),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+    use serde::Deserialize;
+    use serde_json::{json, Value};
+
+    // 完整反序列化作为参照
+    #[derive(Deserialize)]
+    struct FullEntry {
+        name: String,
+        vers: String,
+        cksum: String,
+        #[serde(default)]
+        yanked: bool,
+        #[serde(default)]
+        rust_version: Option<String>,
+    }
+
+    fn other_value() -> impl Strategy<Value = Value> {
+        let leaf = prop_oneof![
+            Just(Value::Null),
+            prop::arbitrary::any::<bool>().prop_map(Value::from),
+            prop::arbitrary::any::<i64>().prop_map(Value::from),
+            prop::arbitrary::any::<f64>().prop_filter("finite", |f| f.is_finite()).prop_map(Value::from),
+            prop::arbitrary::any::<String>().prop_map(Value::from),
+        ];
+        leaf.prop_recursive(3, 16, 4, |inner| {
+            prop_oneof![
+                prop::collection::vec(inner.clone(), 0..4).prop_map(Value::from),
+                prop::collection::btree_map("[a-z_]{1,8}", inner, 0..4)
+                    .prop_map(|map| Value::Object(map.into_iter().collect())),
+            ]
+        })
+    }
+
+    prop_compose! {
+        fn index_line()(
+            name in prop::arbitrary::any::<String>(),
+            vers in prop::arbitrary::any::<String>(),
+            cksum in "[0-9a-f]{64}|\\PC*",
+            yanked in prop::option::of(prop::arbitrary::any::<bool>()),
+            rust_version in prop::option::of(prop::option::of(prop::arbitrary::any::<String>())),
+            // 和需要的字段同名的 key 不能出现两次
+            extra in prop::collection::btree_map("[a-z_]{1,12}", other_value(), 0..6)
+                .prop_map(|map| map.into_iter()
+                    .filter(|(k, _)| !matches!(k.as_str(), "name" | "vers" | "cksum" | "yanked" | "rust_version"))
+                    .collect::<Vec<_>>()),
+            ws in "[ \t\r\n]{0,3}",
+        )(
+            fields in {
+                let mut fields = vec![
+                    ("name", Value::from(name)),
+                    ("vers", Value::from(vers)),
+                    ("cksum", Value::from(cksum)),
+                ];
+                if let Some(yanked) = yanked {
+                    fields.push(("yanked", Value::from(yanked)));
+                }
+                if let Some(rust_version) = rust_version {
+                    fields.push(("rust_version", rust_version.map_or(Value::Null, Value::from)));
+                }
+                let mut fields: Vec<_> = fields.into_iter().map(|(k, v)| (k.to_string(), v)).collect();
+                fields.extend(extra);
+                Just(fields).prop_shuffle()
+            },
+            ws in Just(ws),
+        ) -> String {
+            // 手工拼接，serde_json::Map 会按 key 排序
+            let members: Vec<_> = fields
+                .iter()
+                .map(|(k, v)| format!("{ws}{}{ws}:{ws}{}{ws}", Value::from(k.as_str()), v))
+                .collect();
+            format!("{ws}{{{}}}{ws}", members.join(","))
+        }
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig { cases: 512, ..ProptestConfig::default() })]
+
+        #[test]
+        fn agrees_with_serde_json(line in index_line()) {
+            let full: FullEntry = serde_json::from_str(&line).unwrap();
+            let parsed = fast_parse_index_line(line.as_bytes()).unwrap();
+            prop_assert_eq!(parsed.name, full.name);
+            prop_assert_eq!(parsed.vers, full.vers);
+            prop_assert_eq!(parsed.cksum, full.cksum);
+            prop_assert_eq!(parsed.yanked, full.yanked);
+            prop_assert_eq!(parsed.rust_version.map(Cow::into_owned), full.rust_version);
+        }
+    }
+
+    #[test]
+    fn borrows_unescaped_strings() {
+        let line = br#"{"name":"serde","vers":"1.0.0","deps":[],"cksum":"abc","features":{},"yanked":false}"#;
+        let parsed = fast_parse_index_line(line).unwrap();
+        assert!(matches!(parsed.name, Cow::Borrowed("serde")));
+        let line = json!({"name": "a\"b", "vers": "1.0.0", "cksum": "abc"}).to_string();
+        assert!(matches!(fast_parse_index_line(line.as_bytes()).unwrap().name, Cow::Owned(ref name) if name == "a\"b"));
+    }
+
+    #[test]
+    fn rejects_invalid_lines() {
+        let err = fast_parse_index_line(br#"{"name":"serde","vers":"1.0.0"}"#).unwrap_err();
+        assert_eq!(err.message, "missing name, vers or cksum");
+        let err = fast_parse_index_line(br#"{"name":"a","vers":"1","cksum":"c"} x"#).unwrap_err();
+        assert_eq!((err.offset, err.message.as_str()), (36, "trailing characters"));
+        assert!(fast_parse_index_line(br#"{"name":"a","vers":"1","cksum":"c""#).is_err());
+        assert!(fast_parse_index_line(br#"{"name":"a","yanked":"no","vers":"1","cksum":"c"}"#).is_err());
+        assert!(fast_parse_index_line(b"").is_err());
+    }
+}
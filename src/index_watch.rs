@@ -0,0 +1,113 @@
+use actix_web::web;
+use notify::{event::ModifyKind, Event, EventKind, RecursiveMode, Watcher};
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use tokio::{sync::mpsc, time};
+use tracing::{debug, info, warn};
+
+use crate::{
+    api::{FeaturesResponse, VersionCache, VersionResponse},
+    metrics, sparse,
+};
+
+// 编辑器保存文件时通常会产生多个事件，等待这么久没有新事件后再处理
+const DEBOUNCE: Duration = Duration::from_millis(100);
+
+pub struct IndexCaches {
+    pub versions: web::Data<VersionCache<VersionResponse>>,
+    pub features: web::Data<VersionCache<FeaturesResponse>>,
+}
+
+// 从变化的路径得到 crate 名称；notify 给出的是绝对路径，.git 目录里的文件忽略
+// config.json 等带 . 的文件名不是合法的 crate 名称，se/rd 这样的目录和 libgit2 的临时文件不在对应的前缀目录下
+fn crate_name(path: &Path) -> Option<String> {
+    if path.components().any(|c| c.as_os_str() == ".git") {
+        return None;
+    }
+    let name = path.file_name()?.to_str()?;
+    let index_path = sparse::crate_index_path(name)?;
+    (sparse::valid_crate_name(name) && path.ends_with(index_path)).then(|| name.to_string())
+}
+
+fn is_modification(event: &Event) -> bool {
+    // 保存时先写临时文件再改名的编辑器产生的是 Create 或 Modify(Name)
+    match event.kind {
+        EventKind::Modify(ModifyKind::Metadata(_)) => false,
+        EventKind::Modify(_) | EventKind::Create(_) => true,
+        _ => false,
+    }
+}
+
+fn invalidate(caches: &IndexCaches, names: &HashSet<String>) {
+    for name in names {
+        let removed = caches.versions.invalidate_crate(name) + caches.features.invalidate_crate(name);
+        if removed > 0 {
+            metrics::CACHE_INOTIFY_INVALIDATIONS_TOTAL.inc();
+        }
+        debug!("Index file of {} changed, invalidated {} cached entries", name, removed);
+    }
+}
+
+// notify 在自己的线程里回调，事件通过 channel 交给这个任务处理；watcher 创建失败时只记录警告
+pub async fn watch(root: PathBuf, caches: IndexCaches) {
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let mut watcher = match notify::recommended_watcher(move |event| {
+        let _ = tx.send(event);
+    }) {
+        Ok(watcher) => watcher,
+        Err(e) => {
+            warn!("Failed to create index file watcher: {}", e);
+            return;
+        }
+    };
+    if let Err(e) = watcher.watch(&root, RecursiveMode::Recursive) {
+        warn!("Failed to watch {}: {}", root.display(), e);
+        return;
+    }
+    info!("Watching {} for index file changes", root.display());
+
+    let mut changed = HashSet::new();
+    loop {
+        // 有待处理的文件时最多等待 DEBOUNCE
+        let event = if changed.is_empty() {
+            Ok(rx.recv().await)
+        } else {
+            time::timeout(DEBOUNCE, rx.recv()).await
+        };
+        match event {
+            Ok(Some(Ok(event))) if is_modification(&event) => {
+                changed.extend(event.paths.iter().filter_map(|path| crate_name(path)));
+            }
+            Ok(Some(Ok(_))) => {}
+            Ok(Some(Err(e))) => warn!("Index file watcher error: {}", e),
+            Ok(None) => return,
+            Err(_) => {
+                invalidate(&caches, &changed);
+                changed.clear();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crate_names_from_paths() {
+        let root = Path::new("/srv/index");
+        assert_eq!(crate_name(&root.join("se/rd/serde")).as_deref(), Some("serde"));
+        assert_eq!(crate_name(&root.join("1/a")).as_deref(), Some("a"));
+        assert_eq!(crate_name(&root.join("3/s/syn")).as_deref(), Some("syn"));
+        assert_eq!(crate_name(&root.join("config.json")), None);
+        assert_eq!(crate_name(&root.join(".git/refs/heads/master")), None);
+        // 目录和不在前缀目录下的文件
+        assert_eq!(crate_name(&root.join("se")), None);
+        assert_eq!(crate_name(&root.join("se/rd")), None);
+        assert_eq!(crate_name(&root.join("_git2_2f46cd4ce9191304")), None);
+        assert_eq!(crate_name(&root.join("to/ki/serde")), None);
+    }
+}
@@ -0,0 +1,248 @@
+use dialoguer::{Confirm, Input, Password};
+use serde_json::{json, Value};
+use std::{fmt::Display, path::Path, str::FromStr};
+
+use crate::{
+    config::{self, Config, ConfigFormat},
+    git, listen,
+};
+
+// init 子命令的参数；命令行给出的值不再询问，--non-interactive 时其余的值使用默认值
+#[derive(Debug, Default)]
+pub struct InitArgs {
+    pub config_path: String,
+    pub non_interactive: bool,
+    pub force: bool,
+    pub git_url: Option<String>,
+    pub path: Option<String>,
+    pub update_interval: Option<u64>,
+    pub address: Option<String>,
+    pub port: Option<u16>,
+    pub https_only: Option<bool>,
+    pub canonical_host: Option<String>,
+    pub jwt_secret: Option<String>,
+}
+
+const DEFAULT_GIT_URL: &str = "https://github.com/rust-lang/crates.io-index.git";
+const DEFAULT_PATH: &str = "./crates.io-index";
+const DEFAULT_UPDATE_INTERVAL: u64 = 600;
+const DEFAULT_ADDRESS: &str = "127.0.0.1";
+const DEFAULT_PORT: u16 = 8000;
+const DEFAULT_CANONICAL_HOST: &str = "localhost";
+
+struct Wizard {
+    interactive: bool,
+}
+
+impl Wizard {
+    // 给出的值和输入的值都要通过 validate，非交互模式下校验失败直接退出
+    fn input<T>(
+        &self,
+        prompt: &str,
+        provided: Option<T>,
+        default: T,
+        validate: impl Fn(&T) -> Result<(), String>,
+    ) -> Result<T, String>
+    where
+        T: Clone + Display + FromStr,
+        T::Err: Display,
+    {
+        if provided.is_some() || !self.interactive {
+            let value = provided.unwrap_or(default);
+            validate(&value).map_err(|e| format!("{}: {}", prompt, e))?;
+            return Ok(value);
+        }
+        Input::new()
+            .with_prompt(prompt)
+            .default(default)
+            .validate_with(|value: &T| validate(value))
+            .interact_text()
+            .map_err(|e| e.to_string())
+    }
+
+    fn confirm(&self, prompt: &str, provided: Option<bool>, default: bool) -> Result<bool, String> {
+        if provided.is_some() || !self.interactive {
+            return Ok(provided.unwrap_or(default));
+        }
+        Confirm::new()
+            .with_prompt(prompt)
+            .default(default)
+            .interact()
+            .map_err(|e| e.to_string())
+    }
+}
+
+fn validate_git_url(url: &str) -> Result<(), String> {
+    if url.is_empty() || url.contains(char::is_whitespace) {
+        return Err("must not be empty or contain whitespace".to_string());
+    }
+    let is_remote = ["https://", "http://", "ssh://", "git://", "file://"]
+        .iter()
+        .any(|scheme| url.starts_with(scheme))
+        // scp 风格：git@github.com:rust-lang/crates.io-index.git
+        || url.split_once(':').is_some_and(|(host, _)| host.contains('@'));
+    if !is_remote && !Path::new(url).exists() {
+        return Err(format!("{} is neither a URL nor an existing local repository", url));
+    }
+    Ok(())
+}
+
+// 已有的非空目录会被当作索引仓库直接使用
+fn validate_path(path: &str) -> Result<(), String> {
+    let path = Path::new(path);
+    if path.as_os_str().is_empty() {
+        return Err("must not be empty".to_string());
+    }
+    if path.exists() && !path.is_dir() {
+        return Err(format!("{} is not a directory", path.display()));
+    }
+    let is_empty = path.read_dir().map_or(true, |mut entries| entries.next().is_none());
+    if !is_empty && !path.join(".git").exists() {
+        return Err(format!("{} is not empty and not a git repository", path.display()));
+    }
+    Ok(())
+}
+
+fn validate_update_interval(secs: &u64) -> Result<(), String> {
+    if *secs == 0 {
+        return Err("must be greater than 0".to_string());
+    }
+    Ok(())
+}
+
+fn validate_address(address: &str) -> Result<(), String> {
+    listen::parse_address(address).map(|_| ())
+}
+
+fn validate_port(port: &u16) -> Result<(), String> {
+    if *port == 0 {
+        return Err("must be greater than 0".to_string());
+    }
+    Ok(())
+}
+
+// 0.0.0.0 这类地址不能直接给 cargo 使用，提示里换成本机地址；通过 HTTPS 提供时使用对外的主机名
+fn registry_url(address: &str, port: u16, canonical_host: Option<&str>) -> String {
+    if let Some(host) = canonical_host {
+        return format!("sparse+https://{}/", host);
+    }
+    let host = match address {
+        "0.0.0.0" => "127.0.0.1",
+        "[::]" => "[::1]",
+        _ => address,
+    };
+    format!("sparse+http://{}:{}/", host, port)
+}
+
+pub fn run(args: InitArgs) -> Result<(), String> {
+    let wizard = Wizard {
+        interactive: !args.non_interactive,
+    };
+    let config_path = &args.config_path;
+    let format = ConfigFormat::from_path(config_path)?;
+    if Path::new(config_path).exists() && !args.force {
+        let overwrite = wizard.interactive
+            && wizard.confirm(&format!("{} already exists, overwrite it?", config_path), None, false)?;
+        if !overwrite {
+            return Err(format!("{} already exists, use --force to overwrite it", config_path));
+        }
+    }
+
+    let git_url = wizard.input("Upstream git URL", args.git_url, DEFAULT_GIT_URL.to_string(), |url: &String| {
+        validate_git_url(url)
+    })?;
+    let path = wizard.input("Local index path", args.path, DEFAULT_PATH.to_string(), |path: &String| {
+        validate_path(path)
+    })?;
+    let update_interval = wizard.input(
+        "Update interval in seconds",
+        args.update_interval,
+        DEFAULT_UPDATE_INTERVAL,
+        validate_update_interval,
+    )?;
+    let address = wizard.input("Listen address", args.address, DEFAULT_ADDRESS.to_string(), |address: &String| {
+        validate_address(address)
+    })?;
+    let port = wizard.input("Listen port", args.port, DEFAULT_PORT, validate_port)?;
+    // 服务本身不终止 TLS，由反向代理提供 HTTPS，这里只决定是否把 HTTP 请求重定向到 HTTPS
+    let https_only = wizard.confirm(
+        "Is the mirror served over HTTPS by a reverse proxy (redirect plain HTTP to HTTPS)?",
+        args.https_only,
+        false,
+    )?;
+    // HTTP 请求只重定向到这个主机名
+    let canonical_host = if https_only {
+        Some(wizard.input(
+            "Public host name of the mirror (e.g. crates.example.com)",
+            args.canonical_host,
+            DEFAULT_CANONICAL_HOST.to_string(),
+            |host: &String| config::validate_canonical_host(host),
+        )?)
+    } else {
+        None
+    };
+    let jwt_secret = match args.jwt_secret {
+        Some(secret) => Some(secret),
+        None if wizard.confirm("Enable JWT (HS256) authentication for /api/v1/me?", None, false)? => Some(
+            Password::new()
+                .with_prompt("JWT secret")
+                .with_confirmation("Repeat the JWT secret", "The secrets don't match")
+                .interact()
+                .map_err(|e| e.to_string())?,
+        ),
+        None => None,
+    };
+    if jwt_secret.as_ref().is_some_and(|secret| secret.is_empty()) {
+        return Err("The JWT secret must not be empty".to_string());
+    }
+
+    let mut value = json!({
+        "repo": {
+            "git_url": git_url,
+            "path": path,
+            "update_interval": update_interval,
+        },
+        "web": {
+            "address": address,
+            "port": port,
+        },
+    });
+    if let Some(host) = &canonical_host {
+        value["web"]["https_only"] = Value::Bool(true);
+        value["web"]["canonical_host"] = Value::String(host.clone());
+    }
+    if let Some(secret) = jwt_secret {
+        value["auth"] = json!({ "jwt": { "secret": secret } });
+    }
+    // 写入之前按服务启动时同样的规则校验一遍
+    let config: Config = serde_json::from_value(value.clone()).map_err(|e| e.to_string())?;
+    config.validate()?;
+    std::fs::write(config_path, format.serialize(&value)?)
+        .map_err(|e| format!("Failed to write {}: {}", config_path, e))?;
+    println!("Wrote {}", config_path);
+
+    println!("Checking connectivity to {} ...", git_url);
+    match git::check_connectivity(&git_url, &git::GitAuth::from_config(&config.repo)) {
+        Ok(refs) => println!("Upstream is reachable ({} refs)", refs),
+        Err(e) => {
+            println!("Upstream is not reachable: {}", e);
+            if e.code() == git2::ErrorCode::Auth {
+                println!("For SSH URLs make sure ~/.ssh/id_rsa is set up, or set repo.ssh_use_agent = true");
+                println!("For HTTPS URLs set repo.use_credential_helper = true to use git credential helpers");
+            }
+        }
+    }
+
+    println!();
+    println!("Next steps:");
+    println!("  1. Start the mirror: local_crates_io_index --config {}", config_path);
+    println!("     The first start clones the index into {}, which takes a while", path);
+    println!("  2. Point cargo at it in ~/.cargo/config.toml:");
+    println!();
+    println!("     [source.crates-io]");
+    println!("     replace-with = 'local'");
+    println!();
+    println!("     [source.local]");
+    println!("     registry = \"{}\"", registry_url(&address, port, canonical_host.as_deref()));
+    Ok(())
+}
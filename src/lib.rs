@@ -0,0 +1,56 @@
+// main.rs 和 benches/ 共用的模块
+pub mod api;
+pub mod auth;
+pub mod body_limit;
+pub mod byte_ranges;
+pub mod chunked;
+pub mod circuit_breaker;
+pub mod cli;
+pub mod coalesce;
+pub mod config;
+pub mod content_type;
+pub mod credential;
+pub mod delta;
+pub mod events;
+pub mod file_lock;
+pub mod forwarded;
+pub mod gc;
+pub mod git;
+pub mod git_bundle;
+pub mod git_http;
+pub mod graphql;
+pub mod grpc_health;
+pub mod health;
+pub mod hooks;
+pub mod idle;
+pub mod index;
+pub mod init;
+pub mod index_check;
+pub mod index_parser;
+pub mod index_watch;
+pub mod listen;
+pub mod listing;
+pub mod metrics;
+pub mod notifications;
+pub mod openapi;
+pub mod panic_recovery;
+pub mod prefetch;
+pub mod problem;
+pub mod protocol;
+pub mod pull_timing;
+pub mod rate_limit;
+pub mod redact;
+pub mod registry;
+pub mod replication;
+pub mod repo_stats;
+pub mod reverse_deps;
+pub mod request_timing;
+pub mod security_headers;
+pub mod server;
+pub mod slo;
+pub mod snapshot;
+pub mod sparse;
+#[cfg(test)]
+mod test_support;
+pub mod trie;
+pub mod util;
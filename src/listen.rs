@@ -0,0 +1,270 @@
+use socket2::{Domain, Protocol, SockRef, Socket, Type};
+use std::{
+    collections::HashMap,
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener, ToSocketAddrs},
+};
+use tracing::info;
+
+use crate::config::WebConfig;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ListenAddress {
+    V4(Ipv4Addr),
+    V6(Ipv6Addr),
+    Host(String),
+}
+
+// IPv4 字面量、带方括号的 IPv6 字面量（如 "[::]"）或主机名
+pub fn parse_address(address: &str) -> Result<ListenAddress, String> {
+    if let Some(inner) = address.strip_prefix('[').and_then(|a| a.strip_suffix(']')) {
+        return inner
+            .parse::<Ipv6Addr>()
+            .map(ListenAddress::V6)
+            .map_err(|_| format!("Invalid IPv6 address {:?}", address));
+    }
+    if address.parse::<Ipv6Addr>().is_ok() {
+        return Err(format!(
+            "IPv6 address {:?} must be bracketed, e.g. \"[{}]\"",
+            address, address
+        ));
+    }
+    if let Ok(ip) = address.parse::<Ipv4Addr>() {
+        return Ok(ListenAddress::V4(ip));
+    }
+    let valid_hostname = !address.is_empty()
+        && address
+            .split('.')
+            .all(|label| !label.is_empty() && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-'));
+    if valid_hostname {
+        Ok(ListenAddress::Host(address.to_string()))
+    } else {
+        Err(format!("Invalid listen address {:?}", address))
+    }
+}
+
+// dual_stack 时为IP字面量补上另一个协议族的对应地址，目前只支持未指定地址和回环地址
+fn counterpart(ip: IpAddr) -> Result<IpAddr, String> {
+    match ip {
+        IpAddr::V4(v4) if v4.is_unspecified() => Ok(IpAddr::V6(Ipv6Addr::UNSPECIFIED)),
+        IpAddr::V4(v4) if v4.is_loopback() => Ok(IpAddr::V6(Ipv6Addr::LOCALHOST)),
+        IpAddr::V6(v6) if v6.is_unspecified() => Ok(IpAddr::V4(Ipv4Addr::UNSPECIFIED)),
+        IpAddr::V6(v6) if v6.is_loopback() => Ok(IpAddr::V4(Ipv4Addr::LOCALHOST)),
+        _ => Err(format!(
+            "web.dual_stack requires an unspecified or loopback address, got {}",
+            ip
+        )),
+    }
+}
+
+pub fn resolve_listen_addrs(config: &WebConfig) -> Result<Vec<SocketAddr>, String> {
+    let ip = match parse_address(&config.address)? {
+        ListenAddress::V4(ip) => IpAddr::V4(ip),
+        ListenAddress::V6(ip) => IpAddr::V6(ip),
+        ListenAddress::Host(host) => {
+            let addrs: Vec<SocketAddr> = (host.as_str(), config.port)
+                .to_socket_addrs()
+                .map_err(|e| format!("Failed to resolve {}: {}", host, e))?
+                .collect();
+            for addr in &addrs {
+                info!("Resolved {} to {}", host, addr);
+            }
+            return Ok(addrs);
+        }
+    };
+
+    let mut addrs = vec![SocketAddr::new(ip, config.port)];
+    if config.dual_stack {
+        addrs.push(SocketAddr::new(counterpart(ip)?, config.port));
+    }
+    Ok(addrs)
+}
+
+pub fn bind_listener(addr: SocketAddr, config: &WebConfig) -> io::Result<TcpListener> {
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+    // 不设置 SO_REUSEPORT：重新加载时复用 Listeners 里的 socket，同一用户的第二个实例绑定同一端口时应该报 EADDRINUSE
+    socket.set_reuse_address(true)?;
+    // 双栈模式下 IPv4 单独监听，IPv6 socket 必须设置 V6ONLY 避免端口冲突
+    if wants_only_v6(addr, config) {
+        socket.set_only_v6(true)?;
+    }
+    set_buffer_sizes(&socket, addr, config)?;
+    socket.bind(&addr.into())?;
+    socket.listen(1024)?;
+    Ok(socket.into())
+}
+
+fn wants_only_v6(addr: SocketAddr, config: &WebConfig) -> bool {
+    addr.is_ipv6() && (config.ipv6_only || config.dual_stack)
+}
+
+// 在监听 socket 上设置，accept 得到的连接会继承；Linux 会把值翻倍并受 net.core.wmem_max/rmem_max 限制
+fn set_buffer_sizes(socket: &Socket, addr: SocketAddr, config: &WebConfig) -> io::Result<()> {
+    if config.tcp_send_buffer_kb > 0 {
+        socket.set_send_buffer_size(config.tcp_send_buffer_kb as usize * 1024)?;
+        info!(
+            "TCP send buffer on {}: requested {} KB, got {} KB",
+            addr,
+            config.tcp_send_buffer_kb,
+            socket.send_buffer_size()? / 1024
+        );
+    }
+    if config.tcp_recv_buffer_kb > 0 {
+        socket.set_recv_buffer_size(config.tcp_recv_buffer_kb as usize * 1024)?;
+        info!(
+            "TCP receive buffer on {}: requested {} KB, got {} KB",
+            addr,
+            config.tcp_recv_buffer_kb,
+            socket.recv_buffer_size()? / 1024
+        );
+    }
+    Ok(())
+}
+
+// 在各代 server 之间共享监听 socket，地址不变时新 server 复用同一个 socket，accept 队列里的连接不会丢失
+#[derive(Default)]
+pub struct Listeners {
+    bound: HashMap<SocketAddr, TcpListener>,
+}
+
+impl Listeners {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // 返回的 listener 交给新的 server；不再监听的地址在旧 server 排空后关闭
+    pub fn bind_all(&mut self, addrs: &[SocketAddr], config: &WebConfig) -> io::Result<Vec<TcpListener>> {
+        let mut bound = HashMap::new();
+        for &addr in addrs {
+            // V6ONLY 只能在 bind 之前设置，ipv6_only/dual_stack 改变时需要重新绑定
+            let reusable = self.bound.get(&addr).filter(|listener| {
+                !addr.is_ipv6() || SockRef::from(*listener).only_v6().ok() == Some(wants_only_v6(addr, config))
+            });
+            let listener = match reusable {
+                Some(listener) => {
+                    set_buffer_sizes(&SockRef::from(listener), addr, config)?;
+                    listener.try_clone()?
+                }
+                // 旧 server 还在这个端口上监听，没有 SO_REUSEPORT 无法再绑定一次
+                None if self.bound.contains_key(&addr) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::AddrInUse,
+                        format!("changing ipv6_only or dual_stack for {} requires a restart", addr),
+                    ));
+                }
+                None => bind_listener(addr, config)?,
+            };
+            bound.insert(addr, listener);
+        }
+        let listeners = addrs.iter().map(|addr| bound[addr].try_clone()).collect::<io::Result<_>>()?;
+        self.bound = bound;
+        Ok(listeners)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+    use std::net::TcpStream;
+
+    fn web_config(address: &str, extra: &str) -> WebConfig {
+        let mut config = test_support::web_config(extra);
+        config.address = address.to_string();
+        config
+    }
+
+    #[test]
+    fn parses_addresses() {
+        assert_eq!(parse_address("0.0.0.0"), Ok(ListenAddress::V4(Ipv4Addr::UNSPECIFIED)));
+        assert_eq!(parse_address("[::1]"), Ok(ListenAddress::V6(Ipv6Addr::LOCALHOST)));
+        assert_eq!(parse_address("localhost"), Ok(ListenAddress::Host("localhost".to_string())));
+        assert!(parse_address("::1").unwrap_err().contains("must be bracketed"));
+        assert!(parse_address("[1.2.3.4]").is_err());
+        assert!(parse_address("").is_err());
+        assert!(parse_address("bad host").is_err());
+        assert!(parse_address("a..b").is_err());
+    }
+
+    #[test]
+    fn dual_stack_addresses() {
+        let addrs = |address, extra| resolve_listen_addrs(&web_config(address, extra));
+        assert_eq!(addrs("127.0.0.1", ""), Ok(vec!["127.0.0.1:0".parse().unwrap()]));
+        assert_eq!(
+            addrs("0.0.0.0", "dual_stack = true"),
+            Ok(vec!["0.0.0.0:0".parse().unwrap(), "[::]:0".parse().unwrap()])
+        );
+        assert_eq!(
+            addrs("[::1]", "dual_stack = true"),
+            Ok(vec!["[::1]:0".parse().unwrap(), "127.0.0.1:0".parse().unwrap()])
+        );
+        assert!(addrs("192.0.2.1", "dual_stack = true").is_err());
+    }
+
+    #[test]
+    fn binds_ipv4() {
+        let config = web_config("127.0.0.1", "");
+        let listener = bind_listener("127.0.0.1:0".parse().unwrap(), &config).unwrap();
+        assert!(TcpStream::connect(listener.local_addr().unwrap()).is_ok());
+    }
+
+    #[test]
+    fn binds_ipv6_only() {
+        let config = web_config("[::]", "ipv6_only = true");
+        let listener = bind_listener("[::]:0".parse().unwrap(), &config).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        assert!(TcpStream::connect(("::1", port)).is_ok());
+        // IPv4 的连接不会被 IPv6 socket 接受
+        assert!(TcpStream::connect(("127.0.0.1", port)).is_err());
+    }
+
+    #[test]
+    fn binds_dual_stack_on_one_port() {
+        let config = web_config("[::]", "dual_stack = true");
+        let v6 = bind_listener("[::]:0".parse().unwrap(), &config).unwrap();
+        let port = v6.local_addr().unwrap().port();
+        // V6ONLY 让 IPv4 socket 可以绑定同一个端口
+        let v4 = bind_listener(SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), port), &config).unwrap();
+        assert!(TcpStream::connect(("::1", port)).is_ok());
+        assert!(TcpStream::connect(("127.0.0.1", port)).is_ok());
+        drop((v4, v6));
+    }
+
+    #[test]
+    fn reload_reuses_listeners() {
+        let config = web_config("127.0.0.1", "");
+        let addrs = ["127.0.0.1:0".parse().unwrap()];
+        let mut listeners = Listeners::new();
+        let old = listeners.bind_all(&addrs, &config).unwrap().remove(0);
+        let port = old.local_addr().unwrap().port();
+        // 连接进入 accept 队列后旧 server 关闭它的 listener
+        let client = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        let new = listeners.bind_all(&addrs, &config).unwrap().remove(0);
+        drop(old);
+        assert_eq!(new.local_addr().unwrap().port(), port);
+        let (accepted, _) = new.accept().unwrap();
+        assert_eq!(accepted.peer_addr().unwrap(), client.local_addr().unwrap());
+    }
+
+    #[test]
+    fn v6only_change_needs_a_restart() {
+        let addrs = ["[::]:0".parse().unwrap()];
+        let mut listeners = Listeners::new();
+        let dual = listeners.bind_all(&addrs, &web_config("[::]", "")).unwrap().remove(0);
+        assert!(!SockRef::from(&dual).only_v6().unwrap());
+        let err = listeners.bind_all(&addrs, &web_config("[::]", "ipv6_only = true")).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AddrInUse);
+        assert!(err.to_string().contains("requires a restart"));
+        // 失败的重新加载不影响正在使用的 socket
+        let again = listeners.bind_all(&addrs, &web_config("[::]", "")).unwrap().remove(0);
+        assert_eq!(again.local_addr().unwrap(), dual.local_addr().unwrap());
+    }
+
+    #[test]
+    fn second_instance_cannot_bind_the_same_port() {
+        let config = web_config("127.0.0.1", "");
+        let first = bind_listener("127.0.0.1:0".parse().unwrap(), &config).unwrap();
+        let err = bind_listener(first.local_addr().unwrap(), &config).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AddrInUse);
+    }
+}
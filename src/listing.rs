@@ -0,0 +1,243 @@
+use actix_files::{Directory, FilesService};
+use actix_web::{
+    dev::{fn_service, Service, ServiceFactory, ServiceRequest, ServiceResponse},
+    web, Error, HttpRequest, HttpResponse,
+};
+use bytes::Bytes;
+use futures_util::stream;
+use percent_encoding::{utf8_percent_encode, CONTROLS};
+use std::{cell::RefCell, fs, io, path::PathBuf, rc::Rc};
+use tokio::sync::mpsc;
+
+use crate::{
+    panic_recovery,
+    problem::{not_found, ProblemDetails, ProblemType},
+    snapshot::ServingRoot,
+    util::html_escape,
+};
+
+// 每个chunk包含的目录项数量
+const ENTRIES_PER_CHUNK: usize = 256;
+
+// 在阻塞线程上扫描目录，每 ENTRIES_PER_CHUNK 项发送一次
+fn write_listing(read_dir: fs::ReadDir, tx: mpsc::Sender<Bytes>, base: String, index_of: String, max_entries: usize) {
+    let header = format!(
+        "<html><head><title>{}</title></head><body><h1>{}</h1><ul>",
+        index_of, index_of
+    );
+    if tx.blocking_send(Bytes::from(header)).is_err() {
+        return;
+    }
+
+    let mut chunk = String::new();
+    let mut count = 0;
+    let mut truncated = false;
+    for entry in read_dir.filter_map(|e| e.ok()) {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name.starts_with('.') {
+            continue;
+        }
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+        if count == max_entries {
+            truncated = true;
+            break;
+        }
+        let href = utf8_percent_encode(&format!("{}/{}", base, name), CONTROLS).to_string();
+        let suffix = if file_type.is_dir() { "/" } else { "" };
+        chunk.push_str(&format!(
+            "<li><a href=\"{}\">{}{}</a></li>",
+            html_escape(&href),
+            html_escape(&name),
+            suffix
+        ));
+        count += 1;
+        if count % ENTRIES_PER_CHUNK == 0 {
+            // 客户端断开后停止扫描
+            if tx.blocking_send(Bytes::from(std::mem::take(&mut chunk))).is_err() {
+                return;
+            }
+        }
+    }
+
+    chunk.push_str("</ul>");
+    if truncated {
+        chunk.push_str(&format!(
+            "<p>Listing truncated after {} entries.</p>",
+            max_entries
+        ));
+    }
+    chunk.push_str("</body>\n</html>");
+    let _ = tx.blocking_send(Bytes::from(chunk));
+}
+
+// 替代 actix-files 自带的目录列表：边扫描目录边输出HTML，避免整个列表先缓存在内存里
+pub fn render_streaming(
+    dir: &Directory,
+    req: &HttpRequest,
+    max_entries: usize,
+) -> Result<ServiceResponse, io::Error> {
+    let read_dir = fs::read_dir(&dir.path)?;
+    let base = req.path().trim_end_matches('/').to_string();
+    let index_of = html_escape(&format!("Index of {}", req.path()));
+
+    let (tx, rx) = mpsc::channel::<Bytes>(4);
+    // 响应头已经发出，panic 时只能提前结束响应体
+    tokio::task::spawn_blocking(move || {
+        panic_recovery::catch("Directory listing", || write_listing(read_dir, tx, base, index_of, max_entries))
+    });
+
+    let body = stream::unfold(rx, |mut rx| async move {
+        rx.recv().await.map(|chunk| (Ok::<_, io::Error>(chunk), rx))
+    });
+    let res = HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .streaming(body);
+    Ok(ServiceResponse::new(req.clone(), res))
+}
+
+pub fn files_service(path: impl Into<PathBuf>, show_listing: bool, max_entries: usize) -> actix_files::Files {
+    let files = actix_files::Files::new("/", path.into());
+    if !show_listing {
+        return files;
+    }
+    files
+        .show_files_listing()
+        .files_listing_renderer(move |dir: &Directory, req: &HttpRequest| {
+            render_streaming(dir, req, max_entries)
+        })
+}
+
+// actix-files 在创建时固定目录，atomic_checkout 切换快照后按新的目录重新创建，
+// 静态文件和目录列表跟索引文件一样来自当前的快照，不会读到检出到一半的工作区
+pub fn snapshot_files_service(
+    show_listing: bool,
+    max_entries: usize,
+) -> impl ServiceFactory<ServiceRequest, Config = (), Response = ServiceResponse, Error = Error, InitError = ()> {
+    // 每个 worker 一份
+    let current: Rc<RefCell<Option<(PathBuf, FilesService)>>> = Rc::default();
+    fn_service(move |req: ServiceRequest| {
+        let current = Rc::clone(&current);
+        async move {
+            let root = req
+                .app_data::<web::Data<ServingRoot>>()
+                .expect("ServingRoot not registered")
+                .get();
+            let cached = current
+                .borrow()
+                .as_ref()
+                .filter(|(path, _)| *path == root)
+                .map(|(_, service)| service.clone());
+            let service = match cached {
+                Some(service) => service,
+                None => {
+                    let service = files_service(&root, show_listing, max_entries)
+                        .new_service(())
+                        .await
+                        .map_err(|()| ProblemDetails::new(ProblemType::Internal).with_detail("cannot serve the index snapshot"))?;
+                    *current.borrow_mut() = Some((root, service.clone()));
+                    service
+                }
+            };
+            let res = service.call(req).await?;
+            // 路径中间的一段是文件时（例如 /1/a/a），打开文件得到 ENOTDIR，和不存在的文件一样返回 404
+            let not_a_directory = res
+                .response()
+                .error()
+                .and_then(|e| e.as_error::<io::Error>())
+                .is_some_and(|e| e.kind() == io::ErrorKind::NotADirectory);
+            if not_a_directory {
+                let (req, _) = res.into_parts();
+                return Ok(ServiceResponse::from_err(not_found("no such file in the index"), req));
+            }
+            Ok(res)
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{
+        body::{BodySize, MessageBody},
+        test as actix_test, App,
+    };
+    use std::path::Path;
+
+    async fn get(root: &Path, uri: &str, max_entries: usize) -> (BodySize, Vec<Bytes>) {
+        let app = actix_test::init_service(
+            App::new()
+                .app_data(web::Data::new(ServingRoot::new(root, false, false)))
+                .default_service(snapshot_files_service(true, max_entries)),
+        )
+        .await;
+        let res = actix_test::call_service(&app, actix_test::TestRequest::get().uri(uri).to_request()).await;
+        assert!(res.status().is_success());
+        let mut body = res.into_body();
+        let size = body.size();
+        let mut chunks = Vec::new();
+        while let Some(chunk) = std::future::poll_fn(|cx| std::pin::Pin::new(&mut body).poll_next(cx)).await {
+            chunks.push(chunk.unwrap());
+        }
+        (size, chunks)
+    }
+
+    fn html(chunks: &[Bytes]) -> String {
+        String::from_utf8(chunks.concat()).unwrap()
+    }
+
+    #[actix_web::test]
+    async fn streams_large_directories() {
+        let dir = tempfile::tempdir().unwrap();
+        let big = dir.path().join("big");
+        fs::create_dir(&big).unwrap();
+        for i in 0..20_000 {
+            fs::write(big.join(format!("f{}", i)), "").unwrap();
+        }
+        let (size, chunks) = get(dir.path(), "/big/", 100_000).await;
+        assert_eq!(size, BodySize::Stream);
+        // 头部、每 ENTRIES_PER_CHUNK 项一次、结尾
+        assert_eq!(chunks.len(), 1 + 20_000 / ENTRIES_PER_CHUNK + 1);
+        assert!(chunks.iter().all(|chunk| chunk.len() < 64 * 1024));
+        let listing = html(&chunks);
+        assert_eq!(listing.matches("<li>").count(), 20_000);
+        assert!(listing.contains("<a href=\"/big/f19999\">f19999</a>"));
+        assert!(listing.ends_with("</body>\n</html>"));
+    }
+
+    #[actix_web::test]
+    async fn truncates_and_escapes() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("d")).unwrap();
+        for name in ["<script>", "a", "b", ".hidden"] {
+            fs::write(dir.path().join("d").join(name), "").unwrap();
+        }
+        let listing = html(&get(dir.path(), "/d/", 10).await.1);
+        assert!(listing.contains("&lt;script&gt;"));
+        assert!(!listing.contains("<script>"));
+        assert!(!listing.contains(".hidden"));
+        let truncated = html(&get(dir.path(), "/d/", 2).await.1);
+        assert_eq!(truncated.matches("<li>").count(), 2);
+        assert!(truncated.contains("Listing truncated after 2 entries."));
+    }
+
+    #[actix_web::test]
+    async fn file_used_as_directory_is_not_found() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("1")).unwrap();
+        fs::write(dir.path().join("1").join("a"), "").unwrap();
+        let app = actix_test::init_service(
+            App::new()
+                .app_data(web::Data::new(ServingRoot::new(dir.path(), false, false)))
+                .default_service(snapshot_files_service(false, 100)),
+        )
+        .await;
+        for req in [actix_test::TestRequest::get(), actix_test::TestRequest::default().method(actix_web::http::Method::HEAD)] {
+            let res = actix_test::call_service(&app, req.uri("/1/a/a").to_request()).await;
+            assert_eq!(res.status(), 404);
+        }
+        let res = actix_test::call_service(&app, actix_test::TestRequest::get().uri("/1/a").to_request()).await;
+        assert_eq!(res.status(), 200);
+    }
+}
@@ -1,171 +1,1009 @@
-use actix_files::Files;
-use actix_web::{App, HttpServer};
+use local_crates_io_index::{
+    api, circuit_breaker, cli, config, events, gc, git, grpc_health, health, hooks, idle, init, index_check, index_watch, listen, metrics, notifications, panic_recovery, prefetch, pull_timing, replication, repo_stats, server, slo, snapshot,
+};
+// tests 里和 lib 一样使用 test_support，它引用的 crate::config 等是上面导入的模块
+#[cfg(test)]
+#[allow(dead_code)]
+#[path = "test_support.rs"]
+mod test_support;
+
+use actix_web::web;
 use chrono::Local;
-use git2::{FetchOptions, RemoteCallbacks, Repository};
-use serde::Deserialize;
-use std::{path::Path, sync::Arc, time::Duration};
-use tokio::{time, signal};
+use config::{AlertKind, Config, NonFfStrategy};
+use git::{clone_repo, pull_repo, PullOutcome};
+use git2::{Oid, Repository};
+use notifications::Notifier;
+use std::{
+    path::Path,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::{
+    signal,
+    sync::{mpsc, watch},
+    time,
+};
+use tracing::{error, info, warn};
+use tracing_subscriber::{fmt::time::FormatTime, EnvFilter};
+
+struct LocalTimer;
+
+impl FormatTime for LocalTimer {
+    fn format_time(&self, w: &mut tracing_subscriber::fmt::format::Writer<'_>) -> std::fmt::Result {
+        write!(w, "[{}]", Local::now().format("%Y-%m-%d %H:%M:%S"))
+    }
+}
+
+// SIGHUP 触发重新加载配置，非 unix 平台上只能通过 POST /admin/reload
+struct ReloadSignal {
+    #[cfg(unix)]
+    sighup: signal::unix::Signal,
+}
+
+impl ReloadSignal {
+    fn new() -> std::io::Result<Self> {
+        Ok(ReloadSignal {
+            #[cfg(unix)]
+            sighup: signal::unix::signal(signal::unix::SignalKind::hangup())?,
+        })
+    }
+
+    async fn recv(&mut self) {
+        #[cfg(unix)]
+        self.sighup.recv().await;
+        #[cfg(not(unix))]
+        std::future::pending::<()>().await;
+    }
+}
+
+// Kubernetes 删除 Pod 时发送 SIGTERM，和 SIGINT 一样排空连接后退出；非 unix 平台上只有 Ctrl-C
+struct ShutdownSignal {
+    #[cfg(unix)]
+    sigterm: signal::unix::Signal,
+    #[cfg(unix)]
+    sigint: signal::unix::Signal,
+}
+
+impl ShutdownSignal {
+    fn new() -> std::io::Result<Self> {
+        Ok(ShutdownSignal {
+            #[cfg(unix)]
+            sigterm: signal::unix::signal(signal::unix::SignalKind::terminate())?,
+            #[cfg(unix)]
+            sigint: signal::unix::signal(signal::unix::SignalKind::interrupt())?,
+        })
+    }
+
+    // 返回收到的信号名称
+    async fn recv(&mut self) -> &'static str {
+        #[cfg(unix)]
+        {
+            tokio::select! {
+                _ = self.sigterm.recv() => "SIGTERM",
+                _ = self.sigint.recv() => "SIGINT",
+            }
+        }
+        #[cfg(not(unix))]
+        {
+            let _ = signal::ctrl_c().await;
+            "Ctrl-C"
+        }
+    }
+}
+
+// 检出过程中磁盘满会留下一半新一半旧的工作区，在恢复之前索引文件返回 503
+fn handle_git_error(
+    repo: &Repository,
+    e: &git2::Error,
+    cleanup: bool,
+    health: &health::HealthState,
+    notifier: &Notifier,
+) {
+    if !git::is_disk_full(e, repo.workdir().unwrap_or_else(|| repo.path())) {
+        return;
+    }
+    error!("Disk full while updating the index, marking it unavailable");
+    notifier.alert(AlertKind::DiskFull, &format!("Disk full while updating the index: {}", e));
+    health.set_available(false);
+    if cleanup {
+        cleanup_checkout(repo, health, notifier);
+    }
+}
 
-#[derive(Debug, Deserialize)]
-struct Config {
-    repo: CratesIoIndexRepo,
-    web: WebConfig,
+fn cleanup_checkout(repo: &Repository, health: &health::HealthState, notifier: &Notifier) {
+    match git::cleanup_partial_checkout(repo) {
+        Ok(()) => {
+            info!("Cleaned up partial checkout, index is available again");
+            health.set_available(true);
+        }
+        Err(e) => {
+            error!("Failed to clean up partial checkout: {}", e);
+            notifier.alert(
+                AlertKind::WorkingTreeCorruption,
+                &format!("Failed to clean up the partial checkout, the index stays unavailable: {}", e),
+            );
+        }
+    }
+}
+
+// webhook 地址的路径里通常带着 token，日志只写 scheme 和主机
+fn webhook_origin(webhook_url: &str) -> String {
+    match reqwest::Url::parse(webhook_url) {
+        Ok(url) => url.origin().ascii_serialization(),
+        Err(_) => "<invalid url>".to_string(),
+    }
+}
+
+// 把 payload 以 JSON POST 到 webhook_url，payload["event"] 是事件名称
+async fn post_webhook(webhook_url: String, payload: serde_json::Value) {
+    let event = payload["event"].as_str().unwrap_or_default().to_string();
+    let origin = webhook_origin(&webhook_url);
+    let request = reqwest::Client::new()
+        .post(&webhook_url)
+        .header("Content-Type", "application/json")
+        .body(payload.to_string());
+    match request.send().await {
+        Ok(res) if res.status().is_success() => info!("Sent {} event to {}", event, origin),
+        Ok(res) => warn!("Webhook {} rejected {} event: {}", origin, event, res.status()),
+        Err(e) => warn!("Failed to send {} event to {}: {}", event, origin, e.without_url()),
+    }
+}
+
+// repo.non_ff_strategy = "log_only" 时通知外部系统，由人工决定如何处理
+async fn notify_non_ff(webhook_url: String, git_url: String, local: Option<Oid>, upstream: Oid, events: u32) {
+    let payload = serde_json::json!({
+        "event": "non_fast_forward",
+        "git_url": git_url,
+        "local": local.map(|oid| oid.to_string()),
+        "upstream": upstream.to_string(),
+        "consecutive_events": events,
+    });
+    post_webhook(webhook_url, payload).await;
 }
 
-#[derive(Debug, Deserialize)]
-struct CratesIoIndexRepo {
+// 硬重置之前验证上游从分叉点到 upstream 的每个提交，而不只是 upstream 本身
+fn verify_divergent_commits(repo: &Repository, upstream: git2::Oid) -> Result<(), String> {
+    let local = repo.refname_to_id("refs/heads/master").map_err(|e| e.to_string())?;
+    git::verify_commit_range(repo, local, upstream)
+}
+
+// repo.require_signed_commits 拒绝了上游的更新
+async fn notify_unverified(webhook_url: String, git_url: String, local: Option<Oid>, upstream: Oid) {
+    let payload = serde_json::json!({
+        "event": "signature_verification_failed",
+        "git_url": git_url,
+        "local": local.map(|oid| oid.to_string()),
+        "upstream": upstream.to_string(),
+    });
+    post_webhook(webhook_url, payload).await;
+}
+
+// 结果同时记录日志，失败时只警告，不影响启动
+fn check_connectivity(git_url: &str, auth: &git::GitAuth) -> Result<usize, String> {
+    match git::check_connectivity(git_url, auth) {
+        Ok(refs) => {
+            info!("Upstream {} is reachable ({} refs)", config::redact_url(git_url), refs);
+            Ok(refs)
+        }
+        Err(e) => {
+            warn!("Upstream {} is not reachable: {}", config::redact_url(git_url), e);
+            Err(e.to_string())
+        }
+    }
+}
+
+// 定时 pull 用到的配置和状态，update 在 spawn_blocking 里运行
+struct IndexUpdater {
     git_url: String,
-    path: String,
-    update_interval: u64,
+    repo_path: String,
+    git_auth: git::GitAuth,
+    max_non_ff_events: u32,
+    non_ff_strategy: NonFfStrategy,
+    non_ff_webhook_url: Option<String>,
+    verify_commit_signatures: bool,
+    require_signed_commits: bool,
+    signature_webhook_url: Option<String>,
+    cleanup_on_disk_full: bool,
+    post_pull_hooks: Vec<String>,
+    hook_timeout: Duration,
+    prefetch_top_n: Option<usize>,
+    health: web::Data<health::HealthState>,
+    pull_timings: Arc<pull_timing::PullTimings>,
+    replicator: Arc<replication::Replicator>,
+    notifier: Notifier,
+    state: Mutex<UpdateState>,
+}
+
+// 在多次更新之间保留的状态
+struct UpdateState {
+    breaker: circuit_breaker::CircuitBreaker,
+    // 连续无法 fast-forward 的次数
+    non_ff_events: u32,
+}
+
+impl IndexUpdater {
+    fn new(
+        config: &Config,
+        git_auth: git::GitAuth,
+        prefetch_top_n: Option<usize>,
+        health: web::Data<health::HealthState>,
+        pull_timings: Arc<pull_timing::PullTimings>,
+        replicator: Arc<replication::Replicator>,
+        notifier: Notifier,
+    ) -> Self {
+        IndexUpdater {
+            git_url: config.repo.git_url.clone(),
+            repo_path: config.repo.path.clone(),
+            git_auth,
+            max_non_ff_events: config.repo.max_non_ff_events,
+            non_ff_strategy: config.repo.non_ff_strategy(),
+            non_ff_webhook_url: config.repo.non_ff_webhook_url.clone(),
+            verify_commit_signatures: config.repo.verify_commit_signatures,
+            require_signed_commits: config.repo.require_signed_commits,
+            signature_webhook_url: config.repo.signature_webhook_url.clone(),
+            cleanup_on_disk_full: config.repo.cleanup_on_disk_full,
+            post_pull_hooks: config.repo.post_pull_hooks.clone(),
+            hook_timeout: Duration::from_secs(config.repo.hook_timeout_secs),
+            prefetch_top_n,
+            health,
+            pull_timings,
+            replicator,
+            notifier,
+            state: Mutex::new(UpdateState {
+                breaker: circuit_breaker::CircuitBreaker::new(
+                    config.repo.cb_failure_threshold,
+                    Duration::from_secs(config.repo.cb_open_duration_secs),
+                ),
+                non_ff_events: 0,
+            }),
+        }
+    }
+
+    // 阻塞调用：拿仓库锁、pull、发布快照和记录事件都在这里
+    fn update(&self) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        if !state.breaker.allow() {
+            return;
+        }
+        info!("Pulling repository updates...");
+        let Ok(repo) = Repository::open(&self.repo_path) else {
+            return;
+        };
+        // 另一个实例正在使用这个仓库时跳过这一次更新
+        let guard = match self.replicator.repo_lock.lock() {
+            Ok(guard) => guard,
+            Err(e) => {
+                warn!("Skipping update: {}", e);
+                return;
+            }
+        };
+        // 上次检出失败留下的不完整工作区，先尝试恢复
+        if !self.health.is_available() && self.cleanup_on_disk_full {
+            cleanup_checkout(&repo, &self.health, &self.notifier);
+        }
+        let pull_start = Instant::now();
+        let pull_result = pull_repo(&repo, &self.git_url, &self.git_auth, self.require_signed_commits);
+        let pull_duration = pull_start.elapsed();
+        metrics::INDEX_PULL_DURATION_SECONDS.observe(pull_duration);
+        self.pull_timings.record(pull_duration);
+        let pull_failed = pull_result.is_err();
+        let outcome = match pull_result {
+            Ok(PullOutcome::NonFastForward { upstream }) => {
+                state.non_ff_events += 1;
+                metrics::NON_FAST_FORWARD_EVENTS_TOTAL.inc(self.non_ff_strategy.as_str());
+                if self.non_ff_strategy == NonFfStrategy::LogOnly {
+                    warn!("Non-fast-forward update to {} ({} in a row)", upstream, state.non_ff_events);
+                    if let Some(url) = &self.non_ff_webhook_url {
+                        let local = repo.head().ok().and_then(|head| head.target());
+                        tokio::spawn(notify_non_ff(url.clone(), self.git_url.clone(), local, upstream, state.non_ff_events));
+                    }
+                } else if state.non_ff_events < self.max_non_ff_events {
+                    warn!("Non-fast-forward update {}/{}", state.non_ff_events, self.max_non_ff_events);
+                } else if self.non_ff_strategy == NonFfStrategy::Skip {
+                    warn!(
+                        "Mirror has diverged from upstream for {} updates, set repo.non_ff_strategy = \"reset_hard\" to recover automatically",
+                        state.non_ff_events
+                    );
+                } else if let Some(Err(e)) = (self.verify_commit_signatures || self.require_signed_commits)
+                    .then(|| verify_divergent_commits(&repo, upstream))
+                {
+                    error!("Refusing to hard reset to upstream {}: {}", upstream, e);
+                } else {
+                    match git::move_master(&repo, upstream, "Hard reset to upstream") {
+                        Ok(old) => {
+                            warn!("Hard reset master from {} to upstream {}", old, upstream);
+                            state.non_ff_events = 0;
+                            self.health.set_available(true);
+                        }
+                        Err(e) => {
+                            error!("Failed to hard reset to upstream {}: {}", upstream, e);
+                            handle_git_error(&repo, &e, self.cleanup_on_disk_full, &self.health, &self.notifier);
+                        }
+                    }
+                }
+                Some(PullOutcome::NonFastForward { upstream })
+            }
+            // 保持当前的索引，下次 pull 时重新验证
+            Ok(PullOutcome::Unverified { upstream }) => {
+                metrics::SIGNATURE_VERIFICATION_FAILURES_TOTAL.inc();
+                if let Some(url) = &self.signature_webhook_url {
+                    let local = repo.head().ok().and_then(|head| head.target());
+                    tokio::spawn(notify_unverified(url.clone(), self.git_url.clone(), local, upstream));
+                }
+                None
+            }
+            Ok(outcome) => {
+                state.non_ff_events = 0;
+                if let PullOutcome::FastForward { old, new } = outcome {
+                    self.health.set_available(true);
+                    if let Err(e) = index_check::check_changed_files(&repo, old, new) {
+                        warn!("Failed to check changed index files: {}", e);
+                    }
+                }
+                Some(outcome)
+            }
+            Err(e) => {
+                error!("Failed to pull repository: {}", e);
+                self.notifier.alert(AlertKind::PullFailure, &format!("Failed to pull {}: {}", self.git_url, e));
+                handle_git_error(&repo, &e, self.cleanup_on_disk_full, &self.health, &self.notifier);
+                None
+            }
+        };
+        // 无法 fast-forward 或签名验证失败时上游是可以访问的，不算失败
+        if pull_failed {
+            state.breaker.record_failure();
+        } else {
+            state.breaker.record_success();
+        }
+        if outcome.is_some() {
+            if let Err(e) = self.replicator.serving_root.publish(&repo) {
+                error!("Failed to publish index snapshot: {}", e);
+            }
+            if let Some(events) = &self.replicator.events {
+                events.record(&repo);
+            }
+        }
+        drop(guard);
+        if let Some(outcome) = outcome {
+            self.health.record_update();
+            // 更新后快照目录或者文件变了，旧的页缓存不再有用
+            if let (Some(top_n), false) = (self.prefetch_top_n, outcome == PullOutcome::UpToDate) {
+                let root = self.replicator.serving_root.get();
+                tokio::task::spawn_blocking(move || prefetch::prefetch_popular(&root, top_n));
+            }
+            if let PullOutcome::FastForward { old, new } = outcome {
+                if self.notifier.has_channels() {
+                    let changed_crates = hooks::changed_crates(Path::new(&self.repo_path), old, new)
+                        .map_or_else(
+                            |e| {
+                                warn!("Failed to count changed crates: {}", e);
+                                0
+                            },
+                            |crates| crates.len(),
+                        );
+                    self.notifier.pull_succeeded(&notifications::PullResult {
+                        old,
+                        new,
+                        changed_crates,
+                        time: Local::now(),
+                    });
+                }
+                if !self.replicator.config.peers.is_empty() {
+                    tokio::spawn(replication::push_to_peers(Arc::clone(&self.replicator), old, new));
+                }
+                if !self.post_pull_hooks.is_empty() {
+                    tokio::spawn(hooks::run_post_pull_hooks(
+                        self.post_pull_hooks.clone(),
+                        self.repo_path.clone().into(),
+                        old,
+                        new,
+                        self.hook_timeout,
+                    ));
+                }
+            }
+        }
+    }
+}
+
+// 旧 server 暂停 accept 到开始关闭 worker 之间的间隔
+const RELOAD_HANDOFF_DELAY: Duration = Duration::from_millis(500);
+
+// web.worker_recycle_interval_secs 为 0 时不回收
+fn worker_recycle_interval(config: &Config) -> Option<time::Interval> {
+    let period = Duration::from_secs(config.web.worker_recycle_interval_secs);
+    (!period.is_zero()).then(|| time::interval_at(time::Instant::now() + period, period))
+}
+
+async fn recycle_tick(interval: &mut Option<time::Interval>) {
+    match interval {
+        Some(interval) => {
+            interval.tick().await;
+        }
+        None => std::future::pending().await,
+    }
 }
 
-#[derive(Debug, Deserialize)]
-struct WebConfig {
-    address: String,
-    port: u16,
+fn init_tracing(debug: &config::DebugConfig) {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    #[cfg(feature = "tokio-console")]
+    if debug.tokio_console {
+        use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, Layer};
+        let addr: std::net::SocketAddr = debug.tokio_console_bind.parse().expect("validated by Config::validate");
+        // console 需要 tokio 的 trace 级别事件，EnvFilter 只用于日志输出
+        let fmt = tracing_subscriber::fmt::layer()
+            .with_timer(LocalTimer)
+            .with_target(false)
+            .with_filter(filter);
+        let console = console_subscriber::ConsoleLayer::builder().server_addr(addr).spawn();
+        tracing_subscriber::registry().with(console).with(fmt).init();
+        info!("tokio-console listening on {}", addr);
+        return;
+    }
+    tracing_subscriber::fmt()
+        .with_timer(LocalTimer)
+        .with_target(false)
+        .with_env_filter(filter)
+        .init();
+    #[cfg(not(feature = "tokio-console"))]
+    if debug.tokio_console {
+        warn!("debug.tokio_console is set but this build does not include the tokio-console feature");
+    }
 }
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
+    let command = cli::parse_args(std::env::args().skip(1));
+    // subscriber 只能安装一次，需要先读取 [debug]；配置文件的错误在下面正式加载时报告
+    let debug = match &command {
+        Ok(cli::Command::Serve {
+            config_path,
+            config_format,
+        }) => Config::load(config_path, *config_format).map(|config| config.debug).unwrap_or_default(),
+        _ => Default::default(),
+    };
+    init_tracing(&debug);
+    panic_recovery::install_panic_hook();
+    let (config_path, config_format, check_only) = match command {
+        Ok(cli::Command::Serve {
+            config_path,
+            config_format,
+        }) => (config_path, config_format, false),
+        Ok(cli::Command::Check {
+            config_path,
+            config_format,
+        }) => (config_path, config_format, true),
+        Ok(cli::Command::ConvertConfig { from, to }) => {
+            config::convert_config(&from, &to)
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+            info!("Converted {} to {}", from, to);
+            return Ok(());
+        }
+        Ok(cli::Command::Init(args)) => {
+            init::run(args).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+            return Ok(());
+        }
+        Ok(cli::Command::Help) => {
+            println!("{}", cli::USAGE);
+            return Ok(());
+        }
+        Err(e) => {
+            eprintln!("{}\n{}", e, cli::USAGE);
+            std::process::exit(2);
+        }
+    };
     // 读取配置文件
-    let config_str = std::fs::read_to_string("config.toml").expect("Failed to read config.toml");
-    let config: Config = toml::from_str(&config_str).expect("Failed to parse config.toml");
+    let mut config = Config::load(&config_path, config_format).unwrap_or_else(|e| panic!("{}", e));
+    if config.logging.log_config_on_startup {
+        config::log_effective_config(&config);
+    }
+    metrics::init(&config.app, &config.metrics);
+    if config.web.security_headers.hsts_preload {
+        warn!("web.security_headers.hsts_preload is enabled: once this domain is submitted to the HSTS preload list, browsers will refuse plain HTTP for it and all subdomains, and removal takes months");
+    }
+
+    git::set_trace_level(config.logging.git2_trace_level)
+        .unwrap_or_else(|e| panic!("Failed to set git2 trace level: {}", e));
+    let user_agent = config.repo.effective_user_agent();
+    git::set_user_agent(&user_agent).unwrap_or_else(|e| panic!("Failed to set git user agent: {}", e));
+    info!("Using git user agent {:?}", user_agent);
+    if let Some(path) = &config.repo.ssl_ca_cert {
+        git::set_ssl_ca_cert(path).unwrap_or_else(|e| panic!("Failed to load repo.ssl_ca_cert {}: {}", path, e));
+        info!("Trusting CA certificates from {}", path);
+    }
+    if !config.repo.ssl_verify {
+        warn!("repo.ssl_verify is disabled: TLS certificates of {} are NOT verified and the index can be tampered with in transit", config::redact_url(&config.repo.git_url));
+    }
 
     // 初始化或更新git仓库
+    git::set_sparse_checkout(&config.repo);
+    git::set_trusted_keys(&config.repo).unwrap_or_else(|e| panic!("{}", e));
+    let git_auth = git::GitAuth::from_config(&config.repo);
+    if check_only {
+        let ok = check_connectivity(&config.repo.git_url, &git_auth).is_ok();
+        std::process::exit(if ok { 0 } else { 1 });
+    }
     let repo_path = Path::new(&config.repo.path);
-    // 如果目录存在，直接使用
-    if repo_path.exists() {
-        println!("[{}] Using existing directory at {:?}", Local::now().format("%Y-%m-%d %H:%M:%S"), repo_path);
+    let serving_root = Arc::new(snapshot::ServingRoot::new(
+        repo_path,
+        config.repo.atomic_checkout,
+        config.repo.preserve_mtime,
+    ));
+    let health = web::Data::new(health::HealthState::new());
+    // 如果目录存在，直接使用；否则在后台 clone，web 服务先启动，clone 完成之前索引返回 503
+    // 空目录也需要 clone（例如上次 clone 失败），libgit2 可以 clone 到空目录里
+    let needs_clone = std::fs::read_dir(repo_path).map_or(true, |mut entries| entries.next().is_none());
+    if needs_clone {
+        // 静态文件服务在启动时 canonicalize 目录，目录不存在时会退化成当前目录
+        std::fs::create_dir_all(repo_path)?;
+        health.set_available(false);
     } else {
-        println!("[{}] Cloning repository...", Local::now().format("%Y-%m-%d %H:%M:%S"));
-        clone_repo(&config.repo.git_url, repo_path);
+        info!("Using existing directory at {:?}", repo_path);
+        if let Err(e) = Repository::open(repo_path)
+            .map_err(|e| e.to_string())
+            .and_then(|repo| serving_root.publish(&repo))
+        {
+            panic!("Failed to publish index snapshot: {}", e);
+        }
+        health.set_started();
     }
 
-    // 启动定时pull任务
-    let address = config.web.address.clone();
-    let port = config.web.port;
-    println!("[{}] Starting web server on {}:{}", Local::now().format("%Y-%m-%d %H:%M:%S"), address, port);
-
-    // 启动web服务
-    let static_path = Arc::new(config.repo.path.clone());
-    let static_path_clone = Arc::clone(&static_path);
+    let events = config
+        .events
+        .log_path
+        .as_ref()
+        .map(|path| events::EventLog::open(path, repo_path, config.events.max_events).map(Arc::new))
+        .transpose()
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+    let pull_timings = Arc::new(pull_timing::PullTimings::new(config.stats.timing_window_size));
+    let activity = web::Data::new(idle::Activity::new(Duration::from_secs(config.repo.idle_threshold_secs)));
+    let notifier = Notifier::new(&config.notifications, &config.app)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
 
     // 启动定时pull任务
     let git_url = config.repo.git_url.clone();
     let repo_path = config.repo.path.clone();
     let update_interval = config.repo.update_interval;
+    let update_schedule = config.repo.update_schedule().unwrap_or_else(|e| panic!("{}", e));
+    let connectivity_check = config.repo.connectivity_check_on_startup;
+    let idle_multiplier = config.repo.idle_pull_interval_multiplier;
+    let activity_clone = activity.clone();
+    let prefetch_top_n = config.web.prefetch_popular_crates.then_some(config.web.prefetch_top_n);
+    let health_clone = health.clone();
+    let repo_lock = Arc::new(git::RepoLock::new(config.repo.lock_file_path()));
+    let replicator = Arc::new(replication::Replicator {
+        config: config.replication.clone(),
+        repo_path: config.repo.path.clone().into(),
+        repo_lock: Arc::clone(&repo_lock),
+        serving_root: Arc::clone(&serving_root),
+        events: events.clone(),
+        require_signed_commits: config.repo.require_signed_commits,
+        health: health.clone(),
+    });
+    let updater = Arc::new(IndexUpdater::new(
+        &config,
+        git_auth.clone(),
+        prefetch_top_n,
+        health.clone(),
+        Arc::clone(&pull_timings),
+        Arc::clone(&replicator),
+        notifier,
+    ));
+    if config.repo.stats_interval_secs > 0 {
+        tokio::spawn(repo_stats::run(
+            repo_path.clone().into(),
+            Arc::clone(&repo_lock),
+            Duration::from_secs(config.repo.stats_interval_secs),
+        ));
+    }
+    if config.repo.gc_enabled {
+        tokio::spawn(gc::run(
+            repo_path.clone().into(),
+            Arc::clone(&repo_lock),
+            Duration::from_secs(config.repo.gc_interval_secs),
+        ));
+    }
+    if config.repo.pack_monitor_enabled {
+        tokio::spawn(gc::monitor_packs(
+            repo_path.clone().into(),
+            Arc::clone(&repo_lock),
+            config.repo.max_pack_files,
+            Duration::from_secs(config.repo.pack_monitor_interval_secs),
+        ));
+    }
     tokio::spawn(async move {
+        // 只是提前发现问题，无论结果如何都继续 clone 或定时 pull
+        if connectivity_check {
+            let (url, auth, health) = (git_url.clone(), git_auth.clone(), health_clone.clone());
+            let _ = tokio::task::spawn_blocking(move || match check_connectivity(&url, &auth) {
+                Ok(refs) => health.set_connectivity(true, format!("listed {} refs", refs)),
+                Err(e) => health.set_connectivity(false, e),
+            })
+            .await;
+        }
+        if needs_clone {
+            info!("Cloning repository...");
+            let (url, path, auth) = (git_url.clone(), repo_path.clone(), git_auth.clone());
+            let replicator = Arc::clone(&replicator);
+            let cloned = tokio::task::spawn_blocking(move || {
+                let _guard = replicator.repo_lock.lock().map_err(|e| e.to_string())?;
+                let repo = clone_repo(&url, Path::new(&path), &auth).map_err(|e| e.to_string())?;
+                replicator.publish(&repo)
+            })
+            .await
+            .unwrap_or_else(|e| Err(e.to_string()));
+            if let Err(e) = cloned {
+                error!("Failed to clone repository: {}", e);
+                updater.notifier.alert(AlertKind::PullFailure, &format!("Failed to clone {}: {}", git_url, e));
+                return;
+            }
+            info!("Initial clone completed");
+            health_clone.set_started();
+            health_clone.set_available(true);
+        }
+        if let Some(top_n) = prefetch_top_n {
+            let root = replicator.serving_root.get();
+            tokio::task::spawn_blocking(move || prefetch::prefetch_popular(&root, top_n));
+        }
         let mut interval = time::interval(Duration::from_secs(update_interval)); // 每小时pull一次
         loop {
-            interval.tick().await;
-            println!("[{}] Pulling repository updates...", Local::now().format("%Y-%m-%d %H:%M:%S"));
-            if let Ok(repo) = Repository::open(&repo_path) {
-                pull_repo(&repo, &git_url);
+            match &update_schedule {
+                // 每次都根据当前时间重新计算下一次执行时间
+                Some(schedule) => {
+                    let Some(next) = schedule.upcoming(Local).next() else {
+                        info!("No upcoming time in repo.update_cron, stopping scheduled updates");
+                        return;
+                    };
+                    let wait = (next - Local::now()).to_std().unwrap_or_default();
+                    time::sleep(wait).await;
+                }
+                None if idle_multiplier > 1.0 && activity_clone.is_idle() => {
+                    let wait = interval.period().mul_f64(idle_multiplier);
+                    info!("No recent requests, next update in {}s", wait.as_secs());
+                    tokio::select! {
+                        _ = time::sleep(wait) => {}
+                        _ = activity_clone.resumed() => info!("Traffic resumed, pulling now"),
+                    }
+                    interval.reset();
+                }
+                None => {
+                    interval.tick().await;
+                }
+            }
+            // pull、发布快照和检查变更都是阻塞的，不能放在 actix 的单线程 runtime 上
+            let updater = Arc::clone(&updater);
+            if let Err(e) = tokio::task::spawn_blocking(move || updater.update()).await {
+                error!("Index update task failed: {}", e);
+            }
+            if let Some(next) = update_schedule.as_ref().and_then(|s| s.upcoming(Local).next()) {
+                info!("Next scheduled update at {}", next.format("%Y-%m-%d %H:%M:%S"));
             }
         }
     });
 
-    let server = HttpServer::new(move || {
-        App::new().service(Files::new("/", &*static_path_clone).show_files_listing())
-    })
-    .workers(8)
-    .bind((address.clone(), port))?;
+    // 启动web服务
+    let (generation_tx, generation_rx) = watch::channel(0u64);
+    let (reload_tx, mut reload_rx) = mpsc::channel::<()>(1);
+    let state = server::SharedState {
+        health,
+        repo_lock,
+        reload_tx,
+        active_generation: generation_rx,
+        serving_root,
+        events,
+        pull_timings,
+        activity,
+        slo: web::Data::new(slo::SloMonitor::new(&config.slo)),
+        version_cache: web::Data::new(api::VersionCache::default()),
+        features_cache: web::Data::new(api::VersionCache::default()),
+    };
+    if state.slo.is_enabled() {
+        tokio::spawn(slo::monitor(state.slo.clone()));
+    }
+    if config.web.watch_index_files {
+        if config.repo.atomic_checkout {
+            warn!("web.watch_index_files has no effect with repo.atomic_checkout, index files are served from snapshots");
+        } else {
+            let caches = index_watch::IndexCaches {
+                versions: state.version_cache.clone(),
+                features: state.features_cache.clone(),
+            };
+            let root = Path::new(&config.repo.path).to_path_buf();
+            tokio::spawn(index_watch::watch(root, caches));
+        }
+    }
+    if let Some(port) = config.web.grpc_health_port {
+        grpc_health::start(&config.web, port, state.health.clone())?;
+    }
+    let mut generation = 0;
+    let mut listeners = listen::Listeners::new();
+    let mut server = server::start(&config, &state, generation, &mut listeners)?;
+    let mut reload_signal = ReloadSignal::new()?;
+    let mut shutdown_signal = ShutdownSignal::new()?;
+    let mut recycle_interval = worker_recycle_interval(&config);
 
-    println!("[{}] Web server started at http://{}:{}", Local::now().format("%Y-%m-%d %H:%M:%S"), address, port);
+    loop {
+        let recycle = tokio::select! {
+            result = &mut server => {
+                if let Err(e) = result {
+                    error!("服务器异常关闭: {}", e);
+                } else {
+                    info!("服务器正常关闭");
+                }
+                break;
+            }
+            name = shutdown_signal.recv() => {
+                info!(
+                    "收到 {}，正在优雅关闭 web server，排空 {} 个连接（最多 {}s）...",
+                    name,
+                    server::open_connections(),
+                    config.app.graceful_shutdown_timeout_secs
+                );
+                // stop 需要 server future 继续被 poll；超过 graceful_shutdown_timeout_secs 的连接被强制关闭
+                let handle = server.handle();
+                let (_, result) = tokio::join!(handle.stop(true), &mut server);
+                if let Err(e) = result {
+                    error!("服务器异常关闭: {}", e);
+                }
+                break;
+            }
+            _ = reload_signal.recv() => false,
+            _ = reload_rx.recv() => false,
+            _ = recycle_tick(&mut recycle_interval) => true,
+        };
 
-    let server = server.run();
-    tokio::select! {
-        result = server => {
-            if let Err(e) = result {
-                println!("[{}] 服务器异常关闭: {}", Local::now().format("%Y-%m-%d %H:%M:%S"), e);
-            } else {
-                println!("[{}] 服务器正常关闭", Local::now().format("%Y-%m-%d %H:%M:%S"));
+        // 重新加载配置：先用新配置启动新的 server（复用同一个监听 socket），再排空旧的 server
+        // 回收 worker 的流程相同，只是继续使用当前的配置
+        let new_server = if recycle {
+            info!("Recycling {} web server workers...", config.web.workers);
+            server::start(&config, &state, generation + 1, &mut listeners)
+                .map(|new_server| (new_server, None))
+                .map_err(|e| e.to_string())
+        } else {
+            info!("Reloading {}...", config_path);
+            Config::load(&config_path, config_format).and_then(|new_config| {
+                server::start(&new_config, &state, generation + 1, &mut listeners)
+                    .map(|new_server| (new_server, Some(new_config)))
+                    .map_err(|e| e.to_string())
+            })
+        };
+        match new_server {
+            Ok((new_server, new_config)) => {
+                generation += 1;
+                generation_tx.send_replace(generation);
+                let old_server = std::mem::replace(&mut server, new_server);
+                if let Some(new_config) = new_config {
+                    config = new_config;
+                    recycle_interval = worker_recycle_interval(&config);
+                }
+                if recycle {
+                    metrics::WORKER_RECYCLES_TOTAL.inc();
+                }
+                info!("Server generation {} started, draining the previous server", generation);
+                // Server future 需要继续被 poll 才能处理 stop 命令
+                let old_handle = old_server.handle();
+                actix_web::rt::spawn(old_server);
+                actix_web::rt::spawn(async move {
+                    // 先暂停 accept，共享的监听 socket 之后只由新的 server accept；
+                    // worker 开始关闭后会丢弃还在队列中的连接，所以等已经 accept 的连接交给 worker 后再 stop
+                    old_handle.pause().await;
+                    actix_web::rt::time::sleep(RELOAD_HANDOFF_DELAY).await;
+                    old_handle.stop(true).await;
+                    info!("Previous server drained and stopped");
+                });
             }
-        }
-        _ = signal::ctrl_c() => {
-            println!("[{}] 收到终止信号，正在优雅关闭 web server...", Local::now().format("%Y-%m-%d %H:%M:%S"));
+            Err(e) if recycle => error!("Failed to recycle workers, keeping the current server: {}", e),
+            Err(e) => error!("Reload failed, keeping the current server: {}", e),
         }
     }
 
-    println!("[{}] Web server优雅关闭完成", Local::now().format("%Y-%m-%d %H:%M:%S"));
+    info!("Web server优雅关闭完成");
 
     Ok(())
 }
 
-fn clone_repo(url: &str, path: &Path) -> Repository {
-    let mut callbacks = RemoteCallbacks::new();
-    callbacks.credentials(|_url, username_from_url, allowed_types| {
-        if allowed_types.contains(git2::CredentialType::SSH_KEY) {
-            let home_dir = dirs::home_dir().expect("Failed to get home directory");
-            let private_key = home_dir.join(".ssh").join("id_rsa");
-            let public_key = home_dir.join(".ssh").join("id_rsa.pub");
-            git2::Cred::ssh_key(
-                username_from_url.unwrap_or("git"),
-                Some(&public_key),
-                &private_key,
-                None,
-            )
-        } else {
-            git2::Cred::default()
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{self, index_line, Upstream};
+    use std::fs;
+
+    fn updater(config: &Config) -> IndexUpdater {
+        let health = web::Data::new(health::HealthState::new());
+        let serving_root = Arc::new(snapshot::ServingRoot::new(
+            &config.repo.path,
+            config.repo.atomic_checkout,
+            false,
+        ));
+        let replicator = Arc::new(replication::Replicator {
+            config: config.replication.clone(),
+            repo_path: config.repo.path.clone().into(),
+            repo_lock: Arc::new(git::RepoLock::new(config.repo.lock_file_path())),
+            serving_root,
+            events: None,
+            require_signed_commits: config.repo.require_signed_commits,
+            health: health.clone(),
+        });
+        let notifier = Notifier::new(&config.notifications, &config.app).unwrap();
+        IndexUpdater::new(
+            config,
+            git::GitAuth::from_config(&config.repo),
+            None,
+            health,
+            Arc::new(pull_timing::PullTimings::new(8)),
+            replicator,
+            notifier,
+        )
+    }
+
+    fn head(path: &str) -> Oid {
+        Repository::open(path).unwrap().refname_to_id("refs/heads/master").unwrap()
+    }
+
+    // 上游重写历史：旧的提交不再是新 master 的祖先
+    fn diverged(repo_extra: &str) -> (Upstream, tempfile::TempDir, Config, Oid, Oid) {
+        let upstream = Upstream::new();
+        let serde = index_line("serde", "1.0.0");
+        let old = upstream.commit("add serde", &[("se/rd/serde", Some(&serde)), ("1/a", Some(&index_line("a", "0.1.0")))]);
+        let dir = tempfile::tempdir().unwrap();
+        test_support::mirror(&upstream, dir.path());
+        let new = upstream.rewrite("squash", &[("se/rd/serde", Some(&serde)), ("1/a", None)]);
+        let config = test_support::config(&upstream.url(), &dir.path().join("index"), repo_extra, "");
+        (upstream, dir, config, old, new)
+    }
+
+    #[test]
+    fn fast_forward() {
+        let upstream = Upstream::new();
+        upstream.commit("add a", &[("1/a", Some(&index_line("a", "0.1.0")))]);
+        let dir = tempfile::tempdir().unwrap();
+        test_support::mirror(&upstream, dir.path());
+        let new = upstream.commit("add b", &[("1/b", Some(&index_line("b", "0.1.0")))]);
+        let config = test_support::config(&upstream.url(), &dir.path().join("index"), "", "");
+        updater(&config).update();
+        assert_eq!(head(&config.repo.path), new);
+        assert!(dir.path().join("index/1/b").is_file());
+    }
+
+    #[test]
+    fn reset_hard_after_max_events() {
+        let (_upstream, dir, config, old, new) =
+            diverged("non_ff_strategy = \"reset_hard\"\nmax_non_ff_events = 2");
+        let updater = updater(&config);
+        updater.update();
+        assert_eq!(head(&config.repo.path), old);
+        assert_eq!(updater.state.lock().unwrap().non_ff_events, 1);
+        updater.update();
+        assert_eq!(head(&config.repo.path), new);
+        assert_eq!(updater.state.lock().unwrap().non_ff_events, 0);
+        // 工作区和上游一致，上游删掉的文件也被删除
+        assert!(!dir.path().join("index/1/a").exists());
+        let repo = Repository::open(&config.repo.path).unwrap();
+        assert!(repo.statuses(None).unwrap().is_empty());
+        assert_eq!(
+            fs::read_to_string(dir.path().join("index/se/rd/serde")).unwrap(),
+            index_line("serde", "1.0.0")
+        );
+    }
+
+    #[test]
+    fn allow_hard_reset_defaults_to_reset_hard() {
+        let (_upstream, _dir, config, _old, new) = diverged("allow_hard_reset = true\nmax_non_ff_events = 1");
+        updater(&config).update();
+        assert_eq!(head(&config.repo.path), new);
+    }
+
+    #[test]
+    fn skip_keeps_local_history() {
+        let (_upstream, _dir, config, old, _new) = diverged("non_ff_strategy = \"skip\"\nmax_non_ff_events = 1");
+        let updater = updater(&config);
+        for _ in 0..3 {
+            updater.update();
         }
-    });
+        assert_eq!(head(&config.repo.path), old);
+        assert_eq!(updater.state.lock().unwrap().non_ff_events, 3);
+    }
 
-    let mut fetch_options = FetchOptions::new();
-    fetch_options.remote_callbacks(callbacks);
+    #[test]
+    fn log_only_keeps_local_history() {
+        let (_upstream, _dir, config, old, _new) = diverged("non_ff_strategy = \"log_only\"\nmax_non_ff_events = 1");
+        let updater = updater(&config);
+        updater.update();
+        updater.update();
+        assert_eq!(head(&config.repo.path), old);
+    }
 
-    let mut builder = git2::build::RepoBuilder::new();
-    builder.fetch_options(fetch_options);
+    #[test]
+    fn reset_hard_refuses_unsigned_upstream() {
+        let (_upstream, _dir, config, old, _new) = diverged(
+            "non_ff_strategy = \"reset_hard\"\nmax_non_ff_events = 1\nverify_commit_signatures = true",
+        );
+        updater(&config).update();
+        assert_eq!(head(&config.repo.path), old);
+    }
 
-    builder
-        .clone(url, path)
-        .expect("Failed to clone repository")
-}
+    // 1 MB 的 tmpfs，需要 root；没有权限时返回 None，测试跳过
+    struct Tmpfs(tempfile::TempDir);
+
+    impl Tmpfs {
+        fn mount() -> Option<Tmpfs> {
+            let dir = tempfile::tempdir().unwrap();
+            let mounted = std::process::Command::new("mount")
+                .args(["-t", "tmpfs", "-o", "size=1m", "tmpfs"])
+                .arg(dir.path())
+                .stderr(std::process::Stdio::null())
+                .status()
+                .is_ok_and(|status| status.success());
+            if !mounted {
+                eprintln!("skipping: cannot mount a tmpfs");
+                return None;
+            }
+            Some(Tmpfs(dir))
+        }
+    }
 
-fn pull_repo(repo: &Repository, url: &str) {
-    let mut callbacks = RemoteCallbacks::new();
-    callbacks.credentials(|_url, _username_from_url, _allowed_types| git2::Cred::default());
+    impl Drop for Tmpfs {
+        fn drop(&mut self) {
+            let _ = std::process::Command::new("umount").arg(self.0.path()).status();
+        }
+    }
 
-    let mut fetch_options = FetchOptions::new();
-    fetch_options.remote_callbacks(callbacks);
+    // 压缩后很小、检出后超过 1 MB 的文件：fetch 成功，检出时磁盘满
+    fn disk_full(repo_extra: &str) -> Option<(Upstream, Tmpfs, Config, Oid)> {
+        let tmpfs = Tmpfs::mount()?;
+        let upstream = Upstream::new();
+        let old = upstream.commit("add a", &[("1/a", Some(&index_line("a", "0.1.0")))]);
+        test_support::mirror(&upstream, tmpfs.0.path());
+        let huge = index_line("b", "0.1.0").repeat(20_000);
+        upstream.commit("add b", &[("1/b", Some(&huge))]);
+        let config = test_support::config(&upstream.url(), &tmpfs.0.path().join("index"), repo_extra, "");
+        Some((upstream, tmpfs, config, old))
+    }
 
-    let mut remote = repo
-        .find_remote("origin")
-        .unwrap_or_else(|_| repo.remote("origin", url).expect("Failed to create remote"));
+    #[test]
+    fn disk_full_cleans_up_partial_checkout() {
+        let Some((_upstream, tmpfs, config, old)) = disk_full("") else {
+            return;
+        };
+        let (logs, _guard) = test_support::capture_logs();
+        let updater = updater(&config);
+        updater.update();
+        assert!(logs.contents().contains("Disk full while updating the index"), "{}", logs.contents());
+        assert!(logs.contents().contains("Cleaned up partial checkout"));
+        // 回到旧的提交，写了一半的文件被删除
+        assert_eq!(head(&config.repo.path), old);
+        assert!(!tmpfs.0.path().join("index/1/b").exists());
+        assert!(tmpfs.0.path().join("index/1/a").is_file());
+        assert!(Repository::open(&config.repo.path).unwrap().statuses(None).unwrap().is_empty());
+        assert!(updater.health.is_available());
+    }
 
-    remote
-        .fetch(
-            &["refs/heads/*:refs/heads/*"],
-            Some(&mut fetch_options),
-            None,
-        )
-        .expect("Failed to fetch");
-
-    let fetch_head = repo
-        .find_reference("FETCH_HEAD")
-        .expect("Failed to find FETCH_HEAD");
-    let fetch_commit = repo
-        .reference_to_annotated_commit(&fetch_head)
-        .expect("Failed to get commit from FETCH_HEAD");
-
-    let analysis = repo
-        .merge_analysis(&[&fetch_commit])
-        .expect("Failed to analyze merge");
-
-    if analysis.0.is_up_to_date() {
-        println!("[{}] [{}] Already up-to-date", Local::now().format("%Y-%m-%d %H:%M:%S"), url);
-    } else if analysis.0.is_fast_forward() {
-        println!("[{}] [{}] Performing fast-forward merge", Local::now().format("%Y-%m-%d %H:%M:%S"), url);
-        let mut reference = repo
-            .find_reference("refs/heads/master")
-            .expect("Failed to find master branch");
-        reference
-            .set_target(fetch_commit.id(), "Fast-forward")
-            .expect("Failed to fast-forward");
-        repo.set_head("refs/heads/master")
-            .expect("Failed to set HEAD");
-        repo.checkout_head(Some(git2::build::CheckoutBuilder::default().force()))
-            .expect("Failed to checkout HEAD");
-    } else {
-        println!("[{}] [{}] Merge required but not implemented", Local::now().format("%Y-%m-%d %H:%M:%S"), url);
+    #[test]
+    fn disk_full_detected_from_free_space() {
+        let Some(tmpfs) = Tmpfs::mount() else {
+            return;
+        };
+        let filler = vec![0u8; 64 * 1024];
+        let mut file = fs::File::create(tmpfs.0.path().join("filler")).unwrap();
+        while std::io::Write::write_all(&mut file, &filler).is_ok() {}
+        // 德语 locale 下的 strerror(ENOSPC)
+        let error = git2::Error::new(
+            git2::ErrorCode::GenericError,
+            git2::ErrorClass::Os,
+            "failed to write file: Auf dem Gerät ist kein Speicherplatz mehr verfügbar",
+        );
+        assert!(git::is_disk_full(&error, tmpfs.0.path()));
+    }
+
+    #[test]
+    fn disk_full_without_cleanup_stays_unavailable() {
+        let Some((_upstream, _tmpfs, config, _old)) = disk_full("cleanup_on_disk_full = false") else {
+            return;
+        };
+        let (logs, _guard) = test_support::capture_logs();
+        let updater = updater(&config);
+        updater.update();
+        assert!(logs.contents().contains("Disk full while updating the index"));
+        assert!(!logs.contents().contains("Cleaned up partial checkout"));
+        assert!(!updater.health.is_available());
     }
 }
@@ -0,0 +1,508 @@
+use actix_web::HttpResponse;
+use cadence::{prelude::*, QueuingMetricSink, StatsdClient, UdpMetricSink};
+#[cfg(feature = "metrics")]
+use prometheus::{
+    register_histogram, register_int_counter, register_int_counter_vec, register_int_gauge,
+    register_int_gauge_vec, Encoder, Histogram, IntCounter, IntCounterVec, IntGauge, IntGaugeVec,
+    TextEncoder,
+};
+use std::{
+    collections::HashMap,
+    net::UdpSocket,
+    sync::{LazyLock, OnceLock},
+    time::Duration,
+};
+use tracing::{error, info};
+
+use crate::{
+    config::{AppConfig, MetricsConfig},
+    problem::{ProblemDetails, ProblemType},
+};
+
+// 可以通过 [metrics.buckets] 覆盖 bucket 的 histogram
+pub const HISTOGRAMS: &[&str] = &[
+    "http_request_duration_seconds",
+    "index_pull_duration_seconds",
+    "replication_push_latency_seconds",
+    "gc_duration_seconds",
+];
+
+#[cfg(feature = "metrics")]
+use prometheus::DEFAULT_BUCKETS;
+#[cfg(not(feature = "metrics"))]
+const DEFAULT_BUCKETS: &[f64] = &[];
+
+// git pull 和 gc 可能要几分钟，默认 bucket 最大只到 10 秒
+const PULL_DURATION_BUCKETS: &[f64] = &[1.0, 5.0, 30.0, 60.0, 300.0, 600.0];
+
+static BUCKET_OVERRIDES: OnceLock<HashMap<String, Vec<f64>>> = OnceLock::new();
+
+// 配置了 metrics.statsd_host 时，所有指标同时通过 UDP 发给 StatsD
+static STATSD: OnceLock<StatsdClient> = OnceLock::new();
+
+// 队列满或者发送失败时直接丢弃，不阻塞调用方
+const STATSD_QUEUE_CAPACITY: usize = 4096;
+
+fn statsd_client(config: &MetricsConfig) -> std::io::Result<StatsdClient> {
+    let socket = UdpSocket::bind("0.0.0.0:0")?;
+    socket.set_nonblocking(true)?;
+    let sink = UdpMetricSink::from((config.statsd_host.as_str(), config.statsd_port), socket)
+        .map_err(std::io::Error::other)?;
+    let sink = QueuingMetricSink::with_capacity(sink, STATSD_QUEUE_CAPACITY);
+    Ok(StatsdClient::from_sink(&config.statsd_prefix, sink))
+}
+
+// 同时更新 Prometheus 和 StatsD 的指标；没有 metrics feature 时只发送到 StatsD
+pub struct Counter {
+    name: &'static str,
+    #[cfg(feature = "metrics")]
+    inner: IntCounter,
+}
+
+impl Counter {
+    #[cfg_attr(not(feature = "metrics"), allow(unused_variables))]
+    fn register(name: &'static str, help: &str) -> Self {
+        Counter {
+            name,
+            #[cfg(feature = "metrics")]
+            inner: register_int_counter!(name, help).unwrap(),
+        }
+    }
+
+    pub fn inc(&self) {
+        #[cfg(feature = "metrics")]
+        self.inner.inc();
+        if let Some(statsd) = STATSD.get() {
+            let _ = statsd.count(self.name, 1);
+        }
+    }
+
+    pub fn inc_by(&self, n: u64) {
+        #[cfg(feature = "metrics")]
+        self.inner.inc_by(n);
+        if let Some(statsd) = STATSD.get() {
+            let _ = statsd.count(self.name, n as i64);
+        }
+    }
+}
+
+// StatsD 没有标签，标签值拼到指标名后面，例如 rate_limit_rejections_total.search
+pub struct CounterVec {
+    name: &'static str,
+    #[cfg(feature = "metrics")]
+    inner: IntCounterVec,
+}
+
+impl CounterVec {
+    #[cfg_attr(not(feature = "metrics"), allow(unused_variables))]
+    fn register(name: &'static str, help: &str, label: &str) -> Self {
+        CounterVec {
+            name,
+            #[cfg(feature = "metrics")]
+            inner: register_int_counter_vec!(name, help, &[label]).unwrap(),
+        }
+    }
+
+    pub fn inc(&self, label_value: &str) {
+        #[cfg(feature = "metrics")]
+        self.inner.with_label_values(&[label_value]).inc();
+        if let Some(statsd) = STATSD.get() {
+            let _ = statsd.count(&format!("{}.{}", self.name, label_value), 1);
+        }
+    }
+}
+
+// Prometheus 里是秒为单位的 histogram，StatsD 里是毫秒为单位的 timer
+pub struct Timing {
+    name: &'static str,
+    #[cfg(feature = "metrics")]
+    inner: Histogram,
+}
+
+impl Timing {
+    #[cfg_attr(not(feature = "metrics"), allow(unused_variables))]
+    fn register(name: &'static str, help: &str, default_buckets: &[f64]) -> Self {
+        Timing {
+            name,
+            #[cfg(feature = "metrics")]
+            inner: register_histogram!(name, help, buckets(name, default_buckets)).unwrap(),
+        }
+    }
+
+    pub fn observe(&self, duration: Duration) {
+        #[cfg(feature = "metrics")]
+        self.inner.observe(duration.as_secs_f64());
+        if let Some(statsd) = STATSD.get() {
+            let _ = statsd.time(self.name, duration);
+        }
+    }
+}
+
+pub struct Gauge {
+    name: &'static str,
+    #[cfg(feature = "metrics")]
+    inner: IntGauge,
+}
+
+impl Gauge {
+    #[cfg_attr(not(feature = "metrics"), allow(unused_variables))]
+    fn register(name: &'static str, help: &str) -> Self {
+        Gauge {
+            name,
+            #[cfg(feature = "metrics")]
+            inner: register_int_gauge!(name, help).unwrap(),
+        }
+    }
+
+    pub fn set(&self, value: i64) {
+        #[cfg(feature = "metrics")]
+        self.inner.set(value);
+        if let Some(statsd) = STATSD.get() {
+            let _ = statsd.gauge(self.name, value as f64);
+        }
+    }
+}
+
+pub struct GaugeVec {
+    name: &'static str,
+    #[cfg(feature = "metrics")]
+    inner: IntGaugeVec,
+}
+
+impl GaugeVec {
+    #[cfg_attr(not(feature = "metrics"), allow(unused_variables))]
+    fn register(name: &'static str, help: &str, label: &str) -> Self {
+        GaugeVec {
+            name,
+            #[cfg(feature = "metrics")]
+            inner: register_int_gauge_vec!(name, help, &[label]).unwrap(),
+        }
+    }
+
+    pub fn set(&self, label_value: &str, value: i64) {
+        #[cfg(feature = "metrics")]
+        self.inner.with_label_values(&[label_value]).set(value);
+        if let Some(statsd) = STATSD.get() {
+            let _ = statsd.gauge(&format!("{}.{}", self.name, label_value), value as f64);
+        }
+    }
+}
+
+#[cfg(feature = "metrics")]
+fn buckets(name: &str, default: &[f64]) -> Vec<f64> {
+    BUCKET_OVERRIDES
+        .get()
+        .and_then(|overrides| overrides.get(name))
+        .cloned()
+        .unwrap_or_else(|| default.to_vec())
+}
+
+pub static SLOW_REQUESTS_TOTAL: LazyLock<Counter> = LazyLock::new(|| {
+    Counter::register(
+        "slow_requests_total",
+        "Requests that took longer than web.slow_request_threshold_ms",
+    )
+});
+
+pub static HTTP_REQUEST_DURATION_SECONDS: LazyLock<Timing> = LazyLock::new(|| {
+    Timing::register(
+        "http_request_duration_seconds",
+        "Time taken to handle HTTP requests",
+        DEFAULT_BUCKETS,
+    )
+});
+
+pub static INDEX_PULL_DURATION_SECONDS: LazyLock<Timing> = LazyLock::new(|| {
+    Timing::register(
+        "index_pull_duration_seconds",
+        "Time taken to fetch and check out index updates",
+        PULL_DURATION_BUCKETS,
+    )
+});
+
+pub static REQUEST_PANICS_TOTAL: LazyLock<Counter> = LazyLock::new(|| {
+    Counter::register(
+        "request_panics_total",
+        "Request handlers that panicked and were turned into 500 responses",
+    )
+});
+
+pub static REPLICATION_PUSH_LATENCY_SECONDS: LazyLock<Timing> = LazyLock::new(|| {
+    Timing::register(
+        "replication_push_latency_seconds",
+        "Time taken to push a replication bundle to a peer",
+        DEFAULT_BUCKETS,
+    )
+});
+
+pub static REPLICATION_CONFLICTS_TOTAL: LazyLock<Counter> = LazyLock::new(|| {
+    Counter::register(
+        "replication_conflicts_total",
+        "Replication bundles that did not fast-forward the local index",
+    )
+});
+
+pub static RATE_LIMIT_REJECTIONS_TOTAL: LazyLock<CounterVec> = LazyLock::new(|| {
+    CounterVec::register(
+        "rate_limit_rejections_total",
+        "Requests rejected with 429 by the per-IP rate limiter",
+        "endpoint",
+    )
+});
+
+pub static NON_FAST_FORWARD_EVENTS_TOTAL: LazyLock<CounterVec> = LazyLock::new(|| {
+    CounterVec::register(
+        "non_fast_forward_events_total",
+        "Index updates that could not fast-forward, by repo.non_ff_strategy",
+        "strategy",
+    )
+});
+
+pub static SIGNATURE_VERIFICATION_FAILURES_TOTAL: LazyLock<Counter> = LazyLock::new(|| {
+    Counter::register(
+        "signature_verification_failures_total",
+        "Index updates skipped because repo.require_signed_commits rejected an upstream commit",
+    )
+});
+
+pub static RESPONSE_BODY_LIMIT_EXCEEDED_TOTAL: LazyLock<Counter> = LazyLock::new(|| {
+    Counter::register(
+        "response_body_limit_exceeded_total",
+        "Responses aborted because the body exceeded web.max_response_body_mb",
+    )
+});
+
+pub static WORKER_RECYCLES_TOTAL: LazyLock<Counter> = LazyLock::new(|| {
+    Counter::register(
+        "worker_recycles_total",
+        "Times all web server workers were replaced because of web.worker_recycle_interval_secs",
+    )
+});
+
+pub static CACHE_INOTIFY_INVALIDATIONS_TOTAL: LazyLock<Counter> = LazyLock::new(|| {
+    Counter::register(
+        "cache_inotify_invalidations_total",
+        "Modified index files whose cached versions were invalidated because of web.watch_index_files",
+    )
+});
+
+pub static CACHE_COALESCED_REQUESTS_TOTAL: LazyLock<Counter> = LazyLock::new(|| {
+    Counter::register(
+        "cache_coalesced_requests_total",
+        "Index file requests served from a concurrent identical request's read",
+    )
+});
+
+pub static PREFETCH_REQUESTS_TOTAL: LazyLock<Counter> = LazyLock::new(|| {
+    Counter::register("prefetch_requests_total", "POST /api/v1/prefetch requests")
+});
+
+pub static PREFETCH_CRATES_WARMED_TOTAL: LazyLock<Counter> = LazyLock::new(|| {
+    Counter::register(
+        "prefetch_crates_warmed_total",
+        "Index files read by POST /api/v1/prefetch",
+    )
+});
+
+pub static SLO_VIOLATION_TOTAL: LazyLock<CounterVec> = LazyLock::new(|| {
+    CounterVec::register(
+        "slo_violation_total",
+        "Times an endpoint entered degraded mode because its p99 latency exceeded the [slo] target",
+        "endpoint",
+    )
+});
+
+pub static CIRCUIT_BREAKER_STATE: LazyLock<GaugeVec> = LazyLock::new(|| {
+    GaugeVec::register(
+        "circuit_breaker_state",
+        "State of the circuit breaker for upstream pulls, 1 for the current state",
+        "state",
+    )
+});
+
+pub static CIRCUIT_BREAKER_TRIPS_TOTAL: LazyLock<Counter> = LazyLock::new(|| {
+    Counter::register(
+        "circuit_breaker_trips_total",
+        "Times repo.cb_failure_threshold consecutive pull failures opened the circuit breaker",
+    )
+});
+
+pub static GRPC_HEALTH_CHECKS_TOTAL: LazyLock<CounterVec> = LazyLock::new(|| {
+    CounterVec::register(
+        "grpc_health_checks_total",
+        "gRPC health Check calls by returned status",
+        "status",
+    )
+});
+
+pub static HOOK_FAILURES_TOTAL: LazyLock<CounterVec> = LazyLock::new(|| {
+    CounterVec::register(
+        "hook_failures_total",
+        "repo.post_pull_hooks runs that failed or timed out, by position in the list",
+        "hook_index",
+    )
+});
+
+pub static GC_DURATION_SECONDS: LazyLock<Timing> = LazyLock::new(|| {
+    Timing::register(
+        "gc_duration_seconds",
+        "Time taken by git gc --auto on the index repository",
+        PULL_DURATION_BUCKETS,
+    )
+});
+
+pub static INDEX_INVALID_UTF8_FILES_TOTAL: LazyLock<Counter> = LazyLock::new(|| {
+    Counter::register(
+        "index_invalid_utf8_files_total",
+        "Index files changed by a pull that are not valid UTF-8",
+    )
+});
+
+pub static TRIE_NODE_COUNT: LazyLock<Gauge> =
+    LazyLock::new(|| Gauge::register("trie_node_count", "Nodes in the crate name trie used by /api/v1/suggest"));
+
+pub static GC_RUNS_TOTAL: LazyLock<Counter> =
+    LazyLock::new(|| Counter::register("gc_runs_total", "git gc --auto runs on the index repository"));
+
+pub static PACK_FILE_COUNT: LazyLock<Gauge> = LazyLock::new(|| {
+    Gauge::register(
+        "pack_file_count",
+        "Pack files in the index repository at the last [repo] pack_monitor_interval_secs check",
+    )
+});
+
+pub static REPACKS_TRIGGERED_TOTAL: LazyLock<Counter> = LazyLock::new(|| {
+    Counter::register(
+        "repacks_triggered_total",
+        "git repack runs triggered by more than [repo] max_pack_files pack files",
+    )
+});
+
+pub static GIT_OBJECTS_TOTAL: LazyLock<GaugeVec> = LazyLock::new(|| {
+    GaugeVec::register(
+        "git_objects_total",
+        "Objects in the index repository (loose and packed) by type",
+        "type",
+    )
+});
+
+pub static GIT_PACK_FILES_TOTAL: LazyLock<Gauge> =
+    LazyLock::new(|| Gauge::register("git_pack_files_total", "Pack files in the index repository"));
+
+pub static GIT_REPO_SIZE_BYTES: LazyLock<Gauge> =
+    LazyLock::new(|| Gauge::register("git_repo_size_bytes", "Size of the index repository's .git directory"));
+
+pub static GIT_WORKING_TREE_SIZE_BYTES: LazyLock<Gauge> = LazyLock::new(|| {
+    Gauge::register("git_working_tree_size_bytes", "Size of the index working tree, excluding .git")
+});
+
+pub static GIT_STALE_REFS_TOTAL: LazyLock<Gauge> = LazyLock::new(|| {
+    Gauge::register(
+        "git_stale_refs_total",
+        "Remote-tracking branches that were not present in the last fetch",
+    )
+});
+
+pub static HEALTH_LIVE: LazyLock<Gauge> =
+    LazyLock::new(|| Gauge::register("health_live", "Result of the last /healthz/live probe, 1 if ok"));
+
+pub static HEALTH_READY: LazyLock<Gauge> =
+    LazyLock::new(|| Gauge::register("health_ready", "Result of the last /healthz/ready probe, 1 if ok"));
+
+pub static HEALTH_STARTUP: LazyLock<Gauge> =
+    LazyLock::new(|| Gauge::register("health_startup", "Result of the last /healthz/startup probe, 1 if ok"));
+
+#[cfg(feature = "metrics")]
+pub static INDEX_INFO: LazyLock<IntGaugeVec> = LazyLock::new(|| {
+    register_int_gauge_vec!(
+        "index_info",
+        "Information about this mirror, always 1",
+        &["name", "description"]
+    )
+    .unwrap()
+});
+
+// 启动时注册所有指标，这样 /metrics 从一开始就能看到值为0的指标
+// bucket 在注册时确定，重新加载配置不会改变
+#[cfg_attr(not(feature = "metrics"), allow(unused_variables))]
+pub fn init(app: &AppConfig, config: &MetricsConfig) {
+    let _ = BUCKET_OVERRIDES.set(config.buckets.clone());
+    if !config.statsd_host.is_empty() {
+        match statsd_client(config) {
+            Ok(client) => {
+                info!("Sending metrics to StatsD at {}:{}", config.statsd_host, config.statsd_port);
+                let _ = STATSD.set(client);
+            }
+            Err(e) => error!("Failed to set up StatsD client: {}", e),
+        }
+    }
+    LazyLock::force(&SLOW_REQUESTS_TOTAL);
+    LazyLock::force(&HTTP_REQUEST_DURATION_SECONDS);
+    LazyLock::force(&INDEX_PULL_DURATION_SECONDS);
+    LazyLock::force(&REQUEST_PANICS_TOTAL);
+    LazyLock::force(&REPLICATION_PUSH_LATENCY_SECONDS);
+    LazyLock::force(&REPLICATION_CONFLICTS_TOTAL);
+    LazyLock::force(&RATE_LIMIT_REJECTIONS_TOTAL);
+    LazyLock::force(&NON_FAST_FORWARD_EVENTS_TOTAL);
+    LazyLock::force(&SIGNATURE_VERIFICATION_FAILURES_TOTAL);
+    LazyLock::force(&RESPONSE_BODY_LIMIT_EXCEEDED_TOTAL);
+    LazyLock::force(&WORKER_RECYCLES_TOTAL);
+    LazyLock::force(&CACHE_INOTIFY_INVALIDATIONS_TOTAL);
+    LazyLock::force(&CACHE_COALESCED_REQUESTS_TOTAL);
+    LazyLock::force(&PREFETCH_REQUESTS_TOTAL);
+    LazyLock::force(&PREFETCH_CRATES_WARMED_TOTAL);
+    LazyLock::force(&SLO_VIOLATION_TOTAL);
+    LazyLock::force(&CIRCUIT_BREAKER_STATE);
+    LazyLock::force(&CIRCUIT_BREAKER_TRIPS_TOTAL);
+    LazyLock::force(&GRPC_HEALTH_CHECKS_TOTAL);
+    LazyLock::force(&HOOK_FAILURES_TOTAL);
+    LazyLock::force(&GC_DURATION_SECONDS);
+    LazyLock::force(&GC_RUNS_TOTAL);
+    LazyLock::force(&PACK_FILE_COUNT);
+    LazyLock::force(&REPACKS_TRIGGERED_TOTAL);
+    LazyLock::force(&TRIE_NODE_COUNT);
+    LazyLock::force(&INDEX_INVALID_UTF8_FILES_TOTAL);
+    LazyLock::force(&GIT_OBJECTS_TOTAL);
+    LazyLock::force(&GIT_PACK_FILES_TOTAL);
+    LazyLock::force(&GIT_REPO_SIZE_BYTES);
+    LazyLock::force(&GIT_WORKING_TREE_SIZE_BYTES);
+    LazyLock::force(&GIT_STALE_REFS_TOTAL);
+    LazyLock::force(&HEALTH_LIVE);
+    LazyLock::force(&HEALTH_READY);
+    LazyLock::force(&HEALTH_STARTUP);
+    #[cfg(feature = "metrics")]
+    INDEX_INFO
+        .with_label_values(&[app.name.as_str(), app.description.as_str()])
+        .set(1);
+}
+
+#[cfg(feature = "metrics")]
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    tag = "health",
+    responses((status = 200, description = "Prometheus text exposition format", content_type = "text/plain", body = String))
+)]
+pub async fn metrics_handler() -> HttpResponse {
+    let mut buffer = Vec::new();
+    let encoder = TextEncoder::new();
+    if let Err(e) = encoder.encode(&prometheus::gather(), &mut buffer) {
+        return ProblemDetails::new(ProblemType::Internal).with_detail(e.to_string()).into();
+    }
+    HttpResponse::Ok()
+        .content_type(encoder.format_type())
+        .body(buffer)
+}
+
+#[cfg(not(feature = "metrics"))]
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    tag = "health",
+    responses((status = 501, description = "Built without the metrics feature", content_type = "application/problem+json", body = ProblemDetails))
+)]
+pub async fn metrics_handler() -> HttpResponse {
+    ProblemDetails::new(ProblemType::Http(actix_web::http::StatusCode::NOT_IMPLEMENTED))
+        .with_detail("built without the metrics feature")
+        .into()
+}
@@ -0,0 +1,596 @@
+use lettre::{
+    message::{header::ContentType, Mailbox},
+    transport::smtp::authentication::Credentials,
+    AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+};
+use chrono::{DateTime, Local};
+use git2::Oid;
+use serde_json::{json, Value};
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+use tracing::{info, warn};
+
+use crate::config::{AlertKind, AppConfig, NotificationsConfig, SmtpConfig, SmtpTls};
+
+// 测试时换成 lettre 的 stub，记录发送的邮件
+#[derive(Clone)]
+enum MailTransport {
+    Smtp(AsyncSmtpTransport<Tokio1Executor>),
+    #[cfg(test)]
+    Stub(lettre::transport::stub::AsyncStubTransport),
+}
+
+impl MailTransport {
+    async fn send(&self, message: Message) -> Result<(), String> {
+        match self {
+            MailTransport::Smtp(transport) => transport.send(message).await.map(|_| ()).map_err(|e| e.to_string()),
+            #[cfg(test)]
+            MailTransport::Stub(transport) => transport.send(message).await.map_err(|e| e.to_string()),
+        }
+    }
+}
+
+struct Mailer {
+    transport: MailTransport,
+    from: Mailbox,
+    to: Vec<Mailbox>,
+}
+
+// 一次 fast-forward 更新
+pub struct PullResult {
+    pub old: Oid,
+    pub new: Oid,
+    pub changed_crates: usize,
+    pub time: DateTime<Local>,
+}
+
+// pull 失败，或者更新过程中的磁盘满、工作区无法恢复
+pub struct PullError {
+    pub kind: AlertKind,
+    pub message: String,
+    pub time: DateTime<Local>,
+}
+
+// 聊天工具的通知渠道；在 tokio runtime 里调用，消息在后台发送，失败只记录日志
+pub trait NotificationChannel: Send + Sync {
+    fn send_success(&self, result: &PullResult);
+    fn send_failure(&self, error: &PullError);
+}
+
+struct ChatWebhook {
+    channel: &'static str,
+    client: reqwest::Client,
+    url: String,
+    instance: String,
+    // 配置了 app.public_url 时失败消息里附上状态页的链接
+    status_url: Option<String>,
+}
+
+impl ChatWebhook {
+    fn new(channel: &'static str, url: &str, app: &AppConfig) -> Self {
+        ChatWebhook {
+            channel,
+            client: reqwest::Client::new(),
+            url: url.to_string(),
+            instance: app.name.clone(),
+            status_url: app
+                .public_url
+                .as_ref()
+                .map(|url| format!("{}/status", url.trim_end_matches('/'))),
+        }
+    }
+
+    fn post(&self, event: &'static str, payload: Value) {
+        let channel = self.channel;
+        let request = self
+            .client
+            .post(&self.url)
+            .header("Content-Type", "application/json")
+            .body(payload.to_string());
+        tokio::spawn(async move {
+            match request.send().await {
+                Ok(res) if res.status().is_success() => info!("Sent {} notification to {}", event, channel),
+                Ok(res) => warn!("{} rejected {} notification: {}", channel, event, res.status()),
+                // 错误信息里的 URL 本身就是凭据
+                Err(e) => warn!("Failed to send {} notification to {}: {}", event, channel, e.without_url()),
+            }
+        });
+    }
+}
+
+fn success_title(instance: &str) -> String {
+    format!("[{}] Index updated", instance)
+}
+
+fn failure_title(instance: &str, kind: AlertKind) -> String {
+    format!("[{}] {}", instance, subject(kind))
+}
+
+// Block Kit 本身没有颜色，放在带颜色的 attachment 里显示为左侧的色条
+pub struct SlackNotifier(ChatWebhook);
+
+impl SlackNotifier {
+    const GREEN: &'static str = "#2eb886";
+    const RED: &'static str = "#d40e0d";
+
+    pub fn new(url: &str, app: &AppConfig) -> Self {
+        SlackNotifier(ChatWebhook::new("Slack", url, app))
+    }
+
+    fn payload(title: String, color: &str, mut blocks: Vec<Value>) -> Value {
+        blocks.insert(0, json!({ "type": "header", "text": { "type": "plain_text", "text": title } }));
+        // text 用于通知和不支持 blocks 的客户端
+        json!({ "text": title, "attachments": [{ "color": color, "blocks": blocks }] })
+    }
+}
+
+impl NotificationChannel for SlackNotifier {
+    fn send_success(&self, result: &PullResult) {
+        let fields = json!([
+            { "type": "mrkdwn", "text": format!("*Commit*\n`{}`", result.new) },
+            { "type": "mrkdwn", "text": format!("*Previous*\n`{}`", result.old) },
+            { "type": "mrkdwn", "text": format!("*Changed crates*\n{}", result.changed_crates) },
+            { "type": "mrkdwn", "text": format!("*Time*\n{}", result.time.to_rfc3339()) },
+        ]);
+        let blocks = vec![json!({ "type": "section", "fields": fields })];
+        self.0.post("pull_success", Self::payload(success_title(&self.0.instance), Self::GREEN, blocks));
+    }
+
+    fn send_failure(&self, error: &PullError) {
+        let mut blocks = vec![
+            json!({ "type": "section", "text": { "type": "mrkdwn", "text": format!("```{}```", error.message) } }),
+            json!({ "type": "context", "elements": [{ "type": "mrkdwn", "text": error.time.to_rfc3339() }] }),
+        ];
+        if let Some(url) = &self.0.status_url {
+            blocks.push(json!({
+                "type": "actions",
+                "elements": [{ "type": "button", "text": { "type": "plain_text", "text": "Status page" }, "url": url }],
+            }));
+        }
+        let title = failure_title(&self.0.instance, error.kind);
+        self.0.post(error.kind.as_str(), Self::payload(title, Self::RED, blocks));
+    }
+}
+
+// Teams 的 incoming webhook 接受 Adaptive Card 格式的 attachment
+pub struct TeamsNotifier(ChatWebhook);
+
+impl TeamsNotifier {
+    pub fn new(url: &str, app: &AppConfig) -> Self {
+        TeamsNotifier(ChatWebhook::new("Teams", url, app))
+    }
+
+    // style 是 good 或 attention，分别显示为绿色和红色
+    fn payload(title: String, style: &str, mut body: Vec<Value>, actions: Vec<Value>) -> Value {
+        body.insert(
+            0,
+            json!({ "type": "TextBlock", "text": title, "weight": "Bolder", "size": "Medium", "wrap": true }),
+        );
+        json!({
+            "type": "message",
+            "attachments": [{
+                "contentType": "application/vnd.microsoft.card.adaptive",
+                "content": {
+                    "$schema": "http://adaptivecards.io/schemas/adaptive-card.json",
+                    "type": "AdaptiveCard",
+                    "version": "1.4",
+                    "body": [{ "type": "Container", "style": style, "bleed": true, "items": body }],
+                    "actions": actions,
+                },
+            }],
+        })
+    }
+}
+
+impl NotificationChannel for TeamsNotifier {
+    fn send_success(&self, result: &PullResult) {
+        let facts = json!([
+            { "title": "Commit", "value": result.new.to_string() },
+            { "title": "Previous", "value": result.old.to_string() },
+            { "title": "Changed crates", "value": result.changed_crates.to_string() },
+            { "title": "Time", "value": result.time.to_rfc3339() },
+        ]);
+        let body = vec![json!({ "type": "FactSet", "facts": facts })];
+        self.0.post("pull_success", Self::payload(success_title(&self.0.instance), "good", body, vec![]));
+    }
+
+    fn send_failure(&self, error: &PullError) {
+        let body = vec![
+            json!({ "type": "TextBlock", "text": error.message, "wrap": true, "fontType": "Monospace" }),
+            json!({ "type": "TextBlock", "text": error.time.to_rfc3339(), "isSubtle": true, "size": "Small" }),
+        ];
+        let actions = match &self.0.status_url {
+            Some(url) => vec![json!({ "type": "Action.OpenUrl", "title": "Status page", "url": url })],
+            None => vec![],
+        };
+        let title = failure_title(&self.0.instance, error.kind);
+        self.0.post(error.kind.as_str(), Self::payload(title, "attention", body, actions));
+    }
+}
+
+// 没有配置 [notifications.smtp]、slack 和 teams 时 alert 什么都不做
+pub struct Notifier {
+    mailer: Option<Mailer>,
+    channels: Vec<Box<dyn NotificationChannel>>,
+    alert_on: Vec<AlertKind>,
+    min_interval: Duration,
+    // 每类告警上一次发送的时间
+    last_sent: Mutex<HashMap<AlertKind, Instant>>,
+    instance: String,
+}
+
+fn parse_mailbox(address: &str) -> Result<Mailbox, String> {
+    address
+        .parse()
+        .map_err(|e| format!("Invalid email address {:?}: {}", address, e))
+}
+
+fn build_mailer(smtp: &SmtpConfig) -> Result<Mailer, String> {
+    let builder = match smtp.tls {
+        SmtpTls::Starttls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&smtp.host),
+        SmtpTls::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(&smtp.host),
+        SmtpTls::None => Ok(AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&smtp.host)),
+    }
+    .map_err(|e| format!("Invalid notifications.smtp.host {:?}: {}", smtp.host, e))?;
+    let mut builder = match smtp.port {
+        Some(port) => builder.port(port),
+        None => builder,
+    };
+    if let (Some(username), Some(password)) = (&smtp.username, &smtp.password) {
+        builder = builder.credentials(Credentials::new(username.clone(), password.clone()));
+    }
+    Ok(Mailer {
+        transport: MailTransport::Smtp(builder.build()),
+        from: parse_mailbox(&smtp.from)?,
+        to: smtp.to.iter().map(|to| parse_mailbox(to)).collect::<Result<_, _>>()?,
+    })
+}
+
+fn subject(kind: AlertKind) -> &'static str {
+    match kind {
+        AlertKind::PullFailure => "Index update failed",
+        AlertKind::DiskFull => "Disk full while updating the index",
+        AlertKind::WorkingTreeCorruption => "Index working tree could not be recovered",
+    }
+}
+
+impl Notifier {
+    pub fn new(config: &NotificationsConfig, app: &AppConfig) -> Result<Self, String> {
+        let mut channels: Vec<Box<dyn NotificationChannel>> = Vec::new();
+        if let Some(slack) = &config.slack {
+            channels.push(Box::new(SlackNotifier::new(&slack.webhook_url, app)));
+        }
+        if let Some(teams) = &config.teams {
+            channels.push(Box::new(TeamsNotifier::new(&teams.webhook_url, app)));
+        }
+        Ok(Notifier {
+            mailer: config.smtp.as_ref().map(build_mailer).transpose()?,
+            channels,
+            alert_on: config.alert_on.clone(),
+            min_interval: Duration::from_secs(config.min_interval_secs),
+            last_sent: Mutex::new(HashMap::new()),
+            instance: app.name.clone(),
+        })
+    }
+
+    pub fn has_channels(&self) -> bool {
+        !self.channels.is_empty()
+    }
+
+    // 成功的消息不受 alert_on 和 min_interval_secs 限制
+    pub fn pull_succeeded(&self, result: &PullResult) {
+        for channel in &self.channels {
+            channel.send_success(result);
+        }
+    }
+
+    // 在 tokio runtime 里调用，邮件和消息在后台发送，失败只记录日志
+    pub fn alert(&self, kind: AlertKind, detail: &str) {
+        if self.mailer.is_none() && self.channels.is_empty() {
+            return;
+        }
+        if !self.alert_on.contains(&kind) {
+            return;
+        }
+        {
+            let mut last_sent = self.last_sent.lock().unwrap_or_else(|e| e.into_inner());
+            let now = Instant::now();
+            if last_sent
+                .get(&kind)
+                .is_some_and(|sent| now.duration_since(*sent) < self.min_interval)
+            {
+                return;
+            }
+            last_sent.insert(kind, now);
+        }
+
+        let error = PullError {
+            kind,
+            message: detail.to_string(),
+            time: Local::now(),
+        };
+        for channel in &self.channels {
+            channel.send_failure(&error);
+        }
+        if let Some(mailer) = &self.mailer {
+            self.send_email(mailer, &error);
+        }
+    }
+
+    fn send_email(&self, mailer: &Mailer, error: &PullError) {
+        let kind = error.kind;
+        let mut builder = Message::builder()
+            .from(mailer.from.clone())
+            .subject(format!("[{}] {}", self.instance, subject(kind)))
+            .header(ContentType::TEXT_PLAIN);
+        for to in &mailer.to {
+            builder = builder.to(to.clone());
+        }
+        let body = format!(
+            "Alert: {}\nInstance: {}\nTime: {}\n\n{}\n",
+            kind.as_str(),
+            self.instance,
+            error.time.to_rfc3339(),
+            error.message
+        );
+        let message = match builder.body(body) {
+            Ok(message) => message,
+            Err(e) => {
+                warn!("Failed to build {} alert email: {}", kind.as_str(), e);
+                return;
+            }
+        };
+        let transport = mailer.transport.clone();
+        tokio::spawn(async move {
+            match transport.send(message).await {
+                Ok(_) => info!("Sent {} alert email", kind.as_str()),
+                Err(e) => warn!("Failed to send {} alert email: {}", kind.as_str(), e),
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lettre::transport::stub::AsyncStubTransport;
+
+    const CONFIG: &str = r#"
+alert_on = ["pull_failure", "disk_full", "working_tree_corruption"]
+min_interval_secs = 3600
+
+[smtp]
+host = "smtp.example.com"
+from = "Mirror <mirror@example.com>"
+to = ["ops@example.com", "oncall@example.com"]
+"#;
+
+    fn stub_notifier(config: &str) -> (Notifier, AsyncStubTransport) {
+        let config: NotificationsConfig = toml::from_str(config).unwrap();
+        let app = AppConfig {
+            name: "team-mirror".to_string(),
+            ..AppConfig::default()
+        };
+        let mut notifier = Notifier::new(&config, &app).unwrap();
+        let stub = AsyncStubTransport::new_ok();
+        notifier.mailer.as_mut().unwrap().transport = MailTransport::Stub(stub.clone());
+        (notifier, stub)
+    }
+
+    // 邮件在后台任务里发送
+    async fn sent(stub: &AsyncStubTransport, count: usize) -> Vec<(lettre::address::Envelope, String)> {
+        for _ in 0..100 {
+            if stub.messages().await.len() >= count {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        stub.messages().await
+    }
+
+    fn header<'a>(message: &'a str, name: &str) -> Option<&'a str> {
+        message.lines().find_map(|line| line.strip_prefix(name)?.strip_prefix(": "))
+    }
+
+    #[tokio::test]
+    async fn emails_each_alert_kind() {
+        let (notifier, stub) = stub_notifier(CONFIG);
+        let kinds = [
+            (AlertKind::PullFailure, "Index update failed"),
+            (AlertKind::DiskFull, "Disk full while updating the index"),
+            (AlertKind::WorkingTreeCorruption, "Index working tree could not be recovered"),
+        ];
+        for (i, (kind, subject)) in kinds.into_iter().enumerate() {
+            notifier.alert(kind, &format!("detail of {}", kind.as_str()));
+            let messages = sent(&stub, i + 1).await;
+            assert_eq!(messages.len(), i + 1);
+            let (envelope, message) = &messages[i];
+            let to: Vec<String> = envelope.to().iter().map(|to| to.to_string()).collect();
+            assert_eq!(to, ["ops@example.com", "oncall@example.com"]);
+            assert_eq!(envelope.from().unwrap().to_string(), "mirror@example.com");
+            assert_eq!(header(message, "Subject"), Some(format!("[team-mirror] {}", subject).as_str()));
+            assert_eq!(header(message, "To"), Some("ops@example.com, oncall@example.com"));
+            assert_eq!(header(message, "Content-Type"), Some("text/plain; charset=utf-8"));
+            assert!(message.contains(&format!("Alert: {}\r\nInstance: team-mirror\r\n", kind.as_str())), "{}", message);
+            assert!(message.contains(&format!("detail of {}", kind.as_str())), "{}", message);
+        }
+    }
+
+    #[tokio::test]
+    async fn rate_limits_per_alert_kind() {
+        let (notifier, stub) = stub_notifier(CONFIG);
+        notifier.alert(AlertKind::PullFailure, "first");
+        notifier.alert(AlertKind::PullFailure, "second");
+        notifier.alert(AlertKind::DiskFull, "disk");
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let messages = sent(&stub, 2).await;
+        assert_eq!(messages.len(), 2);
+        assert!(messages[0].1.contains("first"));
+        assert!(messages[1].1.contains("disk"));
+
+        // 间隔为 0 时不限制；不在 alert_on 里的类型不发送
+        let config = CONFIG
+            .replace("min_interval_secs = 3600", "min_interval_secs = 0")
+            .replace(r#"alert_on = ["pull_failure", "disk_full", "working_tree_corruption"]"#, r#"alert_on = ["pull_failure"]"#);
+        let (notifier, stub) = stub_notifier(&config);
+        notifier.alert(AlertKind::PullFailure, "first");
+        notifier.alert(AlertKind::PullFailure, "second");
+        notifier.alert(AlertKind::DiskFull, "disk");
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let messages = sent(&stub, 2).await;
+        assert_eq!(messages.len(), 2);
+        assert!(messages.iter().all(|(_, message)| header(message, "Subject") == Some("[team-mirror] Index update failed")));
+    }
+
+    fn app(public_url: Option<&str>) -> AppConfig {
+        AppConfig {
+            name: "team-mirror".to_string(),
+            public_url: public_url.map(str::to_string),
+            ..AppConfig::default()
+        }
+    }
+
+    fn pull_result() -> PullResult {
+        PullResult {
+            old: Oid::from_str(&"1".repeat(40)).unwrap(),
+            new: Oid::from_str(&"2".repeat(40)).unwrap(),
+            changed_crates: 3,
+            time: Local::now(),
+        }
+    }
+
+    fn pull_error(kind: AlertKind) -> PullError {
+        PullError {
+            kind,
+            message: "failed to connect to github.com".to_string(),
+            time: Local::now(),
+        }
+    }
+
+    async fn hook(status: u16) -> wiremock::MockServer {
+        use wiremock::matchers::{header, method, path};
+        let server = wiremock::MockServer::start().await;
+        wiremock::Mock::given(method("POST"))
+            .and(path("/hook"))
+            .and(header("Content-Type", "application/json"))
+            .respond_with(wiremock::ResponseTemplate::new(status))
+            .mount(&server)
+            .await;
+        server
+    }
+
+    // 消息在后台任务里发送
+    async fn received(server: &wiremock::MockServer, count: usize) -> Vec<Value> {
+        for _ in 0..100 {
+            if server.received_requests().await.unwrap().len() >= count {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        let requests = server.received_requests().await.unwrap();
+        requests.iter().map(|request| request.body_json().unwrap()).collect()
+    }
+
+    #[tokio::test]
+    async fn slack_cards() {
+        let server = hook(200).await;
+        let slack = SlackNotifier::new(&format!("{}/hook", server.uri()), &app(Some("https://mirror.example.com/")));
+        // 每条消息一个后台任务，等上一条到达再发下一条，保证顺序
+        slack.send_success(&pull_result());
+        received(&server, 1).await;
+        slack.send_failure(&pull_error(AlertKind::PullFailure));
+        let payloads = received(&server, 2).await;
+        assert_eq!(payloads.len(), 2);
+
+        let success = &payloads[0];
+        assert_eq!(success["text"], "[team-mirror] Index updated");
+        let attachment = &success["attachments"][0];
+        assert_eq!(attachment["color"], SlackNotifier::GREEN);
+        assert_eq!(attachment["blocks"][0]["text"]["text"], "[team-mirror] Index updated");
+        let fields: Vec<&str> = attachment["blocks"][1]["fields"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|field| field["text"].as_str().unwrap())
+            .collect();
+        assert_eq!(fields[0], format!("*Commit*\n`{}`", "2".repeat(40)));
+        assert_eq!(fields[1], format!("*Previous*\n`{}`", "1".repeat(40)));
+        assert_eq!(fields[2], "*Changed crates*\n3");
+        assert!(fields[3].starts_with("*Time*\n"));
+
+        let failure = &payloads[1];
+        assert_eq!(failure["text"], "[team-mirror] Index update failed");
+        let attachment = &failure["attachments"][0];
+        assert_eq!(attachment["color"], SlackNotifier::RED);
+        assert_eq!(attachment["blocks"][1]["text"]["text"], "```failed to connect to github.com```");
+        let button = &attachment["blocks"][3]["elements"][0];
+        assert_eq!(button["url"], "https://mirror.example.com/status");
+    }
+
+    #[tokio::test]
+    async fn teams_cards() {
+        let server = hook(200).await;
+        let teams = TeamsNotifier::new(&format!("{}/hook", server.uri()), &app(Some("https://mirror.example.com")));
+        teams.send_success(&pull_result());
+        received(&server, 1).await;
+        teams.send_failure(&pull_error(AlertKind::DiskFull));
+        let payloads = received(&server, 2).await;
+        assert_eq!(payloads.len(), 2);
+
+        let card = &payloads[0]["attachments"][0];
+        assert_eq!(card["contentType"], "application/vnd.microsoft.card.adaptive");
+        let container = &card["content"]["body"][0];
+        assert_eq!(container["style"], "good");
+        assert_eq!(container["items"][0]["text"], "[team-mirror] Index updated");
+        let facts = &container["items"][1]["facts"];
+        assert_eq!(facts[0]["value"], "2".repeat(40));
+        assert_eq!(facts[2]["title"], "Changed crates");
+        assert_eq!(facts[2]["value"], "3");
+        assert_eq!(card["content"]["actions"], json!([]));
+
+        let content = &payloads[1]["attachments"][0]["content"];
+        let container = &content["body"][0];
+        assert_eq!(container["style"], "attention");
+        assert_eq!(container["items"][0]["text"], "[team-mirror] Disk full while updating the index");
+        assert_eq!(container["items"][1]["text"], "failed to connect to github.com");
+        assert_eq!(content["actions"][0]["type"], "Action.OpenUrl");
+        assert_eq!(content["actions"][0]["url"], "https://mirror.example.com/status");
+
+        // 没有 app.public_url 时不附链接
+        let server = hook(200).await;
+        let teams = TeamsNotifier::new(&format!("{}/hook", server.uri()), &app(None));
+        teams.send_failure(&pull_error(AlertKind::PullFailure));
+        assert_eq!(received(&server, 1).await[0]["attachments"][0]["content"]["actions"], json!([]));
+    }
+
+    #[tokio::test]
+    async fn alerts_go_to_every_chat_channel() {
+        let (slack, teams) = (hook(200).await, hook(500).await);
+        let config: NotificationsConfig = toml::from_str(&format!(
+            "alert_on = [\"pull_failure\"]\nmin_interval_secs = 3600\n\
+             [slack]\nwebhook_url = \"{}/hook\"\n[teams]\nwebhook_url = \"{}/hook\"",
+            slack.uri(),
+            teams.uri()
+        ))
+        .unwrap();
+        let notifier = Notifier::new(&config, &app(None)).unwrap();
+        assert!(notifier.has_channels());
+        let (logs, _guard) = crate::test_support::capture_logs();
+        notifier.alert(AlertKind::PullFailure, "first");
+        notifier.alert(AlertKind::PullFailure, "second");
+        notifier.alert(AlertKind::DiskFull, "disk");
+        // 成功的消息不受 min_interval_secs 限制
+        notifier.pull_succeeded(&pull_result());
+        notifier.pull_succeeded(&pull_result());
+        for server in [&slack, &teams] {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            assert_eq!(received(server, 3).await.len(), 3);
+        }
+        let logs = logs.contents();
+        assert!(logs.contains("Sent pull_failure notification to Slack"), "{}", logs);
+        assert!(logs.contains("Teams rejected pull_success notification: 500 Internal Server Error"), "{}", logs);
+    }
+}
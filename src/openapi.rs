@@ -0,0 +1,229 @@
+use actix_web::{http::header, web, HttpResponse};
+use serde_json::Value;
+use std::sync::LazyLock;
+use utoipa::{
+    openapi::security::{HttpAuthScheme, HttpBuilder, SecurityScheme},
+    Modify, OpenApi,
+};
+
+use crate::{api, auth, config::WebConfig, events, git_bundle, health, metrics, prefetch, problem, pull_timing, registry, security_headers};
+
+const SWAGGER_UI_VERSION: &str = "5";
+
+struct BearerAuth;
+
+impl Modify for BearerAuth {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        // Cargo.toml 里没有 license，utoipa 会生成一个空的 license
+        openapi.info.license = None;
+        if let Some(components) = openapi.components.as_mut() {
+            components.add_security_scheme(
+                "bearer",
+                SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).bearer_format("JWT").build()),
+            );
+        }
+    }
+}
+
+// 只包含给客户端使用的接口；/admin/reload、/api/v1/replicate、git smart HTTP 和索引文件本身不在里面
+#[derive(OpenApi)]
+#[openapi(
+    paths(
+        api::index_stats,
+        api::search,
+        api::crate_version,
+        api::crate_features,
+        api::crate_versions,
+        api::reverse_dependencies,
+        api::suggest,
+        prefetch::prefetch_crates,
+        git_bundle::git_bundle,
+        git_bundle::git_bundle_timestamp,
+        events::list_events,
+        pull_timing::pull_timing,
+        auth::me,
+        registry::config_json,
+        registry::well_known,
+        health::health_ready,
+        health::healthz_live,
+        health::healthz_ready,
+        health::healthz_startup,
+        metrics::metrics_handler,
+    ),
+    info(title = "local-crates-io-index", description = "Sparse crates.io index mirror"),
+    modifiers(&BearerAuth)
+)]
+struct ApiDoc;
+
+// utoipa 生成的是 3.1，可以为空的字段写成 "type": [.., "null"] 或者 oneOf 里的 {"type": "null"}；
+// 改写成 3.0 的 nullable，只支持 3.0 的代码生成工具也能读取
+fn downgrade_to_3_0(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            if let Some(Value::Array(types)) = map.get_mut("type") {
+                let nullable = types.iter().any(|t| t == "null");
+                types.retain(|t| t != "null");
+                if types.len() == 1 {
+                    let single = types.remove(0);
+                    map.insert("type".to_string(), single);
+                }
+                if nullable {
+                    map.insert("nullable".to_string(), Value::Bool(true));
+                }
+            }
+            let null_variant = |variant: &Value| variant.get("type").is_some_and(|t| t == "null");
+            if let Some(Value::Array(variants)) = map.get_mut("oneOf") {
+                if variants.iter().any(null_variant) {
+                    variants.retain(|variant| !null_variant(variant));
+                    // 3.0 里 $ref 旁边的字段会被忽略，用 allOf 包一层
+                    if variants.len() == 1 {
+                        let single = variants.remove(0);
+                        map.remove("oneOf");
+                        map.insert("allOf".to_string(), Value::Array(vec![single]));
+                    }
+                    map.insert("nullable".to_string(), Value::Bool(true));
+                }
+            }
+            map.values_mut().for_each(downgrade_to_3_0);
+        }
+        Value::Array(values) => values.iter_mut().for_each(downgrade_to_3_0),
+        _ => {}
+    }
+}
+
+static SPEC: LazyLock<String> = LazyLock::new(|| {
+    let mut spec = serde_json::to_value(ApiDoc::openapi()).expect("OpenAPI spec is always serializable");
+    downgrade_to_3_0(&mut spec);
+    spec["openapi"] = Value::String("3.0.3".to_string());
+    serde_json::to_string_pretty(&spec).expect("OpenAPI spec is always serializable")
+});
+
+pub async fn openapi_json(web_config: web::Data<WebConfig>) -> HttpResponse {
+    if !web_config.openapi_enabled {
+        return problem::not_found("OpenAPI documentation is not enabled").into();
+    }
+    HttpResponse::Ok()
+        .content_type("application/json")
+        .body(SPEC.as_str())
+}
+
+// Swagger UI 从 unpkg 加载，页面自己的 CSP 覆盖 web.security_headers.csp
+pub async fn swagger_ui(web_config: web::Data<WebConfig>) -> HttpResponse {
+    if !web_config.openapi_enabled {
+        return problem::not_found("OpenAPI documentation is not enabled").into();
+    }
+    let body = format!(
+        r##"<!DOCTYPE html>
+<html><head><meta charset="utf-8"><title>API</title>
+<link rel="stylesheet" href="https://unpkg.com/swagger-ui-dist@{version}/swagger-ui.css">
+</head>
+<body>
+<div id="swagger-ui"></div>
+<script src="https://unpkg.com/swagger-ui-dist@{version}/swagger-ui-bundle.js"></script>
+<script>SwaggerUIBundle({{ url: "/api/v1/openapi.json", dom_id: "#swagger-ui" }});</script>
+</body></html>
+"##,
+        version = SWAGGER_UI_VERSION,
+    );
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .insert_header((header::CONTENT_SECURITY_POLICY, security_headers::CDN_PAGE_CSP))
+        .body(body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+    use actix_web::{test as actix_test, App};
+
+    async fn get(extra: &str, uri: &str) -> actix_web::dev::ServiceResponse {
+        let app = actix_test::init_service(
+            App::new()
+                .app_data(web::Data::new(test_support::web_config(extra)))
+                .route("/api/v1/openapi.json", web::get().to(openapi_json))
+                .route("/api/v1/swagger-ui", web::get().to(swagger_ui)),
+        )
+        .await;
+        actix_test::call_service(&app, actix_test::TestRequest::get().uri(uri).to_request()).await
+    }
+
+    #[actix_web::test]
+    async fn spec_is_valid_openapi() {
+        let res = get("", "/api/v1/openapi.json").await;
+        assert_eq!(res.status(), 200);
+        assert_eq!(res.headers().get(header::CONTENT_TYPE).unwrap(), "application/json");
+        let body = actix_test::read_body(res).await;
+        let spec: openapiv3::OpenAPI = serde_json::from_reader(body.as_ref()).unwrap();
+        assert_eq!(spec.openapi, "3.0.3");
+        assert_eq!(spec.info.title, "local-crates-io-index");
+        for path in [
+            "/api/v1/crates",
+            "/api/v1/crates/{name}/{version}",
+            "/api/v1/crates/{name}/versions",
+            "/api/v1/suggest",
+            "/api/v1/events",
+            "/healthz/startup",
+            "/config.json",
+        ] {
+            assert!(spec.paths.paths.contains_key(path), "{} is missing", path);
+        }
+        // 每个接口都有响应的说明，返回 JSON 的响应带有 schema
+        for (path, item) in spec.paths.iter() {
+            let item = item.as_item().unwrap();
+            for (method, operation) in item.iter() {
+                assert!(!operation.responses.responses.is_empty(), "{} {}", method, path);
+                for response in operation.responses.responses.values() {
+                    let response = response.as_item().unwrap();
+                    for (content_type, media) in &response.content {
+                        if content_type.contains("json") {
+                            assert!(media.schema.is_some(), "{} {} {}", method, path, content_type);
+                        }
+                    }
+                }
+            }
+        }
+        let schemas = &spec.components.unwrap().schemas;
+        for schema in ["VersionResponse", "ProblemDetails", "EventsResponse", "ProbeResult"] {
+            assert!(schemas.contains_key(schema), "{} is missing", schema);
+        }
+        assert!(!spec.paths.paths.contains_key("/admin/reload"));
+    }
+
+    #[actix_web::test]
+    async fn swagger_ui_loads_spec() {
+        let res = get("", "/api/v1/swagger-ui").await;
+        assert_eq!(res.status(), 200);
+        assert_eq!(res.headers().get(header::CONTENT_SECURITY_POLICY).unwrap(), security_headers::CDN_PAGE_CSP);
+        let body = actix_test::read_body(res).await;
+        assert!(std::str::from_utf8(&body).unwrap().contains(r#"url: "/api/v1/openapi.json""#));
+    }
+
+    #[actix_web::test]
+    async fn can_be_disabled() {
+        assert_eq!(get("openapi_enabled = false", "/api/v1/openapi.json").await.status(), 404);
+        assert_eq!(get("openapi_enabled = false", "/api/v1/swagger-ui").await.status(), 404);
+    }
+
+    #[test]
+    fn downgrades_nullable_fields() {
+        let mut schema = serde_json::json!({
+            "properties": {
+                "links": {"type": ["string", "null"]},
+                "check": {"oneOf": [{"type": "null"}, {"$ref": "#/components/schemas/ConnectivityCheck"}]},
+                "count": {"type": "integer"},
+            }
+        });
+        downgrade_to_3_0(&mut schema);
+        assert_eq!(
+            schema,
+            serde_json::json!({
+                "properties": {
+                    "links": {"type": "string", "nullable": true},
+                    "check": {"allOf": [{"$ref": "#/components/schemas/ConnectivityCheck"}], "nullable": true},
+                    "count": {"type": "integer"},
+                }
+            })
+        );
+    }
+}
@@ -0,0 +1,196 @@
+use actix_web::{
+    body::MessageBody,
+    dev::{ServiceRequest, ServiceResponse},
+    middleware::Next,
+    web, Error,
+};
+use futures_util::FutureExt;
+use std::{
+    any::Any,
+    backtrace::Backtrace,
+    cell::RefCell,
+    panic::{self, AssertUnwindSafe},
+    sync::atomic::{AtomicU64, Ordering},
+};
+use tracing::error;
+
+use crate::{
+    config::WebConfig,
+    metrics,
+    problem::{ProblemDetails, ProblemType},
+    redact,
+};
+
+thread_local! {
+    // panic hook 在发生panic的线程上记录backtrace，catch_unwind 返回后在同一线程取出
+    static LAST_PANIC_BACKTRACE: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+static NEXT_REQUEST_ID: AtomicU64 = AtomicU64::new(1);
+
+pub fn install_panic_hook() {
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        let backtrace = Backtrace::force_capture().to_string();
+        LAST_PANIC_BACKTRACE.with(|b| *b.borrow_mut() = Some(backtrace));
+        default_hook(info);
+    }));
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        s.to_string()
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s.clone()
+    } else {
+        "unknown panic payload".to_string()
+    }
+}
+
+fn take_backtrace() -> String {
+    LAST_PANIC_BACKTRACE
+        .with(|b| b.borrow_mut().take())
+        .unwrap_or_default()
+}
+
+// 在当前线程上运行 f，panic 时记录日志并计入 request_panics_total
+pub fn catch<R>(what: &str, f: impl FnOnce() -> R) -> Option<R> {
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(value) => Some(value),
+        Err(payload) => {
+            error!("{} panicked: {}\n{}", what, panic_message(payload.as_ref()), take_backtrace());
+            metrics::REQUEST_PANICS_TOTAL.inc();
+            None
+        }
+    }
+}
+
+// 代替 web::block：闭包 panic 时 actix 只返回 BlockingError，既没有日志也不计数
+pub async fn block<F, R>(f: F) -> Result<R, ProblemDetails>
+where
+    F: FnOnce() -> R + Send + 'static,
+    R: Send + 'static,
+{
+    match web::block(move || catch("Blocking task", f)).await {
+        Ok(Some(value)) => Ok(value),
+        Ok(None) => Err(ProblemDetails::new(ProblemType::Internal).with_detail("blocking task panicked")),
+        Err(e) => Err(ProblemDetails::new(ProblemType::Internal).with_detail(e.to_string())),
+    }
+}
+
+// 把请求处理中的panic转换成500响应，避免worker线程退出
+// 在最外层，其他中间件里的 panic 也会被捕获；这时里面的 render_problems 不会执行，
+// 拿不到 HttpRequest 来构造响应（克隆会让 match_info_mut 失败），返回 ProblemDetails 错误由 actix 转成响应
+pub async fn recover_panics(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let request_id = req
+        .headers()
+        .get("x-request-id")
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.to_string())
+        .unwrap_or_else(|| NEXT_REQUEST_ID.fetch_add(1, Ordering::Relaxed).to_string());
+    let method = req.method().clone();
+    let uri = req.uri().clone();
+    let config = req.app_data::<web::Data<WebConfig>>().cloned();
+
+    match AssertUnwindSafe(next.call(req)).catch_unwind().await {
+        Ok(res) => res,
+        Err(payload) => {
+            let backtrace = take_backtrace();
+            let uri = match &config {
+                Some(config) => redact::redact_uri(&uri, &config.access_log.redact_params),
+                None => uri.to_string(),
+            };
+            error!(
+                %request_id,
+                %method,
+                %uri,
+                "Request handler panicked: {}\n{}",
+                panic_message(payload.as_ref()),
+                backtrace
+            );
+            metrics::REQUEST_PANICS_TOTAL.inc();
+            Err(ProblemDetails::new(ProblemType::Internal)
+                .with_detail(format!("request {} failed", request_id))
+                .into())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{http::StatusCode, middleware::from_fn, test as actix_test, App, HttpResponse};
+
+    async fn panicking_middleware(
+        req: ServiceRequest,
+        next: Next<impl MessageBody>,
+    ) -> Result<ServiceResponse<impl MessageBody>, Error> {
+        if req.path() == "/middleware-boom" {
+            panic!("middleware panicked");
+        }
+        next.call(req).await
+    }
+
+    #[actix_web::test]
+    async fn panics_become_500_and_the_server_keeps_serving() {
+        let app = actix_test::init_service(
+            App::new()
+                .wrap(from_fn(panicking_middleware))
+                .wrap(from_fn(recover_panics))
+                .route("/ok", web::get().to(|| async { HttpResponse::Ok().body("ok") }))
+                .route(
+                    "/boom",
+                    web::get().to(|| async {
+                        if true {
+                            panic!("handler panicked");
+                        }
+                        HttpResponse::Ok().finish()
+                    }),
+                )
+                .route(
+                    "/block-boom",
+                    web::get().to(|| async {
+                        block(|| panic!("blocking task panicked")).await?;
+                        Ok::<_, Error>(HttpResponse::Ok().finish())
+                    }),
+                ),
+        )
+        .await;
+        // 最外层返回的 Err 由 actix 转成响应
+        let call = |uri: &'static str| {
+            let req = actix_test::TestRequest::get().uri(uri).insert_header(("x-request-id", "req-1")).to_request();
+            let app = &app;
+            async move {
+                match actix_test::try_call_service(app, req).await {
+                    Ok(res) => (res.status(), actix_test::read_body(res).await),
+                    Err(e) => {
+                        let res = e.error_response();
+                        let status = res.status();
+                        (status, actix_web::body::to_bytes(res.into_body()).await.unwrap())
+                    }
+                }
+            }
+        };
+
+        for _ in 0..3 {
+            let (status, body) = call("/boom").await;
+            assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+            let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            assert_eq!(body["status"], 500);
+            assert_eq!(body["detail"], "request req-1 failed");
+            assert_eq!(call("/ok").await, (StatusCode::OK, "ok".into()));
+        }
+        assert_eq!(call("/block-boom").await.0, StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(call("/middleware-boom").await.0, StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(call("/ok").await, (StatusCode::OK, "ok".into()));
+    }
+
+    #[test]
+    fn catch_returns_none_on_panic() {
+        assert_eq!(catch("test", || 1), Some(1));
+        assert_eq!(catch("test", || -> i32 { panic!("boom") }), None);
+    }
+}
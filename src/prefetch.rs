@@ -0,0 +1,289 @@
+use actix_web::{web, HttpResponse};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashSet,
+    fs::File,
+    io::{self, Read},
+    path::Path,
+    time::Instant,
+};
+use tokio::task::JoinSet;
+use tracing::info;
+use utoipa::ToSchema;
+
+use crate::{
+    coalesce::IndexFileCoalescer,
+    config::RegistryConfig,
+    metrics, panic_recovery,
+    problem::{ProblemDetails, ProblemType},
+    slo::{SloEndpoint, SloMonitor},
+    snapshot::ServingRoot,
+    sparse,
+};
+
+// 一次 POST /api/v1/prefetch 最多的 crate 数
+pub const MAX_PREFETCH_CRATES: usize = 100;
+
+// 没有下载统计，使用 crates.io 上累计下载量最高的 crate
+const POPULAR_CRATES: &[&str] = &[
+    "syn", "bitflags", "hashbrown", "proc-macro2", "quote", "libc", "base64", "regex-syntax", "cfg-if", "serde",
+    "rand", "itoa", "serde_derive", "memchr", "rand_core", "once_cell", "getrandom", "unicode-ident", "log",
+    "aho-corasick", "regex", "serde_json", "smallvec", "ryu", "lazy_static", "autocfg", "indexmap", "either",
+    "parking_lot", "parking_lot_core", "itertools", "scopeguard", "lock_api", "heck", "rand_chacha",
+    "ppv-lite86", "thiserror", "thiserror-impl", "socket2", "strsim", "bytes", "num-traits", "fastrand",
+    "tokio", "version_check", "mio", "anyhow", "pin-project-lite", "futures-core", "windows-sys", "futures-util",
+    "futures-task", "futures-sink", "futures-channel", "futures-io", "futures", "futures-executor",
+    "futures-macro", "slab", "pin-utils", "http", "hyper", "clap", "clap_lex", "time", "chrono", "semver",
+    "tracing", "tracing-core", "tokio-macros", "percent-encoding", "url", "idna", "form_urlencoded",
+    "unicode-normalization", "unicode-bidi", "tinyvec", "tinyvec_macros", "crossbeam-utils",
+    "crossbeam-epoch", "crossbeam-deque", "rayon", "rayon-core", "num_cpus", "hermit-abi", "memoffset",
+    "toml", "atty", "textwrap", "termcolor", "env_logger", "humantime", "tempfile", "rustix", "linux-raw-sys",
+    "errno", "byteorder", "digest", "generic-array", "typenum",
+];
+
+#[cfg(target_os = "linux")]
+fn advise_sequential(file: &File) {
+    use std::os::fd::AsRawFd;
+    // 只是提示，失败时照常读取
+    unsafe {
+        libc::posix_fadvise(file.as_raw_fd(), 0, 0, libc::POSIX_FADV_SEQUENTIAL);
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn advise_sequential(_file: &File) {}
+
+fn read_through(path: &Path, buf: &mut Vec<u8>) -> io::Result<usize> {
+    let mut file = File::open(path)?;
+    advise_sequential(&file);
+    buf.clear();
+    file.read_to_end(buf)
+}
+
+// 读一遍最热门的 top_n 个 crate 的索引文件，内容马上丢弃，只为了让它们进入系统的页缓存
+// 启动和每次更新之后在阻塞线程池里执行
+pub fn prefetch_popular(root: &Path, top_n: usize) {
+    let start = Instant::now();
+    let mut buf = Vec::new();
+    let (mut files, mut bytes) = (0, 0);
+    for name in POPULAR_CRATES.iter().take(top_n) {
+        let Some(path) = sparse::crate_index_path(name) else {
+            continue;
+        };
+        if let Ok(size) = read_through(&root.join(path), &mut buf) {
+            files += 1;
+            bytes += size;
+        }
+    }
+    info!(
+        "Prefetched {} popular index files ({} bytes) in {} ms",
+        files,
+        bytes,
+        start.elapsed().as_millis()
+    );
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct PrefetchRequest {
+    crates: Vec<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct PrefetchResponse {
+    cached: Vec<String>,
+    not_found: Vec<String>,
+}
+
+// cargo 解析依赖之前一次性读入它要查询的索引文件，之后的单个请求不用再等磁盘
+// 文件进入系统的页缓存；配置了 [slo.sparse_index] 时同时保存降级模式下使用的副本
+#[utoipa::path(
+    post,
+    path = "/api/v1/prefetch",
+    tag = "index",
+    request_body = PrefetchRequest,
+    responses(
+        (status = 200, description = "Crates whose index files were read, and crates that don't exist", body = PrefetchResponse),
+        (status = 400, description = "More than 100 crates or an invalid crate name", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 429, description = "Rate limited, 5 requests then one every 10s per IP unless [rate_limit.endpoints.prefetch] is set"),
+    )
+)]
+pub async fn prefetch_crates(
+    body: web::Json<PrefetchRequest>,
+    root: web::Data<ServingRoot>,
+    registry: web::Data<RegistryConfig>,
+    coalescer: web::Data<IndexFileCoalescer>,
+    slo: web::Data<SloMonitor>,
+) -> HttpResponse {
+    metrics::PREFETCH_REQUESTS_TOTAL.inc();
+    let mut seen = HashSet::new();
+    let names: Vec<String> = body.into_inner().crates.into_iter().filter(|name| seen.insert(name.clone())).collect();
+    if names.len() > MAX_PREFETCH_CRATES {
+        return ProblemDetails::new(ProblemType::BadRequest)
+            .with_detail(format!("At most {} crates can be prefetched at once", MAX_PREFETCH_CRATES))
+            .into();
+    }
+    if let Some(name) = names.iter().find(|name| !sparse::valid_crate_name(name)) {
+        return ProblemDetails::new(ProblemType::InvalidCrateName)
+            .with_detail(format!("Invalid crate name {:?}", name))
+            .into();
+    }
+
+    let root = root.get();
+    let strict = registry.strict_name_matching;
+    let remember = slo.is_tracked(SloEndpoint::SparseIndex);
+    let mut tasks = JoinSet::new();
+    for (i, name) in names.iter().enumerate() {
+        let (root, name, coalescer) = (root.clone(), name.clone(), coalescer.clone());
+        tasks.spawn(async move {
+            let resolved = {
+                let root = root.clone();
+                panic_recovery::block(move || sparse::resolve_crate_path(&root, &name, strict)).await
+            };
+            let Ok(Some(index_path)) = resolved else {
+                return (i, false);
+            };
+            let Ok(Some(file)) = coalescer.load(root.join(&index_path)).await else {
+                return (i, false);
+            };
+            if remember {
+                // 和 cargo 请求的路径一致（全部小写）
+                let key = format!("/{}", index_path.to_string_lossy().to_ascii_lowercase());
+                coalescer.remember(&key, &file);
+            }
+            (i, true)
+        });
+    }
+    let mut found = vec![false; names.len()];
+    while let Some(result) = tasks.join_next().await {
+        if let Ok((i, true)) = result {
+            found[i] = true;
+        }
+    }
+
+    let (mut cached, mut not_found) = (Vec::new(), Vec::new());
+    for (name, found) in names.into_iter().zip(found) {
+        if found {
+            cached.push(name);
+        } else {
+            not_found.push(name);
+        }
+    }
+    metrics::PREFETCH_CRATES_WARMED_TOTAL.inc_by(cached.len() as u64);
+    HttpResponse::Ok().json(PrefetchResponse { cached, not_found })
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use crate::{
+        config::{SloConfig, SloTarget},
+        delta::DeltaEncoding,
+        test_support::{capture_logs, index_line},
+    };
+    use actix_web::{test as actix_test, App};
+    use std::os::fd::AsRawFd;
+
+    // 在页缓存里的页数和总页数
+    fn resident_pages(path: &Path) -> (usize, usize) {
+        let file = File::open(path).unwrap();
+        let len = file.metadata().unwrap().len() as usize;
+        let page = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+        let mut pages = vec![0u8; len.div_ceil(page)];
+        unsafe {
+            let addr = libc::mmap(std::ptr::null_mut(), len, libc::PROT_READ, libc::MAP_SHARED, file.as_raw_fd(), 0);
+            assert_ne!(addr, libc::MAP_FAILED);
+            assert_eq!(libc::mincore(addr, len, pages.as_mut_ptr()), 0);
+            libc::munmap(addr, len);
+        }
+        (pages.iter().filter(|&&p| p & 1 == 1).count(), pages.len())
+    }
+
+    // 写回磁盘后把文件移出页缓存
+    fn evict(path: &Path) {
+        let file = File::open(path).unwrap();
+        file.sync_all().unwrap();
+        unsafe {
+            libc::posix_fadvise(file.as_raw_fd(), 0, 0, libc::POSIX_FADV_DONTNEED);
+        }
+    }
+
+    #[test]
+    fn prefetch_loads_popular_files_into_page_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let top_n = 10;
+        let (popular, unpopular) = (POPULAR_CRATES[0], POPULAR_CRATES[top_n]);
+        let paths: Vec<_> = [popular, unpopular]
+            .iter()
+            .map(|name| {
+                let path = dir.path().join(sparse::crate_index_path(name).unwrap());
+                std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+                std::fs::write(&path, vec![b'x'; 4 * 1024 * 1024]).unwrap();
+                evict(&path);
+                path
+            })
+            .collect();
+        if resident_pages(&paths[0]).0 != 0 {
+            // tmpfs 上的页不能移出页缓存
+            eprintln!("page cache eviction is not supported here, skipping");
+            return;
+        }
+
+        let (logs, _guard) = capture_logs();
+        prefetch_popular(dir.path(), top_n);
+        // 全部的页都在页缓存里，之后的读取不需要等磁盘；虚拟机里磁盘读取本身可能被宿主机缓存，不比较耗时
+        let (resident, total) = resident_pages(&paths[0]);
+        assert_eq!(resident, total);
+        // 排名在 top_n 之外的不读
+        assert_eq!(resident_pages(&paths[1]).0, 0);
+        assert!(
+            logs.contents().contains(&format!("Prefetched 1 popular index files ({} bytes)", 4 * 1024 * 1024)),
+            "{}",
+            logs.contents()
+        );
+    }
+
+    // 降级模式下 sparse_index 只返回记住的文件，可以看出哪些请求不经过文件系统
+    #[actix_web::test]
+    async fn prefetched_files_are_served_from_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["serde", "tokio", "rand"] {
+            let path = dir.path().join(sparse::crate_index_path(name).unwrap());
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, index_line(name, "1.0.0")).unwrap();
+        }
+        let slo = web::Data::new(SloMonitor::new(&SloConfig {
+            sparse_index: Some(SloTarget { p99_ms: 100 }),
+            search: None,
+        }));
+        let app = actix_test::init_service(
+            App::new()
+                .app_data(web::Data::new(ServingRoot::new(dir.path(), false, false)))
+                .app_data(web::Data::new(RegistryConfig::default()))
+                .app_data(web::Data::new(IndexFileCoalescer::new(false)))
+                .app_data(slo.clone())
+                .app_data(web::Data::new(DeltaEncoding::new(false, dir.path())))
+                .route("/api/v1/prefetch", web::post().to(prefetch_crates))
+                .route("/{path:.*}", web::get().to(sparse::index_file)),
+        )
+        .await;
+
+        let prefetch = actix_test::TestRequest::post()
+            .uri("/api/v1/prefetch")
+            .set_json(serde_json::json!({ "crates": ["Serde", "tokio", "no-such-crate", "tokio"] }))
+            .to_request();
+        let body: serde_json::Value = actix_test::call_and_read_body_json(&app, prefetch).await;
+        assert_eq!(body, serde_json::json!({ "cached": ["Serde", "tokio"], "not_found": ["no-such-crate"] }));
+
+        // 删除磁盘上的文件，之后的请求只能从缓存返回
+        std::fs::remove_file(dir.path().join("se/rd/serde")).unwrap();
+        slo.set_degraded(SloEndpoint::SparseIndex, true);
+        for (uri, name) in [("/se/rd/serde", "serde"), ("/to/ki/tokio", "tokio")] {
+            let res = actix_test::call_service(&app, actix_test::TestRequest::get().uri(uri).to_request()).await;
+            assert_eq!(res.status(), 200);
+            assert_eq!(actix_test::read_body(res).await, index_line(name, "1.0.0").as_bytes());
+        }
+        // 没有预取的文件不在缓存里
+        let res = actix_test::call_service(&app, actix_test::TestRequest::get().uri("/ra/nd/rand").to_request()).await;
+        assert_eq!(res.status(), 503);
+    }
+}
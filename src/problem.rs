@@ -0,0 +1,234 @@
+use actix_web::{
+    body::{self, BoxBody, EitherBody, MessageBody},
+    dev::{ServiceRequest, ServiceResponse},
+    http::{
+        header::{HeaderValue, CONTENT_TYPE},
+        StatusCode,
+    },
+    middleware::Next,
+    Error, HttpResponse, ResponseError,
+};
+use serde::Serialize;
+use std::{fmt, sync::RwLock};
+use utoipa::ToSchema;
+
+pub const CONTENT_TYPE_PROBLEM: &str = "application/problem+json";
+
+// 没有 ProblemDetails 的错误响应，最多读取这么多字节作为 detail
+const MAX_DETAIL_BODY: usize = 4096;
+
+// web.problem_details_base_uri，每一代 server 启动时设置
+static BASE_URI: RwLock<String> = RwLock::new(String::new());
+
+pub fn set_base_uri(uri: &str) {
+    *BASE_URI.write().unwrap_or_else(|e| e.into_inner()) = uri.trim_end_matches('/').to_string();
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProblemType {
+    NotFound,
+    InvalidCrateName,
+    BadRequest,
+    Unauthorized,
+    Forbidden,
+    ReplicationConflict,
+    InvalidBundle,
+    RateLimited,
+    IndexUnavailable,
+    ResponseTooLarge,
+    UnsupportedProtocolVersion,
+    Overloaded,
+    Internal,
+    // 没有专门类型的状态码，例如 actix-web 自己返回的 405
+    Http(StatusCode),
+}
+
+impl ProblemType {
+    fn from_status(status: StatusCode) -> Self {
+        match status {
+            StatusCode::NOT_FOUND => ProblemType::NotFound,
+            StatusCode::BAD_REQUEST => ProblemType::BadRequest,
+            StatusCode::UNAUTHORIZED => ProblemType::Unauthorized,
+            StatusCode::FORBIDDEN => ProblemType::Forbidden,
+            StatusCode::TOO_MANY_REQUESTS => ProblemType::RateLimited,
+            StatusCode::SERVICE_UNAVAILABLE => ProblemType::IndexUnavailable,
+            StatusCode::INTERNAL_SERVER_ERROR => ProblemType::Internal,
+            status => ProblemType::Http(status),
+        }
+    }
+
+    // type URI 的最后一段，发布后不要修改
+    fn slug(&self) -> String {
+        let slug = match self {
+            ProblemType::NotFound => "not-found",
+            ProblemType::InvalidCrateName => "invalid-crate-name",
+            ProblemType::BadRequest => "bad-request",
+            ProblemType::Unauthorized => "unauthorized",
+            ProblemType::Forbidden => "forbidden",
+            ProblemType::ReplicationConflict => "replication-conflict",
+            ProblemType::InvalidBundle => "invalid-bundle",
+            ProblemType::RateLimited => "rate-limited",
+            ProblemType::IndexUnavailable => "index-unavailable",
+            ProblemType::ResponseTooLarge => "response-too-large",
+            ProblemType::UnsupportedProtocolVersion => "unsupported-protocol-version",
+            ProblemType::Overloaded => "overloaded",
+            ProblemType::Internal => "internal-error",
+            ProblemType::Http(status) => {
+                return status
+                    .canonical_reason()
+                    .unwrap_or("error")
+                    .to_ascii_lowercase()
+                    .replace(' ', "-")
+            }
+        };
+        slug.to_string()
+    }
+
+    fn status(&self) -> StatusCode {
+        match self {
+            ProblemType::NotFound => StatusCode::NOT_FOUND,
+            ProblemType::InvalidCrateName | ProblemType::BadRequest => StatusCode::BAD_REQUEST,
+            ProblemType::Unauthorized => StatusCode::UNAUTHORIZED,
+            ProblemType::Forbidden => StatusCode::FORBIDDEN,
+            ProblemType::ReplicationConflict => StatusCode::CONFLICT,
+            ProblemType::InvalidBundle | ProblemType::UnsupportedProtocolVersion => StatusCode::UNPROCESSABLE_ENTITY,
+            ProblemType::RateLimited => StatusCode::TOO_MANY_REQUESTS,
+            ProblemType::IndexUnavailable | ProblemType::Overloaded => StatusCode::SERVICE_UNAVAILABLE,
+            ProblemType::ResponseTooLarge | ProblemType::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+            ProblemType::Http(status) => *status,
+        }
+    }
+
+    fn title(&self) -> &'static str {
+        match self {
+            ProblemType::NotFound => "Not found",
+            ProblemType::InvalidCrateName => "Invalid crate name",
+            ProblemType::BadRequest => "Bad request",
+            ProblemType::Unauthorized => "Authentication required",
+            ProblemType::Forbidden => "Forbidden",
+            ProblemType::ReplicationConflict => "Replication conflict",
+            ProblemType::InvalidBundle => "Invalid replication bundle",
+            ProblemType::RateLimited => "Too many requests",
+            ProblemType::IndexUnavailable => "Index is temporarily unavailable",
+            ProblemType::ResponseTooLarge => "Response body is too large",
+            ProblemType::UnsupportedProtocolVersion => "Unsupported protocol version",
+            ProblemType::Overloaded => "Service is overloaded",
+            ProblemType::Internal => "Internal server error",
+            ProblemType::Http(status) => status.canonical_reason().unwrap_or("Error"),
+        }
+    }
+}
+
+// RFC 7807 的错误响应，Content-Type 是 application/problem+json
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ProblemDetails {
+    #[serde(rename = "type")]
+    kind: String,
+    title: String,
+    status: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    detail: Option<String>,
+    // 请求的路径，由 render_problems 填写
+    #[serde(skip_serializing_if = "Option::is_none")]
+    instance: Option<String>,
+}
+
+impl ProblemDetails {
+    pub fn new(problem: ProblemType) -> Self {
+        let base = BASE_URI.read().unwrap_or_else(|e| e.into_inner());
+        ProblemDetails {
+            kind: format!("{}/{}", base, problem.slug()),
+            title: problem.title().to_string(),
+            status: problem.status().as_u16(),
+            detail: None,
+            instance: None,
+        }
+    }
+
+    pub fn with_detail(mut self, detail: impl Into<String>) -> Self {
+        self.detail = Some(detail.into());
+        self
+    }
+
+    fn body(&self) -> String {
+        serde_json::to_string(self).expect("ProblemDetails is always serializable")
+    }
+}
+
+pub fn not_found(detail: impl Into<String>) -> ProblemDetails {
+    ProblemDetails::new(ProblemType::NotFound).with_detail(detail)
+}
+
+impl fmt::Display for ProblemDetails {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.detail {
+            Some(detail) => write!(f, "{}: {}", self.title, detail),
+            None => f.write_str(&self.title),
+        }
+    }
+}
+
+impl ResponseError for ProblemDetails {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::from_u16(self.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR)
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let mut res = HttpResponse::build(self.status_code())
+            .content_type(CONTENT_TYPE_PROBLEM)
+            .body(self.body());
+        // 让 render_problems 补上 instance
+        res.extensions_mut().insert(self.clone());
+        res
+    }
+}
+
+impl From<ProblemDetails> for HttpResponse {
+    fn from(problem: ProblemDetails) -> Self {
+        problem.error_response()
+    }
+}
+
+// 所有 4xx/5xx 响应都转换成 ProblemDetails，包括 actix-web 自己生成的错误
+// /health/ready 等返回 JSON 状态的响应保持不变
+pub async fn render_problems(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<BoxBody, impl MessageBody>>, Error> {
+    let instance = req.path().to_string();
+    let res = next.call(req).await?;
+    let status = res.status();
+    if !status.is_client_error() && !status.is_server_error() {
+        return Ok(res.map_into_right_body());
+    }
+    let problem = res.response().extensions().get::<ProblemDetails>().cloned();
+    let is_json = res
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("application/json"));
+    if problem.is_none() && is_json {
+        return Ok(res.map_into_right_body());
+    }
+
+    let (req, res) = res.into_parts();
+    let (mut head, body) = res.into_parts();
+    let mut problem = match problem {
+        Some(problem) => problem,
+        None => {
+            let problem = ProblemDetails::new(ProblemType::from_status(status));
+            // 纯文本的错误信息作为 detail，例如 extractor 的解析错误
+            match body::to_bytes_limited(body, MAX_DETAIL_BODY).await {
+                Ok(Ok(bytes)) if !bytes.trim_ascii().is_empty() => {
+                    problem.with_detail(String::from_utf8_lossy(bytes.trim_ascii()))
+                }
+                _ => problem,
+            }
+        }
+    };
+    problem.instance = Some(instance);
+    head.headers_mut()
+        .insert(CONTENT_TYPE, HeaderValue::from_static(CONTENT_TYPE_PROBLEM));
+    let res = head.set_body(BoxBody::new(problem.body()));
+    Ok(ServiceResponse::new(req, res).map_into_left_body())
+}
@@ -0,0 +1,122 @@
+use actix_web::{
+    body::{EitherBody, MessageBody},
+    dev::{Payload, ServiceRequest, ServiceResponse},
+    middleware::Next,
+    web, Error, FromRequest, HttpMessage, HttpRequest, ResponseError,
+};
+use serde::{Serialize, Serializer};
+use std::future::{ready, Ready};
+
+use crate::{
+    config::RegistryConfig,
+    problem::{ProblemDetails, ProblemType},
+};
+
+pub const PROTOCOL_HEADER: &str = "Cargo-Protocol";
+
+// 协商出的 API 协议版本，存放在请求的 extensions 里
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ProtocolVersion(pub u32);
+
+impl ProtocolVersion {
+    pub fn includes_v2_fields(&self) -> bool {
+        self.0 >= 2
+    }
+}
+
+// 没有经过 negotiate 的请求（不在 /api/ 下）按 registry.max_protocol_version 处理
+impl FromRequest for ProtocolVersion {
+    type Error = Error;
+    type Future = Ready<Result<Self, Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let version = req.extensions().get::<ProtocolVersion>().copied().unwrap_or_else(|| {
+            ProtocolVersion(
+                req.app_data::<web::Data<RegistryConfig>>()
+                    .map_or(2, |registry| registry.max_protocol_version),
+            )
+        });
+        ready(Ok(version))
+    }
+}
+
+// Cargo-Protocol: version=2，可能还带有其他用 , 或 ; 分隔的参数
+fn parse_header(value: &str) -> Option<u32> {
+    value
+        .split([',', ';'])
+        .find_map(|param| param.trim().strip_prefix("version="))
+        .and_then(|version| version.trim().parse().ok())
+}
+
+// 只在协议 v2 的响应里出现的字段，v1 的响应里省略
+#[derive(Debug, Clone)]
+pub struct V2Field<T> {
+    value: T,
+    included: bool,
+}
+
+impl<T> V2Field<T> {
+    pub fn new(value: T) -> Self {
+        V2Field {
+            value,
+            included: true,
+        }
+    }
+
+    pub fn is_omitted(&self) -> bool {
+        !self.included
+    }
+
+    pub fn negotiate(&mut self, version: ProtocolVersion) {
+        self.included = version.includes_v2_fields();
+    }
+}
+
+impl<T: Serialize> Serialize for V2Field<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.value.serialize(serializer)
+    }
+}
+
+// 按协商出的版本调整响应的格式
+pub trait Negotiate {
+    fn negotiate(&mut self, version: ProtocolVersion);
+}
+
+// 没有 Cargo-Protocol 请求头时使用 registry.max_protocol_version，也就是最新的格式
+pub async fn negotiate(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    if !req.path().starts_with("/api/") {
+        return Ok(next.call(req).await?.map_into_left_body());
+    }
+    let registry = req
+        .app_data::<web::Data<RegistryConfig>>()
+        .cloned()
+        .expect("RegistryConfig not registered");
+    let header = req
+        .headers()
+        .get(PROTOCOL_HEADER)
+        .map(|value| value.to_str().ok().and_then(parse_header));
+    let version = match header {
+        None => registry.max_protocol_version,
+        Some(Some(version))
+            if (registry.min_protocol_version..=registry.max_protocol_version).contains(&version) =>
+        {
+            version
+        }
+        Some(_) => {
+            let detail = format!(
+                "Unsupported protocol version, this registry supports versions {} to {}",
+                registry.min_protocol_version, registry.max_protocol_version
+            );
+            let res = ProblemDetails::new(ProblemType::UnsupportedProtocolVersion)
+                .with_detail(detail)
+                .error_response();
+            return Ok(req.into_response(res).map_into_right_body());
+        }
+    };
+    req.extensions_mut().insert(ProtocolVersion(version));
+    Ok(next.call(req).await?.map_into_left_body())
+}
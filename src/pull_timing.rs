@@ -0,0 +1,137 @@
+use actix_web::{web, HttpResponse};
+use serde::Serialize;
+use std::{collections::VecDeque, sync::Mutex, time::Duration};
+use utoipa::ToSchema;
+
+use crate::server::SharedState;
+
+// 最近 stats.timing_window_size 次 pull 的耗时（秒），旧的先被挤出
+pub struct PullTimings {
+    capacity: usize,
+    durations: Mutex<VecDeque<f64>>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct PullTimingSummary {
+    // 按 pull 的先后顺序
+    durations: Vec<f64>,
+    count: usize,
+    // 窗口为空时下面的值都是 null
+    min: Option<f64>,
+    max: Option<f64>,
+    mean: Option<f64>,
+    // 总体标准差
+    stddev: Option<f64>,
+    p50: Option<f64>,
+    p95: Option<f64>,
+    p99: Option<f64>,
+}
+
+// nearest-rank：不小于 p 比例样本的最小值
+fn percentile(sorted: &[f64], p: f64) -> Option<f64> {
+    let rank = (p * sorted.len() as f64).ceil() as usize;
+    sorted.get(rank.max(1) - 1).copied()
+}
+
+impl PullTimings {
+    pub fn new(capacity: usize) -> Self {
+        PullTimings {
+            capacity,
+            durations: Mutex::new(VecDeque::with_capacity(capacity)),
+        }
+    }
+
+    pub fn record(&self, duration: Duration) {
+        let mut durations = self.durations.lock().unwrap_or_else(|e| e.into_inner());
+        if durations.len() == self.capacity {
+            durations.pop_front();
+        }
+        durations.push_back(duration.as_secs_f64());
+    }
+
+    fn summary(&self) -> PullTimingSummary {
+        let durations: Vec<f64> = self
+            .durations
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .copied()
+            .collect();
+        let mut sorted = durations.clone();
+        sorted.sort_by(f64::total_cmp);
+        let count = durations.len();
+        let mean = (count > 0).then(|| durations.iter().sum::<f64>() / count as f64);
+        let stddev = mean.map(|mean| {
+            let variance = durations.iter().map(|d| (d - mean).powi(2)).sum::<f64>() / count as f64;
+            variance.sqrt()
+        });
+        PullTimingSummary {
+            count,
+            min: sorted.first().copied(),
+            max: sorted.last().copied(),
+            mean,
+            stddev,
+            p50: percentile(&sorted, 0.50),
+            p95: percentile(&sorted, 0.95),
+            p99: percentile(&sorted, 0.99),
+            durations,
+        }
+    }
+}
+
+// 直接给出百分位数，不需要自己从 index_pull_duration_seconds 直方图计算
+#[utoipa::path(
+    get,
+    path = "/api/v1/stats/pull-timing",
+    tag = "index",
+    responses((status = 200, description = "Durations of the most recent pulls in seconds", body = PullTimingSummary))
+)]
+pub async fn pull_timing(state: web::Data<SharedState>) -> HttpResponse {
+    HttpResponse::Ok().json(state.pull_timings.summary())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn percentiles_over_last_pulls() {
+        let timings = PullTimings::new(100);
+        // 前 100 次很慢，会被后面的挤出窗口
+        for _ in 0..100 {
+            timings.record(Duration::from_secs(1000));
+        }
+        // 打乱顺序的 1..=100 秒
+        for i in 0..100 {
+            timings.record(Duration::from_secs(i * 37 % 100 + 1));
+        }
+        let summary = timings.summary();
+        assert_eq!(summary.count, 100);
+        assert_eq!(summary.durations.len(), 100);
+        assert_eq!(summary.durations[..3], [1.0, 38.0, 75.0]);
+        assert_eq!(summary.min, Some(1.0));
+        assert_eq!(summary.max, Some(100.0));
+        assert_eq!(summary.p50, Some(50.0));
+        assert_eq!(summary.p95, Some(95.0));
+        assert_eq!(summary.p99, Some(99.0));
+        assert_eq!(summary.mean, Some(50.5));
+        // 1..=n 的总体标准差是 sqrt((n^2 - 1) / 12)
+        assert!((summary.stddev.unwrap() - (9999.0f64 / 12.0).sqrt()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn small_windows() {
+        let timings = PullTimings::new(3);
+        let summary = timings.summary();
+        assert_eq!((summary.count, summary.p99, summary.mean), (0, None, None));
+        timings.record(Duration::from_millis(1500));
+        let summary = timings.summary();
+        assert_eq!((summary.p50, summary.p99, summary.stddev), (Some(1.5), Some(1.5), Some(0.0)));
+        for secs in [4, 2, 3] {
+            timings.record(Duration::from_secs(secs));
+        }
+        let summary = timings.summary();
+        assert_eq!(summary.durations, [4.0, 2.0, 3.0]);
+        assert_eq!((summary.p50, summary.p99), (Some(3.0), Some(4.0)));
+    }
+}
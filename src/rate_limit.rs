@@ -0,0 +1,271 @@
+use actix_web::{
+    body::{EitherBody, MessageBody},
+    dev::{ServiceRequest, ServiceResponse},
+    middleware::Next,
+    web, Error, HttpResponse,
+};
+use dashmap::DashMap;
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    config::{RateLimitConfig, TokenBucketConfig},
+    forwarded::TrustedProxies,
+    metrics, sparse,
+};
+
+// 每天至少补充一个令牌，Retry-After 最多一天
+pub const MIN_REFILL_PER_SEC: f64 = 1.0 / 86400.0;
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(86400);
+
+// 可以单独限流的接口分组
+pub const ENDPOINT_GROUPS: &[&str] = &["index", "search", "stats", "prefetch"];
+
+// 一个 prefetch 请求最多读 100 个索引文件，没有配置 [rate_limit.endpoints.prefetch] 时也限流
+fn default_bucket(group: &str) -> Option<TokenBucketConfig> {
+    (group == "prefetch").then_some(TokenBucketConfig {
+        capacity: 5,
+        refill_per_sec: 0.1,
+    })
+}
+
+fn endpoint_group(path: &str) -> Option<&'static str> {
+    match path {
+        "/api/v1/crates" => Some("search"),
+        "/api/v1/index/stats" => Some("stats"),
+        "/api/v1/prefetch" => Some("prefetch"),
+        _ if sparse::is_crate_request(path) => Some("index"),
+        _ => None,
+    }
+}
+
+struct TokenBucketState {
+    tokens: f64,
+    last_seen: Instant,
+}
+
+struct TokenBucket {
+    config: TokenBucketConfig,
+    states: DashMap<IpAddr, TokenBucketState>,
+}
+
+impl TokenBucket {
+    // 令牌不足时返回需要等待的时间
+    fn try_acquire(&self, ip: IpAddr, now: Instant) -> Result<(), Duration> {
+        let capacity = self.config.capacity as f64;
+        // entry() 持有分片写锁，同一个 IP 在多个 worker 上的并发请求不会重复消费令牌
+        let mut state = self.states.entry(ip).or_insert_with(|| TokenBucketState {
+            tokens: capacity,
+            last_seen: now,
+        });
+        let elapsed = now.saturating_duration_since(state.last_seen).as_secs_f64();
+        state.tokens = (state.tokens + elapsed * self.config.refill_per_sec).min(capacity);
+        state.last_seen = now;
+        if state.tokens >= 1.0 {
+            state.tokens -= 1.0;
+            Ok(())
+        } else {
+            // refill_per_sec 很小时商可能超出 Duration 的范围
+            let wait = (1.0 - state.tokens) / self.config.refill_per_sec;
+            Err(Duration::try_from_secs_f64(wait).map_or(MAX_RETRY_AFTER, |wait| wait.min(MAX_RETRY_AFTER)))
+        }
+    }
+}
+
+pub struct RateLimiter {
+    buckets: HashMap<&'static str, TokenBucket>,
+    cleanup_interval: Duration,
+    last_cleanup: Mutex<Instant>,
+}
+
+impl RateLimiter {
+    pub fn new(config: &RateLimitConfig) -> Self {
+        let buckets = ENDPOINT_GROUPS
+            .iter()
+            .filter_map(|&group| {
+                config.endpoints.get(group).cloned().or_else(|| default_bucket(group)).map(|bucket| {
+                    (
+                        group,
+                        TokenBucket {
+                            config: bucket,
+                            states: DashMap::new(),
+                        },
+                    )
+                })
+            })
+            .collect();
+        RateLimiter {
+            buckets,
+            cleanup_interval: Duration::from_secs(config.cleanup_interval_secs),
+            last_cleanup: Mutex::new(Instant::now()),
+        }
+    }
+
+    // 由请求顺带触发，清理超过 cleanup_interval 没有请求的 IP
+    fn cleanup_if_due(&self, now: Instant) {
+        let Ok(mut last_cleanup) = self.last_cleanup.try_lock() else {
+            return;
+        };
+        if now.saturating_duration_since(*last_cleanup) < self.cleanup_interval {
+            return;
+        }
+        *last_cleanup = now;
+        for bucket in self.buckets.values() {
+            bucket
+                .states
+                .retain(|_, state| now.saturating_duration_since(state.last_seen) < self.cleanup_interval);
+        }
+    }
+}
+
+pub async fn limit_requests(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    let limiter = req
+        .app_data::<web::Data<RateLimiter>>()
+        .cloned()
+        .expect("RateLimiter not registered");
+    let proxies = req
+        .app_data::<web::Data<TrustedProxies>>()
+        .cloned()
+        .expect("TrustedProxies not registered");
+    let group = endpoint_group(req.path());
+    let bucket = group.and_then(|group| limiter.buckets.get(group));
+    // 经过反向代理时所有请求的对端地址都是代理，按 X-Forwarded-For 里的客户端地址限流
+    if let (Some(group), Some(bucket), Some(client)) = (group, bucket, proxies.client_ip(req.request())) {
+        let now = Instant::now();
+        limiter.cleanup_if_due(now);
+        if let Err(retry_after) = bucket.try_acquire(client, now) {
+            metrics::RATE_LIMIT_REJECTIONS_TOTAL.inc(group);
+            let res = HttpResponse::TooManyRequests()
+                .insert_header(("Retry-After", (retry_after.as_secs_f64().ceil() as u64).max(1).to_string()))
+                .finish();
+            return Ok(req.into_response(res).map_into_right_body());
+        }
+    }
+    next.call(req).await.map(|res| res.map_into_left_body())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+    use actix_web::{middleware::from_fn, test as actix_test, App};
+    use futures_util::future::join_all;
+
+    fn rate_limit_config(endpoints: &[(&str, u32, f64)]) -> RateLimitConfig {
+        RateLimitConfig {
+            cleanup_interval_secs: 300,
+            endpoints: endpoints
+                .iter()
+                .map(|&(group, capacity, refill_per_sec)| {
+                    (group.to_string(), TokenBucketConfig { capacity, refill_per_sec })
+                })
+                .collect(),
+        }
+    }
+
+    fn bucket(capacity: u32, refill_per_sec: f64) -> TokenBucket {
+        TokenBucket {
+            config: TokenBucketConfig { capacity, refill_per_sec },
+            states: DashMap::new(),
+        }
+    }
+
+    #[test]
+    fn concurrent_threads_share_one_bucket() {
+        let bucket = bucket(50, MIN_REFILL_PER_SEC);
+        let ip: IpAddr = "192.0.2.1".parse().unwrap();
+        let now = Instant::now();
+        let granted: usize = std::thread::scope(|scope| {
+            let threads: Vec<_> = (0..16)
+                .map(|_| scope.spawn(|| (0..20).filter(|_| bucket.try_acquire(ip, now).is_ok()).count()))
+                .collect();
+            threads.into_iter().map(|thread| thread.join().unwrap()).sum()
+        });
+        assert_eq!(granted, 50);
+    }
+
+    #[test]
+    fn refills_per_ip() {
+        let bucket = bucket(2, 0.5);
+        let (a, b): (IpAddr, IpAddr) = ("192.0.2.1".parse().unwrap(), "192.0.2.2".parse().unwrap());
+        let now = Instant::now();
+        assert!(bucket.try_acquire(a, now).is_ok());
+        assert!(bucket.try_acquire(a, now).is_ok());
+        assert_eq!(bucket.try_acquire(a, now), Err(Duration::from_secs(2)));
+        // 其他 IP 有自己的令牌
+        assert!(bucket.try_acquire(b, now).is_ok());
+        assert!(bucket.try_acquire(a, now + Duration::from_secs(2)).is_ok());
+        // 补充不超过 capacity
+        let later = now + Duration::from_secs(3600);
+        assert!(bucket.try_acquire(a, later).is_ok());
+        assert!(bucket.try_acquire(a, later).is_ok());
+        assert!(bucket.try_acquire(a, later).is_err());
+    }
+
+    #[test]
+    fn retry_after_is_bounded() {
+        let bucket = bucket(1, f64::MIN_POSITIVE);
+        let ip: IpAddr = "192.0.2.1".parse().unwrap();
+        let now = Instant::now();
+        assert!(bucket.try_acquire(ip, now).is_ok());
+        assert_eq!(bucket.try_acquire(ip, now), Err(MAX_RETRY_AFTER));
+    }
+
+    #[test]
+    fn cleans_up_idle_ips() {
+        let limiter = RateLimiter::new(&rate_limit_config(&[("index", 5, 1.0)]));
+        let now = Instant::now();
+        let bucket = &limiter.buckets["index"];
+        bucket.try_acquire("192.0.2.1".parse().unwrap(), now).unwrap();
+        bucket.try_acquire("192.0.2.2".parse().unwrap(), now + Duration::from_secs(200)).unwrap();
+        limiter.cleanup_if_due(now + Duration::from_secs(400));
+        assert_eq!(bucket.states.len(), 1);
+        assert!(bucket.states.contains_key(&"192.0.2.2".parse::<IpAddr>().unwrap()));
+    }
+
+    #[actix_web::test]
+    async fn limits_concurrent_requests_per_group() {
+        let web_config = test_support::web_config("trusted_proxies = [\"10.0.0.1\"]");
+        let app = actix_test::init_service(
+            App::new()
+                .app_data(web::Data::new(RateLimiter::new(&rate_limit_config(&[("index", 3, MIN_REFILL_PER_SEC)]))))
+                .app_data(web::Data::new(TrustedProxies::from_config(&web_config)))
+                .wrap(from_fn(limit_requests))
+                .default_service(web::to(HttpResponse::Ok)),
+        )
+        .await;
+        let request = |path: &'static str, client: &'static str| {
+            let app = &app;
+            async move {
+                let req = actix_test::TestRequest::get()
+                    .uri(path)
+                    .peer_addr("10.0.0.1:50000".parse().unwrap())
+                    .insert_header(("X-Forwarded-For", client))
+                    .to_request();
+                actix_test::call_service(app, req).await
+            }
+        };
+
+        let responses = join_all((0..10).map(|_| request("/se/rd/serde", "192.0.2.1"))).await;
+        let statuses: Vec<_> = responses.iter().map(|res| res.status().as_u16()).collect();
+        assert_eq!(statuses.iter().filter(|&&status| status == 200).count(), 3, "{:?}", statuses);
+        assert_eq!(statuses.iter().filter(|&&status| status == 429).count(), 7, "{:?}", statuses);
+        let rejected = responses.iter().find(|res| res.status() == 429).unwrap();
+        assert_eq!(rejected.headers().get("Retry-After").unwrap(), "86400");
+
+        // 另一个客户端、没有配置的分组和不限流的路径不受影响
+        assert_eq!(request("/se/rd/serde", "192.0.2.2").await.status(), 200);
+        assert_eq!(request("/api/v1/crates", "192.0.2.1").await.status(), 200);
+        assert_eq!(request("/health", "192.0.2.1").await.status(), 200);
+        // prefetch 没有配置时使用默认的令牌桶
+        let prefetch = join_all((0..6).map(|_| request("/api/v1/prefetch", "192.0.2.1"))).await;
+        assert_eq!(prefetch.iter().filter(|res| res.status() == 429).count(), 1);
+    }
+}
@@ -0,0 +1,77 @@
+use actix_web::http::{header::HeaderMap, Uri};
+use percent_encoding::percent_decode_str;
+
+const REDACTED: &str = "<redacted>";
+
+// 查询参数名不区分大小写，例如 ?token=xxx&q=serde -> ?token=<redacted>&q=serde
+pub fn redact_uri(uri: &Uri, params: &[String]) -> String {
+    let Some(query) = uri.query() else {
+        return uri.to_string();
+    };
+    let query: Vec<String> = query
+        .split('&')
+        .map(|pair| {
+            let (key, _) = pair.split_once('=').unwrap_or((pair, ""));
+            let decoded = percent_decode_str(key).decode_utf8_lossy();
+            if params.iter().any(|p| p.eq_ignore_ascii_case(&decoded)) {
+                format!("{}={}", key, REDACTED)
+            } else {
+                pair.to_string()
+            }
+        })
+        .collect();
+    format!("{}?{}", uri.path(), query.join("&"))
+}
+
+pub fn redact_headers(headers: &HeaderMap, names: &[String]) -> String {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            let value = if names.iter().any(|n| n.eq_ignore_ascii_case(name.as_str())) {
+                REDACTED
+            } else {
+                value.to_str().unwrap_or("<binary>")
+            };
+            format!("{}: {}", name, value)
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::header::{HeaderName, HeaderValue};
+
+    fn params(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn redacts_matching_query_params() {
+        let redact = |uri: &str| redact_uri(&uri.parse().unwrap(), &params(&["token", "api_key"]));
+        assert_eq!(
+            redact("/se/rd/serde?token=eyJhbGciOi.x.y&q=serde"),
+            "/se/rd/serde?token=<redacted>&q=serde"
+        );
+        // 参数名不区分大小写，也按解码后的名字匹配
+        assert_eq!(redact("/a?TOKEN=1&api%5Fkey=2&page=3"), "/a?TOKEN=<redacted>&api%5Fkey=<redacted>&page=3");
+        assert_eq!(redact("/a?token&q="), "/a?token=<redacted>&q=");
+        assert_eq!(redact("/a?tokens=1&my_token=2"), "/a?tokens=1&my_token=2");
+        assert_eq!(redact("/a"), "/a");
+        assert_eq!(redact_uri(&"/a?token=1".parse().unwrap(), &[]), "/a?token=1");
+    }
+
+    #[test]
+    fn redacts_matching_headers() {
+        let mut headers = HeaderMap::new();
+        for (name, value) in [("authorization", "Bearer secret"), ("cookie", "session=secret"), ("user-agent", "cargo/1.80")] {
+            headers.insert(HeaderName::from_static(name), HeaderValue::from_static(value));
+        }
+        let logged = redact_headers(&headers, &params(&["Authorization", "Cookie"]));
+        assert!(!logged.contains("secret"), "{}", logged);
+        assert!(logged.contains("authorization: <redacted>"), "{}", logged);
+        assert!(logged.contains("cookie: <redacted>"), "{}", logged);
+        assert!(logged.contains("user-agent: cargo/1.80"), "{}", logged);
+    }
+}
@@ -0,0 +1,183 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::path::Path;
+use utoipa::ToSchema;
+
+use crate::{config::AppConfig, forwarded::TrustedProxies, snapshot::ServingRoot};
+
+const DEFAULT_DL: &str = "https://static.crates.io/crates";
+
+// 未配置 app.public_url 时根据请求的 scheme 和 Host（含端口）推断，X-Forwarded-* 只在来自 web.trusted_proxies 时使用
+fn base_url(app: &AppConfig, req: &HttpRequest) -> String {
+    match &app.public_url {
+        Some(url) => url.trim_end_matches('/').to_string(),
+        None => {
+            let proxies = req
+                .app_data::<web::Data<TrustedProxies>>()
+                .cloned()
+                .expect("TrustedProxies not registered");
+            let scheme = if proxies.is_https(req) { "https" } else { "http" };
+            format!("{}://{}", scheme, proxies.host(req))
+        }
+    }
+}
+
+// 上游 config.json 的内容，读取失败时返回空对象
+fn upstream_config(repo_path: &Path) -> Map<String, Value> {
+    std::fs::read(repo_path.join("config.json"))
+        .ok()
+        .and_then(|content| serde_json::from_slice(&content).ok())
+        .unwrap_or_default()
+}
+
+// 本服务提供索引和搜索 API；crate 文件本身不经过本服务，dl 保留上游的地址
+fn registry_config(app: &AppConfig, root: &ServingRoot, req: &HttpRequest) -> Map<String, Value> {
+    let base = base_url(app, req);
+    let mut config = upstream_config(&root.get());
+    config
+        .entry("dl")
+        .or_insert_with(|| Value::String(DEFAULT_DL.to_string()));
+    config.insert("api".to_string(), Value::String(base));
+    config
+}
+
+#[utoipa::path(
+    get,
+    path = "/config.json",
+    tag = "index",
+    responses((status = 200, description = "Sparse index config.json with api pointing to this server", body = Object))
+)]
+pub async fn config_json(
+    req: HttpRequest,
+    app: web::Data<AppConfig>,
+    root: web::Data<ServingRoot>,
+) -> HttpResponse {
+    HttpResponse::Ok().json(registry_config(&app, &root, &req))
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct WellKnown {
+    registry: &'static str,
+    name: String,
+    index: String,
+    #[schema(value_type = String)]
+    dl: Option<Value>,
+    #[schema(value_type = String)]
+    api: Option<Value>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/.well-known/cargo-registry",
+    tag = "index",
+    responses((status = 200, description = "Registry discovery document", body = WellKnown))
+)]
+pub async fn well_known(
+    req: HttpRequest,
+    app: web::Data<AppConfig>,
+    root: web::Data<ServingRoot>,
+) -> HttpResponse {
+    let mut config = registry_config(&app, &root, &req);
+    HttpResponse::Ok().json(WellKnown {
+        registry: "cargo",
+        name: app.name.clone(),
+        index: format!("sparse+{}/", base_url(&app, &req)),
+        dl: config.remove("dl"),
+        api: config.remove("api"),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+    use actix_web::{test as actix_test, App};
+    use serde_json::json;
+
+    // 用同样的请求头分别请求 config.json 和 /.well-known/cargo-registry
+    async fn fetch(
+        app_config: AppConfig,
+        upstream_config: Option<&str>,
+        req: impl Fn() -> actix_test::TestRequest,
+    ) -> (Value, Value) {
+        let dir = tempfile::tempdir().unwrap();
+        if let Some(content) = upstream_config {
+            std::fs::write(dir.path().join("config.json"), content).unwrap();
+        }
+        let web_config = test_support::web_config("trusted_proxies = [\"10.0.0.1\"]");
+        let app = actix_test::init_service(
+            App::new()
+                .app_data(web::Data::new(app_config))
+                .app_data(web::Data::new(ServingRoot::new(dir.path(), false, false)))
+                .app_data(web::Data::new(TrustedProxies::from_config(&web_config)))
+                .route("/config.json", web::get().to(config_json))
+                .route("/.well-known/cargo-registry", web::get().to(well_known)),
+        )
+        .await;
+        let config = actix_test::call_and_read_body_json(&app, req().uri("/config.json").to_request()).await;
+        let well_known = req().uri("/.well-known/cargo-registry").to_request();
+        (config, actix_test::call_and_read_body_json(&app, well_known).await)
+    }
+
+    fn client(host: &str) -> actix_test::TestRequest {
+        actix_test::TestRequest::get()
+            .insert_header(("Host", host))
+            .peer_addr("192.0.2.7:50000".parse().unwrap())
+    }
+
+    #[actix_web::test]
+    async fn infers_http_host() {
+        let upstream = r#"{"dl":"https://static.crates.io/crates","api":"https://crates.io"}"#;
+        let (config, well_known) = fetch(AppConfig::default(), Some(upstream), || client("mirror.example")).await;
+        assert_eq!(config, json!({"dl": "https://static.crates.io/crates", "api": "http://mirror.example"}));
+        assert_eq!(well_known["index"], "sparse+http://mirror.example/");
+        assert_eq!(well_known["api"], "http://mirror.example");
+        assert_eq!(well_known["registry"], "cargo");
+        assert_eq!(well_known["name"], "local-crates-io-index");
+    }
+
+    #[actix_web::test]
+    async fn keeps_non_standard_port() {
+        let (config, well_known) = fetch(AppConfig::default(), None, || client("mirror.example:8443")).await;
+        assert_eq!(config["api"], "http://mirror.example:8443");
+        assert_eq!(well_known["index"], "sparse+http://mirror.example:8443/");
+        // 上游没有 config.json 时使用 crates.io 的下载地址
+        assert_eq!(well_known["dl"], DEFAULT_DL);
+    }
+
+    #[actix_web::test]
+    async fn uses_https_from_trusted_proxy() {
+        let req = || {
+            client("10.0.0.1:8080")
+                .peer_addr("10.0.0.1:50000".parse().unwrap())
+                .insert_header(("X-Forwarded-Proto", "https"))
+                .insert_header(("X-Forwarded-Host", "index.example"))
+        };
+        let (config, well_known) = fetch(AppConfig::default(), None, req).await;
+        assert_eq!(config["api"], "https://index.example");
+        assert_eq!(well_known["index"], "sparse+https://index.example/");
+    }
+
+    #[actix_web::test]
+    async fn ignores_forwarded_headers_from_clients() {
+        let req = || {
+            client("mirror.example")
+                .insert_header(("X-Forwarded-Proto", "https"))
+                .insert_header(("X-Forwarded-Host", "evil.example"))
+        };
+        let (config, _) = fetch(AppConfig::default(), None, req).await;
+        assert_eq!(config["api"], "http://mirror.example");
+    }
+
+    #[actix_web::test]
+    async fn public_url_overrides_host() {
+        let app = AppConfig {
+            public_url: Some("https://index.example/".to_string()),
+            ..AppConfig::default()
+        };
+        let (config, well_known) = fetch(app, None, || client("mirror.example:8080")).await;
+        assert_eq!(config["api"], "https://index.example");
+        assert_eq!(well_known["index"], "sparse+https://index.example/");
+    }
+}
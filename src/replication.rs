@@ -0,0 +1,225 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use git2::{Oid, Repository};
+use serde_json::json;
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+    sync::Arc,
+    time::Instant,
+};
+use subtle::ConstantTimeEq;
+use tracing::{error, info, warn};
+
+use crate::{
+    config::ReplicationConfig,
+    events::EventLog,
+    git::{self, RepoLock},
+    health::HealthState,
+    metrics, panic_recovery,
+    problem::{ProblemDetails, ProblemType},
+    snapshot::ServingRoot,
+};
+
+const INSTANCE_HEADER: &str = "X-Replication-Instance-Id";
+const HEAD_HEADER: &str = "X-Replication-Head";
+pub const MAX_BUNDLE_SIZE: usize = 256 * 1024 * 1024;
+
+pub struct Replicator {
+    pub config: ReplicationConfig,
+    pub repo_path: PathBuf,
+    pub repo_lock: Arc<RepoLock>,
+    pub serving_root: Arc<ServingRoot>,
+    pub events: Option<Arc<EventLog>>,
+    // repo.require_signed_commits：peer 推送的提交和上游的一样必须带有可以验证的签名
+    pub require_signed_commits: bool,
+    pub health: web::Data<HealthState>,
+}
+
+fn run_git(repo_path: &Path, args: &[&str]) -> Result<(), String> {
+    let output = Command::new("git")
+        .current_dir(repo_path)
+        .args(args)
+        .output()
+        .map_err(|e| format!("Failed to run git {}: {}", args.join(" "), e))?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(format!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))
+    }
+}
+
+// 增量 bundle：只包含 old..new 之间的提交，对端必须已经有 old
+fn create_bundle(repo_path: &Path, old: Oid, new: Oid) -> Result<Vec<u8>, String> {
+    // git 在仓库目录下执行，bundle 路径相对于仓库
+    let bundle = format!(".git/replication-{}.bundle", new);
+    let bundle_path = repo_path.join(&bundle);
+    let exclude = format!("^{}", old);
+    let result = run_git(repo_path, &["bundle", "create", &bundle, "refs/heads/master", &exclude])
+        .and_then(|_| std::fs::read(&bundle_path).map_err(|e| e.to_string()));
+    let _ = std::fs::remove_file(&bundle_path);
+    result
+}
+
+// pull 成功 fast-forward 之后把新的提交推送给所有 peer
+pub async fn push_to_peers(replicator: Arc<Replicator>, old: Oid, new: Oid) {
+    let repo_path = replicator.repo_path.clone();
+    let bundle = match tokio::task::spawn_blocking(move || create_bundle(&repo_path, old, new)).await {
+        Ok(Ok(bundle)) => bundle,
+        Ok(Err(e)) => {
+            error!("Failed to create replication bundle: {}", e);
+            return;
+        }
+        Err(e) => {
+            error!("Replication bundle task failed: {}", e);
+            return;
+        }
+    };
+
+    let client = reqwest::Client::new();
+    for peer in &replicator.config.peers {
+        let url = format!("{}/api/v1/replicate", peer.trim_end_matches('/'));
+        let start = Instant::now();
+        let result = client
+            .post(&url)
+            .bearer_auth(&replicator.config.token)
+            .header(INSTANCE_HEADER, &replicator.config.instance_id)
+            .header(HEAD_HEADER, new.to_string())
+            .body(bundle.clone())
+            .send()
+            .await;
+        metrics::REPLICATION_PUSH_LATENCY_SECONDS.observe(start.elapsed());
+        match result {
+            Ok(res) if res.status().is_success() => info!("Replicated {} to {}", new, peer),
+            Ok(res) => warn!("Peer {} rejected replication of {}: {}", peer, new, res.status()),
+            Err(e) => warn!("Failed to replicate {} to {}: {}", new, peer, e),
+        }
+    }
+}
+
+impl Replicator {
+    // HEAD 变化之后更新快照并记录事件；按 last_update 失效的缓存随之失效
+    pub fn publish(&self, repo: &Repository) -> Result<(), String> {
+        self.serving_root.publish(repo)?;
+        if let Some(events) = &self.events {
+            events.record(repo);
+        }
+        self.health.record_update();
+        Ok(())
+    }
+}
+
+enum ApplyOutcome {
+    UpToDate,
+    Applied,
+    KeptLocal,
+}
+
+fn apply_bundle(replicator: &Replicator, peer_id: &str, bundle: &[u8]) -> Result<ApplyOutcome, String> {
+    let repo_path = &replicator.repo_path;
+    let _guard = replicator.repo_lock.lock().map_err(|e| e.to_string())?;
+
+    let bundle_arg = ".git/replication-incoming.bundle";
+    let bundle_path = repo_path.join(bundle_arg);
+    std::fs::write(&bundle_path, bundle).map_err(|e| e.to_string())?;
+    let result = run_git(repo_path, &["bundle", "verify", "-q", bundle_arg]).and_then(|_| {
+        run_git(
+            repo_path,
+            &["fetch", "-q", bundle_arg, "+refs/heads/master:refs/replication/incoming"],
+        )
+    });
+    let _ = std::fs::remove_file(&bundle_path);
+    result?;
+
+    let repo = Repository::open(repo_path).map_err(|e| e.to_string())?;
+    let incoming = repo
+        .refname_to_id("refs/replication/incoming")
+        .map_err(|e| e.to_string())?;
+    let local = repo.refname_to_id("refs/heads/master").map_err(|e| e.to_string())?;
+
+    if incoming == local || repo.graph_descendant_of(local, incoming).unwrap_or(false) {
+        return Ok(ApplyOutcome::UpToDate);
+    }
+    // fast-forward 和冲突时的重置都要验证 local 和 incoming 的分叉点之后的每个提交
+    if replicator.require_signed_commits {
+        if let Err(e) = git::verify_commit_range(&repo, local, incoming) {
+            metrics::SIGNATURE_VERIFICATION_FAILURES_TOTAL.inc();
+            return Err(format!("Refusing unverified replication bundle {}: {}", incoming, e));
+        }
+    }
+    if repo.graph_descendant_of(incoming, local).unwrap_or(false) {
+        git::move_master(&repo, incoming, "Replication fast-forward").map_err(|e| e.to_string())?;
+        replicator.publish(&repo)?;
+        return Ok(ApplyOutcome::Applied);
+    }
+
+    // 历史分叉时以 instance_id 字典序最小的实例为准
+    metrics::REPLICATION_CONFLICTS_TOTAL.inc();
+    if peer_id < replicator.config.instance_id.as_str() {
+        warn!("Replication conflict with {}, resetting master to peer's {}", peer_id, incoming);
+        git::move_master(&repo, incoming, "Replication conflict reset").map_err(|e| e.to_string())?;
+        replicator.publish(&repo)?;
+        Ok(ApplyOutcome::Applied)
+    } else {
+        warn!("Replication conflict with {}, keeping local {}", peer_id, local);
+        Ok(ApplyOutcome::KeptLocal)
+    }
+}
+
+pub async fn receive(
+    req: HttpRequest,
+    replicator: web::Data<Replicator>,
+    body: web::Bytes,
+) -> actix_web::Result<HttpResponse> {
+    let config = &replicator.config;
+    let authorized = !config.token.is_empty()
+        && req
+            .headers()
+            .get("Authorization")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "))
+            // 常数时间比较，不从响应时间泄露 token 的前缀
+            .is_some_and(|token| bool::from(token.as_bytes().ct_eq(config.token.as_bytes())));
+    if !authorized {
+        return Ok(ProblemDetails::new(ProblemType::Forbidden)
+            .with_detail("missing or invalid replication token")
+            .into());
+    }
+    let Some(peer_id) = req
+        .headers()
+        .get(INSTANCE_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.to_string())
+    else {
+        return Ok(ProblemDetails::new(ProblemType::BadRequest)
+            .with_detail(format!("Missing {} header", INSTANCE_HEADER))
+            .into());
+    };
+
+    let replicator = replicator.into_inner();
+    let result = panic_recovery::block(move || {
+        let outcome = apply_bundle(&replicator, &peer_id, &body);
+        (peer_id, outcome)
+    })
+    .await?;
+
+    Ok(match result {
+        (peer_id, Ok(ApplyOutcome::Applied)) => {
+            info!("Applied replication bundle from {}", peer_id);
+            HttpResponse::Ok().json(json!({ "status": "applied" }))
+        }
+        (_, Ok(ApplyOutcome::UpToDate)) => HttpResponse::Ok().json(json!({ "status": "up-to-date" })),
+        (_, Ok(ApplyOutcome::KeptLocal)) => {
+            ProblemDetails::new(ProblemType::ReplicationConflict)
+                .with_detail("kept local history")
+                .into()
+        }
+        (peer_id, Err(e)) => {
+            warn!("Failed to apply replication bundle from {}: {}", peer_id, e);
+            ProblemDetails::new(ProblemType::InvalidBundle).with_detail(e).into()
+        }
+    })
+}
@@ -0,0 +1,214 @@
+use git2::{ObjectType, Repository};
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+use tokio::time;
+use tracing::{error, warn};
+
+use crate::{git::RepoLock, metrics};
+
+const OBJECT_TYPES: &[ObjectType] = &[
+    ObjectType::Commit,
+    ObjectType::Tree,
+    ObjectType::Blob,
+    ObjectType::Tag,
+];
+
+struct RepoStats {
+    objects: HashMap<&'static str, i64>,
+    pack_files: i64,
+    repo_size: u64,
+    working_tree_size: u64,
+    stale_refs: i64,
+}
+
+// 不跟随符号链接；.git 目录单独统计
+pub fn dir_size(path: &Path, skip: Option<&Path>) -> u64 {
+    let Ok(entries) = fs::read_dir(path) else {
+        return 0;
+    };
+    entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| {
+            let path = entry.path();
+            match entry.metadata() {
+                Ok(_) if Some(path.as_path()) == skip => 0,
+                Ok(metadata) if metadata.is_dir() => dir_size(&path, skip),
+                Ok(metadata) => metadata.len(),
+                Err(_) => 0,
+            }
+        })
+        .sum()
+}
+
+// 上次 fetch 的 FETCH_HEAD 里没有出现的远程跟踪分支，说明上游已经删除了它
+fn stale_refs(repo: &Repository) -> Result<i64, git2::Error> {
+    let mut fetched = HashSet::new();
+    let mut entries = 0;
+    let fetch_head = repo.fetchhead_foreach(|name, _, _, _| {
+        entries += 1;
+        if let Some(branch) = name.strip_prefix("refs/heads/") {
+            fetched.insert(format!("refs/remotes/origin/{}", branch));
+        }
+        true
+    });
+    // 还没有 fetch 过；clone 之后 FETCH_HEAD 是空文件
+    if fetch_head.is_err() || entries == 0 {
+        return Ok(0);
+    }
+    let mut stale = 0;
+    for reference in repo.references_glob("refs/remotes/origin/*")? {
+        let reference = reference?;
+        let Some(name) = reference.name() else {
+            continue;
+        };
+        if name != "refs/remotes/origin/HEAD" && !fetched.contains(name) {
+            stale += 1;
+        }
+    }
+    Ok(stale)
+}
+
+fn collect(repo_path: &Path, repo_lock: &RepoLock) -> Result<RepoStats, git2::Error> {
+    let repo = Repository::open(repo_path)?;
+    let odb = repo.odb()?;
+    let mut objects: HashMap<&'static str, i64> = HashMap::new();
+    // 同一个对象可能同时存在于多个 pack 或者既有 loose 又有 packed 的副本
+    let mut seen = HashSet::new();
+    odb.foreach(|oid| {
+        if seen.insert(*oid) {
+            if let Ok((_, kind)) = odb.read_header(*oid) {
+                *objects.entry(kind.str()).or_default() += 1;
+            }
+        }
+        true
+    })?;
+
+    // repo.path() 以 "/" 结尾，Path 按路径分量比较，不影响和 read_dir 返回的路径比较
+    let git_dir = repo.path().to_path_buf();
+    let pack_files = count_pack_files(&git_dir);
+    let working_tree_size = repo
+        .workdir()
+        .map(|workdir| dir_size(workdir, Some(&git_dir)))
+        .unwrap_or(0);
+
+    // fetch 过程中 FETCH_HEAD 会被重写，只在这一步持有锁，不阻塞 pull 太久
+    let stale_refs = {
+        let _guard = repo_lock
+            .lock()
+            .map_err(|e| git2::Error::from_str(&e.to_string()))?;
+        stale_refs(&repo)?
+    };
+
+    Ok(RepoStats {
+        objects,
+        pack_files,
+        repo_size: dir_size(&git_dir, None),
+        working_tree_size,
+        stale_refs,
+    })
+}
+
+// .git/objects/pack 里的 .pack 文件数，目录不存在时为 0
+pub fn count_pack_files(git_dir: &Path) -> i64 {
+    fs::read_dir(git_dir.join("objects/pack"))
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .filter(|entry| entry.path().extension().is_some_and(|ext| ext == "pack"))
+                .count() as i64
+        })
+        .unwrap_or(0)
+}
+
+fn publish(stats: &RepoStats) {
+    for kind in OBJECT_TYPES {
+        let kind = kind.str();
+        metrics::GIT_OBJECTS_TOTAL.set(kind, stats.objects.get(kind).copied().unwrap_or(0));
+    }
+    metrics::GIT_PACK_FILES_TOTAL.set(stats.pack_files);
+    metrics::GIT_REPO_SIZE_BYTES.set(stats.repo_size as i64);
+    metrics::GIT_WORKING_TREE_SIZE_BYTES.set(stats.working_tree_size as i64);
+    metrics::GIT_STALE_REFS_TOTAL.set(stats.stale_refs);
+}
+
+// 遍历所有对象比较慢，按 repo.stats_interval_secs 定时收集，/metrics 返回上一次的结果
+pub async fn run(repo_path: PathBuf, repo_lock: Arc<RepoLock>, interval: Duration) {
+    let mut interval = time::interval(interval);
+    loop {
+        interval.tick().await;
+        let path = repo_path.clone();
+        let repo_lock = Arc::clone(&repo_lock);
+        match tokio::task::spawn_blocking(move || collect(&path, &repo_lock)).await {
+            Ok(Ok(stats)) => publish(&stats),
+            Ok(Err(e)) => warn!("Failed to collect repository statistics: {}", e),
+            Err(e) => error!("Repository statistics task failed: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{self, index_line, Upstream};
+
+    fn fetch(repo: &Repository) {
+        repo.find_remote("origin")
+            .unwrap()
+            .fetch(&["+refs/heads/*:refs/remotes/origin/*"], None, None)
+            .unwrap();
+    }
+
+    #[test]
+    fn collects_non_zero_stats() {
+        let upstream = Upstream::new();
+        upstream.commit("add serde", &[("se/rd/serde", Some(&index_line("serde", "1.0.0")))]);
+        let dir = tempfile::tempdir().unwrap();
+        let repo = test_support::mirror(&upstream, dir.path());
+        let lock = RepoLock::new(dir.path().join("index.lock"));
+
+        let stats = collect(repo.workdir().unwrap(), &lock).unwrap();
+        assert_eq!(stats.objects["commit"], 1);
+        // 根目录、se、se/rd 三个 tree
+        assert_eq!(stats.objects["tree"], 3);
+        assert_eq!(stats.objects["blob"], 1);
+        assert!(!stats.objects.contains_key("tag"));
+        assert!(stats.repo_size > 0);
+        assert_eq!(stats.working_tree_size, index_line("serde", "1.0.0").len() as u64);
+        assert_eq!(stats.stale_refs, 0);
+
+        // 本地 clone 复制的是 loose 对象；打包后对象不重复计数
+        let repacked = std::process::Command::new("git")
+            .args(["repack", "-q", "-a"])
+            .current_dir(repo.workdir().unwrap())
+            .status()
+            .unwrap();
+        assert!(repacked.success());
+        let stats = collect(repo.workdir().unwrap(), &lock).unwrap();
+        assert_eq!(stats.pack_files, 1);
+        assert_eq!((stats.objects["commit"], stats.objects["tree"], stats.objects["blob"]), (1, 3, 1));
+    }
+
+    #[test]
+    fn counts_stale_remote_branches() {
+        let upstream = Upstream::new();
+        let head = upstream.commit("add a", &[("1/a", Some(&index_line("a", "0.1.0")))]);
+        let commit = upstream.repo.find_commit(head).unwrap();
+        upstream.repo.branch("old", &commit, false).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let repo = test_support::mirror(&upstream, dir.path());
+        let lock = RepoLock::new(dir.path().join("index.lock"));
+        fetch(&repo);
+        assert_eq!(collect(repo.workdir().unwrap(), &lock).unwrap().stale_refs, 0);
+
+        // 上游删除分支后，没有 prune 的远程跟踪分支留在本地
+        upstream.repo.find_branch("old", git2::BranchType::Local).unwrap().delete().unwrap();
+        fetch(&repo);
+        assert!(repo.find_reference("refs/remotes/origin/old").is_ok());
+        assert_eq!(collect(repo.workdir().unwrap(), &lock).unwrap().stale_refs, 1);
+    }
+}
@@ -0,0 +1,133 @@
+use actix_web::{
+    body::MessageBody,
+    dev::{ServiceRequest, ServiceResponse},
+    middleware::Next,
+    web, Error,
+};
+use std::time::{Duration, Instant};
+use tracing::{debug, error, warn};
+
+use crate::{config::WebConfig, forwarded::TrustedProxies, metrics, redact};
+
+pub async fn log_request_duration(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let config = req
+        .app_data::<web::Data<WebConfig>>()
+        .cloned()
+        .expect("WebConfig not registered");
+    let method = req.method().clone();
+    let access_log = &config.access_log;
+    let uri = redact::redact_uri(req.uri(), &access_log.redact_params);
+    let headers = config
+        .log_all_request_durations
+        .then(|| redact::redact_headers(req.headers(), &access_log.redact_headers));
+    let client_ip = req
+        .app_data::<web::Data<TrustedProxies>>()
+        .and_then(|proxies| proxies.client_ip(req.request()))
+        .map_or_else(|| "-".to_string(), |ip| ip.to_string());
+
+    let start = Instant::now();
+    let res = next.call(req).await?;
+    let elapsed = start.elapsed();
+    metrics::HTTP_REQUEST_DURATION_SECONDS.observe(elapsed);
+
+    let status = res.status().as_u16();
+    let duration_ms = elapsed.as_millis() as u64;
+    let threshold = Duration::from_millis(config.slow_request_threshold_ms);
+    if elapsed > threshold {
+        metrics::SLOW_REQUESTS_TOTAL.inc();
+        // 超过阈值5倍的请求按 error 级别记录
+        if elapsed > threshold * 5 {
+            error!(%method, %uri, status, duration_ms, %client_ip, "Very slow request");
+        } else {
+            warn!(%method, %uri, status, duration_ms, %client_ip, "Slow request");
+        }
+    } else if config.log_all_request_durations {
+        let headers = headers.unwrap_or_default();
+        debug!(%method, %uri, status, duration_ms, %client_ip, %headers, "Request completed");
+    }
+
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+    use actix_web::{middleware::from_fn, test as actix_test, App, HttpResponse};
+
+    async fn sleep_ms(ms: u64) -> HttpResponse {
+        tokio::time::sleep(Duration::from_millis(ms)).await;
+        HttpResponse::Ok().finish()
+    }
+
+    async fn request(extra: &str, uri: &str) -> String {
+        request_with(extra, actix_test::TestRequest::get().uri(uri)).await
+    }
+
+    async fn request_with(extra: &str, req: actix_test::TestRequest) -> String {
+        let (logs, _guard) = test_support::capture_logs();
+        let config = test_support::web_config(&format!("slow_request_threshold_ms = 50\n{}", extra));
+        let app = actix_test::init_service(
+            App::new()
+                .app_data(web::Data::new(TrustedProxies::from_config(&config)))
+                .app_data(web::Data::new(config))
+                .wrap(from_fn(log_request_duration))
+                .route("/fast", web::get().to(|| sleep_ms(0)))
+                .route("/slow", web::get().to(|| sleep_ms(100)))
+                .route("/very-slow", web::get().to(|| sleep_ms(300))),
+        )
+        .await;
+        let req = req.peer_addr("192.0.2.7:5000".parse().unwrap()).to_request();
+        actix_test::call_service(&app, req).await;
+        logs.contents()
+    }
+
+    #[actix_web::test]
+    async fn slow_requests_are_logged() {
+        let logs = request("", "/slow?token=secret").await;
+        assert!(logs.contains("WARN"), "{}", logs);
+        assert!(logs.contains("Slow request"), "{}", logs);
+        assert!(logs.contains("method=GET"), "{}", logs);
+        assert!(logs.contains("status=200"), "{}", logs);
+        assert!(logs.contains("client_ip=192.0.2.7"), "{}", logs);
+        assert!(logs.contains("duration_ms="), "{}", logs);
+        assert!(!logs.contains("secret"), "{}", logs);
+    }
+
+    #[actix_web::test]
+    async fn very_slow_requests_are_errors() {
+        let logs = request("", "/very-slow").await;
+        assert!(logs.contains("ERROR"), "{}", logs);
+        assert!(logs.contains("Very slow request"), "{}", logs);
+    }
+
+    #[actix_web::test]
+    async fn fast_requests_are_not_logged() {
+        assert_eq!(request("", "/fast").await, "");
+        let logs = request("log_all_request_durations = true", "/fast").await;
+        assert!(logs.contains("DEBUG"), "{}", logs);
+        assert!(logs.contains("Request completed"), "{}", logs);
+    }
+
+    #[actix_web::test]
+    async fn redacts_tokens_and_credential_headers() {
+        let logs = request("", "/slow?token=secret&q=serde&API_KEY=secret").await;
+        assert!(logs.contains("/slow?token=<redacted>&q=serde&API_KEY=<redacted>"), "{}", logs);
+        assert!(!logs.contains("secret"), "{}", logs);
+
+        let req = actix_test::TestRequest::get()
+            .uri("/fast?password=secret&page=2")
+            .insert_header(("Authorization", "Bearer secret"))
+            .insert_header(("Cookie", "session=secret"))
+            .insert_header(("X-Request-Id", "req-7"));
+        let logs = request_with("log_all_request_durations = true", req).await;
+        assert!(logs.contains("/fast?password=<redacted>&page=2"), "{}", logs);
+        assert!(logs.contains("authorization: <redacted>"), "{}", logs);
+        assert!(logs.contains("cookie: <redacted>"), "{}", logs);
+        assert!(logs.contains("x-request-id: req-7"), "{}", logs);
+        assert!(!logs.contains("secret"), "{}", logs);
+    }
+}
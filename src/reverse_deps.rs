@@ -0,0 +1,122 @@
+use actix_web::web;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fs, path::Path, sync::Arc};
+use utoipa::ToSchema;
+
+use crate::{index::IndexScanner, panic_recovery, snapshot::IndexVersion, sparse};
+
+// 依赖某个 crate 的一个 crate，只看它版本号最大的未 yank 版本
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ReverseDependency {
+    name: String,
+    version: String,
+    // 对被依赖 crate 的版本要求
+    req: String,
+    // normal、dev 或 build
+    kind: String,
+    optional: bool,
+}
+
+impl ReverseDependency {
+    // 版本要求不合法时不匹配任何版本
+    pub fn matches(&self, version: &semver::Version) -> bool {
+        semver::VersionReq::parse(&self.req).is_ok_and(|req| req.matches(version))
+    }
+}
+
+// 被依赖的 crate（normalize_name 之后）到依赖它的 crate，按名称排序
+pub type ReverseGraph = HashMap<String, Vec<ReverseDependency>>;
+
+#[derive(Deserialize)]
+struct Dependency {
+    name: String,
+    req: String,
+    #[serde(default)]
+    optional: bool,
+    kind: Option<String>,
+    // 重命名依赖时的原始 crate 名称
+    package: Option<String>,
+    // 来自其他注册表的依赖
+    registry: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Line {
+    name: String,
+    vers: String,
+    #[serde(default)]
+    yanked: bool,
+    #[serde(default)]
+    deps: Vec<Dependency>,
+}
+
+// 返回一个 crate 的最新版本对其他 crate 的依赖：(被依赖的 crate, 依赖)
+fn read_dependencies(path: &Path) -> Option<Vec<(String, ReverseDependency)>> {
+    let content = fs::read(path).ok()?;
+    let latest = content
+        .split(|&b| b == b'\n')
+        .filter(|line| !line.trim_ascii().is_empty())
+        .filter_map(|line| serde_json::from_slice::<Line>(line).ok())
+        .filter(|line| !line.yanked)
+        .filter_map(|line| semver::Version::parse(&line.vers).ok().map(|parsed| (parsed, line)))
+        .max_by(|a, b| a.0.cmp(&b.0))
+        .map(|(_, line)| line)?;
+    Some(
+        latest
+            .deps
+            .into_iter()
+            .filter(|dep| dep.registry.is_none())
+            .map(|dep| {
+                let target = sparse::normalize_name(dep.package.as_deref().unwrap_or(&dep.name));
+                let dependent = ReverseDependency {
+                    name: latest.name.clone(),
+                    version: latest.vers.clone(),
+                    req: dep.req,
+                    kind: dep.kind.unwrap_or_else(|| "normal".to_string()),
+                    optional: dep.optional,
+                };
+                (target, dependent)
+            })
+            .collect(),
+    )
+}
+
+pub fn build(scanner: &IndexScanner) -> ReverseGraph {
+    let mut graph = ReverseGraph::new();
+    for (target, dependent) in scanner.map_files(read_dependencies).into_iter().flatten() {
+        graph.entry(target).or_default().push(dependent);
+    }
+    for dependents in graph.values_mut() {
+        // 同一个 crate 可能以不同 kind 依赖两次，都保留
+        dependents.sort_by(|a, b| a.name.cmp(&b.name).then_with(|| a.kind.cmp(&b.kind)));
+    }
+    graph
+}
+
+// 每次更新后第一次请求时重新扫描整个索引；多个请求同时发现过期时只扫描一次
+pub struct ReverseDependencyIndex {
+    scanner: web::Data<IndexScanner>,
+    graph: tokio::sync::Mutex<Option<(IndexVersion, Arc<ReverseGraph>)>>,
+}
+
+impl ReverseDependencyIndex {
+    pub fn new(scanner: web::Data<IndexScanner>) -> Self {
+        ReverseDependencyIndex {
+            scanner,
+            graph: Default::default(),
+        }
+    }
+
+    pub async fn graph(&self, updated: IndexVersion) -> actix_web::Result<Arc<ReverseGraph>> {
+        let mut cached = self.graph.lock().await;
+        if let Some((checked, graph)) = cached.as_ref() {
+            if *checked == updated {
+                return Ok(Arc::clone(graph));
+            }
+        }
+        let scanner = self.scanner.clone().into_inner();
+        let graph = Arc::new(panic_recovery::block(move || build(&scanner)).await?);
+        *cached = Some((updated, Arc::clone(&graph)));
+        Ok(graph)
+    }
+}
@@ -0,0 +1,403 @@
+use actix_web::{
+    body::{EitherBody, MessageBody},
+    dev::{ServiceRequest, ServiceResponse},
+    http::header::{self, HeaderValue},
+    middleware::Next,
+    web, Error, HttpMessage, HttpRequest, HttpResponse,
+};
+
+use crate::{
+    config::{CspConfig, SecurityHeadersConfig, WebConfig},
+    forwarded::TrustedProxies,
+    util,
+};
+
+pub fn hsts_value(config: &SecurityHeadersConfig) -> String {
+    let mut value = format!("max-age={}", config.hsts_max_age);
+    if config.hsts_include_subdomains {
+        value.push_str("; includeSubDomains");
+    }
+    if config.hsts_preload {
+        value.push_str("; preload");
+    }
+    value
+}
+
+// 每次启动（包括重新加载配置）时计算一次，不在每个响应里格式化；web.security_headers.hsts 关闭时是 None
+pub struct HstsHeader(Option<HeaderValue>);
+
+impl HstsHeader {
+    pub fn from_config(config: &SecurityHeadersConfig) -> Self {
+        HstsHeader(
+            config
+                .hsts
+                .then(|| HeaderValue::from_str(&hsts_value(config)).expect("HSTS value is always a valid header")),
+        )
+    }
+
+    pub fn value(&self) -> Option<&HeaderValue> {
+        self.0.as_ref()
+    }
+}
+
+const NONCE_PLACEHOLDER: &str = "{nonce}";
+
+// web.csp 在启动时拼好；use_nonces 时另外保存一个带占位符的模板，向页面提供了 nonce 的响应替换成自己的 nonce
+pub struct HtmlCsp {
+    value: Option<HeaderValue>,
+    nonce_template: Option<String>,
+}
+
+// 当前请求的 nonce，生成后放在请求的 extensions 里，add_security_headers 用它生成响应的 CSP
+#[derive(Debug, Clone)]
+pub struct CspNonce(pub String);
+
+fn csp_policy(config: &CspConfig, nonce: Option<&str>) -> String {
+    let mut policy = Vec::new();
+    for (name, value) in config.directives() {
+        let mut sources: Vec<String> = value.split_whitespace().map(str::to_string).collect();
+        // style-src 没有设置时只允许带 nonce 的 <style>；script-src 没有设置时仍然按 default-src 处理
+        if let Some(nonce) = nonce.filter(|_| name == "style-src" || (name == "script-src" && !sources.is_empty())) {
+            sources.push(format!("'nonce-{}'", nonce));
+        }
+        if !sources.is_empty() {
+            policy.push(format!("{} {}", name, sources.join(" ")));
+        }
+    }
+    policy.join("; ")
+}
+
+impl HtmlCsp {
+    pub fn from_config(config: &CspConfig) -> Self {
+        let policy = csp_policy(config, None);
+        HtmlCsp {
+            value: (!policy.is_empty())
+                .then(|| HeaderValue::from_str(&policy).expect("web.csp is validated at startup")),
+            nonce_template: config.use_nonces.then(|| csp_policy(config, Some(NONCE_PLACEHOLDER))),
+        }
+    }
+
+    fn header(&self, nonce: Option<&CspNonce>) -> Option<HeaderValue> {
+        match (&self.nonce_template, nonce) {
+            (Some(template), Some(nonce)) => HeaderValue::from_str(&template.replace(NONCE_PLACEHOLDER, &nonce.0)).ok(),
+            _ => self.value.clone(),
+        }
+    }
+}
+
+// 页面里的 <style nonce="..."> 使用；web.csp.use_nonces 关闭时是 None
+pub fn csp_nonce(req: &HttpRequest) -> Option<String> {
+    let csp = req.app_data::<web::Data<HtmlCsp>>()?;
+    csp.nonce_template.as_ref()?;
+    if let Some(nonce) = req.extensions().get::<CspNonce>() {
+        return Some(nonce.0.clone());
+    }
+    let nonce = util::random_hex(16)?;
+    req.extensions_mut().insert(CspNonce(nonce.clone()));
+    Some(nonce)
+}
+
+fn is_html(headers: &header::HeaderMap) -> bool {
+    headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.trim_start().to_ascii_lowercase().starts_with("text/html"))
+}
+
+// Swagger UI、GraphiQL 等从 unpkg 加载的页面使用的 CSP
+pub const CDN_PAGE_CSP: &str = "default-src 'none'; script-src https://unpkg.com 'unsafe-inline'; style-src https://unpkg.com 'unsafe-inline'; img-src 'self' data: https:; font-src https://unpkg.com; connect-src 'self'";
+
+// 健康检查和指标通常由内网直接通过 HTTP 访问，不重定向
+const HTTP_ALLOWED_PATHS: &[&str] = &[
+    "/health/ready",
+    "/healthz/live",
+    "/healthz/ready",
+    "/healthz/startup",
+    "/metrics",
+];
+
+pub async fn redirect_to_https(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    let web_config = req
+        .app_data::<web::Data<WebConfig>>()
+        .cloned()
+        .expect("WebConfig not registered");
+    if web_config.https_only && !HTTP_ALLOWED_PATHS.contains(&req.path()) {
+        let proxies = req
+            .app_data::<web::Data<TrustedProxies>>()
+            .cloned()
+            .expect("TrustedProxies not registered");
+        if !proxies.is_https(req.request()) {
+            // 不使用请求里的 Host，否则可以构造指向任意网站的重定向
+            let host = web_config.canonical_host.as_deref().expect("validated by Config::validate");
+            let path_and_query = req.uri().path_and_query().map_or("/", |p| p.as_str());
+            let location = format!("https://{}{}", host, path_and_query);
+            let res = HttpResponse::PermanentRedirect()
+                .insert_header((header::LOCATION, location))
+                .finish();
+            return Ok(req.into_response(res).map_into_right_body());
+        }
+    }
+    next.call(req).await.map(|res| res.map_into_left_body())
+}
+
+pub async fn add_security_headers(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let web_config = req
+        .app_data::<web::Data<WebConfig>>()
+        .cloned()
+        .expect("WebConfig not registered");
+    let config = &web_config.security_headers;
+    let hsts = req
+        .app_data::<web::Data<HstsHeader>>()
+        .cloned()
+        .expect("HstsHeader not registered");
+    let html_csp = req
+        .app_data::<web::Data<HtmlCsp>>()
+        .cloned()
+        .expect("HtmlCsp not registered");
+    let proxies = req
+        .app_data::<web::Data<TrustedProxies>>()
+        .cloned()
+        .expect("TrustedProxies not registered");
+    // HSTS 只能通过 HTTPS 下发（包括 web.trusted_proxies 转发的 X-Forwarded-Proto）
+    let is_https = proxies.is_https(req.request());
+
+    let mut res = next.call(req).await?;
+    let nonce = res.request().extensions().get::<CspNonce>().cloned();
+    let headers = res.headers_mut();
+
+    if let (Some(value), true) = (hsts.value(), is_https) {
+        headers.insert(header::STRICT_TRANSPORT_SECURITY, value.clone());
+    }
+    // 响应自己设置了 CSP 时（例如 Swagger UI 需要加载外部脚本）保留它
+    if !headers.contains_key(header::CONTENT_SECURITY_POLICY) {
+        if is_html(headers) {
+            if let Some(value) = html_csp.header(nonce.as_ref()) {
+                headers.insert(header::CONTENT_SECURITY_POLICY, value);
+            }
+        } else if !config.csp.is_empty() {
+            if let Ok(value) = HeaderValue::from_str(&config.csp) {
+                headers.insert(header::CONTENT_SECURITY_POLICY, value);
+            }
+        }
+    }
+    if !config.x_frame_options.is_empty() {
+        if let Ok(value) = HeaderValue::from_str(&config.x_frame_options) {
+            headers.insert(header::X_FRAME_OPTIONS, value);
+        }
+    }
+    if config.x_content_type_options {
+        headers.insert(header::X_CONTENT_TYPE_OPTIONS, HeaderValue::from_static("nosniff"));
+    }
+    if !config.referrer_policy.is_empty() {
+        if let Ok(value) = HeaderValue::from_str(&config.referrer_policy) {
+            headers.insert(header::REFERRER_POLICY, value);
+        }
+    }
+
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+    use actix_web::{
+        dev::Service,
+        middleware::from_fn,
+        test::{self as actix_test, TestRequest},
+        App,
+    };
+
+    const PEER: &str = "127.0.0.1:40000";
+
+    async fn page(req: HttpRequest) -> HttpResponse {
+        let nonce = csp_nonce(&req).unwrap_or_default();
+        HttpResponse::Ok()
+            .content_type("text/html; charset=utf-8")
+            .body(format!("<style nonce=\"{}\"></style>", nonce))
+    }
+
+    async fn get(web_config: WebConfig, req: TestRequest) -> Response {
+        let app = actix_test::init_service(
+            App::new()
+                .app_data(web::Data::new(HstsHeader::from_config(&web_config.security_headers)))
+                .app_data(web::Data::new(HtmlCsp::from_config(&web_config.csp)))
+                .app_data(web::Data::new(TrustedProxies::from_config(&web_config)))
+                .app_data(web::Data::new(web_config))
+                .wrap(from_fn(redirect_to_https))
+                .wrap(from_fn(add_security_headers))
+                .route("/index", web::get().to(|| async { HttpResponse::Ok().body("{}") }))
+                .route("/page", web::get().to(page))
+                .route(
+                    "/swagger",
+                    web::get().to(|| async {
+                        HttpResponse::Ok()
+                            .content_type("text/html")
+                            .insert_header((header::CONTENT_SECURITY_POLICY, CDN_PAGE_CSP))
+                            .finish()
+                    }),
+                ),
+        )
+        .await;
+        let res = app.call(req.to_request()).await.unwrap();
+        let (status, headers) = (res.status(), res.headers().clone());
+        let body = String::from_utf8(actix_test::read_body(res).await.to_vec()).unwrap();
+        Response { status, headers, body }
+    }
+
+    struct Response {
+        status: actix_web::http::StatusCode,
+        headers: header::HeaderMap,
+        body: String,
+    }
+
+    fn header(res: &Response, name: header::HeaderName) -> Option<String> {
+        res.headers.get(name).map(|value| value.to_str().unwrap().to_string())
+    }
+
+    fn https(uri: &str) -> TestRequest {
+        TestRequest::get()
+            .uri(uri)
+            .peer_addr(PEER.parse().unwrap())
+            .insert_header(("X-Forwarded-Proto", "https"))
+    }
+
+    #[actix_web::test]
+    async fn default_headers() {
+        let res = get(test_support::web_config(""), https("/index")).await;
+        assert_eq!((res.status, res.body.as_str()), (actix_web::http::StatusCode::OK, "{}"));
+        assert_eq!(header(&res, header::STRICT_TRANSPORT_SECURITY).as_deref(), Some("max-age=31536000"));
+        assert_eq!(
+            header(&res, header::CONTENT_SECURITY_POLICY).as_deref(),
+            Some("default-src 'none'; frame-ancestors 'none'")
+        );
+        assert_eq!(header(&res, header::X_FRAME_OPTIONS).as_deref(), Some("DENY"));
+        assert_eq!(header(&res, header::X_CONTENT_TYPE_OPTIONS).as_deref(), Some("nosniff"));
+        assert_eq!(header(&res, header::REFERRER_POLICY).as_deref(), Some("no-referrer"));
+    }
+
+    #[actix_web::test]
+    async fn configured_headers() {
+        let config = test_support::web_config(
+            "[security_headers]\ncsp = \"default-src 'self'\"\nx_frame_options = \"SAMEORIGIN\"\n\
+             referrer_policy = \"same-origin\"\nx_content_type_options = false",
+        );
+        let res = get(config, https("/index")).await;
+        assert_eq!(header(&res, header::CONTENT_SECURITY_POLICY).as_deref(), Some("default-src 'self'"));
+        assert_eq!(header(&res, header::X_FRAME_OPTIONS).as_deref(), Some("SAMEORIGIN"));
+        assert_eq!(header(&res, header::REFERRER_POLICY).as_deref(), Some("same-origin"));
+        assert_eq!(header(&res, header::X_CONTENT_TYPE_OPTIONS), None);
+    }
+
+    #[actix_web::test]
+    async fn disabled_headers() {
+        let config = test_support::web_config(
+            "[security_headers]\nhsts = false\ncsp = \"\"\nx_frame_options = \"\"\nreferrer_policy = \"\"",
+        );
+        let res = get(config, https("/index")).await;
+        for name in [
+            header::STRICT_TRANSPORT_SECURITY,
+            header::CONTENT_SECURITY_POLICY,
+            header::X_FRAME_OPTIONS,
+            header::REFERRER_POLICY,
+        ] {
+            assert_eq!(header(&res, name.clone()), None, "{}", name);
+        }
+    }
+
+    #[actix_web::test]
+    async fn hsts_only_over_https() {
+        let res = get(test_support::web_config(""), TestRequest::get().uri("/index").peer_addr(PEER.parse().unwrap())).await;
+        assert_eq!(header(&res, header::STRICT_TRANSPORT_SECURITY), None);
+        // 不可信的来源伪造 X-Forwarded-Proto
+        let req = TestRequest::get()
+            .uri("/index")
+            .peer_addr("203.0.113.9:40000".parse().unwrap())
+            .insert_header(("X-Forwarded-Proto", "https"));
+        let res = get(test_support::web_config(""), req).await;
+        assert_eq!(header(&res, header::STRICT_TRANSPORT_SECURITY), None);
+    }
+
+    #[actix_web::test]
+    async fn html_pages_use_web_csp() {
+        let res = get(test_support::web_config(""), https("/page")).await;
+        assert_eq!(
+            header(&res, header::CONTENT_SECURITY_POLICY).as_deref(),
+            Some("default-src 'none'; style-src 'unsafe-inline'")
+        );
+        // 响应自己的 CSP 不被覆盖
+        let res = get(test_support::web_config(""), https("/swagger")).await;
+        assert_eq!(header(&res, header::CONTENT_SECURITY_POLICY).as_deref(), Some(CDN_PAGE_CSP));
+    }
+
+    #[test]
+    fn hsts_directives() {
+        let cases = [
+            (false, false, "max-age=600"),
+            (true, false, "max-age=600; includeSubDomains"),
+            (false, true, "max-age=600; preload"),
+            (true, true, "max-age=600; includeSubDomains; preload"),
+        ];
+        for (include_subdomains, preload, expected) in cases {
+            let config = SecurityHeadersConfig {
+                hsts_max_age: 600,
+                hsts_include_subdomains: include_subdomains,
+                hsts_preload: preload,
+                ..Default::default()
+            };
+            assert_eq!(hsts_value(&config), expected);
+            assert_eq!(HstsHeader::from_config(&config).0.unwrap(), expected);
+        }
+    }
+
+    #[actix_web::test]
+    async fn https_only_redirects_to_canonical_host() {
+        let config = || test_support::web_config("https_only = true\ncanonical_host = \"index.example.com\"");
+        let req = TestRequest::get()
+            .uri("/index?x=1")
+            .peer_addr(PEER.parse().unwrap())
+            .insert_header(("Host", "evil.example.net"));
+        let res = get(config(), req).await;
+        assert_eq!(res.status, actix_web::http::StatusCode::PERMANENT_REDIRECT);
+        assert_eq!(
+            header(&res, header::LOCATION).as_deref(),
+            Some("https://index.example.com/index?x=1")
+        );
+
+        // 代理已经终止了 TLS
+        let res = get(config(), https("/index")).await;
+        assert_eq!(res.status, actix_web::http::StatusCode::OK);
+        // 健康检查不重定向
+        let req = TestRequest::get().uri("/healthz/live").peer_addr(PEER.parse().unwrap());
+        assert_eq!(get(config(), req).await.status, actix_web::http::StatusCode::NOT_FOUND);
+    }
+
+    #[actix_web::test]
+    async fn nonces_are_unique_per_request() {
+        let config = || test_support::web_config("[csp]\nuse_nonces = true\nscript_src = \"'self'\"");
+        let mut nonces = std::collections::HashSet::new();
+        for _ in 0..20 {
+            let res = get(config(), https("/page")).await;
+            let csp = header(&res, header::CONTENT_SECURITY_POLICY).unwrap();
+            let nonce = res.body.split('"').nth(1).unwrap().to_string();
+            assert_eq!(nonce.len(), 32);
+            assert_eq!(
+                csp,
+                format!(
+                    "default-src 'none'; script-src 'self' 'nonce-{n}'; style-src 'unsafe-inline' 'nonce-{n}'",
+                    n = nonce
+                )
+            );
+            assert!(nonces.insert(nonce));
+        }
+        // 不是 HTML 的响应不带 nonce
+        let res = get(config(), https("/index")).await;
+        assert!(!header(&res, header::CONTENT_SECURITY_POLICY).unwrap().contains("nonce"));
+    }
+}
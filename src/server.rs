@@ -0,0 +1,292 @@
+use actix_web::{
+    body::MessageBody,
+    dev::{Server, ServiceRequest, ServiceResponse},
+    http::{header, ConnectionType},
+    middleware::{from_fn, DefaultHeaders, Next},
+    guard, web, App, Error, HttpRequest, HttpResponse, HttpServer,
+};
+use serde_json::json;
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+use subtle::ConstantTimeEq;
+use tokio::sync::{mpsc, watch};
+use tracing::info;
+
+use crate::{
+    api, auth, body_limit, chunked, coalesce, config::{Config, WebConfig}, content_type, delta, events, forwarded, git::RepoLock, git_bundle, git_http, graphql, health, idle, index, listen, listing, metrics,
+    openapi, panic_recovery, prefetch, problem::{self, ProblemDetails, ProblemType}, protocol, pull_timing, rate_limit, registry, replication, request_timing, reverse_deps, security_headers,
+    slo, snapshot::ServingRoot, sparse,
+};
+
+// 在所有 server 代之间共享的状态
+#[derive(Clone)]
+pub struct SharedState {
+    pub health: web::Data<health::HealthState>,
+    pub repo_lock: Arc<RepoLock>,
+    pub reload_tx: mpsc::Sender<()>,
+    pub active_generation: watch::Receiver<u64>,
+    pub serving_root: Arc<ServingRoot>,
+    pub events: Option<Arc<events::EventLog>>,
+    pub pull_timings: Arc<pull_timing::PullTimings>,
+    // 缓存按 last_update 和快照代数失效，可以在 server 代之间共享；web.watch_index_files 也会修改它们
+    pub version_cache: web::Data<api::VersionCache<api::VersionResponse>>,
+    pub features_cache: web::Data<api::VersionCache<api::FeaturesResponse>>,
+    pub activity: web::Data<idle::Activity>,
+    pub slo: web::Data<slo::SloMonitor>,
+}
+
+// 所有 server 代打开的 TCP 连接数，关闭时记录要排空多少连接
+static OPEN_CONNECTIONS: AtomicUsize = AtomicUsize::new(0);
+
+pub fn open_connections() -> usize {
+    OPEN_CONNECTIONS.load(Ordering::Relaxed)
+}
+
+// 放进连接的 extensions，连接关闭时随之释放
+struct ConnectionGuard;
+
+impl ConnectionGuard {
+    fn new() -> Self {
+        OPEN_CONNECTIONS.fetch_add(1, Ordering::Relaxed);
+        ConnectionGuard
+    }
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        OPEN_CONNECTIONS.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+struct ServerGeneration {
+    own: u64,
+    active: watch::Receiver<u64>,
+}
+
+// 旧的 server 在排空期间对 keep-alive 连接返回 Connection: close，让客户端重连到新的 server
+async fn close_stale_connections(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let generation = req
+        .app_data::<web::Data<ServerGeneration>>()
+        .cloned()
+        .expect("ServerGeneration not registered");
+    let mut res = next.call(req).await?;
+    if *generation.active.borrow() != generation.own {
+        res.response_mut()
+            .head_mut()
+            .set_connection_type(ConnectionType::Close);
+    }
+    Ok(res)
+}
+
+// 需要 web.admin_token，不按来源地址判断
+async fn admin_reload(req: HttpRequest, state: web::Data<SharedState>, web_config: web::Data<WebConfig>) -> HttpResponse {
+    if web_config.admin_token.is_empty() {
+        return problem::not_found("set web.admin_token to enable /admin/reload").into();
+    }
+    let authorized = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .is_some_and(|token| bool::from(token.as_bytes().ct_eq(web_config.admin_token.as_bytes())));
+    if !authorized {
+        return ProblemDetails::new(ProblemType::Unauthorized)
+            .with_detail("missing or invalid admin token")
+            .into();
+    }
+    // 通道已满说明已经有一个待处理的重新加载请求
+    let _ = state.reload_tx.try_send(());
+    HttpResponse::Accepted().json(json!({ "status": "reloading" }))
+}
+
+pub fn start(
+    config: &Config,
+    state: &SharedState,
+    generation: u64,
+    listeners: &mut listen::Listeners,
+) -> std::io::Result<Server> {
+    let listen_addrs = listen::resolve_listen_addrs(&config.web)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+
+    problem::set_base_uri(&config.web.problem_details_base_uri);
+    let app_config = web::Data::new(config.app.clone());
+    let web_config = web::Data::new(config.web.clone());
+    let hsts_header = web::Data::new(security_headers::HstsHeader::from_config(&config.web.security_headers));
+    let html_csp = web::Data::new(security_headers::HtmlCsp::from_config(&config.web.csp));
+    let trusted_proxies = web::Data::new(forwarded::TrustedProxies::from_config(&config.web));
+    let health_config = web::Data::new(config.health.clone());
+    let registry_config = web::Data::new(config.registry.clone());
+    let scanner = web::Data::new(index::IndexScanner::new(
+        Arc::clone(&state.serving_root),
+        config.search.scan_parallelism,
+    ));
+    let serving_root = web::Data::from(Arc::clone(&state.serving_root));
+    let graphql_schema = web::Data::new(graphql::build_schema(
+        Arc::clone(&state.serving_root),
+        scanner.clone(),
+        config.registry.clone(),
+        config.web.graphql_depth_limit,
+    ));
+    let server_generation = web::Data::new(ServerGeneration {
+        own: generation,
+        active: state.active_generation.clone(),
+    });
+    let replicator = web::Data::new(replication::Replicator {
+        config: config.replication.clone(),
+        repo_path: config.repo.path.clone().into(),
+        repo_lock: Arc::clone(&state.repo_lock),
+        serving_root: Arc::clone(&state.serving_root),
+        events: state.events.clone(),
+        require_signed_commits: config.repo.require_signed_commits,
+        health: state.health.clone(),
+    });
+    let git_http = web::Data::new(git_http::GitHttp::new(
+        config.web.git_smart_http,
+        &config.repo.path,
+        config.web.git_smart_http_max_concurrent,
+    ));
+    let git_bundle = web::Data::new(git_bundle::GitBundle::new(
+        config.web.git_bundle_enabled,
+        config.repo.path.clone(),
+    ));
+    let version_cache = state.version_cache.clone();
+    let features_cache = state.features_cache.clone();
+    let suggestion_index = web::Data::new(api::SuggestionIndex::new(
+        config.search.trie_enabled,
+        config.repo.path.clone(),
+    ));
+    let index_coalescer = web::Data::new(coalesce::IndexFileCoalescer::new(config.web.delta_encoding));
+    let delta_encoding = web::Data::new(delta::DeltaEncoding::new(
+        config.web.delta_encoding,
+        config.repo.path.clone(),
+    ));
+    let reverse_deps_index = web::Data::new(reverse_deps::ReverseDependencyIndex::new(scanner.clone()));
+    let rate_limiter = web::Data::new(rate_limit::RateLimiter::new(&config.rate_limit));
+    let jwt_auth = web::Data::new(
+        auth::JwtAuth::from_config(&config.auth)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?,
+    );
+    let shared = web::Data::new(state.clone());
+    let health = state.health.clone();
+    let activity = state.activity.clone();
+    let slo = state.slo.clone();
+
+    let mut server = HttpServer::new(move || {
+        App::new()
+            .app_data(app_config.clone())
+            .app_data(web_config.clone())
+            .app_data(hsts_header.clone())
+            .app_data(html_csp.clone())
+            .app_data(trusted_proxies.clone())
+            .app_data(health_config.clone())
+            .app_data(registry_config.clone())
+            .app_data(serving_root.clone())
+            .app_data(scanner.clone())
+            .app_data(health.clone())
+            .app_data(server_generation.clone())
+            .app_data(shared.clone())
+            .app_data(replicator.clone())
+            .app_data(rate_limiter.clone())
+            .app_data(version_cache.clone())
+            .app_data(features_cache.clone())
+            .app_data(git_http.clone())
+            .app_data(git_bundle.clone())
+            .app_data(graphql_schema.clone())
+            .app_data(suggestion_index.clone())
+            .app_data(reverse_deps_index.clone())
+            .app_data(jwt_auth.clone())
+            .app_data(activity.clone())
+            .app_data(index_coalescer.clone())
+            .app_data(delta_encoding.clone())
+            .app_data(slo.clone())
+            .wrap(from_fn(protocol::negotiate))
+            .wrap(from_fn(body_limit::limit_response_body))
+            .wrap(from_fn(rate_limit::limit_requests))
+            .wrap(from_fn(security_headers::redirect_to_https))
+            .wrap(from_fn(security_headers::add_security_headers))
+            .wrap(DefaultHeaders::new().add(("X-Registry-Name", app_config.name.as_str())))
+            .wrap(from_fn(slo::observe))
+            .wrap(from_fn(problem::render_problems))
+            .wrap(from_fn(idle::track_activity))
+            .wrap(from_fn(close_stale_connections))
+            .wrap(from_fn(request_timing::log_request_duration))
+            .wrap(from_fn(panic_recovery::recover_panics))
+            .route("/metrics", web::get().to(metrics::metrics_handler))
+            .route("/health/ready", web::get().to(health::health_ready))
+            .route("/healthz/live", web::get().to(health::healthz_live))
+            .route("/healthz/ready", web::get().to(health::healthz_ready))
+            .route("/healthz/startup", web::get().to(health::healthz_startup))
+            .route("/status", web::get().to(health::status_page))
+            .route("/admin/reload", web::post().to(admin_reload))
+            .route("/.well-known/cargo-registry", web::get().to(registry::well_known))
+            .route("/config.json", web::get().to(registry::config_json))
+            .route("/api/v1/index/stats", web::get().to(api::index_stats))
+            .route("/api/v1/crates", web::get().to(api::search))
+            .route("/api/v1/crates/{name}/versions", web::get().to(api::crate_versions))
+            .route(
+                "/api/v1/crates/{name}/reverse-dependencies",
+                web::get().to(api::reverse_dependencies),
+            )
+            .route("/api/v1/crates/{name}/{version}", web::get().to(api::crate_version))
+            .route("/api/v1/crates/{name}/{version}/features", web::get().to(api::crate_features))
+            .route("/api/v1/suggest", web::get().to(api::suggest))
+            .route("/api/v1/prefetch", web::post().to(prefetch::prefetch_crates))
+            .route("/api/v1/git-bundle", web::get().to(git_bundle::git_bundle))
+            .route("/api/v1/git-bundle/timestamp", web::get().to(git_bundle::git_bundle_timestamp))
+            .route("/api/v1/me", web::get().to(auth::me))
+            .route("/api/v1/openapi.json", web::get().to(openapi::openapi_json))
+            .route("/api/v1/swagger-ui", web::get().to(openapi::swagger_ui))
+            .route("/api/v1/events", web::get().to(events::list_events))
+            .route("/api/v1/stats/pull-timing", web::get().to(pull_timing::pull_timing))
+            .service(
+                web::resource("/api/v1/replicate")
+                    .app_data(web::PayloadConfig::new(replication::MAX_BUNDLE_SIZE))
+                    .route(web::post().to(replication::receive)),
+            )
+            .route("/graphql", web::get().to(graphql::graphql))
+            .route("/graphql", web::post().to(graphql::graphql))
+            .route("/graphql/playground", web::get().to(graphql::playground))
+            .route("/info/refs", web::get().to(git_http::info_refs))
+            .service(
+                web::resource("/git-upload-pack")
+                    .app_data(web::PayloadConfig::new(git_http::MAX_UPLOAD_PACK_REQUEST_SIZE))
+                    .route(web::post().to(git_http::upload_pack_rpc)),
+            )
+            .service(
+                web::scope("")
+                    .wrap(from_fn(chunked::chunk_large_responses))
+                    .wrap(from_fn(content_type::override_content_type))
+                    .wrap(from_fn(health::require_available))
+                    .route(
+                        "/{path:.*}",
+                        web::get().guard(guard::fn_guard(sparse::guard)).to(sparse::index_file),
+                    )
+                    .route(
+                        "/{path:.*}",
+                        web::head().guard(guard::fn_guard(sparse::guard)).to(sparse::index_file_head),
+                    )
+                    .default_service(listing::snapshot_files_service(
+                        web_config.show_listing,
+                        web_config.max_listing_entries,
+                    )),
+            )
+    })
+    .on_connect(|_, extensions| {
+        extensions.insert(ConnectionGuard::new());
+    })
+    .workers(config.web.workers)
+    // 信号由 main 处理：SIGHUP 重新加载，SIGTERM/SIGINT 排空后退出
+    .disable_signals()
+    .shutdown_timeout(config.app.graceful_shutdown_timeout_secs);
+    for (addr, listener) in listen_addrs.iter().zip(listeners.bind_all(&listen_addrs, &config.web)?) {
+        info!("Starting web server on {}", addr);
+        server = server.listen(listener)?;
+        info!("Web server started at http://{}", addr);
+    }
+    Ok(server.run())
+}
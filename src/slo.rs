@@ -0,0 +1,319 @@
+use actix_web::{
+    body::{EitherBody, MessageBody},
+    dev::{ServiceRequest, ServiceResponse},
+    http::{header::RETRY_AFTER, StatusCode},
+    middleware::Next,
+    web, Error, HttpResponse, ResponseError,
+};
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+use tracing::{info, warn};
+
+use crate::{
+    config::{SloConfig, SloTarget},
+    metrics,
+    problem::{ProblemDetails, ProblemType},
+    sparse,
+};
+
+// p99 超过目标的这么多倍并持续 SUSTAINED 后进入降级模式，回到这个值以下并持续 SUSTAINED 后退出
+const VIOLATION_FACTOR: u32 = 3;
+const SUSTAINED: Duration = Duration::from_secs(60);
+// 每次检查只看最近 WINDOW 内的请求
+const WINDOW: Duration = Duration::from_secs(10);
+const CHECK_INTERVAL: Duration = Duration::from_secs(5);
+// 每个接口最多保留的样本数，请求很多时窗口实际上更短
+const MAX_SAMPLES: usize = 10_000;
+pub const RETRY_AFTER_SECS: u64 = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SloEndpoint {
+    SparseIndex,
+    Search,
+}
+
+impl SloEndpoint {
+    const ALL: [SloEndpoint; 2] = [SloEndpoint::SparseIndex, SloEndpoint::Search];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SloEndpoint::SparseIndex => "sparse_index",
+            SloEndpoint::Search => "search",
+        }
+    }
+
+    fn classify(path: &str) -> Option<Self> {
+        match path {
+            "/api/v1/crates" => Some(SloEndpoint::Search),
+            _ if sparse::is_crate_request(path) => Some(SloEndpoint::SparseIndex),
+            _ => None,
+        }
+    }
+}
+
+struct Tracker {
+    endpoint: SloEndpoint,
+    target: Duration,
+    samples: Mutex<VecDeque<(Instant, Duration)>>,
+    degraded: AtomicBool,
+    // p99 开始偏离当前模式的时间，回到当前模式的范围内时清空
+    diverging_since: Mutex<Option<Instant>>,
+}
+
+// nearest-rank，和 /api/v1/stats/pull-timing 相同
+fn p99(mut durations: Vec<Duration>) -> Option<Duration> {
+    durations.sort();
+    let rank = (0.99 * durations.len() as f64).ceil() as usize;
+    durations.get(rank.max(1) - 1).copied()
+}
+
+impl Tracker {
+    fn new(endpoint: SloEndpoint, target: &SloTarget) -> Self {
+        Tracker {
+            endpoint,
+            target: Duration::from_millis(target.p99_ms),
+            samples: Mutex::new(VecDeque::new()),
+            degraded: AtomicBool::new(false),
+            diverging_since: Mutex::new(None),
+        }
+    }
+
+    fn record(&self, elapsed: Duration) {
+        let mut samples = self.samples.lock().unwrap_or_else(|e| e.into_inner());
+        if samples.len() == MAX_SAMPLES {
+            samples.pop_front();
+        }
+        samples.push_back((Instant::now(), elapsed));
+    }
+
+    fn check(&self, now: Instant) {
+        let observed = {
+            let mut samples = self.samples.lock().unwrap_or_else(|e| e.into_inner());
+            while samples.front().is_some_and(|(at, _)| now.duration_since(*at) > WINDOW) {
+                samples.pop_front();
+            }
+            p99(samples.iter().map(|(_, elapsed)| *elapsed).collect())
+        };
+        // 没有请求时视为正常
+        let violating = observed.is_some_and(|p99| p99 > self.target * VIOLATION_FACTOR);
+        let degraded = self.degraded.load(Ordering::Relaxed);
+        let mut diverging_since = self.diverging_since.lock().unwrap_or_else(|e| e.into_inner());
+        if violating == degraded {
+            *diverging_since = None;
+            return;
+        }
+        let since = *diverging_since.get_or_insert(now);
+        if now.duration_since(since) < SUSTAINED {
+            return;
+        }
+        *diverging_since = None;
+        self.degraded.store(violating, Ordering::Relaxed);
+        let observed_ms = observed.map_or(0, |p99| p99.as_millis());
+        if violating {
+            metrics::SLO_VIOLATION_TOTAL.inc(self.endpoint.as_str());
+            warn!(
+                "{} p99 latency {}ms has exceeded {}x the {}ms SLO for {}s, entering degraded mode",
+                self.endpoint.as_str(),
+                observed_ms,
+                VIOLATION_FACTOR,
+                self.target.as_millis(),
+                SUSTAINED.as_secs()
+            );
+        } else {
+            info!(
+                "{} p99 latency is back to {}ms, leaving degraded mode",
+                self.endpoint.as_str(),
+                observed_ms
+            );
+        }
+    }
+}
+
+// [slo.<endpoint>] 里配置了目标的接口才会被跟踪；重新加载配置时不会改变
+pub struct SloMonitor {
+    trackers: Vec<Tracker>,
+}
+
+impl SloMonitor {
+    pub fn new(config: &SloConfig) -> Self {
+        let trackers = SloEndpoint::ALL
+            .into_iter()
+            .filter_map(|endpoint| {
+                let target = match endpoint {
+                    SloEndpoint::SparseIndex => config.sparse_index.as_ref(),
+                    SloEndpoint::Search => config.search.as_ref(),
+                };
+                target.map(|target| Tracker::new(endpoint, target))
+            })
+            .collect();
+        SloMonitor { trackers }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.trackers.is_empty()
+    }
+
+    pub fn is_tracked(&self, endpoint: SloEndpoint) -> bool {
+        self.tracker(endpoint).is_some()
+    }
+
+    fn tracker(&self, endpoint: SloEndpoint) -> Option<&Tracker> {
+        self.trackers.iter().find(|tracker| tracker.endpoint == endpoint)
+    }
+
+    pub fn is_degraded(&self, endpoint: SloEndpoint) -> bool {
+        self.tracker(endpoint)
+            .is_some_and(|tracker| tracker.degraded.load(Ordering::Relaxed))
+    }
+
+    // 其他模块的测试直接切换到降级模式，不用等 SUSTAINED
+    #[cfg(test)]
+    pub fn set_degraded(&self, endpoint: SloEndpoint, degraded: bool) {
+        self.tracker(endpoint).expect("endpoint is not tracked").degraded.store(degraded, Ordering::Relaxed);
+    }
+}
+
+pub async fn monitor(slo: web::Data<SloMonitor>) {
+    let mut interval = tokio::time::interval(CHECK_INTERVAL);
+    loop {
+        interval.tick().await;
+        let now = Instant::now();
+        for tracker in &slo.trackers {
+            tracker.check(now);
+        }
+    }
+}
+
+// 降级时拒绝不重要的请求，让客户端稍后重试
+pub fn shed() -> HttpResponse {
+    let mut res = ProblemDetails::new(ProblemType::Overloaded)
+        .with_detail(format!("Degraded by latency SLO, retry in {}s", RETRY_AFTER_SECS))
+        .error_response();
+    res.headers_mut().insert(RETRY_AFTER, RETRY_AFTER_SECS.into());
+    res
+}
+
+// 记录被跟踪接口的耗时；搜索在降级时直接拒绝，索引文件在降级时只从缓存返回（见 sparse::index_file）
+pub async fn observe(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    let slo = req
+        .app_data::<web::Data<SloMonitor>>()
+        .cloned()
+        .expect("SloMonitor not registered");
+    let Some(tracker) = SloEndpoint::classify(req.path()).and_then(|endpoint| slo.tracker(endpoint)) else {
+        return Ok(next.call(req).await?.map_into_left_body());
+    };
+    if tracker.endpoint == SloEndpoint::Search && tracker.degraded.load(Ordering::Relaxed) {
+        return Ok(req.into_response(shed()).map_into_right_body());
+    }
+    let start = Instant::now();
+    let res = next.call(req).await?;
+    // 被拒绝的请求不算，否则降级本身会让 p99 看起来恢复正常
+    if res.status() != StatusCode::SERVICE_UNAVAILABLE {
+        tracker.record(start.elapsed());
+    }
+    Ok(res.map_into_left_body())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::capture_logs;
+    use actix_web::{middleware::from_fn, test as actix_test, App};
+
+    // 让 check 认为偏离已经持续了 SUSTAINED
+    fn sustain(tracker: &Tracker) {
+        let since = Instant::now().checked_sub(SUSTAINED + Duration::from_secs(1)).unwrap();
+        *tracker.diverging_since.lock().unwrap() = Some(since);
+    }
+
+    #[actix_web::test]
+    async fn slow_handler_enters_degraded_mode() {
+        let (logs, _guard) = capture_logs();
+        let slo = web::Data::new(SloMonitor::new(&SloConfig {
+            sparse_index: None,
+            search: Some(SloTarget { p99_ms: 10 }),
+        }));
+        let app = actix_test::init_service(
+            App::new()
+                .app_data(slo.clone())
+                .wrap(from_fn(observe))
+                // 人为加入 50ms 的延迟，超过目标的 3 倍
+                .route(
+                    "/api/v1/crates",
+                    web::get().to(|| async {
+                        actix_web::rt::time::sleep(Duration::from_millis(50)).await;
+                        HttpResponse::Ok().finish()
+                    }),
+                )
+                .route("/api/v1/other", web::get().to(HttpResponse::Ok)),
+        )
+        .await;
+        let get = |uri: &str| actix_test::TestRequest::get().uri(uri).to_request();
+
+        for _ in 0..5 {
+            assert_eq!(actix_test::call_service(&app, get("/api/v1/crates")).await.status(), 200);
+        }
+        let tracker = slo.tracker(SloEndpoint::Search).unwrap();
+        assert!(!slo.is_tracked(SloEndpoint::SparseIndex));
+        // 刚开始超过时不会立即降级
+        tracker.check(Instant::now());
+        assert!(!slo.is_degraded(SloEndpoint::Search));
+        assert!(tracker.diverging_since.lock().unwrap().is_some());
+
+        sustain(tracker);
+        tracker.check(Instant::now());
+        assert!(slo.is_degraded(SloEndpoint::Search));
+        let entered = "has exceeded 3x the 10ms SLO for 60s, entering degraded mode";
+        assert!(logs.contents().contains(entered), "{}", logs.contents());
+
+        let res = actix_test::call_service(&app, get("/api/v1/crates")).await;
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(res.headers().get(RETRY_AFTER).unwrap(), "5");
+        // 没有跟踪的接口不受影响，被拒绝的请求不计入样本
+        assert_eq!(actix_test::call_service(&app, get("/api/v1/other")).await.status(), 200);
+        assert_eq!(tracker.samples.lock().unwrap().len(), 5);
+
+        // 窗口里没有样本后恢复
+        let later = Instant::now() + WINDOW + Duration::from_secs(1);
+        tracker.check(later);
+        assert!(slo.is_degraded(SloEndpoint::Search));
+        sustain(tracker);
+        tracker.check(later);
+        assert!(!slo.is_degraded(SloEndpoint::Search));
+        assert!(logs.contents().contains("search p99 latency is back to 0ms, leaving degraded mode"));
+        assert_eq!(actix_test::call_service(&app, get("/api/v1/crates")).await.status(), 200);
+    }
+
+    #[actix_web::test]
+    async fn fast_handler_stays_normal() {
+        let slo = SloMonitor::new(&SloConfig {
+            sparse_index: Some(SloTarget { p99_ms: 1000 }),
+            search: None,
+        });
+        let tracker = slo.tracker(SloEndpoint::SparseIndex).unwrap();
+        for _ in 0..100 {
+            tracker.record(Duration::from_millis(5));
+        }
+        sustain(tracker);
+        tracker.check(Instant::now());
+        assert!(!slo.is_degraded(SloEndpoint::SparseIndex));
+        assert!(tracker.diverging_since.lock().unwrap().is_none());
+    }
+
+    #[test]
+    fn p99_nearest_rank() {
+        let durations: Vec<Duration> = (1..=100).map(Duration::from_millis).collect();
+        assert_eq!(p99(durations), Some(Duration::from_millis(99)));
+        assert_eq!(p99(vec![Duration::from_millis(7)]), Some(Duration::from_millis(7)));
+        assert_eq!(p99(Vec::new()), None);
+    }
+}
@@ -0,0 +1,300 @@
+use chrono::{DateTime, Local};
+use filetime::FileTime;
+use git2::{ObjectType, Oid, Repository, Tree, TreeWalkMode, TreeWalkResult};
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        RwLock,
+    },
+};
+use tracing::{info, warn};
+
+use crate::health::HealthState;
+
+// cargo 读取的索引文件所在的目录
+// atomic_checkout 关闭时就是 git 工作区；开启时是按提交导出的只读快照，pull 完成后整体切换，
+// 客户端不会看到检出到一半的索引
+pub struct ServingRoot {
+    repo_path: PathBuf,
+    atomic: bool,
+    preserve_mtime: bool,
+    current: RwLock<PathBuf>,
+    // 每次 publish 成功后加一，不经过 record_update 的更新也会让缓存失效
+    generation: AtomicU64,
+}
+
+// 缓存属于哪一次索引更新：last_update 或已发布快照的代数变化后重建
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IndexVersion {
+    updated: DateTime<Local>,
+    generation: u64,
+}
+
+impl IndexVersion {
+    pub fn current(health: &HealthState, root: &ServingRoot) -> Self {
+        IndexVersion {
+            updated: health.last_update(),
+            generation: root.generation(),
+        }
+    }
+}
+
+impl ServingRoot {
+    pub fn new(repo_path: impl Into<PathBuf>, atomic: bool, preserve_mtime: bool) -> Self {
+        let repo_path = repo_path.into();
+        ServingRoot {
+            current: RwLock::new(repo_path.clone()),
+            repo_path,
+            atomic,
+            preserve_mtime,
+            generation: AtomicU64::new(0),
+        }
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::Acquire)
+    }
+
+    pub fn get(&self) -> PathBuf {
+        self.current.read().unwrap().clone()
+    }
+
+    fn snapshots_dir(&self) -> PathBuf {
+        let mut dir = self.repo_path.clone().into_os_string();
+        dir.push(".snapshots");
+        dir.into()
+    }
+
+    // 把 HEAD 导出到 <path>.new，再整体 rename 成 <path>.snapshots/<oid> 并切换过去
+    pub fn publish(&self, repo: &Repository) -> Result<(), String> {
+        self.export(repo)?;
+        self.generation.fetch_add(1, Ordering::AcqRel);
+        Ok(())
+    }
+
+    fn export(&self, repo: &Repository) -> Result<(), String> {
+        if !self.atomic {
+            return Ok(());
+        }
+        let commit = repo
+            .head()
+            .and_then(|head| head.peel_to_commit())
+            .map_err(|e| e.to_string())?;
+        let snapshots_dir = self.snapshots_dir();
+        let target = snapshots_dir.join(commit.id().to_string());
+        if !target.exists() {
+            let mut staging = self.repo_path.clone().into_os_string();
+            staging.push(".new");
+            let staging = PathBuf::from(staging);
+            if staging.exists() {
+                fs::remove_dir_all(&staging).map_err(|e| e.to_string())?;
+            }
+            fs::create_dir_all(&snapshots_dir).map_err(|e| e.to_string())?;
+            fs::create_dir(&staging).map_err(|e| e.to_string())?;
+            // libgit2 只会在绝对路径的 target_dir 下创建子目录
+            let staging = staging.canonicalize().map_err(|e| e.to_string())?;
+            // 只写到目标目录，不更新仓库自己的 index
+            let tree = commit.tree().map_err(|e| e.to_string())?;
+            repo.checkout_tree(
+                commit.as_object(),
+                Some(
+                    crate::git::checkout_builder(&tree)
+                        .force()
+                        .update_index(false)
+                        .target_dir(&staging),
+                ),
+            )
+            .map_err(|e| e.to_string())?;
+            if self.preserve_mtime {
+                let previous = self.get();
+                let previous_commit = previous_commit(&previous, &snapshots_dir);
+                match copy_unchanged_mtimes(repo, &tree, previous_commit, &previous, &staging) {
+                    Ok(count) => info!("Preserved mtime of {} unchanged index files", count),
+                    Err(e) => warn!("Failed to preserve index file mtimes: {}", e),
+                }
+            }
+            fs::rename(&staging, &target).map_err(|e| e.to_string())?;
+        }
+
+        let previous = std::mem::replace(&mut *self.current.write().unwrap(), target.clone());
+        if previous != target {
+            info!("Serving index snapshot {}", commit.id());
+        }
+        prune(&snapshots_dir, &[&target, &previous]);
+        Ok(())
+    }
+}
+
+// 快照目录名就是导出的提交；工作区没有对应的目录名，用仓库的 index 代替
+fn previous_commit(previous: &Path, snapshots_dir: &Path) -> Option<Oid> {
+    if previous.parent() != Some(snapshots_dir) {
+        return None;
+    }
+    previous.file_name()?.to_str()?.parse().ok()
+}
+
+// 导出会让所有文件的 mtime 变成当前时间；内容没有变化的文件沿用上一个快照（或工作区）里的 mtime，
+// 避免客户端按 mtime 缓存的索引文件在每次 pull 后全部失效
+fn copy_unchanged_mtimes(
+    repo: &Repository,
+    tree: &Tree,
+    previous_commit: Option<Oid>,
+    previous: &Path,
+    staging: &Path,
+) -> Result<usize, git2::Error> {
+    let diff = match previous_commit {
+        Some(oid) => {
+            let old_tree = repo.find_commit(oid)?.tree()?;
+            repo.diff_tree_to_tree(Some(&old_tree), Some(tree), None)?
+        }
+        None => repo.diff_tree_to_index(Some(tree), Some(&repo.index()?), None)?,
+    };
+    let changed: HashSet<PathBuf> = diff
+        .deltas()
+        .flat_map(|delta| [delta.old_file().path(), delta.new_file().path()])
+        .flatten()
+        .map(Path::to_path_buf)
+        .collect();
+
+    let mut count = 0;
+    tree.walk(TreeWalkMode::PreOrder, |dir, entry| {
+        if entry.kind() != Some(ObjectType::Blob) {
+            return TreeWalkResult::Ok;
+        }
+        let Some(name) = entry.name() else {
+            return TreeWalkResult::Ok;
+        };
+        let path = Path::new(dir).join(name);
+        if changed.contains(&path) {
+            return TreeWalkResult::Ok;
+        }
+        if let Ok(metadata) = fs::symlink_metadata(previous.join(&path)) {
+            let mtime = FileTime::from_last_modification_time(&metadata);
+            if filetime::set_file_mtime(staging.join(&path), mtime).is_ok() {
+                count += 1;
+            }
+        }
+        TreeWalkResult::Ok
+    })?;
+    Ok(count)
+}
+
+// 保留当前和上一个快照，上一个快照可能还有请求正在读取
+fn prune(snapshots_dir: &Path, keep: &[&Path]) {
+    let Ok(entries) = fs::read_dir(snapshots_dir) else {
+        return;
+    };
+    for entry in entries.filter_map(|e| e.ok()) {
+        let path = entry.path();
+        if keep.contains(&path.as_path()) {
+            continue;
+        }
+        if let Err(e) = fs::remove_dir_all(&path) {
+            warn!("Failed to remove old index snapshot {:?}: {}", path, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{index_line, Upstream};
+    use std::{
+        sync::{atomic::AtomicBool, Arc},
+        thread,
+    };
+
+    fn files(version: u32) -> [(&'static str, Option<String>); 2] {
+        let version = format!("1.0.{}", version);
+        [
+            ("1/a", Some(index_line("a", &version))),
+            ("1/b", Some(index_line("b", &version))),
+        ]
+    }
+
+    fn commit(upstream: &Upstream, version: u32) {
+        let files = files(version);
+        let files: Vec<_> = files.iter().map(|(path, content)| (*path, content.as_deref())).collect();
+        upstream.commit(&format!("version {}", version), &files);
+    }
+
+    #[test]
+    fn serves_work_tree_without_atomic_checkout() {
+        let upstream = Upstream::new();
+        commit(&upstream, 0);
+        let root = ServingRoot::new(upstream.dir.path(), false, false);
+        root.publish(&upstream.repo).unwrap();
+        assert_eq!(root.get(), upstream.dir.path());
+        assert_eq!(root.generation(), 1);
+    }
+
+    #[test]
+    fn publish_switches_whole_snapshots() {
+        let upstream = Upstream::new();
+        commit(&upstream, 0);
+        let root = Arc::new(ServingRoot::new(upstream.dir.path(), true, false));
+        root.publish(&upstream.repo).unwrap();
+        let first = root.get();
+        assert_ne!(first, upstream.dir.path());
+        assert_eq!(fs::read_to_string(first.join("1/a")).unwrap(), index_line("a", "1.0.0"));
+
+        // 读取方同时读两个文件，它们必须来自同一次提交
+        let done = Arc::new(AtomicBool::new(false));
+        let readers: Vec<_> = (0..4)
+            .map(|_| {
+                let (root, done) = (Arc::clone(&root), Arc::clone(&done));
+                thread::spawn(move || {
+                    let mut checked = 0;
+                    while !done.load(Ordering::Acquire) {
+                        let dir = root.get();
+                        // 两个快照之前的目录可能已经被删除
+                        let (Ok(a), Ok(b)) = (fs::read_to_string(dir.join("1/a")), fs::read_to_string(dir.join("1/b")))
+                        else {
+                            continue;
+                        };
+                        let version = |line: &str| line.split("\"vers\":").nth(1).map(str::to_string);
+                        assert!(a.ends_with('\n') && b.ends_with('\n'), "partial file in {:?}", dir);
+                        assert_eq!(version(&a), version(&b), "inconsistent snapshot {:?}", dir);
+                        checked += 1;
+                    }
+                    checked
+                })
+            })
+            .collect();
+        for version in 1..=20 {
+            commit(&upstream, version);
+            root.publish(&upstream.repo).unwrap();
+        }
+        done.store(true, Ordering::Release);
+        let checked: usize = readers.into_iter().map(|reader| reader.join().unwrap()).sum();
+        assert!(checked > 0);
+
+        assert_eq!(fs::read_to_string(root.get().join("1/b")).unwrap(), index_line("b", "1.0.20"));
+        assert_eq!(root.generation(), 21);
+        // 只保留当前和上一个快照，不留下 .new
+        assert_eq!(fs::read_dir(root.snapshots_dir()).unwrap().count(), 2);
+        let mut staging = upstream.dir.path().as_os_str().to_owned();
+        staging.push(".new");
+        assert!(!Path::new(&staging).exists());
+    }
+
+    #[test]
+    fn preserve_mtime_of_unchanged_files() {
+        let upstream = Upstream::new();
+        upstream.commit("add", &[("1/a", Some(&index_line("a", "1.0.0"))), ("1/b", Some(&index_line("b", "1.0.0")))]);
+        let root = ServingRoot::new(upstream.dir.path(), true, true);
+        root.publish(&upstream.repo).unwrap();
+        let old_mtime = FileTime::from_unix_time(1_000_000, 0);
+        filetime::set_file_mtime(root.get().join("1/a"), old_mtime).unwrap();
+        filetime::set_file_mtime(root.get().join("1/b"), old_mtime).unwrap();
+
+        upstream.commit("bump b", &[("1/b", Some(&index_line("b", "1.0.1")))]);
+        root.publish(&upstream.repo).unwrap();
+        let mtime = |path: &str| FileTime::from_last_modification_time(&fs::metadata(root.get().join(path)).unwrap());
+        assert_eq!(mtime("1/a"), old_mtime);
+        assert_ne!(mtime("1/b"), old_mtime);
+    }
+}
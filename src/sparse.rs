@@ -0,0 +1,398 @@
+use actix_web::{
+    guard::GuardContext,
+    http::header::{
+        EntityTag, ETag, HttpDate, IfModifiedSince, IfNoneMatch, LastModified, ACCEPT_RANGES, CONTENT_TYPE,
+    },
+    web, HttpMessage, HttpRequest, HttpResponse,
+};
+use bytes::Bytes;
+use futures_util::stream;
+use percent_encoding::percent_decode_str;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    byte_ranges, chunked,
+    delta::{self, DeltaEncoding},
+    coalesce::{IndexFileCoalescer, LoadedFile},
+    config::{RegistryConfig, WebConfig},
+    panic_recovery,
+    problem::{self, ProblemDetails, ProblemType},
+    slo::{self, SloEndpoint, SloMonitor},
+    snapshot::ServingRoot,
+};
+
+// crates.io 对 crate 名称长度的限制
+const MAX_NAME_LENGTH: usize = 64;
+
+// cargo 的索引目录规则：1/a、2/ab、3/a/abc、ab/cd/abcd
+// 按字节切分前缀，空名称和 crate 名称字符集以外的字符（非 ASCII、/、. 等）返回 None
+pub fn crate_index_path(name: &str) -> Option<PathBuf> {
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+        return None;
+    }
+    let name = name.to_ascii_lowercase();
+    Some(match name.len() {
+        1 => Path::new("1").join(&name),
+        2 => Path::new("2").join(&name),
+        3 => Path::new("3").join(&name[..1]).join(&name),
+        _ => Path::new(&name[..2]).join(&name[2..4]).join(&name),
+    })
+}
+
+// cargo 把 - 和 _ 视为相同，serde-derive 和 serde_derive 是同一个 crate
+pub fn normalize_name(name: &str) -> String {
+    name.to_ascii_lowercase().replace('-', "_")
+}
+
+// 返回 name 对应的索引文件相对 root 的路径，文件不存在时返回 None
+// 非 strict 时如果精确路径不存在，在全部换成 _ 和全部换成 - 的名称所在的目录里找规范化后相同的文件；
+// 前四个字符里同时有 - 和 _ 的名称可能在其他目录，这里不处理
+pub fn resolve_crate_path(root: &Path, name: &str, strict: bool) -> Option<PathBuf> {
+    let exact = crate_index_path(name)?;
+    if root.join(&exact).is_file() {
+        return Some(exact);
+    }
+    if strict {
+        return None;
+    }
+    let normalized = normalize_name(name);
+    let mut dirs: Vec<PathBuf> = [normalized.clone(), normalized.replace('_', "-")]
+        .iter()
+        .filter_map(|candidate| Some(crate_index_path(candidate)?.parent()?.to_path_buf()))
+        .collect();
+    dirs.dedup();
+    dirs.into_iter().find_map(|dir| {
+        fs::read_dir(root.join(&dir))
+            .ok()?
+            .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+            .find(|file_name| normalize_name(file_name) == normalized)
+            .map(|file_name| dir.join(file_name))
+    })
+}
+
+pub fn valid_crate_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= MAX_NAME_LENGTH
+        && name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+// 路径形状符合某个 crate 的索引文件时才交给 sparse handler，其余路径（目录列表等）仍由静态文件服务处理
+// 按解码后的字符数判断，非 ASCII 名称（例如 /ca/f%C3%A9/caf%C3%A9）也交给 sparse handler 返回 400
+fn is_crate_path(segments: &[String]) -> bool {
+    let chars = |segment: &String| segment.chars().count();
+    match segments {
+        [prefix, name] if prefix == "1" || prefix == "2" => !name.is_empty(),
+        [prefix, first, name] if prefix == "3" => chars(first) == 1 && !name.is_empty(),
+        [a, b, name] => chars(a) == 2 && chars(b) == 2 && !name.is_empty(),
+        _ => false,
+    }
+}
+
+pub fn is_crate_request(path: &str) -> bool {
+    let segments: Vec<String> = path
+        .trim_start_matches('/')
+        .split('/')
+        .map(|segment| percent_decode_str(segment).decode_utf8_lossy().into_owned())
+        .collect();
+    is_crate_path(&segments)
+}
+
+pub fn guard(ctx: &GuardContext<'_>) -> bool {
+    is_crate_request(ctx.head().uri.path())
+}
+
+const INDEX_CONTENT_TYPE: &str = "text/plain; charset=utf-8";
+
+struct ResolvedIndexFile {
+    path: PathBuf,
+    // 相对仓库根目录，delta 编码用它在 git 历史里查找旧版本
+    relative: PathBuf,
+}
+
+// 请求路径对应的索引文件，不存在时返回 ProblemDetails 错误
+async fn resolve_index_file(
+    req: &HttpRequest,
+    root: &ServingRoot,
+    registry: &RegistryConfig,
+) -> actix_web::Result<ResolvedIndexFile> {
+    let path = req.path().trim_start_matches('/');
+    let (prefix, raw_name) = path.rsplit_once('/').unwrap_or_default();
+    let name = percent_decode_str(raw_name).decode_utf8_lossy();
+    if !valid_crate_name(&name) {
+        return Err(ProblemDetails::new(ProblemType::InvalidCrateName)
+            .with_detail(format!("Invalid crate name {:?}", name))
+            .into());
+    }
+    let not_found = || problem::not_found(format!("crate `{}` does not exist", name)).into();
+    let index_path = crate_index_path(&name).expect("valid crate names have an index path");
+    // 前缀必须和名称算出来的一致，例如 /ab/cd/abxx 不存在
+    if index_path.parent() != Some(Path::new(&prefix.to_ascii_lowercase())) {
+        return Err(not_found());
+    }
+    let root = root.get();
+    let strict = registry.strict_name_matching;
+    let resolved = {
+        let (root, name) = (root.clone(), name.to_string());
+        panic_recovery::block(move || resolve_crate_path(&root, &name, strict)).await?
+    };
+    resolved
+        .map(|relative| ResolvedIndexFile {
+            path: root.join(&relative),
+            relative,
+        })
+        .ok_or_else(not_found)
+}
+
+fn file_response(req: &HttpRequest, file: &LoadedFile) -> HttpResponse {
+    let not_modified = not_modified(req, &file.etag, file.last_modified);
+    let mut res = if not_modified {
+        HttpResponse::NotModified()
+    } else {
+        HttpResponse::Ok()
+    };
+    res.insert_header(ETag(file.etag.clone()))
+        .insert_header(LastModified(file.last_modified))
+        // 索引文件没有扩展名，不设置的话客户端会当作 application/octet-stream
+        .insert_header((CONTENT_TYPE, INDEX_CONTENT_TYPE));
+    if not_modified {
+        return res.finish();
+    }
+    res.insert_header((ACCEPT_RANGES, "bytes"));
+    // 一次请求多个区间时返回 multipart/byteranges，见 byte_ranges.rs
+    if let Some(partial) =
+        byte_ranges::partial_response(req, &mut res, &file.body, &file.etag, file.last_modified, INDEX_CONTENT_TYPE)
+    {
+        return partial;
+    }
+    res.body(file.body.clone())
+}
+
+// 同一个文件的并发请求合并成一次读取，见 coalesce.rs
+// sparse_index 因为 [slo] 降级时不访问文件系统，只返回最近返回过的文件，其余请求返回 503
+pub async fn index_file(
+    req: HttpRequest,
+    root: web::Data<ServingRoot>,
+    registry: web::Data<RegistryConfig>,
+    coalescer: web::Data<IndexFileCoalescer>,
+    slo: web::Data<SloMonitor>,
+    delta: web::Data<DeltaEncoding>,
+) -> actix_web::Result<HttpResponse> {
+    if slo.is_degraded(SloEndpoint::SparseIndex) {
+        return Ok(match coalescer.recent(req.path()) {
+            Some(file) => file_response(&req, &file),
+            None => slo::shed(),
+        });
+    }
+    let ResolvedIndexFile { path, relative } = resolve_index_file(&req, &root, &registry).await?;
+    // 带条件的请求大多是 304，只读元数据就够了；ETag 是 blob OID 时必须读取内容
+    if !delta.enabled && (req.get_header::<IfNoneMatch>().is_some() || req.get_header::<IfModifiedSince>().is_some()) {
+        let metadata_path = path.clone();
+        if let Ok(metadata) = panic_recovery::block(move || fs::metadata(metadata_path)).await? {
+            let modified = metadata.modified().unwrap_or(UNIX_EPOCH);
+            let etag = file_etag(&metadata, modified);
+            let last_modified = HttpDate::from(modified);
+            if not_modified(&req, &etag, last_modified) {
+                return Ok(HttpResponse::NotModified()
+                    .insert_header(ETag(etag))
+                    .insert_header(LastModified(last_modified))
+                    .insert_header((CONTENT_TYPE, INDEX_CONTENT_TYPE))
+                    .finish());
+            }
+        }
+    }
+    let Some(file) = coalescer.load(path).await? else {
+        return Ok(problem::not_found("index file disappeared").into());
+    };
+    if slo.is_tracked(SloEndpoint::SparseIndex) {
+        coalescer.remember(req.path(), &file);
+    }
+    if !not_modified(&req, &file.etag, file.last_modified) {
+        if let Some(res) = delta::delta_response(&req, &delta, relative, &file).await {
+            return Ok(res);
+        }
+    }
+    Ok(file_response(&req, &file))
+}
+
+fn not_modified(req: &HttpRequest, etag: &EntityTag, last_modified: HttpDate) -> bool {
+    match req.get_header::<IfNoneMatch>() {
+        Some(IfNoneMatch::Any) => true,
+        Some(IfNoneMatch::Items(tags)) => tags.iter().any(|tag| tag.weak_eq(etag)),
+        None => req
+            .get_header::<IfModifiedSince>()
+            .is_some_and(|since| SystemTime::from(last_modified) <= SystemTime::from(since.0)),
+    }
+}
+
+// 和 actix-files 的 ETag 格式相同，HEAD 和 GET 返回的 ETag 一致
+pub fn file_etag(metadata: &fs::Metadata, modified: SystemTime) -> EntityTag {
+    #[cfg(unix)]
+    let ino = {
+        use std::os::unix::fs::MetadataExt;
+        metadata.ino()
+    };
+    #[cfg(not(unix))]
+    let ino = 0;
+    let mtime = modified.duration_since(UNIX_EPOCH).unwrap_or_default();
+    EntityTag::new_strong(format!(
+        "{:x}:{:x}:{:x}:{:x}",
+        ino,
+        metadata.len(),
+        mtime.as_secs(),
+        mtime.subsec_nanos()
+    ))
+}
+
+// HEAD 只读取文件的元数据，不打开文件
+pub async fn index_file_head(
+    req: HttpRequest,
+    root: web::Data<ServingRoot>,
+    registry: web::Data<RegistryConfig>,
+    web_config: web::Data<WebConfig>,
+) -> actix_web::Result<HttpResponse> {
+    let path = resolve_index_file(&req, &root, &registry).await?.path;
+    // web.delta_encoding 时 ETag 由内容计算，和 GET 一致
+    let blob_etags = web_config.delta_encoding;
+    let Ok((metadata, blob_etag)) = panic_recovery::block(move || {
+        let metadata = fs::metadata(&path)?;
+        let blob_etag = if blob_etags {
+            Some(delta::blob_etag(&fs::read(&path)?))
+        } else {
+            None
+        };
+        Ok::<_, std::io::Error>((metadata, blob_etag))
+    })
+    .await?
+    else {
+        return Ok(problem::not_found("index file disappeared").into());
+    };
+    let modified = metadata.modified().unwrap_or(UNIX_EPOCH);
+    let etag = blob_etag.unwrap_or_else(|| file_etag(&metadata, modified));
+    let last_modified = HttpDate::from(modified);
+
+    let not_modified = not_modified(&req, &etag, last_modified);
+    let mut res = if not_modified {
+        HttpResponse::NotModified()
+    } else {
+        HttpResponse::Ok()
+    };
+    res.insert_header(ETag(etag))
+        .insert_header(LastModified(last_modified))
+        .insert_header((CONTENT_TYPE, INDEX_CONTENT_TYPE));
+    if not_modified {
+        return Ok(res.finish());
+    }
+    res.insert_header((ACCEPT_RANGES, "bytes"));
+    // GET 会用 chunked 发送的文件不返回 Content-Length，和 GET 的响应头一致
+    if chunked::applies(&web_config, metadata.len()) {
+        return Ok(res.streaming(stream::empty::<Result<Bytes, actix_web::Error>>()));
+    }
+    // 大小已知的响应体会按实际大小重写 Content-Length，空的流式响应体保留这里设置的值
+    // HEAD 响应本来就不会发送响应体
+    Ok(res
+        .no_chunking(metadata.len())
+        .streaming(stream::empty::<Result<Bytes, actix_web::Error>>()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{guard, http::StatusCode, test as actix_test, App};
+
+    #[test]
+    fn index_path_by_name_length() {
+        let path = |name| crate_index_path(name).unwrap();
+        assert_eq!(path("a"), Path::new("1/a"));
+        assert_eq!(path("ab"), Path::new("2/ab"));
+        assert_eq!(path("abc"), Path::new("3/a/abc"));
+        assert_eq!(path("abcd"), Path::new("ab/cd/abcd"));
+        assert_eq!(path("abcde"), Path::new("ab/cd/abcde"));
+        assert_eq!(path("Serde"), Path::new("se/rd/serde"));
+    }
+
+    #[test]
+    fn index_path_rejects_names_outside_the_charset() {
+        for name in ["", "éabc", "aé", "ab/cd", "../config", "a.b"] {
+            assert_eq!(crate_index_path(name), None, "{:?}", name);
+        }
+    }
+
+    #[test]
+    fn crate_names() {
+        assert!(valid_crate_name("serde_json"));
+        assert!(valid_crate_name("_private"));
+        assert!(valid_crate_name(&"a".repeat(MAX_NAME_LENGTH)));
+        assert!(!valid_crate_name(""));
+        assert!(!valid_crate_name(&"a".repeat(MAX_NAME_LENGTH + 1)));
+        assert!(!valid_crate_name("1abc"));
+        assert!(!valid_crate_name("caf\u{e9}"));
+        assert!(!valid_crate_name(".."));
+        assert!(!valid_crate_name("../config"));
+    }
+
+    #[test]
+    fn crate_request_shapes() {
+        assert!(is_crate_request("/1/a"));
+        assert!(is_crate_request("/3/a/abc"));
+        assert!(is_crate_request("/se/rd/serde"));
+        assert!(!is_crate_request("/config.json"));
+        assert!(!is_crate_request("/3/ab/abc"));
+        assert!(!is_crate_request("/se/rd/"));
+        assert!(!is_crate_request("/a/b/c/d"));
+        assert!(is_crate_request("/ca/f%C3%A9/caf%C3%A9"));
+    }
+
+    #[test]
+    fn resolves_normalized_names() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("se/rd")).unwrap();
+        fs::write(dir.path().join("se/rd/serde_derive"), "{}\n").unwrap();
+        assert_eq!(
+            resolve_crate_path(dir.path(), "serde_derive", true),
+            Some(PathBuf::from("se/rd/serde_derive"))
+        );
+        assert_eq!(
+            resolve_crate_path(dir.path(), "Serde-Derive", false),
+            Some(PathBuf::from("se/rd/serde_derive"))
+        );
+        assert_eq!(resolve_crate_path(dir.path(), "serde-derive", true), None);
+        assert_eq!(resolve_crate_path(dir.path(), "serde", false), None);
+    }
+
+    async fn head(dir: &Path, uri: &str) -> StatusCode {
+        let web_config = crate::test_support::web_config("");
+        let app = actix_test::init_service(
+            App::new()
+                .app_data(web::Data::new(ServingRoot::new(dir, false, false)))
+                .app_data(web::Data::new(RegistryConfig::default()))
+                .app_data(web::Data::new(web_config))
+                .route("/{path:.*}", web::head().guard(guard::fn_guard(guard)).to(index_file_head)),
+        )
+        .await;
+        let req = actix_test::TestRequest::default().method(actix_web::http::Method::HEAD).uri(uri).to_request();
+        actix_test::call_service(&app, req).await.status()
+    }
+
+    #[actix_web::test]
+    async fn rejects_invalid_names_and_traversal() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("se/rd")).unwrap();
+        fs::write(dir.path().join("se/rd/serde"), "{}\n").unwrap();
+        fs::write(dir.path().join("config.json"), "{}\n").unwrap();
+
+        assert_eq!(head(dir.path(), "/se/rd/serde").await, StatusCode::OK);
+        assert_eq!(head(dir.path(), "/se/rd/SERDE").await, StatusCode::OK);
+        // 非 ASCII 名称
+        assert_eq!(head(dir.path(), "/ca/f%C3%A9/caf%C3%A9").await, StatusCode::BAD_REQUEST);
+        assert_eq!(head(dir.path(), "/se/rd/..%2fconfig.json").await, StatusCode::BAD_REQUEST);
+        assert_eq!(head(dir.path(), "/se/rd/%2e%2e").await, StatusCode::BAD_REQUEST);
+        // 前缀和名称不一致
+        assert_eq!(head(dir.path(), "/ab/cd/serde").await, StatusCode::NOT_FOUND);
+        assert_eq!(head(dir.path(), "/se/rd/serdex").await, StatusCode::NOT_FOUND);
+    }
+}
@@ -0,0 +1,181 @@
+// 测试用的上游仓库和配置
+use git2::{Oid, Repository, RepositoryInitOptions, Signature};
+use std::{fs, path::Path};
+use tempfile::TempDir;
+
+use crate::{config::Config, git};
+
+// 带工作区的上游仓库，作为镜像的 git_url
+pub struct Upstream {
+    pub dir: TempDir,
+    pub repo: Repository,
+}
+
+impl Upstream {
+    pub fn new() -> Self {
+        let dir = tempfile::tempdir().unwrap();
+        let mut options = RepositoryInitOptions::new();
+        options.initial_head("master");
+        let repo = Repository::init_opts(dir.path(), &options).unwrap();
+        Upstream { dir, repo }
+    }
+
+    pub fn url(&self) -> String {
+        self.dir.path().to_str().unwrap().to_string()
+    }
+
+    // 写入（内容为 None 时删除）文件并提交到 master
+    pub fn commit(&self, message: &str, files: &[(&str, Option<&str>)]) -> Oid {
+        let parent = self.repo.head().ok().and_then(|head| head.peel_to_commit().ok());
+        let tree = self.write_tree(files);
+        let parents: Vec<_> = parent.iter().collect();
+        let signature = signature();
+        self.repo
+            .commit(Some("HEAD"), &signature, &signature, message, &tree, &parents)
+            .unwrap()
+    }
+
+    // 用没有父提交的新提交替换 master，模拟上游重写历史
+    pub fn rewrite(&self, message: &str, files: &[(&str, Option<&str>)]) -> Oid {
+        let tree = self.write_tree(files);
+        let signature = signature();
+        let oid = self.repo.commit(None, &signature, &signature, message, &tree, &[]).unwrap();
+        self.repo.reference("refs/heads/master", oid, true, "rewrite").unwrap();
+        self.repo.checkout_head(Some(git2::build::CheckoutBuilder::new().force())).unwrap();
+        oid
+    }
+
+    fn write_tree(&self, files: &[(&str, Option<&str>)]) -> git2::Tree<'_> {
+        let mut index = self.repo.index().unwrap();
+        for (path, content) in files {
+            let full = self.dir.path().join(path);
+            match content {
+                Some(content) => {
+                    fs::create_dir_all(full.parent().unwrap()).unwrap();
+                    fs::write(&full, content).unwrap();
+                    index.add_path(Path::new(path)).unwrap();
+                }
+                None => {
+                    let _ = fs::remove_file(&full);
+                    index.remove_path(Path::new(path)).unwrap();
+                }
+            }
+        }
+        index.write().unwrap();
+        let oid = index.write_tree().unwrap();
+        self.repo.find_tree(oid).unwrap()
+    }
+}
+
+pub fn signature() -> Signature<'static> {
+    Signature::now("Index Bot", "bot@example.com").unwrap()
+}
+
+// 一行索引记录
+pub fn index_line(name: &str, version: &str) -> String {
+    format!(
+        "{{\"name\":\"{}\",\"vers\":\"{}\",\"deps\":[],\"cksum\":\"{}\",\"features\":{{}},\"yanked\":false}}\n",
+        name,
+        version,
+        "0".repeat(64)
+    )
+}
+
+// repo_extra 追加到 [repo]，rest 追加在后面（其他段）
+pub fn config(git_url: &str, path: &Path, repo_extra: &str, rest: &str) -> Config {
+    let text = format!(
+        "[repo]\ngit_url = {:?}\npath = {:?}\nupdate_interval = 60\n{}\n[web]\naddress = \"127.0.0.1\"\nport = 0\n{}\n",
+        git_url,
+        path.to_str().unwrap(),
+        repo_extra,
+        rest
+    );
+    toml::from_str(&text).unwrap()
+}
+
+// 把上游 clone 到 dir/index
+pub fn mirror(upstream: &Upstream, dir: &Path) -> Repository {
+    let auth = git::GitAuth {
+        use_credential_helper: false,
+        ssh_use_agent: false,
+        ssl_verify: true,
+    };
+    git::clone_repo(&upstream.url(), &dir.join("index"), &auth).unwrap()
+}
+
+// 用 SSH 密钥签名的提交，libgit2 不能创建签名，改用 git 命令
+pub fn signed_commit(upstream: &Upstream, key: &Path, message: &str, path: &str, content: &str) -> Oid {
+    let full = upstream.dir.path().join(path);
+    fs::create_dir_all(full.parent().unwrap()).unwrap();
+    fs::write(&full, content).unwrap();
+    let git = |args: &[&str]| {
+        let output = std::process::Command::new("git")
+            .current_dir(upstream.dir.path())
+            .args(["-c", "user.name=Index Bot", "-c", "user.email=bot@example.com", "-c", "gpg.format=ssh"])
+            .arg("-c")
+            .arg(format!("user.signingkey={}", key.display()))
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    };
+    git(&["add", path]);
+    git(&["commit", "-q", "-S", "-m", message]);
+    upstream.repo.refname_to_id("refs/heads/master").unwrap()
+}
+
+// 生成一对 ed25519 密钥，返回私钥路径，公钥在 <私钥>.pub
+pub fn ssh_key(dir: &Path, name: &str) -> std::path::PathBuf {
+    let key = dir.join(name);
+    let status = std::process::Command::new("ssh-keygen")
+        .args(["-q", "-t", "ed25519", "-N", "", "-C", name, "-f"])
+        .arg(&key)
+        .status()
+        .unwrap();
+    assert!(status.success());
+    key
+}
+
+// 只有 [web] 段的配置，extra 是这一段的其他字段
+pub fn web_config(extra: &str) -> crate::config::WebConfig {
+    toml::from_str(&format!("address = \"127.0.0.1\"\nport = 0\n{}", extra)).unwrap()
+}
+
+// 收集当前线程的日志，actix_web::test 的运行时在当前线程上执行
+#[derive(Clone, Default)]
+pub struct Logs(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+
+impl Logs {
+    pub fn contents(&self) -> String {
+        String::from_utf8_lossy(&self.0.lock().unwrap()).into_owned()
+    }
+}
+
+impl std::io::Write for Logs {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a> tracing_subscriber::fmt::MakeWriter<'a> for Logs {
+    type Writer = Logs;
+
+    fn make_writer(&'a self) -> Logs {
+        self.clone()
+    }
+}
+
+pub fn capture_logs() -> (Logs, tracing::subscriber::DefaultGuard) {
+    let logs = Logs::default();
+    let subscriber = tracing_subscriber::fmt()
+        .with_writer(logs.clone())
+        .with_max_level(tracing::Level::TRACE)
+        .with_ansi(false)
+        .finish();
+    (logs, tracing::subscriber::set_default(subscriber))
+}
@@ -0,0 +1,248 @@
+use git2::{Oid, Repository};
+use std::path::Path;
+use tracing::warn;
+
+use crate::{index::IndexScanner, index_parser::fast_parse_index_line, metrics, sparse};
+
+// crate 名称只包含 ASCII 字符，按小写字节建树；子节点按字节排序，遍历结果和按小写名称排序一致
+#[derive(Default)]
+struct TrieNode {
+    children: Vec<(u8, TrieNode)>,
+    // 以这个节点结尾的 crate 的原始名称
+    name: Option<String>,
+}
+
+impl TrieNode {
+    fn child(&self, byte: u8) -> Option<&TrieNode> {
+        self.children
+            .binary_search_by_key(&byte, |(b, _)| *b)
+            .ok()
+            .map(|i| &self.children[i].1)
+    }
+
+    fn collect<'a>(&'a self, limit: usize, out: &mut Vec<&'a str>) {
+        if out.len() >= limit {
+            return;
+        }
+        if let Some(name) = &self.name {
+            out.push(name);
+        }
+        for (_, child) in &self.children {
+            if out.len() >= limit {
+                return;
+            }
+            child.collect(limit, out);
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct NameTrie {
+    root: TrieNode,
+    nodes: usize,
+}
+
+impl NameTrie {
+    pub fn node_count(&self) -> usize {
+        self.nodes
+    }
+
+    pub fn insert(&mut self, name: &str) {
+        let mut node = &mut self.root;
+        for byte in name.to_ascii_lowercase().bytes() {
+            let i = match node.children.binary_search_by_key(&byte, |(b, _)| *b) {
+                Ok(i) => i,
+                Err(i) => {
+                    node.children.insert(i, (byte, TrieNode::default()));
+                    self.nodes += 1;
+                    i
+                }
+            };
+            node = &mut node.children[i].1;
+        }
+        node.name = Some(name.to_string());
+    }
+
+    // 删除名称，并删掉不再通向任何名称的节点
+    pub fn remove(&mut self, name: &str) {
+        fn remove(node: &mut TrieNode, key: &[u8], nodes: &mut usize) -> bool {
+            match key.split_first() {
+                None => node.name = None,
+                Some((byte, rest)) => {
+                    let Ok(i) = node.children.binary_search_by_key(byte, |(b, _)| *b) else {
+                        return false;
+                    };
+                    if remove(&mut node.children[i].1, rest, nodes) {
+                        node.children.remove(i);
+                        *nodes -= 1;
+                    }
+                }
+            }
+            node.name.is_none() && node.children.is_empty()
+        }
+        let key = name.to_ascii_lowercase();
+        remove(&mut self.root, key.as_bytes(), &mut self.nodes);
+    }
+
+    // 不区分大小写的前缀匹配，按小写名称排序返回最多 limit 个
+    pub fn prefix(&self, prefix: &str, limit: usize) -> Vec<&str> {
+        let mut node = &self.root;
+        for byte in prefix.to_ascii_lowercase().bytes() {
+            match node.child(byte) {
+                Some(child) => node = child,
+                None => return Vec::new(),
+            }
+        }
+        let mut out = Vec::new();
+        node.collect(limit, &mut out);
+        out
+    }
+}
+
+// 建树时的 HEAD；索引更新后只根据 diff 插入、删除变化的 crate，不重新扫描整个索引
+pub struct TrieState {
+    built_at: Option<Oid>,
+    pub trie: NameTrie,
+}
+
+fn head_commit(repo_path: &Path) -> Option<Oid> {
+    Repository::open(repo_path)
+        .ok()
+        .and_then(|repo| repo.head().ok().and_then(|head| head.target()))
+}
+
+fn build(scanner: &IndexScanner, head: Option<Oid>) -> TrieState {
+    let mut trie = NameTrie::default();
+    for summary in scanner.scan() {
+        trie.insert(&summary.name);
+    }
+    TrieState { built_at: head, trie }
+}
+
+fn apply_diff(repo_path: &Path, trie: &mut NameTrie, old: Oid, new: Oid) -> Result<(), git2::Error> {
+    let repo = Repository::open(repo_path)?;
+    let old_tree = repo.find_commit(old)?.tree()?;
+    let new_tree = repo.find_commit(new)?.tree()?;
+    let diff = repo.diff_tree_to_tree(Some(&old_tree), Some(&new_tree), None)?;
+    for delta in diff.deltas() {
+        let Some(path) = delta.new_file().path().or(delta.old_file().path()) else {
+            continue;
+        };
+        if !path.to_str().is_some_and(sparse::is_crate_request) {
+            continue;
+        }
+        if delta.new_file().id().is_zero() {
+            if let Some(file_name) = path.file_name().and_then(|name| name.to_str()) {
+                trie.remove(file_name);
+            }
+            continue;
+        }
+        // 原始大小写的名称在文件内容里
+        let blob = repo.find_blob(delta.new_file().id())?;
+        if let Some(entry) = blob
+            .content()
+            .split(|&b| b == b'\n')
+            .find_map(|line| fast_parse_index_line(line).ok())
+        {
+            trie.insert(&entry.name);
+        }
+    }
+    Ok(())
+}
+
+// 在阻塞线程池里执行
+pub fn refresh(repo_path: &Path, scanner: &IndexScanner, state: Option<TrieState>) -> TrieState {
+    let head = head_commit(repo_path);
+    let state = match state {
+        Some(state) if state.built_at == head => state,
+        Some(mut state) => match (state.built_at, head) {
+            (Some(old), Some(new)) => match apply_diff(repo_path, &mut state.trie, old, new) {
+                Ok(()) => TrieState {
+                    built_at: head,
+                    trie: state.trie,
+                },
+                Err(e) => {
+                    warn!("Failed to update the name trie from {}..{}, rebuilding: {}", old, new, e);
+                    build(scanner, head)
+                }
+            },
+            _ => build(scanner, head),
+        },
+        None => build(scanner, head),
+    };
+    metrics::TRIE_NODE_COUNT.set(state.trie.node_count() as i64);
+    state
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+    use std::collections::{BTreeMap, BTreeSet};
+
+    // 和关闭 trie_enabled 时一样：按小写名称排序后线性查找
+    fn linear<'a>(names: &'a BTreeMap<String, String>, prefix: &str, limit: usize) -> Vec<&'a str> {
+        let prefix = prefix.to_ascii_lowercase();
+        names
+            .iter()
+            .filter(|(lower, _)| lower.starts_with(&prefix))
+            .take(limit)
+            .map(|(_, name)| name.as_str())
+            .collect()
+    }
+
+    // 不同名称的不同前缀的个数
+    fn prefix_count(names: &BTreeMap<String, String>) -> usize {
+        let prefixes: BTreeSet<&str> = names
+            .keys()
+            .flat_map(|lower| (1..=lower.len()).map(move |end| &lower[..end]))
+            .collect();
+        prefixes.len()
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(256))]
+
+        #[test]
+        fn matches_linear_scan(
+            names in prop::collection::vec("[a-cA-C][a-c_-]{0,6}", 0..64),
+            removed in prop::collection::vec("[a-c][a-c_-]{0,6}", 0..16),
+            prefixes in prop::collection::vec("[a-cA-C_-]{0,4}", 1..16),
+            limit in 1..25usize,
+        ) {
+            let mut trie = NameTrie::default();
+            // 同一个小写名称只保留最后插入的原始名称
+            let mut expected = BTreeMap::new();
+            for name in &names {
+                trie.insert(name);
+                expected.insert(name.to_ascii_lowercase(), name.clone());
+            }
+            for name in &removed {
+                trie.remove(name);
+                expected.remove(&name.to_ascii_lowercase());
+            }
+            for prefix in &prefixes {
+                prop_assert_eq!(trie.prefix(prefix, limit), linear(&expected, prefix, limit), "prefix {:?}", prefix);
+            }
+            prop_assert_eq!(trie.node_count(), prefix_count(&expected));
+        }
+    }
+
+    #[test]
+    fn remove_prunes_nodes() {
+        let mut trie = NameTrie::default();
+        trie.insert("serde");
+        trie.insert("serde_json");
+        assert_eq!(trie.node_count(), 10);
+        trie.remove("SERDE_JSON");
+        assert_eq!(trie.node_count(), 5);
+        assert_eq!(trie.prefix("se", 10), ["serde"]);
+        // 不存在的名称不影响
+        trie.remove("serde_yaml");
+        trie.remove("ser");
+        assert_eq!(trie.prefix("", 10), ["serde"]);
+        trie.remove("serde");
+        assert_eq!(trie.node_count(), 0);
+        assert!(trie.prefix("", 10).is_empty());
+    }
+}
@@ -0,0 +1,413 @@
+mod common;
+
+use common::{index_line, index_path, wait_until, Server, ServerConfig, Upstream};
+use std::time::{Duration, Instant};
+
+fn json(res: reqwest::blocking::Response) -> serde_json::Value {
+    serde_json::from_str(&res.text().unwrap()).unwrap()
+}
+
+fn serde_versions(yanked: bool) -> String {
+    let mut lines = index_line("serde", "1.0.0") + &index_line("serde", "1.0.1");
+    if yanked {
+        lines = lines.replacen("\"yanked\":false", "\"yanked\":true", 1);
+    }
+    lines
+}
+
+#[test]
+fn single_version_metadata() {
+    let upstream = Upstream::with_crates(&["tokio"]);
+    upstream.commit("serde", &[("se/rd/serde", Some(&serde_versions(false)))]);
+    let server = Server::start(&upstream, ServerConfig::new().repo("update_cron = \"* * * * * *\""));
+
+    let res = server.get("/api/v1/crates/serde/1.0.1");
+    assert_eq!(res.status(), 200);
+    let version = &json(res)["version"];
+    assert_eq!(version["crate"], "serde");
+    assert_eq!(version["num"], "1.0.1");
+    assert_eq!(version["checksum"], "0".repeat(64));
+    assert_eq!(version["yanked"], false);
+
+    let res = server.get("/api/v1/crates/no-such-crate/1.0.0");
+    assert_eq!(res.status(), 404);
+    assert_eq!(json(res)["detail"], "crate `no-such-crate` does not exist");
+    let res = server.get("/api/v1/crates/serde/2.0.0");
+    assert_eq!(res.status(), 404);
+    assert_eq!(json(res)["detail"], "crate `serde` does not have a version `2.0.0`");
+
+    // 缓存的结果在下一次 pull 之后失效
+    assert_eq!(json(server.get("/api/v1/crates/serde/1.0.0"))["version"]["yanked"], false);
+    upstream.commit("yank", &[("se/rd/serde", Some(&serde_versions(true)))]);
+    let updated = wait_until(Duration::from_secs(15), || {
+        json(server.get("/api/v1/crates/serde/1.0.0"))["version"]["yanked"] == true
+    });
+    assert!(updated, "cached version was not invalidated:\n{}", server.log());
+}
+
+const NAME_PATHS: [&str; 5] = [
+    "/api/v1/crates/{}/versions",
+    "/api/v1/crates/{}/1.0.0",
+    "/api/v1/crates/{}/1.0.0/features",
+    "/api/v1/crates/{}/reverse-dependencies",
+    "/se/rd/{}",
+];
+
+fn statuses(server: &Server, name: &str) -> Vec<u16> {
+    NAME_PATHS
+        .iter()
+        .map(|path| server.get(&path.replace("{}", name)).status().as_u16())
+        .collect()
+}
+
+#[test]
+fn names_are_normalized() {
+    let upstream = Upstream::with_crates(&["serde_derive"]);
+    let server = Server::start(&upstream, ServerConfig::new().web("reverse_deps_enabled = true"));
+    for name in ["serde_derive", "serde-derive", "Serde_Derive", "SERDE-DERIVE"] {
+        assert_eq!(statuses(&server, name), [200; 5], "{}", name);
+    }
+    // 返回的是索引里的名称
+    assert_eq!(json(server.get("/api/v1/crates/Serde-Derive/1.0.0"))["version"]["crate"], "serde_derive");
+    assert_eq!(server.get("/se/rd/serde-derive").text().unwrap(), index_line("serde_derive", "1.0.0"));
+    assert_eq!(json(server.get("/api/v1/crates?q=SERDE-DER"))["meta"]["total"], 1);
+    for name in ["serde", "serde_deriv", "serde__derive", "serde.derive"] {
+        let statuses = statuses(&server, name);
+        assert!(statuses.iter().all(|status| [400, 404].contains(status)), "{} {:?}", name, statuses);
+    }
+}
+
+#[test]
+fn strict_name_matching() {
+    let upstream = Upstream::with_crates(&["serde_derive"]);
+    let server = Server::start(
+        &upstream,
+        ServerConfig::new()
+            .web("reverse_deps_enabled = true")
+            .rest("[registry]\nstrict_name_matching = true"),
+    );
+    assert_eq!(statuses(&server, "serde_derive"), [200; 5]);
+    // 大小写仍然不区分，- 和 _ 不再相同
+    assert_eq!(statuses(&server, "Serde_Derive"), [200; 5]);
+    assert_eq!(statuses(&server, "serde-derive"), [404; 5]);
+    assert_eq!(json(server.get("/api/v1/crates?q=serde-der"))["meta"]["total"], 0);
+}
+
+fn features_line(name: &str, features: &str, features2: Option<&str>, deps: &str) -> String {
+    let features2 = features2.map(|f| format!(",\"features2\":{},\"v\":2", f)).unwrap_or_default();
+    format!(
+        "{{\"name\":\"{}\",\"vers\":\"1.0.0\",\"deps\":{},\"cksum\":\"{}\",\"features\":{}{},\"yanked\":false}}\n",
+        name,
+        deps,
+        "0".repeat(64),
+        features,
+        features2
+    )
+}
+
+fn optional_dep(name: &str) -> String {
+    format!(
+        "{{\"name\":\"{}\",\"req\":\"^1\",\"features\":[],\"optional\":true,\"default_features\":true,\"target\":null,\"kind\":\"normal\"}}",
+        name
+    )
+}
+
+#[test]
+fn version_features() {
+    let upstream = Upstream::new();
+    let deps = format!("[{},{},{}]", optional_dep("serde"), optional_dep("rayon"), optional_dep("log"));
+    upstream.commit(
+        "crates",
+        &[
+            (
+                "ma/ny/many",
+                Some(&features_line(
+                    "many",
+                    r#"{"default":["std","serde"],"std":[],"derive":["serde/derive"]}"#,
+                    Some(r#"{"parallel":["dep:rayon"]}"#),
+                    &deps,
+                )),
+            ),
+            ("no/ne/none", Some(&features_line("none", "{}", None, "[]"))),
+        ],
+    );
+    let server = Server::start(&upstream, ServerConfig::new());
+
+    let res = server.get("/api/v1/crates/many/1.0.0/features");
+    assert_eq!(res.status(), 200);
+    assert_eq!(
+        json(res),
+        serde_json::json!({
+            "features": {"default": ["std", "serde"], "std": [], "derive": ["serde/derive"]},
+            "features2": {"parallel": ["dep:rayon"]},
+            // rayon 被 dep: 引用过，不会生成隐式 feature
+            "implicit": ["log", "serde"],
+        })
+    );
+
+    assert_eq!(
+        json(server.get("/api/v1/crates/none/1.0.0/features")),
+        serde_json::json!({"features": {}, "features2": {}, "implicit": []})
+    );
+
+    let res = server.get("/api/v1/crates/none/2.0.0/features");
+    assert_eq!(res.status(), 404);
+    assert_eq!(json(res)["detail"], "crate `none` does not have a version `2.0.0`");
+}
+
+#[test]
+fn manual_edits_invalidate_the_cache() {
+    let upstream = Upstream::with_crates(&["tokio"]);
+    upstream.commit("serde", &[("se/rd/serde", Some(&serde_versions(false)))]);
+    let server = Server::start(&upstream, ServerConfig::new().web("watch_index_files = true"));
+    assert!(server.wait_for_log("for index file changes", Duration::from_secs(10)), "{}", server.log());
+    assert_eq!(json(server.get("/api/v1/crates/serde/1.0.0"))["version"]["yanked"], false);
+    assert_eq!(json(server.get("/api/v1/crates/serde/1.0.0/features"))["implicit"], serde_json::json!([]));
+
+    // 不经过 pull 直接修改工作区里的文件
+    let edited = Instant::now();
+    std::fs::write(server.index_path().join("se/rd/serde"), serde_versions(true)).unwrap();
+    // wait_until 的间隔太长；复用同一个 client，每次新建 client 本身就要几十毫秒
+    let (client, url) = (common::client(), server.url("/api/v1/crates/serde/1.0.0"));
+    while json(client.get(&url).send().unwrap())["version"]["yanked"] == false {
+        assert!(edited.elapsed() < Duration::from_secs(5), "cached version was not invalidated:\n{}", server.log());
+        std::thread::sleep(Duration::from_millis(5));
+    }
+    let elapsed = edited.elapsed();
+    assert!(elapsed < Duration::from_millis(200), "invalidated after {:?}", elapsed);
+    #[cfg(feature = "metrics")]
+    {
+        let metrics = server.get("/metrics").text().unwrap();
+        assert!(metrics.contains("cache_inotify_invalidations_total 1"), "{}", metrics);
+    }
+}
+
+fn dependent_line(name: &str, dependency: &str, req: &str) -> String {
+    let dep = format!(
+        "{{\"name\":\"{}\",\"req\":\"{}\",\"features\":[],\"optional\":false,\"default_features\":true,\"target\":null,\"kind\":\"normal\"}}",
+        dependency, req
+    );
+    index_line(name, "1.0.0").replacen("\"deps\":[]", &format!("\"deps\":[{}]", dep), 1)
+}
+
+// 1000 个 crate，其中 50 个依赖 target：25 个要求 ^1，25 个要求 ^2
+#[test]
+fn reverse_dependencies_of_1000_crates() {
+    let upstream = Upstream::new();
+    let mut files = vec![(index_path("target"), index_line("target", "1.0.0") + &index_line("target", "2.0.0"))];
+    for i in 0..1000 {
+        let name = format!("crate-{:04}", i);
+        let line = match i % 40 {
+            0 => dependent_line(&name, "target", "^1"),
+            20 => dependent_line(&name, "target", "^2"),
+            _ => dependent_line(&name, "other", "^1"),
+        };
+        files.push((index_path(&name), line));
+    }
+    let files: Vec<(&str, Option<&str>)> = files.iter().map(|(p, c)| (p.as_str(), Some(c.as_str()))).collect();
+    upstream.commit("crates", &files);
+    let server = Server::start(
+        &upstream,
+        ServerConfig::new()
+            .web("reverse_deps_enabled = true")
+            .repo("update_cron = \"* * * * * *\""),
+    );
+
+    let body = json(server.get("/api/v1/crates/target/reverse-dependencies?per_page=100"));
+    assert_eq!(body["meta"]["total"], 50);
+    let names: Vec<&str> = body["dependents"].as_array().unwrap().iter().map(|d| d["name"].as_str().unwrap()).collect();
+    let expected: Vec<String> = (0..1000).step_by(20).map(|i| format!("crate-{:04}", i)).collect();
+    assert_eq!(names, expected);
+    assert_eq!(body["dependents"][0]["req"], "^1");
+    assert_eq!(body["dependents"][0]["kind"], "normal");
+    assert_eq!(body["dependents"][1]["req"], "^2");
+
+    // 默认每页 10 个
+    let pages: Vec<usize> = (1..=6)
+        .map(|page| {
+            let body = json(server.get(&format!("/api/v1/crates/target/reverse-dependencies?page={}", page)));
+            assert_eq!(body["meta"]["total"], 50);
+            body["dependents"].as_array().unwrap().len()
+        })
+        .collect();
+    assert_eq!(pages, [10, 10, 10, 10, 10, 0]);
+    let body = json(server.get("/api/v1/crates/target/reverse-dependencies?page=2&per_page=20"));
+    assert_eq!(body["dependents"][0]["name"], "crate-0400");
+
+    for (version, total) in [("1.5.0", 25), ("2.0.0", 25), ("3.0.0", 0)] {
+        let body = json(server.get(&format!("/api/v1/crates/target/reverse-dependencies?version={}", version)));
+        assert_eq!(body["meta"]["total"], total, "{}", version);
+    }
+    assert_eq!(json(server.get("/api/v1/crates/crate-0001/reverse-dependencies"))["meta"]["total"], 0);
+
+    // pull 之后重新构建
+    upstream.commit("new dependent", &[("ne/w-/new-dependent", Some(&dependent_line("new-dependent", "target", "^2")))]);
+    let rebuilt = wait_until(Duration::from_secs(15), || {
+        json(server.get("/api/v1/crates/target/reverse-dependencies"))["meta"]["total"] == 51
+    });
+    assert!(rebuilt, "reverse dependencies were not rebuilt:\n{}", server.log());
+}
+
+fn with_protocol(server: &Server, path: &str, version: Option<&str>) -> reqwest::blocking::Response {
+    let mut req = common::client().get(server.url(path));
+    if let Some(version) = version {
+        req = req.header("Cargo-Protocol", version);
+    }
+    req.send().unwrap()
+}
+
+// v1 的响应里没有 rust_version，v2 里有；没有请求头时使用最新的版本
+#[test]
+fn protocol_versions() {
+    let upstream = Upstream::new();
+    let line = index_line("serde", "1.0.0").replacen("\"yanked\":false", "\"yanked\":false,\"rust_version\":\"1.60\"", 1);
+    upstream.commit("serde", &[("se/rd/serde", Some(&line))]);
+    let server = Server::start(&upstream, ServerConfig::new());
+
+    for (header, rust_version) in [(Some("version=1"), None), (Some("version=2"), Some("1.60")), (None, Some("1.60"))] {
+        let version = json(with_protocol(&server, "/api/v1/crates/serde/1.0.0", header));
+        assert_eq!(version["version"]["rust_version"].as_str(), rust_version, "{:?}", header);
+        assert_eq!(version["version"].get("rust_version").is_some(), rust_version.is_some(), "{:?}", header);
+        let search = json(with_protocol(&server, "/api/v1/crates?q=serde", header));
+        assert_eq!(search["crates"][0]["name"], "serde");
+        assert_eq!(search["crates"][0].get("rust_version").is_some(), rust_version.is_some(), "{:?}", header);
+    }
+    // 参数顺序和空格不影响
+    let version = json(with_protocol(&server, "/api/v1/crates/serde/1.0.0", Some("foo=bar; version = 1")));
+    assert!(version["version"].get("rust_version").is_none());
+
+    for header in ["version=3", "version=0", "version=abc"] {
+        let res = with_protocol(&server, "/api/v1/crates/serde/1.0.0", Some(header));
+        assert_eq!(res.status(), 422, "{}", header);
+        assert_eq!(json(res)["detail"], "Unsupported protocol version, this registry supports versions 1 to 2");
+    }
+    // 索引文件本身不受影响
+    assert_eq!(with_protocol(&server, "/se/rd/serde", Some("version=3")).status(), 200);
+}
+
+#[test]
+fn protocol_version_range() {
+    let upstream = Upstream::with_crates(&["serde"]);
+    let server = Server::start(
+        &upstream,
+        ServerConfig::new().rest("[registry]\nmin_protocol_version = 2\nmax_protocol_version = 2"),
+    );
+    let res = with_protocol(&server, "/api/v1/crates?q=serde", Some("version=1"));
+    assert_eq!(res.status(), 422);
+    assert_eq!(json(res)["detail"], "Unsupported protocol version, this registry supports versions 2 to 2");
+    assert_eq!(with_protocol(&server, "/api/v1/crates?q=serde", Some("version=2")).status(), 200);
+}
+
+fn yanked_line(name: &str, version: &str) -> String {
+    index_line(name, version).replace("\"yanked\":false", "\"yanked\":true")
+}
+
+fn version_list(res: reqwest::blocking::Response) -> Vec<(String, bool)> {
+    json(res)["versions"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|entry| (entry["version"].as_str().unwrap().to_string(), entry["yanked"].as_bool().unwrap()))
+        .collect()
+}
+
+#[test]
+fn versions_sorted_by_semver() {
+    let upstream = Upstream::new();
+    // 文件里的顺序是乱的，不合法的版本号被跳过
+    let serde = [
+        index_line("serde", "1.0.0"),
+        index_line("serde", "1.0.0-beta.10"),
+        yanked_line("serde", "1.1.0"),
+        index_line("serde", "0.9.0"),
+        index_line("serde", "1.0.0-alpha.1"),
+        index_line("serde", "1.0.0-beta.2"),
+        yanked_line("serde", "1.0.1"),
+        index_line("serde", "1.0.2-rc.1"),
+        index_line("serde", "one.two"),
+    ]
+    .concat();
+    let preview = index_line("preview", "0.1.0-alpha") + &index_line("preview", "0.1.0-beta");
+    let gone = yanked_line("gone", "1.0.0");
+    upstream.commit(
+        "crates",
+        &[("se/rd/serde", Some(&serde)), ("pr/ev/preview", Some(&preview)), ("go/ne/gone", Some(&gone))],
+    );
+    let server = Server::start(&upstream, ServerConfig::new());
+
+    let res = server.get("/api/v1/crates/serde/versions");
+    assert_eq!(res.status(), 200);
+    // 最大的未 yank 的正式版本，不是更大的预发布版本
+    assert_eq!(res.headers()["X-Latest-Version"], "1.0.0");
+    let all = version_list(res);
+    let order: Vec<_> = all.iter().map(|(version, _)| version.as_str()).collect();
+    assert_eq!(
+        order,
+        ["0.9.0", "1.0.0-alpha.1", "1.0.0-beta.2", "1.0.0-beta.10", "1.0.0", "1.0.1", "1.0.2-rc.1", "1.1.0"]
+    );
+    let yanked: Vec<_> = all.iter().filter(|(_, yanked)| *yanked).map(|(version, _)| version.as_str()).collect();
+    assert_eq!(yanked, ["1.0.1", "1.1.0"]);
+    let entry = &json(server.get("/api/v1/crates/serde/versions"))["versions"][0];
+    assert_eq!(entry["cksum"], "0".repeat(64));
+
+    assert_eq!(version_list(server.get("/api/v1/crates/serde/versions?yanked=include")), all);
+    let res = server.get("/api/v1/crates/serde/versions?yanked=exclude");
+    // 过滤不影响 X-Latest-Version
+    assert_eq!(res.headers()["X-Latest-Version"], "1.0.0");
+    let excluded = version_list(res);
+    assert_eq!(excluded.len(), 6);
+    assert!(excluded.iter().all(|(_, yanked)| !yanked));
+    let only: Vec<_> = version_list(server.get("/api/v1/crates/serde/versions?yanked=only"))
+        .into_iter()
+        .map(|(version, _)| version)
+        .collect();
+    assert_eq!(only, ["1.0.1", "1.1.0"]);
+    assert_eq!(server.get("/api/v1/crates/serde/versions?yanked=maybe").status(), 400);
+
+    // 只有预发布版本时选最大的预发布版本；全部 yank 时没有这个头
+    let res = server.get("/api/v1/crates/preview/versions");
+    assert_eq!(res.headers()["X-Latest-Version"], "0.1.0-beta");
+    let res = server.get("/api/v1/crates/gone/versions");
+    assert!(res.headers().get("X-Latest-Version").is_none());
+    assert_eq!(version_list(res), [("1.0.0".to_string(), true)]);
+    assert!(version_list(server.get("/api/v1/crates/gone/versions?yanked=exclude")).is_empty());
+    assert_eq!(server.get("/api/v1/crates/missing/versions").status(), 404);
+}
+
+fn prefetch(server: &Server, crates: serde_json::Value) -> reqwest::blocking::Response {
+    common::client()
+        .post(server.url("/api/v1/prefetch"))
+        .header("Content-Type", "application/json")
+        .body(serde_json::json!({ "crates": crates }).to_string())
+        .send()
+        .unwrap()
+}
+
+#[test]
+fn prefetch_warms_index_files() {
+    let upstream = Upstream::with_crates(&["serde", "tokio"]);
+    let server = Server::start(&upstream, ServerConfig::new().rest("[slo.sparse_index]\np99_ms = 500"));
+
+    let res = prefetch(&server, serde_json::json!(["serde", "tokio", "unknown-crate"]));
+    assert_eq!(res.status(), 200);
+    assert_eq!(json(res), serde_json::json!({ "cached": ["serde", "tokio"], "not_found": ["unknown-crate"] }));
+    assert_eq!(server.get("/se/rd/serde").status(), 200);
+
+    let names: Vec<String> = (0..101).map(|i| format!("crate-{}", i)).collect();
+    let res = prefetch(&server, serde_json::json!(names));
+    assert_eq!(res.status(), 400);
+    assert_eq!(json(res)["detail"], "At most 100 crates can be prefetched at once");
+    let res = prefetch(&server, serde_json::json!(["serde", "bad!name"]));
+    assert_eq!(res.status(), 400);
+    #[cfg(feature = "metrics")]
+    {
+        let metrics = server.get("/metrics").text().unwrap();
+        assert!(metrics.contains("\nprefetch_requests_total 3\n"), "{}", metrics);
+        assert!(metrics.contains("\nprefetch_crates_warmed_total 2\n"), "{}", metrics);
+    }
+
+    // 默认 5 个请求之后限流，单个 crate 的请求不受影响
+    let statuses: Vec<u16> = (0..3).map(|_| prefetch(&server, serde_json::json!(["serde"])).status().as_u16()).collect();
+    assert_eq!(statuses, [200, 200, 429]);
+    assert_eq!(server.get("/se/rd/serde").status(), 200);
+}
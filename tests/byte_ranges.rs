@@ -0,0 +1,79 @@
+mod common;
+
+use common::{client, index_line, Server, ServerConfig, Upstream};
+
+fn ranged(server: &Server, range: &str) -> reqwest::blocking::Response {
+    client().get(server.url("/se/rd/serde")).header("Range", range).send().unwrap()
+}
+
+// 按 boundary 拆开 multipart/byteranges，返回每一部分的头和内容
+fn split_parts(body: &[u8], boundary: &str) -> Vec<(String, Vec<u8>)> {
+    let body = String::from_utf8(body.to_vec()).unwrap();
+    let delimiter = format!("--{}", boundary);
+    let rest = body.strip_prefix(&format!("{}\r\n", delimiter)).unwrap();
+    let rest = rest.strip_suffix(&format!("{}--\r\n", delimiter)).unwrap();
+    rest.split(&format!("\r\n{}\r\n", delimiter))
+        .map(|part| {
+            let part = part.strip_suffix("\r\n").unwrap_or(part);
+            let (head, content) = part.split_once("\r\n\r\n").unwrap();
+            (head.to_string(), content.as_bytes().to_vec())
+        })
+        .collect()
+}
+
+#[test]
+fn two_part_range_request() {
+    let upstream = Upstream::new();
+    let content = index_line("serde", "1.0.0") + &index_line("serde", "1.0.1");
+    upstream.commit("serde", &[("se/rd/serde", Some(&content))]);
+    let server = Server::start(&upstream, ServerConfig::new());
+    let len = content.len();
+
+    let res = ranged(&server, "bytes=0-9, 20-29");
+    assert_eq!(res.status(), 206);
+    assert!(res.headers().contains_key("ETag"));
+    let content_type = res.headers()["Content-Type"].to_str().unwrap().to_string();
+    let boundary = content_type.strip_prefix("multipart/byteranges; boundary=").unwrap().to_string();
+    let body = res.bytes().unwrap();
+    let parts = split_parts(&body, &boundary);
+    assert_eq!(parts.len(), 2);
+    for ((head, part), (start, end)) in parts.iter().zip([(0, 9), (20, 29)]) {
+        assert_eq!(
+            head,
+            &format!("content-type: text/plain; charset=utf-8\r\ncontent-range: bytes {}-{}/{}", start, end, len)
+        );
+        assert_eq!(part.as_slice(), &content.as_bytes()[start..=end]);
+    }
+    // 每个响应的 boundary 不同；后缀区间按文件长度计算
+    let res = ranged(&server, "bytes=0-0, -5");
+    let other = res.headers()["Content-Type"].to_str().unwrap().to_string();
+    assert_ne!(other, content_type);
+    let parts = split_parts(&res.bytes().unwrap(), other.strip_prefix("multipart/byteranges; boundary=").unwrap());
+    assert_eq!(parts[1].1, content.as_bytes()[len - 5..]);
+}
+
+#[test]
+fn single_and_unsatisfiable_ranges() {
+    let upstream = Upstream::with_crates(&["serde"]);
+    let server = Server::start(&upstream, ServerConfig::new());
+    let full = server.get("/se/rd/serde").text().unwrap();
+
+    // 单个区间返回整个文件
+    let res = ranged(&server, "bytes=0-9");
+    assert_eq!(res.status(), 200);
+    assert_eq!(res.text().unwrap(), full);
+
+    let res = ranged(&server, "bytes=10000-20000, 30000-");
+    assert_eq!(res.status(), 416);
+    assert_eq!(res.headers()["Content-Range"], format!("bytes */{}", full.len()));
+
+    // If-Range 不匹配时返回整个文件
+    let res = client()
+        .get(server.url("/se/rd/serde"))
+        .header("Range", "bytes=0-1, 3-4")
+        .header("If-Range", "\"stale\"")
+        .send()
+        .unwrap();
+    assert_eq!(res.status(), 200);
+    assert_eq!(res.text().unwrap(), full);
+}
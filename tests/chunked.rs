@@ -0,0 +1,75 @@
+mod common;
+
+use common::{index_line, Server, ServerConfig, Upstream};
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::TcpStream,
+};
+
+// 直接读 socket，reqwest 会把 chunk 合并掉；返回响应头和每个 chunk 的大小
+fn get_raw(server: &Server, path: &str) -> (String, Vec<usize>, Vec<u8>) {
+    let mut stream = TcpStream::connect(("127.0.0.1", server.port)).unwrap();
+    write!(stream, "GET {} HTTP/1.1\r\nHost: 127.0.0.1\r\nConnection: close\r\n\r\n", path).unwrap();
+    let mut reader = BufReader::new(stream);
+    let mut head = String::new();
+    while reader.read_line(&mut head).unwrap() > 0 && !head.ends_with("\r\n\r\n") {}
+    let head = head.to_ascii_lowercase();
+    let (mut sizes, mut body) = (Vec::new(), Vec::new());
+    if head.contains("transfer-encoding: chunked") {
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            let size = usize::from_str_radix(line.trim_end(), 16).unwrap();
+            let mut chunk = vec![0; size + 2];
+            reader.read_exact(&mut chunk).unwrap();
+            if size == 0 {
+                break;
+            }
+            sizes.push(size);
+            body.extend_from_slice(&chunk[..size]);
+        }
+    } else {
+        reader.read_to_end(&mut body).unwrap();
+    }
+    (head, sizes, body)
+}
+
+fn large_index() -> String {
+    // 大约 1.6 MB，超过默认的 chunked_threshold_kb
+    (0..15000).map(|i| index_line("tokio", &format!("1.{}.0", i))).collect()
+}
+
+#[test]
+fn large_files_use_chunked_transfer() {
+    let upstream = Upstream::with_crates(&["serde"]);
+    let large = large_index();
+    upstream.commit("tokio", &[("to/ki/tokio", Some(&large))]);
+    let server = Server::start(&upstream, ServerConfig::new().web("chunk_size_kb = 256"));
+
+    let (head, sizes, body) = get_raw(&server, "/to/ki/tokio");
+    assert!(head.starts_with("http/1.1 200"), "{}", head);
+    assert!(head.contains("transfer-encoding: chunked\r\n"), "{}", head);
+    assert!(!head.contains("content-length"), "{}", head);
+    assert_eq!(body, large.as_bytes());
+    // 除了最后一块都是 chunk_size_kb
+    assert!(sizes.len() > 1);
+    assert!(sizes[..sizes.len() - 1].iter().all(|&size| size == 256 * 1024), "{:?}", sizes);
+
+    let (head, sizes, _) = get_raw(&server, "/se/rd/serde");
+    assert!(head.contains("content-length: "), "{}", head);
+    assert!(!head.contains("transfer-encoding"), "{}", head);
+    assert!(sizes.is_empty());
+}
+
+#[test]
+fn chunked_transfer_can_be_disabled() {
+    let upstream = Upstream::with_crates(&["serde"]);
+    let large = large_index();
+    upstream.commit("tokio", &[("to/ki/tokio", Some(&large))]);
+    let server = Server::start(&upstream, ServerConfig::new().web("use_chunked_transfer = false"));
+
+    let (head, _, body) = get_raw(&server, "/to/ki/tokio");
+    assert!(head.contains(&format!("content-length: {}\r\n", large.len())), "{}", head);
+    assert!(!head.contains("transfer-encoding"), "{}", head);
+    assert_eq!(body, large.as_bytes());
+}
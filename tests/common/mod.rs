@@ -0,0 +1,338 @@
+// 集成测试共用：临时的上游仓库和在随机端口上运行的镜像进程
+#![allow(dead_code)]
+
+use git2::{Oid, Repository, RepositoryInitOptions, Signature};
+use std::{
+    fs,
+    io::{BufRead, BufReader, Write},
+    net::TcpListener,
+    path::{Path, PathBuf},
+    process::{Child, Command, Stdio},
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+use tempfile::TempDir;
+
+pub const BIN: &str = env!("CARGO_BIN_EXE_local_crates_io_index");
+
+pub struct Upstream {
+    pub dir: TempDir,
+    pub repo: Repository,
+}
+
+impl Upstream {
+    pub fn new() -> Self {
+        let dir = tempfile::tempdir().unwrap();
+        let mut options = RepositoryInitOptions::new();
+        options.initial_head("master");
+        let repo = Repository::init_opts(dir.path(), &options).unwrap();
+        Upstream { dir, repo }
+    }
+
+    // 带有几个 crate 的上游，每个 crate 一个版本
+    pub fn with_crates(names: &[&str]) -> Self {
+        let upstream = Upstream::new();
+        let files: Vec<(String, String)> = names
+            .iter()
+            .map(|name| (index_path(name), index_line(name, "1.0.0")))
+            .chain([("config.json".to_string(), "{\"dl\":\"https://static.crates.io/crates\"}".to_string())])
+            .collect();
+        let files: Vec<(&str, Option<&str>)> = files.iter().map(|(p, c)| (p.as_str(), Some(c.as_str()))).collect();
+        upstream.commit("initial", &files);
+        upstream
+    }
+
+    pub fn url(&self) -> String {
+        self.dir.path().to_str().unwrap().to_string()
+    }
+
+    pub fn commit(&self, message: &str, files: &[(&str, Option<&str>)]) -> Oid {
+        let mut index = self.repo.index().unwrap();
+        for (path, content) in files {
+            let full = self.dir.path().join(path);
+            match content {
+                Some(content) => {
+                    fs::create_dir_all(full.parent().unwrap()).unwrap();
+                    fs::write(&full, content).unwrap();
+                    index.add_path(Path::new(path)).unwrap();
+                }
+                None => {
+                    let _ = fs::remove_file(&full);
+                    index.remove_path(Path::new(path)).unwrap();
+                }
+            }
+        }
+        index.write().unwrap();
+        let tree = self.repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let parent = self.repo.head().ok().and_then(|head| head.peel_to_commit().ok());
+        let parents: Vec<_> = parent.iter().collect();
+        let signature = Signature::now("Index Bot", "bot@example.com").unwrap();
+        self.repo
+            .commit(Some("HEAD"), &signature, &signature, message, &tree, &parents)
+            .unwrap()
+    }
+
+    pub fn head(&self) -> Oid {
+        self.repo.refname_to_id("refs/heads/master").unwrap()
+    }
+}
+
+// 和 cargo 的目录规则相同
+pub fn index_path(name: &str) -> String {
+    match name.len() {
+        1 => format!("1/{}", name),
+        2 => format!("2/{}", name),
+        3 => format!("3/{}/{}", &name[..1], name),
+        _ => format!("{}/{}/{}", &name[..2], &name[2..4], name),
+    }
+}
+
+pub fn index_line(name: &str, version: &str) -> String {
+    format!(
+        "{{\"name\":\"{}\",\"vers\":\"{}\",\"deps\":[],\"cksum\":\"{}\",\"features\":{{}},\"yanked\":false}}\n",
+        name,
+        version,
+        "0".repeat(64)
+    )
+}
+
+pub fn free_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port()
+}
+
+pub struct Server {
+    pub dir: TempDir,
+    pub port: u16,
+    child: Child,
+}
+
+// 配置文本里的 {port}、{dir} 和 {upstream} 会被替换
+pub struct ServerConfig {
+    pub repo: String,
+    pub web: String,
+    pub rest: String,
+    pub env: Vec<(String, String)>,
+}
+
+impl ServerConfig {
+    pub fn new() -> Self {
+        ServerConfig {
+            repo: String::new(),
+            web: String::new(),
+            rest: String::new(),
+            env: Vec::new(),
+        }
+    }
+
+    pub fn repo(mut self, text: &str) -> Self {
+        self.repo.push_str(text);
+        self.repo.push('\n');
+        self
+    }
+
+    pub fn web(mut self, text: &str) -> Self {
+        self.web.push_str(text);
+        self.web.push('\n');
+        self
+    }
+
+    pub fn rest(mut self, text: &str) -> Self {
+        self.rest.push_str(text);
+        self.rest.push('\n');
+        self
+    }
+
+    // 镜像进程的环境变量
+    pub fn env(mut self, key: &str, value: &str) -> Self {
+        self.env.push((key.to_string(), value.to_string()));
+        self
+    }
+
+    fn render(&self, upstream: &str, dir: &Path, port: u16) -> String {
+        // repo 里设置了的默认值不再输出，TOML 不允许重复的键
+        let defaults: String = [("update_interval", "3600"), ("connectivity_check_on_startup", "false")]
+            .iter()
+            .filter(|(key, _)| !self.repo.lines().any(|line| line.split('=').next().unwrap().trim() == *key))
+            .map(|(key, value)| format!("{} = {}\n", key, value))
+            .collect();
+        let text = format!(
+            "[repo]\ngit_url = \"{{upstream}}\"\npath = \"{{dir}}/index\"\n{}{}\n\
+             [web]\naddress = \"127.0.0.1\"\nport = {{port}}\nworkers = 2\n{}\n{}\n",
+            defaults, self.repo, self.web, self.rest
+        );
+        text.replace("{upstream}", upstream)
+            .replace("{dir}", dir.to_str().unwrap())
+            .replace("{port}", &port.to_string())
+    }
+}
+
+impl Server {
+    // 启动并等待初始 clone 完成
+    pub fn start(upstream: &Upstream, config: ServerConfig) -> Server {
+        let server = Server::spawn(upstream, config);
+        server.wait_ready();
+        server
+    }
+
+    // 只启动，不等待
+    pub fn spawn(upstream: &Upstream, config: ServerConfig) -> Server {
+        Server::spawn_url(&upstream.url(), config)
+    }
+
+    // 上游不是本地仓库时使用，比如 HttpRecorder 的地址
+    pub fn spawn_url(upstream: &str, config: ServerConfig) -> Server {
+        let dir = tempfile::tempdir().unwrap();
+        let port = free_port();
+        fs::write(dir.path().join("config.toml"), config.render(upstream, dir.path(), port)).unwrap();
+        let log = fs::File::create(dir.path().join("server.log")).unwrap();
+        let child = Command::new(BIN)
+            .current_dir(dir.path())
+            .args(["--config", "config.toml"])
+            .env("RUST_LOG", "info")
+            .envs(config.env.iter().map(|(k, v)| (k, v)))
+            .stdin(Stdio::null())
+            .stdout(log.try_clone().unwrap())
+            .stderr(log)
+            .spawn()
+            .unwrap();
+        Server { dir, port, child }
+    }
+
+    pub fn config_path(&self) -> PathBuf {
+        self.dir.path().join("config.toml")
+    }
+
+    pub fn index_path(&self) -> PathBuf {
+        self.dir.path().join("index")
+    }
+
+    pub fn url(&self, path: &str) -> String {
+        format!("http://127.0.0.1:{}{}", self.port, path)
+    }
+
+    pub fn get(&self, path: &str) -> reqwest::blocking::Response {
+        client().get(self.url(path)).send().unwrap()
+    }
+
+    pub fn log(&self) -> String {
+        fs::read_to_string(self.dir.path().join("server.log")).unwrap_or_default()
+    }
+
+    pub fn pid(&self) -> i32 {
+        self.child.id() as i32
+    }
+
+    pub fn signal(&self, signal: i32) {
+        assert_eq!(unsafe { libc::kill(self.pid(), signal) }, 0);
+    }
+
+    // 进程退出时返回退出状态
+    pub fn wait_exit(&mut self, timeout: Duration) -> Option<std::process::ExitStatus> {
+        let deadline = Instant::now() + timeout;
+        while Instant::now() < deadline {
+            if let Some(status) = self.child.try_wait().unwrap() {
+                return Some(status);
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+        None
+    }
+
+    pub fn wait_ready(&self) {
+        let ready = wait_until(Duration::from_secs(30), || {
+            client()
+                .get(self.url("/healthz/ready"))
+                .send()
+                .is_ok_and(|res| res.status().is_success())
+        });
+        assert!(ready, "server did not become ready:\n{}", self.log());
+    }
+
+    pub fn wait_for_log(&self, needle: &str, timeout: Duration) -> bool {
+        wait_until(timeout, || self.log().contains(needle))
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+// 记录收到的 HTTP 请求头，按 respond 返回的完整响应回复，用来观察 libgit2 发给上游的请求
+pub struct HttpRecorder {
+    pub port: u16,
+    requests: Arc<Mutex<Vec<String>>>,
+}
+
+impl HttpRecorder {
+    pub fn start(respond: impl Fn(&str) -> String + Send + 'static) -> HttpRecorder {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let recorded = Arc::clone(&requests);
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else { continue };
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut head = String::new();
+                while reader.read_line(&mut head).is_ok_and(|n| n > 0) && !head.ends_with("\r\n\r\n") {}
+                let response = respond(&head);
+                recorded.lock().unwrap().push(head);
+                let _ = stream.write_all(response.as_bytes());
+            }
+        });
+        HttpRecorder { port, requests }
+    }
+
+    pub fn url(&self, path: &str) -> String {
+        format!("http://127.0.0.1:{}{}", self.port, path)
+    }
+
+    pub fn requests(&self) -> Vec<String> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+// 请求头里某个字段的值，字段名不区分大小写
+pub fn header<'a>(request: &'a str, name: &str) -> Option<&'a str> {
+    request.lines().find_map(|line| {
+        let (key, value) = line.split_once(':')?;
+        key.eq_ignore_ascii_case(name).then(|| value.trim())
+    })
+}
+
+pub fn client() -> reqwest::blocking::Client {
+    reqwest::blocking::Client::builder()
+        .timeout(Duration::from_secs(30))
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .unwrap()
+}
+
+pub fn wait_until(timeout: Duration, mut condition: impl FnMut() -> bool) -> bool {
+    let deadline = Instant::now() + timeout;
+    while Instant::now() < deadline {
+        if condition() {
+            return true;
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+    condition()
+}
+
+// 不读取系统和用户的 git 配置
+pub fn git(dir: &Path, args: &[&str]) -> String {
+    let output = Command::new("git")
+        .current_dir(dir)
+        .args(args)
+        .env("GIT_CONFIG_NOSYSTEM", "1")
+        .env("GIT_CONFIG_GLOBAL", "/dev/null")
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "git {:?} failed: {}", args, String::from_utf8_lossy(&output.stderr));
+    String::from_utf8(output.stdout).unwrap()
+}
@@ -0,0 +1,43 @@
+mod common;
+
+use common::{index_line, Server, ServerConfig, Upstream};
+
+fn content_type(res: &reqwest::blocking::Response) -> &str {
+    res.headers()["Content-Type"].to_str().unwrap()
+}
+
+#[test]
+fn extensionless_files_are_text_with_nosniff() {
+    let upstream = Upstream::with_crates(&["serde"]);
+    upstream.commit("readme", &[("README", Some("index mirror\n")), ("3/s/syn", Some(&index_line("syn", "2.0.0")))]);
+    let server = Server::start(&upstream, ServerConfig::new());
+
+    for path in ["/se/rd/serde", "/3/s/syn", "/README"] {
+        let res = server.get(path);
+        assert_eq!(res.status(), 200, "{}", path);
+        assert_eq!(content_type(&res), "text/plain; charset=utf-8", "{}", path);
+        assert_eq!(res.headers()["X-Content-Type-Options"], "nosniff", "{}", path);
+    }
+    // 有扩展名的文件、目录列表和错误响应保持自己的类型
+    for (path, expected) in [
+        ("/config.json", "application/json"),
+        ("/se/rd/", "text/html; charset=utf-8"),
+        ("/se/rd/nope", "application/problem+json"),
+    ] {
+        let res = server.get(path);
+        assert_eq!(content_type(&res), expected, "{}", path);
+        assert_eq!(res.headers()["X-Content-Type-Options"], "nosniff", "{}", path);
+    }
+}
+
+#[test]
+fn nosniff_can_be_disabled() {
+    let upstream = Upstream::with_crates(&["serde"]);
+    let server = Server::start(
+        &upstream,
+        ServerConfig::new().rest("[web.security_headers]\nx_content_type_options = false"),
+    );
+    let res = server.get("/se/rd/serde");
+    assert_eq!(content_type(&res), "text/plain; charset=utf-8");
+    assert!(res.headers().get("X-Content-Type-Options").is_none());
+}
@@ -0,0 +1,138 @@
+mod common;
+
+use common::{Server, ServerConfig, Upstream};
+use std::{
+    fs,
+    io::{self, BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream},
+    os::unix::fs::PermissionsExt,
+    path::Path,
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+use tempfile::TempDir;
+
+// base64("alice:s3cret")
+const AUTHORIZATION: &str = "Basic YWxpY2U6czNjcmV0";
+
+// 要求 Basic 认证的上游：认证通过的连接转发给开启了 git_smart_http 的镜像，否则返回 401
+struct AuthProxy {
+    port: u16,
+    authorizations: Arc<Mutex<Vec<Option<String>>>>,
+}
+
+impl AuthProxy {
+    fn start(backend_port: u16) -> AuthProxy {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let authorizations = Arc::new(Mutex::new(Vec::new()));
+        let recorded = Arc::clone(&authorizations);
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else { continue };
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut head = String::new();
+                while reader.read_line(&mut head).is_ok_and(|n| n > 0) && !head.ends_with("\r\n\r\n") {}
+                let authorization = common::header(&head, "Authorization").map(str::to_string);
+                recorded.lock().unwrap().push(authorization.clone());
+                if authorization.as_deref() != Some(AUTHORIZATION) {
+                    let _ = stream.write_all(
+                        b"HTTP/1.1 401 Unauthorized\r\nWWW-Authenticate: Basic realm=\"index\"\r\n\
+                          Content-Length: 0\r\nConnection: close\r\n\r\n",
+                    );
+                    continue;
+                }
+                let mut backend = TcpStream::connect(("127.0.0.1", backend_port)).unwrap();
+                backend.write_all(head.as_bytes()).unwrap();
+                backend.write_all(reader.buffer()).unwrap();
+                let mut to_client = backend.try_clone().unwrap();
+                let mut from_client = stream.try_clone().unwrap();
+                thread::spawn(move || io::copy(&mut from_client, &mut backend));
+                thread::spawn(move || io::copy(&mut to_client, &mut stream));
+            }
+        });
+        AuthProxy { port, authorizations }
+    }
+
+    fn url(&self) -> String {
+        format!("http://127.0.0.1:{}/", self.port)
+    }
+
+    fn authorizations(&self) -> Vec<Option<String>> {
+        self.authorizations.lock().unwrap().clone()
+    }
+}
+
+// 模拟的 credential helper：记录每次调用的动作和输入，get 时返回 alice 和 password 文件里的密码
+fn credential_helper(dir: &Path, password: &str) -> ServerConfig {
+    let helper = dir.join("helper.sh");
+    fs::write(
+        &helper,
+        format!(
+            "#!/bin/sh\necho \"action=$1\" >> {log}\ncat >> {log}\n\
+             if [ \"$1\" = get ]; then echo username=alice; echo password={password}; fi\n",
+            log = dir.join("helper.log").display(),
+        ),
+    )
+    .unwrap();
+    fs::set_permissions(&helper, fs::Permissions::from_mode(0o755)).unwrap();
+    fs::write(dir.join("gitconfig"), format!("[credential]\n\thelper = {}\n", helper.display())).unwrap();
+    ServerConfig::new()
+        .repo("use_credential_helper = true")
+        .env("GIT_CONFIG_GLOBAL", dir.join("gitconfig").to_str().unwrap())
+        .env("GIT_CONFIG_NOSYSTEM", "1")
+}
+
+fn helper_log(dir: &Path) -> String {
+    fs::read_to_string(dir.join("helper.log")).unwrap_or_default()
+}
+
+fn backend(upstream: &Upstream) -> Server {
+    Server::start(upstream, ServerConfig::new().web("git_smart_http = true"))
+}
+
+#[test]
+fn approves_working_credentials() {
+    let upstream = Upstream::with_crates(&["serde"]);
+    let backend = backend(&upstream);
+    let proxy = AuthProxy::start(backend.port);
+    let helper = TempDir::new().unwrap();
+
+    let server = Server::spawn_url(&proxy.url(), credential_helper(helper.path(), "s3cret"));
+    server.wait_ready();
+    assert_eq!(server.get("/se/rd/serde").status(), 200);
+
+    // libgit2 每个请求先不带认证发送，收到 401 后带上同一个凭据重试
+    let authorizations = proxy.authorizations();
+    assert!(authorizations.contains(&Some(AUTHORIZATION.to_string())), "{:?}", authorizations);
+    assert!(authorizations.iter().flatten().all(|a| a == AUTHORIZATION), "{:?}", authorizations);
+    let log = helper_log(helper.path());
+    let actions: Vec<_> = log.lines().filter(|line| line.starts_with("action=")).collect();
+    // 启动时的 clone 和之后的每次 fetch 都各自 fill 并 approve
+    assert!(!actions.is_empty() && actions.chunks(2).all(|pair| pair == ["action=get", "action=store"]), "{}", log);
+    assert!(log.contains(&format!("protocol=http\nhost=127.0.0.1:{}\n", proxy.port)), "{}", log);
+    assert!(log.contains("username=alice\npassword=s3cret\n"), "{}", log);
+}
+
+#[test]
+fn rejects_failing_credentials() {
+    let upstream = Upstream::with_crates(&["serde"]);
+    let backend = backend(&upstream);
+    let proxy = AuthProxy::start(backend.port);
+    let helper = TempDir::new().unwrap();
+
+    let server = Server::spawn_url(&proxy.url(), credential_helper(helper.path(), "wrong"));
+    assert!(
+        common::wait_until(Duration::from_secs(30), || helper_log(helper.path()).contains("action=erase")),
+        "{}\n{}",
+        helper_log(helper.path()),
+        server.log()
+    );
+    let log = helper_log(helper.path());
+    let actions: Vec<_> = log.lines().filter(|line| line.starts_with("action=")).collect();
+    assert_eq!(actions, ["action=get", "action=erase"], "{}", log);
+    assert!(log.contains("username=alice\npassword=wrong\n"), "{}", log);
+    // 被拒绝后不再用同一个凭据重试
+    assert_eq!(proxy.authorizations().len(), 2);
+}
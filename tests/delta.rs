@@ -0,0 +1,113 @@
+mod common;
+
+use common::{client, index_line, wait_until, Server, ServerConfig, Upstream};
+use std::time::Duration;
+
+// 客户端按 v1 格式还原新文件，见 src/delta.rs
+fn apply(old: &[u8], delta: &[u8]) -> Vec<u8> {
+    let mut old_lines = old.split_inclusive(|&b| b == b'\n');
+    let mut out = Vec::new();
+    let mut rest = delta;
+    while !rest.is_empty() {
+        let end = rest.iter().position(|&b| b == b'\n').unwrap();
+        let (op, n) = std::str::from_utf8(&rest[..end]).unwrap().split_once(' ').unwrap();
+        let n: usize = n.parse().unwrap();
+        rest = &rest[end + 1..];
+        match op {
+            "copy" => (0..n).for_each(|_| out.extend_from_slice(old_lines.next().unwrap())),
+            "skip" => {
+                old_lines.nth(n - 1).unwrap();
+            }
+            "insert" => {
+                out.extend_from_slice(&rest[..n]);
+                rest = &rest[n..];
+            }
+            other => panic!("unknown delta command {}", other),
+        }
+    }
+    out
+}
+
+fn versions(versions: &[&str]) -> String {
+    versions.iter().map(|version| index_line("serde", version)).collect()
+}
+
+fn get(server: &Server, etag: Option<&str>, delta: bool) -> reqwest::blocking::Response {
+    let mut req = client().get(server.url("/se/rd/serde"));
+    if let Some(etag) = etag {
+        req = req.header("If-None-Match", etag);
+    }
+    if delta {
+        req = req.header("X-Delta-Encoding", "v1");
+    }
+    req.send().unwrap()
+}
+
+fn etag(res: &reqwest::blocking::Response) -> String {
+    res.headers()["ETag"].to_str().unwrap().to_string()
+}
+
+#[test]
+fn delta_reconstructs_the_new_file() {
+    let upstream = Upstream::with_crates(&["tokio"]);
+    let old = versions(&["1.0.0", "1.0.1", "1.0.2"]);
+    upstream.commit("serde", &[("se/rd/serde", Some(&old))]);
+    let server = Server::start(
+        &upstream,
+        ServerConfig::new().repo("update_cron = \"* * * * * *\"").web("delta_encoding = true"),
+    );
+
+    let res = get(&server, None, false);
+    let old_etag = etag(&res);
+    // ETag 是文件内容的 blob OID
+    let oid = git2::Oid::hash_object(git2::ObjectType::Blob, old.as_bytes()).unwrap();
+    assert_eq!(old_etag, format!("\"{}\"", oid));
+    assert_eq!(res.text().unwrap(), old);
+
+    // 追加一个版本，并且 yank 中间的版本
+    let new = versions(&["1.0.0", "1.0.1", "1.0.2", "1.0.3"]).replacen(
+        &index_line("serde", "1.0.1"),
+        &index_line("serde", "1.0.1").replace("\"yanked\":false", "\"yanked\":true"),
+        1,
+    );
+    upstream.commit("serde 1.0.3", &[("se/rd/serde", Some(&new))]);
+    assert!(wait_until(Duration::from_secs(15), || get(&server, None, false).text().unwrap() == new), "{}", server.log());
+    let new_etag = etag(&get(&server, None, false));
+
+    let res = get(&server, Some(&old_etag), true);
+    assert_eq!(res.status(), 200);
+    assert_eq!(res.headers()["Content-Type"], "application/x-index-delta");
+    assert_eq!(res.headers()["Content-Encoding"], "identity");
+    assert_eq!(res.headers()["X-Delta-Encoding"], "v1");
+    assert_eq!(etag(&res), new_etag);
+    let delta = res.bytes().unwrap();
+    assert!(delta.len() < new.len());
+    assert_eq!(String::from_utf8(apply(old.as_bytes(), &delta)).unwrap(), new);
+
+    // 已经是最新的返回 304；没有声明支持时返回整个文件
+    assert_eq!(get(&server, Some(&new_etag), true).status(), 304);
+    let res = get(&server, Some(&old_etag), false);
+    assert_eq!(res.headers()["Content-Type"], "text/plain; charset=utf-8");
+    assert_eq!(res.text().unwrap(), new);
+    // 不是这个文件历史版本的 blob 不能作为 base
+    let foreign = etag(&client().get(server.url("/to/ki/tokio")).send().unwrap());
+    let res = get(&server, Some(&foreign), true);
+    assert!(res.headers().get("X-Delta-Encoding").is_none());
+    assert_eq!(res.text().unwrap(), new);
+}
+
+#[test]
+fn delta_encoding_is_off_by_default() {
+    let upstream = Upstream::with_crates(&["serde"]);
+    let server = Server::start(&upstream, ServerConfig::new());
+    let res = get(&server, None, false);
+    let etag = etag(&res);
+    let content = res.text().unwrap();
+    let oid = git2::Oid::hash_object(git2::ObjectType::Blob, content.as_bytes()).unwrap();
+    assert_ne!(etag, format!("\"{}\"", oid));
+    // ETag 匹配时仍然是 304
+    assert_eq!(get(&server, Some(&etag), true).status(), 304);
+    let res = get(&server, Some(&format!("\"{}\"", oid)), true);
+    assert!(res.headers().get("X-Delta-Encoding").is_none());
+    assert_eq!(res.text().unwrap(), content);
+}
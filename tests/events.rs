@@ -0,0 +1,62 @@
+mod common;
+
+use common::{client, index_line, wait_until, Server, ServerConfig, Upstream};
+use std::time::Duration;
+
+fn events(server: &Server, query: &str) -> serde_json::Value {
+    serde_json::from_str(&server.get(&format!("/api/v1/events?{}", query)).text().unwrap()).unwrap()
+}
+
+fn seqs(body: &serde_json::Value) -> Vec<u64> {
+    body["events"].as_array().unwrap().iter().map(|event| event["seq"].as_u64().unwrap()).collect()
+}
+
+#[test]
+fn since_seq_after_pulls() {
+    let upstream = Upstream::with_crates(&["serde"]);
+    let server = Server::start(
+        &upstream,
+        ServerConfig::new()
+            .repo("update_cron = \"* * * * * *\"")
+            .rest("[events]\nlog_path = \"{dir}/events.ndjson\""),
+    );
+    assert_eq!(events(&server, "since_seq=0")["last_seq"], 0);
+
+    // serde 已有 1.0.0，再发布 100 个版本
+    let versions: String = (0..=100).map(|i| index_line("serde", &format!("1.0.{}", i))).collect();
+    upstream.commit("publish", &[("se/rd/serde", Some(&versions))]);
+    let pulled = wait_until(Duration::from_secs(15), || events(&server, "since_seq=0")["last_seq"] == 100);
+    assert!(pulled, "events were not recorded:\n{}", server.log());
+
+    let all = events(&server, "since_seq=0");
+    assert_eq!(seqs(&all), (1..=100).collect::<Vec<_>>());
+    assert_eq!(all["events"][0]["event"], "crate_added");
+    assert_eq!(all["events"][0]["name"], "serde");
+    assert_eq!(all["events"][0]["version"], "1.0.1");
+    assert_eq!(seqs(&events(&server, "since_seq=40")), (41..=100).collect::<Vec<_>>());
+    assert_eq!(seqs(&events(&server, "since_seq=40&limit=5")), (41..=45).collect::<Vec<_>>());
+    assert!(seqs(&events(&server, "since_seq=100")).is_empty());
+
+    // 没有新事件时 If-None-Match 返回 304；等待期间有新事件时返回 200
+    let url = server.url("/api/v1/events?since_seq=100");
+    let etag = client().get(&url).send().unwrap().headers()["ETag"].to_str().unwrap().to_string();
+    let res = client().get(&url).header("If-None-Match", &etag).send().unwrap();
+    assert_eq!(res.status(), 304);
+    let yanked = versions.replacen("\"yanked\":false", "\"yanked\":true", 1);
+    upstream.commit("yank", &[("se/rd/serde", Some(&yanked))]);
+    let res = client()
+        .get(format!("{}&wait=30", url))
+        .header("If-None-Match", &etag)
+        .send()
+        .unwrap();
+    assert_eq!(res.status(), 200);
+    assert_ne!(res.headers()["ETag"].to_str().unwrap(), etag);
+    let body: serde_json::Value = serde_json::from_str(&res.text().unwrap()).unwrap();
+    assert_eq!(seqs(&body), [101]);
+    assert_eq!(body["events"][0]["event"], "crate_yanked");
+    assert_eq!(body["events"][0]["version"], "1.0.0");
+
+    // 事件也追加到了文件里
+    let log = std::fs::read_to_string(server.dir.path().join("events.ndjson")).unwrap();
+    assert_eq!(log.lines().count(), 101);
+}
@@ -0,0 +1,44 @@
+mod common;
+
+use common::{git, Server, ServerConfig, Upstream};
+use std::fs;
+
+#[test]
+fn bundle_can_be_verified_and_cloned() {
+    let upstream = Upstream::with_crates(&["serde", "tokio"]);
+    let server = Server::start(&upstream, ServerConfig::new().web("git_bundle_enabled = true"));
+
+    let res = server.get("/api/v1/git-bundle");
+    assert_eq!(res.status(), 200);
+    assert_eq!(res.headers()["Content-Type"], "application/x-git-bundle");
+    let dir = tempfile::tempdir().unwrap();
+    let bundle = dir.path().join("index.bundle");
+    fs::write(&bundle, res.bytes().unwrap()).unwrap();
+
+    // verify 需要在仓库里运行
+    git(dir.path(), &["init", "--quiet", "verify"]);
+    let verified = git(&dir.path().join("verify"), &["bundle", "verify", bundle.to_str().unwrap()]);
+    assert!(verified.contains("refs/heads/master"), "{}", verified);
+
+    git(dir.path(), &["clone", "--quiet", bundle.to_str().unwrap(), "clone"]);
+    let clone = dir.path().join("clone");
+    assert!(clone.join("se/rd/serde").is_file());
+    let head = git(&clone, &["rev-parse", "HEAD"]);
+    assert_eq!(head.trim(), upstream.head().to_string());
+
+    let res = server.get("/api/v1/git-bundle/timestamp");
+    assert_eq!(res.status(), 200);
+    let body: serde_json::Value = serde_json::from_str(&res.text().unwrap()).unwrap();
+    assert_eq!(body["head"], head.trim());
+    let timestamp = chrono::DateTime::parse_from_rfc3339(body["timestamp"].as_str().unwrap()).unwrap();
+    let committed = upstream.repo.head().unwrap().peel_to_commit().unwrap().time().seconds();
+    assert_eq!(timestamp.timestamp(), committed);
+}
+
+#[test]
+fn bundles_are_disabled_by_default() {
+    let upstream = Upstream::with_crates(&["serde"]);
+    let server = Server::start(&upstream, ServerConfig::new());
+    assert_eq!(server.get("/api/v1/git-bundle").status(), 404);
+    assert_eq!(server.get("/api/v1/git-bundle/timestamp").status(), 404);
+}
@@ -0,0 +1,50 @@
+mod common;
+
+use common::{git, index_line, wait_until, Server, ServerConfig, Upstream};
+use std::{fs, time::Duration};
+
+#[test]
+fn git_clone_from_server() {
+    let upstream = Upstream::with_crates(&["serde", "tokio"]);
+    let server = Server::start(
+        &upstream,
+        ServerConfig::new().repo("update_cron = \"* * * * * *\"").web("git_smart_http = true"),
+    );
+    let res = server.get("/info/refs?service=git-upload-pack");
+    assert_eq!(res.status(), 200);
+    assert_eq!(res.headers()["Content-Type"], "application/x-git-upload-pack-advertisement");
+    assert!(res.text().unwrap().starts_with("001e# service=git-upload-pack\n0000"));
+
+    let dir = tempfile::tempdir().unwrap();
+    git(dir.path(), &["clone", "--quiet", &server.url("/"), "clone"]);
+    let clone = dir.path().join("clone");
+    assert_eq!(git(&clone, &["rev-parse", "HEAD"]).trim(), upstream.head().to_string());
+    assert_eq!(fs::read_to_string(clone.join("se/rd/serde")).unwrap(), index_line("serde", "1.0.0"));
+    git(&clone, &["fsck", "--no-progress"]);
+
+    // 镜像 pull 之后 git fetch 拿到新的提交
+    let new = upstream.commit("publish", &[("ra/nd/rand", Some(&index_line("rand", "0.8.5")))]);
+    let pulled = wait_until(Duration::from_secs(15), || {
+        git(&clone, &["ls-remote", "origin", "HEAD"]).starts_with(&new.to_string())
+    });
+    assert!(pulled, "mirror did not pull:\n{}", server.log());
+    git(&clone, &["pull", "--quiet", "--ff-only"]);
+    assert_eq!(fs::read_to_string(clone.join("ra/nd/rand")).unwrap(), index_line("rand", "0.8.5"));
+}
+
+#[test]
+fn smart_http_is_disabled_by_default() {
+    let upstream = Upstream::with_crates(&["serde"]);
+    let server = Server::start(&upstream, ServerConfig::new());
+    assert_eq!(server.get("/info/refs?service=git-upload-pack").status(), 404);
+    let res = common::client().post(server.url("/git-upload-pack")).body("0000").send().unwrap();
+    assert_eq!(res.status(), 404);
+}
+
+#[test]
+fn only_upload_pack_is_served() {
+    let upstream = Upstream::with_crates(&["serde"]);
+    let server = Server::start(&upstream, ServerConfig::new().web("git_smart_http = true"));
+    assert_eq!(server.get("/info/refs?service=git-receive-pack").status(), 403);
+    assert_eq!(server.get("/info/refs").status(), 403);
+}
@@ -0,0 +1,32 @@
+mod common;
+
+use common::{HttpRecorder, Server, ServerConfig};
+use std::time::Duration;
+
+const UNAUTHORIZED: &str = "HTTP/1.1 401 Unauthorized\r\nWWW-Authenticate: Basic realm=\"index\"\r\n\
+                            Content-Length: 0\r\nConnection: close\r\n\r\n";
+
+// 上游要求认证，镜像没有可用的凭据，初始 clone 失败
+fn failed_clone(config: ServerConfig) -> String {
+    let upstream = HttpRecorder::start(|_| UNAUTHORIZED.to_string());
+    let server = Server::spawn_url(&upstream.url("/index"), config.env("RUST_LOG", "info,git2=trace"));
+    let failed = server.wait_for_log("Failed to clone repository", Duration::from_secs(30));
+    assert!(failed, "clone did not fail:\n{}", server.log());
+    server.log()
+}
+
+#[test]
+fn trace_shows_credential_failure() {
+    let log = failed_clone(ServerConfig::new().rest("[logging]\ngit2_trace_level = \"trace\""));
+    assert!(log.contains("libgit2: Sending GET request to http://127.0.0.1:"), "{}", log);
+    // 上游的 401 响应和 libgit2 最终返回的认证错误
+    let received = "libgit2: Received:\nHTTP/1.1 401 Unauthorized\r\nWWW-Authenticate: Basic realm=\"index\"";
+    assert!(log.contains(received), "{}", log);
+    assert!(log.contains("remote authentication required"), "{}", log);
+}
+
+#[test]
+fn trace_is_off_by_default() {
+    let log = failed_clone(ServerConfig::new());
+    assert!(!log.contains("libgit2:"), "{}", log);
+}
@@ -0,0 +1,51 @@
+mod common;
+
+use common::{client, index_line, Server, ServerConfig, Upstream};
+use reqwest::header::{HeaderMap, CONTENT_LENGTH, TRANSFER_ENCODING};
+
+const HEADERS: [&str; 4] = ["Content-Type", "ETag", "Last-Modified", "Accept-Ranges"];
+
+// HEAD 和 GET 的响应头一致，GET 返回 Content-Length 时等于响应体的大小
+fn compare(server: &Server, path: &str) -> (HeaderMap, usize) {
+    let head = client().head(server.url(path)).send().unwrap();
+    assert_eq!(head.status(), 200, "{}", path);
+    let head = head.headers().clone();
+    let get = server.get(path);
+    assert_eq!(get.status(), 200, "{}", path);
+    let get_headers = get.headers().clone();
+    let body = get.bytes().unwrap();
+    for name in HEADERS {
+        assert_eq!(head.get(name), get_headers.get(name), "{} of {}", name, path);
+    }
+    assert_eq!(head.get(CONTENT_LENGTH), get_headers.get(CONTENT_LENGTH), "{}", path);
+    if let Some(length) = get_headers.get(CONTENT_LENGTH) {
+        assert_eq!(length.to_str().unwrap(), body.len().to_string(), "{}", path);
+    }
+    (head, body.len())
+}
+
+#[test]
+fn head_matches_get() {
+    let upstream = Upstream::with_crates(&["a", "syn", "serde"]);
+    // 超过 chunked_threshold_kb 的文件按流发送，GET 和 HEAD 都没有 Content-Length
+    let large: String = (0..2000).map(|i| index_line("tokio", &format!("1.0.{}", i))).collect();
+    upstream.commit("tokio", &[("to/ki/tokio", Some(&large))]);
+    let server = Server::start(&upstream, ServerConfig::new().web("chunked_threshold_kb = 64"));
+
+    for path in ["/1/a", "/3/s/syn", "/se/rd/serde", "/Se/Rd/Serde"] {
+        let (head, len) = compare(&server, path);
+        assert_eq!(head[CONTENT_LENGTH].to_str().unwrap(), len.to_string());
+    }
+    let (head, len) = compare(&server, "/to/ki/tokio");
+    assert_eq!(len, large.len());
+    assert!(head.get(CONTENT_LENGTH).is_none());
+    assert_eq!(head[TRANSFER_ENCODING], "chunked");
+
+    let res = client().head(server.url("/no/ne/nonexistent")).send().unwrap();
+    assert_eq!(res.status(), 404);
+
+    // 条件请求返回 304
+    let etag = client().head(server.url("/se/rd/serde")).send().unwrap().headers()["ETag"].clone();
+    let res = client().head(server.url("/se/rd/serde")).header("If-None-Match", etag).send().unwrap();
+    assert_eq!(res.status(), 304);
+}
@@ -0,0 +1,110 @@
+mod common;
+
+use common::{wait_until, Server, ServerConfig, Upstream};
+use std::{net::TcpListener, thread, time::Duration};
+
+fn json(res: reqwest::blocking::Response) -> serde_json::Value {
+    serde_json::from_str(&res.text().unwrap()).unwrap()
+}
+
+#[test]
+fn mirror_name_in_health_and_status() {
+    let upstream = Upstream::with_crates(&["serde"]);
+    let server = Server::start(
+        &upstream,
+        ServerConfig::new().rest("[app]\nname = \"team-mirror\"\ndescription = \"Mirror <for> the team\""),
+    );
+    let res = server.get("/health/ready");
+    assert_eq!(res.status(), 200);
+    let body = json(res);
+    assert_eq!(body["name"], "team-mirror");
+    assert_eq!(body["description"], "Mirror <for> the team");
+    assert_eq!(body["status"], "ok");
+
+    let page = server.get("/status").text().unwrap();
+    assert!(page.contains("<title>team-mirror</title>"));
+    assert!(page.contains("Mirror &lt;for&gt; the team"));
+}
+
+#[test]
+fn default_mirror_name() {
+    let upstream = Upstream::with_crates(&["serde"]);
+    let server = Server::start(&upstream, ServerConfig::new());
+    assert_eq!(json(server.get("/health/ready"))["name"], "local-crates-io-index");
+}
+
+// 上游接受连接后一直不响应，初始 clone 卡住
+#[test]
+fn startup_probe_fails_after_timeout() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/index", listener.local_addr().unwrap());
+    thread::spawn(move || {
+        // 保留连接，collect 不会返回
+        let _connections: Vec<_> = listener.incoming().collect();
+    });
+    let server = Server::spawn_url(&url, ServerConfig::new().rest("[health]\nstartup_timeout_secs = 3"));
+    let live = wait_until(Duration::from_secs(10), || {
+        common::client().get(server.url("/healthz/live")).send().is_ok_and(|res| res.status() == 200)
+    });
+    assert!(live, "server did not start:\n{}", server.log());
+
+    let res = server.get("/healthz/startup");
+    assert_eq!(res.status(), 200);
+    let body = json(res);
+    assert_eq!(body["ok"], true);
+    assert!(body["detail"].as_str().unwrap().starts_with("initial clone in progress"), "{}", body);
+    let ready = json(server.get("/healthz/ready"));
+    assert_eq!(ready["ok"], false);
+    assert_eq!(ready["detail"], "initial clone has not completed");
+
+    let failed = wait_until(Duration::from_secs(10), || server.get("/healthz/startup").status() == 503);
+    assert!(failed, "startup probe did not fail:\n{}", server.log());
+    let body = json(server.get("/healthz/startup"));
+    assert_eq!(body["ok"], false);
+    assert_eq!(body["detail"], "initial clone did not complete within 3s");
+    // 进程本身仍然能响应
+    assert_eq!(server.get("/healthz/live").status(), 200);
+}
+
+fn check(server: &Server) -> std::process::Output {
+    std::process::Command::new(common::BIN)
+        .current_dir(server.dir.path())
+        .args(["check", "--config", "config.toml"])
+        .output()
+        .unwrap()
+}
+
+// 上游不存在时只警告，server 照常启动
+#[test]
+fn unreachable_upstream_is_reported() {
+    let url = format!("http://127.0.0.1:{}/index", common::free_port());
+    let server = Server::spawn_url(&url, ServerConfig::new().repo("connectivity_check_on_startup = true"));
+    let warned = format!("Upstream {} is not reachable", url);
+    assert!(server.wait_for_log(&warned, Duration::from_secs(20)), "{}", server.log());
+    let live = wait_until(Duration::from_secs(10), || {
+        common::client().get(server.url("/healthz/live")).send().is_ok_and(|res| res.status() == 200)
+    });
+    assert!(live, "server did not start:\n{}", server.log());
+
+    let upstream = &json(server.get("/health/ready"))["upstream"];
+    assert_eq!(upstream["ok"], false, "{}", upstream);
+    assert!(upstream["checked_at"].is_string(), "{}", upstream);
+    assert!(!upstream["detail"].as_str().unwrap().is_empty(), "{}", upstream);
+
+    let output = check(&server);
+    assert_eq!(output.status.code(), Some(1));
+    let output = String::from_utf8_lossy(&output.stdout) + String::from_utf8_lossy(&output.stderr);
+    assert!(output.contains(&warned), "{}", output);
+}
+
+#[test]
+fn reachable_upstream_is_reported() {
+    let upstream = Upstream::with_crates(&["serde"]);
+    let server = Server::start(&upstream, ServerConfig::new().repo("connectivity_check_on_startup = true"));
+    // HEAD 和 master 两个 ref
+    assert!(server.wait_for_log("is reachable (2 refs)", Duration::from_secs(10)), "{}", server.log());
+    let body = json(server.get("/health/ready"));
+    assert_eq!(body["upstream"]["ok"], true, "{}", body);
+    assert_eq!(body["upstream"]["detail"], "listed 2 refs");
+    assert!(check(&server).status.success());
+}
@@ -0,0 +1,170 @@
+mod common;
+
+use common::{Upstream, BIN};
+use std::{
+    fs,
+    io::{Read, Write},
+    os::fd::{FromRawFd, OwnedFd},
+    process::{Child, Command, Stdio},
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+// dialoguer 只在终端上交互，所以 stdin 接到一个伪终端上，像 expect 一样等待提示再输入
+struct Terminal {
+    child: Child,
+    master: fs::File,
+    output: Arc<Mutex<String>>,
+}
+
+impl Terminal {
+    fn spawn(args: &[&str]) -> Terminal {
+        let (mut master, mut slave) = (0, 0);
+        let size = libc::winsize {
+            ws_row: 24,
+            ws_col: 200,
+            ws_xpixel: 0,
+            ws_ypixel: 0,
+        };
+        let ret = unsafe { libc::openpty(&mut master, &mut slave, std::ptr::null_mut(), std::ptr::null(), &size) };
+        assert_eq!(ret, 0);
+        let (master, slave) = unsafe { (OwnedFd::from_raw_fd(master), OwnedFd::from_raw_fd(slave)) };
+        let child = Command::new(BIN)
+            .args(args)
+            .stdin(Stdio::from(slave.try_clone().unwrap()))
+            .stdout(Stdio::from(slave.try_clone().unwrap()))
+            .stderr(Stdio::from(slave))
+            .spawn()
+            .unwrap();
+        let master = fs::File::from(master);
+        let output = Arc::new(Mutex::new(String::new()));
+        let (mut reader, shared) = (master.try_clone().unwrap(), Arc::clone(&output));
+        thread::spawn(move || {
+            let mut buf = [0; 4096];
+            // 子进程退出后读取返回 EIO
+            while let Ok(n @ 1..) = reader.read(&mut buf) {
+                shared.lock().unwrap().push_str(&String::from_utf8_lossy(&buf[..n]));
+            }
+        });
+        Terminal { child, master, output }
+    }
+
+    fn output(&self) -> String {
+        self.output.lock().unwrap().clone()
+    }
+
+    // 等待输出里出现 prompt（从上一次匹配之后开始找），然后输入一行
+    fn answer(&mut self, prompt: &str, line: &str, seen: &mut usize) {
+        self.press(prompt, &format!("{}\r", line), seen);
+    }
+
+    // Confirm 读到 y 或 n 就返回，不需要回车
+    fn press(&mut self, prompt: &str, keys: &str, seen: &mut usize) {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        loop {
+            let output = self.output();
+            if let Some(at) = output[*seen..].find(prompt) {
+                *seen += at + prompt.len();
+                break;
+            }
+            assert!(Instant::now() < deadline, "prompt {:?} not shown:\n{}", prompt, output);
+            thread::sleep(Duration::from_millis(20));
+        }
+        self.master.write_all(keys.as_bytes()).unwrap();
+    }
+
+    fn wait(mut self) -> (bool, String) {
+        let deadline = Instant::now() + Duration::from_secs(30);
+        let status = loop {
+            if let Some(status) = self.child.try_wait().unwrap() {
+                break status;
+            }
+            assert!(Instant::now() < deadline, "init did not exit:\n{}", self.output());
+            thread::sleep(Duration::from_millis(20));
+        };
+        // 读线程把剩下的输出读完
+        thread::sleep(Duration::from_millis(200));
+        (status.success(), self.output())
+    }
+}
+
+#[test]
+fn wizard_with_canned_input() {
+    let upstream = Upstream::with_crates(&["serde"]);
+    let dir = tempfile::tempdir().unwrap();
+    let config_path = dir.path().join("config.toml");
+    let index = dir.path().join("index");
+    let mut term = Terminal::spawn(&["init", "--config", config_path.to_str().unwrap()]);
+
+    let mut seen = 0;
+    term.answer("Upstream git URL", &upstream.url(), &mut seen);
+    term.answer("Local index path", index.to_str().unwrap(), &mut seen);
+    // 不合法的值会提示错误并重新询问
+    term.answer("Update interval in seconds", "0", &mut seen);
+    term.answer("must be greater than 0", "300", &mut seen);
+    term.answer("Listen address", "", &mut seen);
+    term.answer("Listen port", "8123", &mut seen);
+    term.press("served over HTTPS by a reverse proxy", "y", &mut seen);
+    term.answer("Public host name of the mirror", "crates.example.com", &mut seen);
+    term.press("Enable JWT (HS256) authentication", "y", &mut seen);
+    term.answer("JWT secret", "s3cret", &mut seen);
+    term.answer("Repeat the JWT secret", "s3cret", &mut seen);
+    let (success, output) = term.wait();
+    assert!(success, "{}", output);
+    assert!(output.contains("Upstream is reachable (2 refs)"), "{}", output);
+    assert!(output.contains("registry = \"sparse+https://crates.example.com/\""), "{}", output);
+    // 输入的密码不回显
+    assert!(!output.contains("s3cret"), "{}", output);
+
+    let config: toml::Value = toml::from_str(&fs::read_to_string(&config_path).unwrap()).unwrap();
+    assert_eq!(config["repo"]["git_url"].as_str(), Some(upstream.url().as_str()));
+    assert_eq!(config["repo"]["path"].as_str(), index.to_str());
+    assert_eq!(config["repo"]["update_interval"].as_integer(), Some(300));
+    assert_eq!(config["web"]["address"].as_str(), Some("127.0.0.1"));
+    assert_eq!(config["web"]["port"].as_integer(), Some(8123));
+    assert_eq!(config["web"]["https_only"].as_bool(), Some(true));
+    assert_eq!(config["web"]["canonical_host"].as_str(), Some("crates.example.com"));
+    assert_eq!(config["auth"]["jwt"]["secret"].as_str(), Some("s3cret"));
+
+    // 已有的配置文件需要确认才覆盖
+    let mut term = Terminal::spawn(&["init", "--config", config_path.to_str().unwrap()]);
+    term.press("already exists, overwrite it?", "n", &mut 0);
+    let (success, output) = term.wait();
+    assert!(!success);
+    assert!(output.contains("use --force to overwrite it"), "{}", output);
+}
+
+#[test]
+fn non_interactive_uses_defaults() {
+    let upstream = Upstream::with_crates(&["serde"]);
+    let dir = tempfile::tempdir().unwrap();
+    let config_path = dir.path().join("config.yaml");
+    let output = Command::new(BIN)
+        .args(["init", "--non-interactive", "--config", config_path.to_str().unwrap()])
+        .args(["--git-url", &upstream.url(), "--port", "9000"])
+        .current_dir(dir.path())
+        .stdin(Stdio::null())
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}{}", stdout, String::from_utf8_lossy(&output.stderr));
+    assert!(stdout.contains("registry = \"sparse+http://127.0.0.1:9000/\""), "{}", stdout);
+    let config: serde_json::Value = serde_yaml::from_str(&fs::read_to_string(&config_path).unwrap()).unwrap();
+    assert_eq!(config["repo"]["path"], "./crates.io-index");
+    assert_eq!(config["repo"]["update_interval"], 600);
+    assert_eq!(config["web"]["port"], 9000);
+    assert!(config.get("auth").is_none());
+
+    // 给出的值不合法时直接失败
+    let output = Command::new(BIN)
+        .args(["init", "--non-interactive", "--force", "--config", config_path.to_str().unwrap()])
+        .args(["--git-url", &upstream.url(), "--port", "0"])
+        .current_dir(dir.path())
+        .stdin(Stdio::null())
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Listen port: must be greater than 0"), "{}", stderr);
+}
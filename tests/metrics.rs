@@ -0,0 +1,182 @@
+mod common;
+
+use common::{Server, ServerConfig, Upstream};
+use std::{
+    net::UdpSocket,
+    time::{Duration, Instant},
+};
+
+// /metrics 里某个 histogram 的所有 le 值；关闭 metrics feature 时 /metrics 返回 501
+#[cfg(feature = "metrics")]
+fn bucket_bounds(metrics: &str, name: &str) -> Vec<String> {
+    let prefix = format!("{}_bucket{{", name);
+    metrics
+        .lines()
+        .filter(|line| line.starts_with(&prefix))
+        .filter_map(|line| Some(line.split("le=\"").nth(1)?.split('"').next()?.to_string()))
+        .collect()
+}
+
+#[cfg(feature = "metrics")]
+fn metrics(server: &Server) -> String {
+    let res = server.get("/metrics");
+    assert_eq!(res.status(), 200);
+    res.text().unwrap()
+}
+
+#[cfg(feature = "metrics")]
+#[test]
+fn custom_pull_duration_buckets() {
+    let upstream = Upstream::with_crates(&["serde"]);
+    let server = Server::start(
+        &upstream,
+        ServerConfig::new().rest("[metrics.buckets]\nindex_pull_duration_seconds = [2.5, 45.0, 900.0]"),
+    );
+    let metrics = metrics(&server);
+    assert_eq!(bucket_bounds(&metrics, "index_pull_duration_seconds"), ["2.5", "45", "900", "+Inf"]);
+    // 没有覆盖的 histogram 保持默认
+    let http = bucket_bounds(&metrics, "http_request_duration_seconds");
+    assert_eq!(http.first().map(String::as_str), Some("0.005"), "{}", metrics);
+}
+
+#[cfg(feature = "metrics")]
+#[test]
+fn default_pull_duration_buckets() {
+    let upstream = Upstream::with_crates(&["serde"]);
+    let server = Server::start(&upstream, ServerConfig::new());
+    assert_eq!(
+        bucket_bounds(&metrics(&server), "index_pull_duration_seconds"),
+        ["1", "5", "30", "60", "300", "600", "+Inf"]
+    );
+}
+
+// 收集 StatsD 数据报，直到 done 返回 true 或超时
+fn statsd_datagrams(socket: &UdpSocket, mut done: impl FnMut(&[String]) -> bool) -> Vec<String> {
+    socket.set_read_timeout(Some(Duration::from_millis(200))).unwrap();
+    let deadline = Instant::now() + Duration::from_secs(30);
+    let mut datagrams = Vec::new();
+    let mut buf = [0; 65536];
+    while Instant::now() < deadline && !done(&datagrams) {
+        if let Ok(n) = socket.recv(&mut buf) {
+            // 一个数据报里可能有多行
+            datagrams.extend(String::from_utf8_lossy(&buf[..n]).lines().map(str::to_string));
+        }
+    }
+    datagrams
+}
+
+fn statsd_server(upstream: &Upstream, socket: &UdpSocket, extra: &str) -> Server {
+    let port = socket.local_addr().unwrap().port();
+    Server::start(
+        upstream,
+        ServerConfig::new().rest(&format!("[metrics]\nstatsd_host = \"127.0.0.1\"\nstatsd_port = {}\n{}", port, extra)),
+    )
+}
+
+#[test]
+fn sends_statsd_after_pull() {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let upstream = Upstream::with_crates(&["serde"]);
+    let server = statsd_server(&upstream, &socket, "");
+    let datagrams = statsd_datagrams(&socket, |lines| {
+        lines.iter().any(|line| line.starts_with("local_crates_io.index_pull_duration_seconds:"))
+    });
+    let pull = datagrams
+        .iter()
+        .find(|line| line.starts_with("local_crates_io.index_pull_duration_seconds:"))
+        .unwrap_or_else(|| panic!("no pull timing in {:?}\n{}", datagrams, server.log()));
+    assert!(pull.ends_with("|ms"), "{}", pull);
+
+    // 请求计数同样发送
+    assert_eq!(server.get("/se/rd/serde").status(), 200);
+    let datagrams = statsd_datagrams(&socket, |lines| {
+        lines.iter().any(|line| line.starts_with("local_crates_io.http_request_duration_seconds:"))
+    });
+    assert!(
+        datagrams.iter().any(|line| line.starts_with("local_crates_io.http_request_duration_seconds:")),
+        "{:?}",
+        datagrams
+    );
+}
+
+#[test]
+fn statsd_prefix() {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let upstream = Upstream::with_crates(&["serde"]);
+    let _server = statsd_server(&upstream, &socket, "statsd_prefix = \"mirror.eu\"");
+    let datagrams = statsd_datagrams(&socket, |lines| !lines.is_empty());
+    assert!(!datagrams.is_empty());
+    assert!(datagrams.iter().all(|line| line.starts_with("mirror.eu.")), "{:?}", datagrams);
+}
+
+// /metrics 里不带标签或带指定标签的值
+#[cfg(feature = "metrics")]
+fn metric_value(metrics: &str, series: &str) -> Option<f64> {
+    metrics
+        .lines()
+        .find_map(|line| line.strip_prefix(series)?.strip_prefix(' ')?.parse().ok())
+}
+
+#[cfg(feature = "metrics")]
+#[test]
+fn repository_stats_are_non_zero() {
+    let upstream = Upstream::with_crates(&["serde", "tokio"]);
+    let server = Server::start(&upstream, ServerConfig::new().repo("stats_interval_secs = 1"));
+    let mut text = String::new();
+    let collected = common::wait_until(Duration::from_secs(20), || {
+        text = metrics(&server);
+        metric_value(&text, "git_repo_size_bytes").is_some_and(|size| size > 0.0)
+    });
+    assert!(collected, "{}", text);
+    assert_eq!(metric_value(&text, "git_objects_total{type=\"commit\"}"), Some(1.0), "{}", text);
+    assert_eq!(metric_value(&text, "git_objects_total{type=\"blob\"}"), Some(3.0), "{}", text);
+    assert!(metric_value(&text, "git_objects_total{type=\"tree\"}").is_some_and(|trees| trees > 0.0));
+    assert!(metric_value(&text, "git_working_tree_size_bytes").is_some_and(|size| size > 0.0));
+    assert_eq!(metric_value(&text, "git_stale_refs_total"), Some(0.0), "{}", text);
+}
+
+// 超过 web.max_response_body_mb 的索引文件：大小已知时返回 500，chunked 发送时连接在中途断开
+#[cfg(feature = "metrics")]
+#[test]
+fn oversized_responses_are_counted() {
+    let upstream = Upstream::with_crates(&["tokio"]);
+    let large = common::index_line("serde", "1.0.0").repeat(2 * 1024 * 1024 / 150);
+    assert!(large.len() > 1024 * 1024);
+    upstream.commit("large", &[("se/rd/serde", Some(&large))]);
+    for chunked in [false, true] {
+        let server = Server::start(
+            &upstream,
+            ServerConfig::new().web(&format!("max_response_body_mb = 1\nuse_chunked_transfer = {}", chunked)),
+        );
+        assert_eq!(server.get("/to/ki/tokio").status(), 200);
+        assert_eq!(metric_value(&metrics(&server), "response_body_limit_exceeded_total"), Some(0.0));
+
+        let res = server.get("/se/rd/serde");
+        if chunked {
+            assert_eq!(res.status(), 200);
+            // 收到的内容少于文件大小，读取以错误结束
+            let mut body = Vec::new();
+            let result = std::io::Read::read_to_end(&mut { res }, &mut body);
+            assert!(result.is_err(), "read {} bytes", body.len());
+            assert!(body.len() < large.len());
+        } else {
+            assert_eq!(res.status(), 500);
+        }
+        // 第二次请求也被计数
+        let _ = server.get("/se/rd/serde").bytes();
+        assert_eq!(metric_value(&metrics(&server), "response_body_limit_exceeded_total"), Some(2.0));
+        assert!(server.log().contains("Response body exceeds web.max_response_body_mb"), "{}", server.log());
+    }
+}
+
+// 关闭 metrics feature 编译时 server 照常启动，/metrics 返回 501
+#[cfg(not(feature = "metrics"))]
+#[test]
+fn metrics_endpoint_not_implemented() {
+    let upstream = Upstream::with_crates(&["serde"]);
+    let server = Server::start(&upstream, ServerConfig::new());
+    assert_eq!(server.get("/se/rd/serde").status(), 200);
+    let res = server.get("/metrics");
+    assert_eq!(res.status(), 501);
+    assert_eq!(res.headers()["Content-Type"], "application/problem+json");
+}
@@ -0,0 +1,79 @@
+mod common;
+
+use common::{Server, ServerConfig, Upstream};
+use std::time::Duration;
+use wiremock::{matchers::method, Mock, MockServer, ResponseTemplate};
+
+// wiremock 的 server 在自己的线程里运行，这里只用 runtime 调用它的 async 接口
+struct Hook {
+    runtime: tokio::runtime::Runtime,
+    server: MockServer,
+}
+
+impl Hook {
+    fn start() -> Hook {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let server = runtime.block_on(async {
+            let server = MockServer::start().await;
+            Mock::given(method("POST")).respond_with(ResponseTemplate::new(200)).mount(&server).await;
+            server
+        });
+        Hook { runtime, server }
+    }
+
+    fn url(&self) -> String {
+        format!("{}/services/T0/B0/hook", self.server.uri())
+    }
+
+    fn payloads(&self) -> Vec<serde_json::Value> {
+        let requests = self.runtime.block_on(self.server.received_requests()).unwrap();
+        requests.iter().map(|request| request.body_json().unwrap()).collect()
+    }
+
+    fn wait_for(&self, count: usize, server: &Server) -> Vec<serde_json::Value> {
+        assert!(
+            common::wait_until(Duration::from_secs(15), || self.payloads().len() >= count),
+            "expected {} notifications:\n{}",
+            count,
+            server.log()
+        );
+        self.payloads()
+    }
+}
+
+#[test]
+fn slack_cards_for_pull_success_and_failure() {
+    let upstream = Upstream::with_crates(&["serde"]);
+    let hook = Hook::start();
+    let server = Server::start(
+        &upstream,
+        ServerConfig::new()
+            .repo("update_cron = \"* * * * * *\"")
+            .rest(&format!("[notifications.slack]\nwebhook_url = \"{}\"", hook.url())),
+    );
+    // 首次 clone 不算更新
+    std::thread::sleep(Duration::from_millis(1500));
+    assert!(hook.payloads().is_empty());
+
+    let old = upstream.head();
+    upstream.commit("tokio", &[("to/ki/tokio", Some(&common::index_line("tokio", "1.0.0")))]);
+    let payloads = hook.wait_for(1, &server);
+    let attachment = &payloads[0]["attachments"][0];
+    assert_eq!(payloads[0]["text"], "[local-crates-io-index] Index updated");
+    assert_eq!(attachment["color"], "#2eb886");
+    let fields = attachment["blocks"][1]["fields"].to_string();
+    assert!(fields.contains(&upstream.head().to_string()), "{}", fields);
+    assert!(fields.contains(&old.to_string()), "{}", fields);
+    assert!(fields.contains("*Changed crates*\\n1"), "{}", fields);
+
+    std::fs::remove_dir_all(upstream.dir.path()).unwrap();
+    let payloads = hook.wait_for(2, &server);
+    let attachment = &payloads[1]["attachments"][0];
+    assert_eq!(payloads[1]["text"], "[local-crates-io-index] Index update failed");
+    assert_eq!(attachment["color"], "#d40e0d");
+    assert!(attachment["blocks"][1]["text"]["text"].as_str().unwrap().starts_with("```"));
+    // 默认 min_interval_secs 内不再重复发送
+    std::thread::sleep(Duration::from_millis(2500));
+    assert_eq!(hook.payloads().len(), 2);
+    assert!(!server.log().contains("T0/B0/hook"), "{}", server.log());
+}
@@ -0,0 +1,71 @@
+mod common;
+
+use common::{Server, ServerConfig, Upstream};
+use std::{path::Path, time::Duration};
+
+fn pack_files(repo: &Path) -> usize {
+    std::fs::read_dir(repo.join(".git/objects/pack"))
+        .unwrap()
+        .filter(|entry| entry.as_ref().unwrap().path().extension().is_some_and(|ext| ext == "pack"))
+        .count()
+}
+
+// 每次写一个新对象并单独打成一个 pack，不改变任何引用
+// 先写到别的目录再一起移进去，避免 monitor 在中途检查
+fn add_packs(repo: &Path, count: usize) {
+    let repo = git2::Repository::open(repo).unwrap();
+    let staging = tempfile::tempdir().unwrap();
+    for i in 0..count {
+        let oid = repo.blob(format!("fragment {}\n", i).as_bytes()).unwrap();
+        let mut builder = repo.packbuilder().unwrap();
+        builder.insert_object(oid, None).unwrap();
+        builder.write(staging.path(), 0o444).unwrap();
+    }
+    let mut files: Vec<_> = std::fs::read_dir(staging.path()).unwrap().map(|entry| entry.unwrap().path()).collect();
+    // .idx 要比 .pack 先到
+    files.sort_by_key(|path| path.extension().is_some_and(|ext| ext == "pack"));
+    for file in files {
+        std::fs::rename(&file, repo.path().join("objects/pack").join(file.file_name().unwrap())).unwrap();
+    }
+}
+
+#[test]
+fn fragmented_repository_is_repacked() {
+    let upstream = Upstream::with_crates(&["serde"]);
+    let server = Server::start(
+        &upstream,
+        ServerConfig::new().repo("pack_monitor_enabled = true\npack_monitor_interval_secs = 1\nmax_pack_files = 3"),
+    );
+    let repo = server.index_path();
+    let initial = pack_files(&repo);
+    assert!(initial <= 3, "{}", initial);
+    add_packs(&repo, 5);
+    assert_eq!(pack_files(&repo), initial + 5);
+
+    assert!(server.wait_for_log("Repository repack finished", Duration::from_secs(20)), "{}", server.log());
+    let log = server.log();
+    assert!(log.contains("pack files (repo.max_pack_files = 3), repacking"), "{}", log);
+    assert!(log.contains(" -> 1"), "{}", log);
+    assert_eq!(pack_files(&repo), 1);
+    // 仓库完好，索引照常返回
+    common::git(&repo, &["fsck", "--no-dangling"]);
+    assert_eq!(server.get("/se/rd/serde").status(), 200);
+    #[cfg(feature = "metrics")]
+    {
+        assert!(common::wait_until(Duration::from_secs(5), || {
+            server.get("/metrics").text().unwrap().contains("\npack_file_count 1\n")
+        }));
+        assert!(server.get("/metrics").text().unwrap().contains("\nrepacks_triggered_total 1\n"));
+    }
+}
+
+#[test]
+fn monitor_is_off_by_default() {
+    let upstream = Upstream::with_crates(&["serde"]);
+    let server = Server::start(&upstream, ServerConfig::new().repo("max_pack_files = 1"));
+    let repo = server.index_path();
+    add_packs(&repo, 3);
+    std::thread::sleep(Duration::from_secs(2));
+    assert!(pack_files(&repo) >= 3);
+    assert!(!server.log().contains("repacking"), "{}", server.log());
+}
@@ -0,0 +1,78 @@
+mod common;
+
+use common::{client, Server, ServerConfig, Upstream};
+use reqwest::{blocking::RequestBuilder, Method};
+
+const TOKEN: &str = "admin-secret";
+
+// 检查响应是合法的 RFC 7807 JSON，返回 type 的最后一段
+fn problem(req: RequestBuilder, status: u16) -> (String, serde_json::Value) {
+    let res = req.send().unwrap();
+    assert_eq!(res.status(), status, "{:?}", res.url().path());
+    assert_eq!(res.headers()["Content-Type"], "application/problem+json");
+    let path = res.url().path().to_string();
+    let body: serde_json::Value = serde_json::from_str(&res.text().unwrap()).unwrap();
+    assert_eq!(body["status"], status, "{}", body);
+    assert!(body["title"].as_str().is_some_and(|title| !title.is_empty()), "{}", body);
+    assert_eq!(body["instance"], path, "{}", body);
+    let kind = body["type"].as_str().unwrap();
+    let (base, slug) = kind.rsplit_once('/').unwrap();
+    assert_eq!(base, "https://registry.local/problems", "{}", body);
+    (slug.to_string(), body)
+}
+
+#[test]
+fn error_conditions_return_problem_details() {
+    let upstream = Upstream::with_crates(&["serde"]);
+    let server = Server::start(
+        &upstream,
+        ServerConfig::new()
+            .web(&format!(
+                "admin_token = \"{}\"\nreverse_deps_enabled = true\ngit_smart_http = true",
+                TOKEN
+            ))
+            .rest("[rate_limit.endpoints.search]\ncapacity = 1\nrefill_per_sec = 0.001"),
+    );
+    let get = |path: &str| client().get(server.url(path));
+
+    let cases: Vec<(RequestBuilder, u16, &str)> = vec![
+        (get("/se/rd/nope"), 404, "not-found"),
+        (get("/no/such/route/at/all"), 404, "not-found"),
+        (get("/api/v1/crates/nope/1.0.0"), 404, "not-found"),
+        (get("/ba/dn/bad!name"), 400, "invalid-crate-name"),
+        (get("/api/v1/crates/serde/reverse-dependencies?version=x"), 400, "bad-request"),
+        (get("/api/v1/me"), 401, "unauthorized"),
+        (client().post(server.url("/admin/reload")), 401, "unauthorized"),
+        (client().post(server.url("/admin/reload")).bearer_auth("wrong"), 401, "unauthorized"),
+        (get("/info/refs?service=git-receive-pack"), 403, "forbidden"),
+        (get("/api/v1/crates/serde/1.0.0").header("Cargo-Protocol", "version=9"), 422, "unsupported-protocol-version"),
+        (client().request(Method::DELETE, server.url("/se/rd/serde")), 405, "method-not-allowed"),
+    ];
+    for (req, status, slug) in cases {
+        let (kind, body) = problem(req, status);
+        assert_eq!(kind, slug, "{}", body);
+    }
+
+    // 第二个请求超过限流
+    assert_eq!(get("/api/v1/crates?q=serde").send().unwrap().status(), 200);
+    let (kind, _) = problem(get("/api/v1/crates?q=serde"), 429);
+    assert_eq!(kind, "rate-limited");
+
+    let (_, body) = problem(get("/se/rd/nope"), 404);
+    assert_eq!(body["detail"], "crate `nope` does not exist");
+    let (_, body) = problem(get("/api/v1/crates/serde/1.0.0").header("Cargo-Protocol", "version=9"), 422);
+    assert_eq!(body["detail"], "Unsupported protocol version, this registry supports versions 1 to 2");
+}
+
+#[test]
+fn custom_base_uri() {
+    let upstream = Upstream::with_crates(&["serde"]);
+    let server = Server::start(
+        &upstream,
+        ServerConfig::new().web("problem_details_base_uri = \"https://mirror.example.com/errors/\""),
+    );
+    let res = server.get("/se/rd/nope");
+    assert_eq!(res.status(), 404);
+    let body: serde_json::Value = serde_json::from_str(&res.text().unwrap()).unwrap();
+    assert_eq!(body["type"], "https://mirror.example.com/errors/not-found");
+}
@@ -0,0 +1,188 @@
+// 用 PATH 里真正的 cargo 访问镜像：CARGO_HOME 指向临时目录，crates-io 被替换成镜像
+// 镜像只读，没有发布接口，所以不测试 cargo publish
+mod common;
+
+use assert_cmd::Command;
+use common::{index_path, Server, ServerConfig, Upstream};
+use std::{
+    fs,
+    io::{BufRead, BufReader, Write},
+    net::TcpListener,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    thread,
+};
+use tempfile::TempDir;
+
+fn cargo_available() -> bool {
+    std::process::Command::new("cargo").arg("--version").output().is_ok_and(|out| out.status.success())
+}
+
+// 按 dl 模板 {crate}/{version}/download 返回 .crate 文件，并记录请求的路径
+struct Downloads {
+    port: u16,
+    requests: Arc<Mutex<Vec<String>>>,
+}
+
+impl Downloads {
+    fn serve(dir: PathBuf) -> Downloads {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let recorded = Arc::clone(&requests);
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else { continue };
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut head = String::new();
+                while reader.read_line(&mut head).is_ok_and(|n| n > 0) && !head.ends_with("\r\n\r\n") {}
+                let path = head.split(' ').nth(1).unwrap_or_default().to_string();
+                let file = path
+                    .strip_prefix('/')
+                    .and_then(|path| path.strip_suffix("/download"))
+                    .and_then(|path| path.split_once('/'))
+                    .and_then(|(name, version)| fs::read(dir.join(format!("{}-{}.crate", name, version))).ok());
+                recorded.lock().unwrap().push(path);
+                let _ = match file {
+                    Some(body) => {
+                        let head = format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n", body.len());
+                        stream.write_all(head.as_bytes()).and_then(|_| stream.write_all(&body))
+                    }
+                    None => stream.write_all(b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"),
+                };
+            }
+        });
+        Downloads { port, requests }
+    }
+}
+
+// 用系统的 tar 打包一个最小的库 crate，返回 sha256
+fn package(dir: &Path, name: &str, version: &str, deps: &str) -> String {
+    let root = dir.join(format!("{}-{}", name, version));
+    fs::create_dir_all(root.join("src")).unwrap();
+    fs::write(
+        root.join("Cargo.toml"),
+        format!("[package]\nname = \"{}\"\nversion = \"{}\"\nedition = \"2021\"\n\n[dependencies]\n{}", name, version, deps),
+    )
+    .unwrap();
+    fs::write(root.join("src/lib.rs"), "pub fn answer() -> u32 { 42 }\n").unwrap();
+    let file = format!("{}-{}.crate", name, version);
+    let status = std::process::Command::new("tar")
+        .current_dir(dir)
+        .args(["czf", &file, &format!("{}-{}", name, version)])
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let sha = openssl::sha::sha256(&fs::read(dir.join(&file)).unwrap());
+    sha.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn line(name: &str, version: &str, cksum: &str, deps: &str, yanked: bool) -> String {
+    format!(
+        "{{\"name\":\"{}\",\"vers\":\"{}\",\"deps\":[{}],\"cksum\":\"{}\",\"features\":{{}},\"yanked\":{}}}\n",
+        name, version, deps, cksum, yanked
+    )
+}
+
+struct Registry {
+    _server: Server,
+    downloads: Downloads,
+    _crates: TempDir,
+    home: TempDir,
+    project: TempDir,
+}
+
+impl Registry {
+    // mirror-demo 依赖 mirror-dep；0.3.0 被 yank，0.4.0-alpha.1 是预发布版本，cargo add 都不会选
+    fn start() -> Registry {
+        let crates = tempfile::tempdir().unwrap();
+        let dep_cksum = package(crates.path(), "mirror-dep", "1.2.0", "");
+        let dep = "{\"name\":\"mirror-dep\",\"req\":\"^1\",\"features\":[],\"optional\":false,\
+                   \"default_features\":true,\"target\":null,\"kind\":\"normal\"}";
+        let demo_cksum = package(crates.path(), "mirror-demo", "0.2.0", "mirror-dep = \"1\"\n");
+        let downloads = Downloads::serve(crates.path().to_path_buf());
+
+        let upstream = Upstream::new();
+        let demo = line("mirror-demo", "0.1.0", &"0".repeat(64), "", false)
+            + &line("mirror-demo", "0.2.0", &demo_cksum, dep, false)
+            + &line("mirror-demo", "0.3.0", &"0".repeat(64), "", true)
+            + &line("mirror-demo", "0.4.0-alpha.1", &"0".repeat(64), "", false);
+        let config = format!("{{\"dl\":\"http://127.0.0.1:{}/{{crate}}/{{version}}/download\"}}", downloads.port);
+        upstream.commit(
+            "crates",
+            &[
+                ("config.json", Some(&config)),
+                (&index_path("mirror-demo"), Some(&demo)),
+                (&index_path("mirror-dep"), Some(&line("mirror-dep", "1.2.0", &dep_cksum, "", false))),
+            ],
+        );
+        let server = Server::start(&upstream, ServerConfig::new());
+
+        let home = tempfile::tempdir().unwrap();
+        fs::write(
+            home.path().join("config.toml"),
+            format!(
+                "[source.crates-io]\nreplace-with = \"mirror\"\n\n[source.mirror]\nregistry = \"sparse+{}\"\n",
+                server.url("/")
+            ),
+        )
+        .unwrap();
+        let project = tempfile::tempdir().unwrap();
+        let registry = Registry {
+            _server: server,
+            downloads,
+            _crates: crates,
+            home,
+            project,
+        };
+        registry.cargo(&["init", "--lib", "--vcs", "none", "--name", "compat-check"]).assert().success();
+        registry
+    }
+
+    fn cargo(&self, args: &[&str]) -> Command {
+        let mut cmd = Command::new("cargo");
+        // 外层 cargo test 设置的变量不能影响被测的 cargo
+        for (key, _) in std::env::vars() {
+            if key.starts_with("CARGO_") && key != "CARGO_HOME" {
+                cmd.env_remove(key);
+            }
+        }
+        cmd.current_dir(self.project.path()).env("CARGO_HOME", self.home.path()).env("CARGO_TERM_COLOR", "never").args(args);
+        cmd
+    }
+}
+
+#[test]
+fn cargo_add_and_fetch() {
+    if !cargo_available() {
+        eprintln!("cargo is not in PATH, skipping");
+        return;
+    }
+    let registry = Registry::start();
+    registry.cargo(&["add", "mirror-demo"]).assert().success();
+    let manifest = fs::read_to_string(registry.project.path().join("Cargo.toml")).unwrap();
+    assert!(manifest.contains("mirror-demo = \"0.2.0\""), "{}", manifest);
+
+    registry.cargo(&["fetch"]).assert().success();
+    let lock = fs::read_to_string(registry.project.path().join("Cargo.lock")).unwrap();
+    assert!(lock.contains("name = \"mirror-demo\"\nversion = \"0.2.0\""), "{}", lock);
+    assert!(lock.contains("name = \"mirror-dep\"\nversion = \"1.2.0\""), "{}", lock);
+    let mut downloads = registry.downloads.requests.lock().unwrap().clone();
+    downloads.sort();
+    assert_eq!(downloads, ["/mirror-demo/0.2.0/download", "/mirror-dep/1.2.0/download"]);
+
+    // 下载的包能编译
+    registry.cargo(&["build", "--offline"]).assert().success();
+}
+
+#[test]
+fn cargo_reports_missing_crates() {
+    if !cargo_available() {
+        eprintln!("cargo is not in PATH, skipping");
+        return;
+    }
+    let registry = Registry::start();
+    let output = registry.cargo(&["add", "no-such-crate"]).assert().failure().get_output().stderr.clone();
+    let stderr = String::from_utf8_lossy(&output);
+    assert!(stderr.contains("the crate `no-such-crate` could not be found in registry index"), "{}", stderr);
+}
@@ -0,0 +1,148 @@
+mod common;
+
+use common::{Server, ServerConfig, Upstream};
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+const TOKEN: &str = "reload-secret";
+
+// 持续请求直到 done，任何失败的请求都会让测试失败
+fn request_loop(server: &Server, done: &Arc<AtomicBool>) -> Vec<thread::JoinHandle<usize>> {
+    (0..4)
+        .map(|_| {
+            let (url, done) = (server.url("/se/rd/serde"), Arc::clone(done));
+            thread::spawn(move || {
+                // 每个请求一个新连接，旧 server 停止 accept 后新连接必须由新 server 接受
+                let client = reqwest::blocking::Client::builder().pool_max_idle_per_host(0).build().unwrap();
+                let mut ok = 0;
+                while !done.load(Ordering::Acquire) {
+                    let res = client.get(&url).send().unwrap_or_else(|e| panic!("request failed during reload: {:?}", e));
+                    assert_eq!(res.status(), 200);
+                    ok += 1;
+                }
+                ok
+            })
+        })
+        .collect()
+}
+
+fn reload(server: &Server, token: Option<&str>) -> reqwest::StatusCode {
+    let mut req = common::client().post(server.url("/admin/reload"));
+    if let Some(token) = token {
+        req = req.bearer_auth(token);
+    }
+    req.send().unwrap().status()
+}
+
+#[test]
+fn requests_succeed_during_reload() {
+    let upstream = Upstream::with_crates(&["serde"]);
+    let server = Server::start(&upstream, ServerConfig::new().web(&format!("admin_token = \"{}\"", TOKEN)));
+
+    let done = Arc::new(AtomicBool::new(false));
+    let clients = request_loop(&server, &done);
+
+    for generation in 1..=3 {
+        assert_eq!(reload(&server, Some(TOKEN)), 202);
+        let started = format!("Server generation {} started", generation);
+        assert!(server.wait_for_log(&started, Duration::from_secs(20)), "{}", server.log());
+        thread::sleep(Duration::from_millis(300));
+    }
+    done.store(true, Ordering::Release);
+    let ok: usize = clients.into_iter().map(|client| client.join().unwrap()).sum();
+    assert!(ok > 0);
+}
+
+#[test]
+fn workers_are_recycled_without_dropping_requests() {
+    let upstream = Upstream::with_crates(&["serde"]);
+    let server = Server::start(&upstream, ServerConfig::new().web("worker_recycle_interval_secs = 1"));
+
+    let done = Arc::new(AtomicBool::new(false));
+    let clients = request_loop(&server, &done);
+    // 每次回收都用新的 server 替换全部 worker
+    for generation in 1..=3 {
+        let started = format!("Server generation {} started", generation);
+        assert!(server.wait_for_log(&started, Duration::from_secs(20)), "{}", server.log());
+    }
+    assert!(server.wait_for_log("Previous server drained and stopped", Duration::from_secs(10)), "{}", server.log());
+    done.store(true, Ordering::Release);
+    let ok: usize = clients.into_iter().map(|client| client.join().unwrap()).sum();
+    assert!(ok > 0);
+
+    let log = server.log();
+    assert!(log.matches("Recycling 2 web server workers").count() >= 3, "{}", log);
+    assert!(!log.contains("Failed to recycle workers"), "{}", log);
+    #[cfg(feature = "metrics")]
+    {
+        let metrics = server.get("/metrics").text().unwrap();
+        let recycles = metrics
+            .lines()
+            .find_map(|line| line.strip_prefix("worker_recycles_total ")?.parse::<f64>().ok())
+            .unwrap();
+        assert!(recycles >= 3.0, "{}", metrics);
+    }
+}
+
+#[test]
+fn reload_requires_admin_token() {
+    let upstream = Upstream::with_crates(&["serde"]);
+    let server = Server::start(&upstream, ServerConfig::new().web(&format!("admin_token = \"{}\"", TOKEN)));
+    assert_eq!(reload(&server, None), 401);
+    assert_eq!(reload(&server, Some("wrong")), 401);
+    assert!(!server.log().contains("Reloading"));
+
+    let disabled = Server::start(&upstream, ServerConfig::new());
+    assert_eq!(reload(&disabled, Some(TOKEN)), 404);
+}
+
+// 预先计算的 Strict-Transport-Security 随新配置一起替换
+#[test]
+fn hsts_header_follows_reloaded_config() {
+    let upstream = Upstream::with_crates(&["serde"]);
+    let server = Server::start(
+        &upstream,
+        ServerConfig::new()
+            .web(&format!("admin_token = \"{}\"", TOKEN))
+            .rest("[web.security_headers]\nhsts_max_age = 600"),
+    );
+    let client = reqwest::blocking::Client::builder().pool_max_idle_per_host(0).build().unwrap();
+    let hsts = || {
+        let res = client
+            .get(server.url("/se/rd/serde"))
+            .header("X-Forwarded-Proto", "https")
+            .send()
+            .unwrap();
+        assert_eq!(res.status(), 200);
+        res.headers().get("Strict-Transport-Security").map(|value| value.to_str().unwrap().to_string())
+    };
+    let rewrite = |from: &str, to: &str| {
+        let text = std::fs::read_to_string(server.config_path()).unwrap();
+        assert!(text.contains(from), "{}", text);
+        std::fs::write(server.config_path(), text.replace(from, to)).unwrap();
+    };
+    // 旧 server 停止之前还可能 accept 新连接
+    let drained = |count: usize| {
+        let drained = || server.log().matches("Previous server drained and stopped").count() == count;
+        assert!(common::wait_until(Duration::from_secs(20), drained), "{}", server.log());
+    };
+    assert_eq!(hsts().as_deref(), Some("max-age=600"));
+    // 普通 HTTP 不下发
+    assert!(server.get("/se/rd/serde").headers().get("Strict-Transport-Security").is_none());
+
+    rewrite("hsts_max_age = 600", "hsts_max_age = 86400\nhsts_include_subdomains = true");
+    server.signal(libc::SIGHUP);
+    drained(1);
+    assert_eq!(hsts().as_deref(), Some("max-age=86400; includeSubDomains"));
+
+    rewrite("hsts_max_age = 86400", "hsts = false");
+    assert_eq!(reload(&server, Some(TOKEN)), 202);
+    drained(2);
+    assert_eq!(hsts(), None);
+}
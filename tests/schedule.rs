@@ -0,0 +1,78 @@
+mod common;
+
+use common::{Server, ServerConfig, Upstream};
+use std::time::Duration;
+
+#[test]
+fn cron_schedule_pulls_every_second() {
+    let upstream = Upstream::with_crates(&["serde"]);
+    let server = Server::start(&upstream, ServerConfig::new().repo("update_cron = \"* * * * * *\""));
+    assert!(
+        common::wait_until(Duration::from_secs(10), || server.log().matches("Pulling repository updates").count() >= 3),
+        "{}",
+        server.log()
+    );
+    assert!(server.log().contains("Next scheduled update at"));
+
+    // 新的提交在下一秒被拉取
+    upstream.commit("add tokio", &[("to/ki/tokio", Some(&common::index_line("tokio", "1.0.0")))]);
+    assert!(common::wait_until(Duration::from_secs(10), || server.get("/to/ki/tokio").status() == 200));
+}
+
+#[test]
+fn circuit_breaker_skips_pulls_while_open() {
+    let upstream = Upstream::with_crates(&["serde"]);
+    let server = Server::start(
+        &upstream,
+        ServerConfig::new().repo("update_cron = \"* * * * * *\"\ncb_failure_threshold = 3\ncb_open_duration_secs = 3600"),
+    );
+    // 上游不可访问后每次 pull 都失败
+    std::fs::remove_dir_all(upstream.dir.path()).unwrap();
+    assert!(
+        server.wait_for_log("Circuit breaker opened after 3 consecutive failures", Duration::from_secs(20)),
+        "{}",
+        server.log()
+    );
+    assert!(common::wait_until(Duration::from_secs(10), || server
+        .log()
+        .matches("Skipping update: circuit breaker is open")
+        .count()
+        >= 3));
+    let log = server.log();
+    assert_eq!(log.matches("Failed to pull repository").count(), 3, "{}", log);
+    // 打开期间 server 继续提供本地的索引
+    assert_eq!(server.get("/se/rd/serde").status(), 200);
+    #[cfg(feature = "metrics")]
+    {
+        let metrics = server.get("/metrics").text().unwrap();
+        assert!(metrics.contains("circuit_breaker_state{state=\"open\"} 1"), "{}", metrics);
+        assert!(metrics.contains("circuit_breaker_trips_total 1"), "{}", metrics);
+    }
+}
+
+// 没有请求时间隔乘以 idle_pull_interval_multiplier，恢复请求后立即 pull
+#[test]
+fn idle_interval_multiplier() {
+    let upstream = Upstream::with_crates(&["serde"]);
+    let server = Server::start(
+        &upstream,
+        ServerConfig::new().repo("update_interval = 2\nidle_threshold_secs = 1\nidle_pull_interval_multiplier = 5"),
+    );
+    // 健康检查不算请求
+    assert!(
+        server.wait_for_log("No recent requests, next update in 10s", Duration::from_secs(10)),
+        "{}",
+        server.log()
+    );
+    let body: serde_json::Value = serde_json::from_str(&server.get("/health/ready").text().unwrap()).unwrap();
+    assert_eq!(body["is_idle"], true);
+    let pulls = server.log().matches("Pulling repository updates").count();
+
+    upstream.commit("add tokio", &[("to/ki/tokio", Some(&common::index_line("tokio", "1.0.0")))]);
+    assert_eq!(server.get("/se/rd/serde").status(), 200);
+    assert!(server.wait_for_log("Traffic resumed, pulling now", Duration::from_secs(5)), "{}", server.log());
+    assert!(common::wait_until(Duration::from_secs(5), || server.get("/to/ki/tokio").status() == 200));
+    assert!(server.log().matches("Pulling repository updates").count() > pulls);
+    let body: serde_json::Value = serde_json::from_str(&server.get("/health/ready").text().unwrap()).unwrap();
+    assert_eq!(body["is_idle"], false);
+}
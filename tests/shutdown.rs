@@ -0,0 +1,54 @@
+mod common;
+
+use common::{client, Server, ServerConfig, Upstream};
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+// 长轮询的请求在收到信号之后还要等几秒才完成
+fn drains_in_flight_requests(signal: i32, name: &str) {
+    let upstream = Upstream::with_crates(&["serde"]);
+    let mut server = Server::start(
+        &upstream,
+        ServerConfig::new()
+            .rest("[events]\nlog_path = \"{dir}/events.ndjson\"")
+            .rest("[app]\ngraceful_shutdown_timeout_secs = 20"),
+    );
+    let url = server.url("/api/v1/events?since_seq=0");
+    let etag = client().get(&url).send().unwrap().headers()["ETag"].to_str().unwrap().to_string();
+
+    let requests: Vec<_> = (0..4)
+        .map(|_| {
+            let (url, etag) = (format!("{}&wait=3", url), etag.clone());
+            thread::spawn(move || {
+                let started = Instant::now();
+                let res = client().get(&url).header("If-None-Match", &etag).send();
+                (res.map(|res| res.status().as_u16()), started.elapsed())
+            })
+        })
+        .collect();
+    thread::sleep(Duration::from_millis(500));
+    server.signal(signal);
+
+    for request in requests {
+        let (status, elapsed) = request.join().unwrap();
+        assert_eq!(status.unwrap(), 304, "in-flight request was dropped:\n{}", server.log());
+        assert!(elapsed >= Duration::from_secs(3));
+    }
+    let status = server.wait_exit(Duration::from_secs(20));
+    assert!(status.is_some_and(|status| status.success()), "{:?}\n{}", status, server.log());
+    let log = server.log();
+    assert!(log.contains(&format!("收到 {}，正在优雅关闭 web server，排空 4 个连接（最多 20s）", name)), "{}", log);
+    assert!(log.contains("Web server优雅关闭完成"), "{}", log);
+}
+
+#[test]
+fn sigterm_drains_connections() {
+    drains_in_flight_requests(libc::SIGTERM, "SIGTERM");
+}
+
+#[test]
+fn sigint_drains_connections() {
+    drains_in_flight_requests(libc::SIGINT, "SIGINT");
+}
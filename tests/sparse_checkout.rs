@@ -0,0 +1,75 @@
+mod common;
+
+use common::{index_line, Server, ServerConfig, Upstream};
+use std::{fs, path::Path, time::Duration};
+
+// 工作区里除了 .git 之外的所有文件，按路径排序
+fn work_tree_files(root: &Path) -> Vec<String> {
+    fn walk(root: &Path, dir: &Path, files: &mut Vec<String>) {
+        for entry in fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.file_name().unwrap() == ".git" {
+                continue;
+            }
+            if path.is_dir() {
+                walk(root, &path, files);
+            } else {
+                files.push(path.strip_prefix(root).unwrap().to_str().unwrap().to_string());
+            }
+        }
+    }
+    let mut files = Vec::new();
+    walk(root, root, &mut files);
+    files.sort();
+    files
+}
+
+#[test]
+fn checks_out_matching_patterns_only() {
+    let upstream = Upstream::with_crates(&["serde", "serde_json", "tokio", "rand"]);
+    let server = Server::start(
+        &upstream,
+        ServerConfig::new().repo("sparse_checkout = true\nsparse_checkout_patterns = [\"se/*\", \"config.json\"]"),
+    );
+    assert_eq!(
+        work_tree_files(&server.index_path()),
+        ["config.json", "se/rd/serde", "se/rd/serde_json"]
+    );
+    assert_eq!(server.get("/se/rd/serde").status(), 200);
+    assert_eq!(server.get("/to/ki/tokio").status(), 404);
+    let sparse = fs::read_to_string(server.index_path().join(".git/info/sparse-checkout")).unwrap();
+    assert_eq!(sparse, "se/*\nconfig.json\n");
+}
+
+#[test]
+fn cone_mode_keeps_root_files_and_listed_directories() {
+    let upstream = Upstream::with_crates(&["serde", "tokio", "rand"]);
+    let server = Server::start(
+        &upstream,
+        ServerConfig::new().repo("sparse_checkout = true\ncone_mode = true\nsparse_checkout_patterns = [\"to/ki\"]"),
+    );
+    assert_eq!(work_tree_files(&server.index_path()), ["config.json", "to/ki/tokio"]);
+    let sparse = fs::read_to_string(server.index_path().join(".git/info/sparse-checkout")).unwrap();
+    assert_eq!(sparse, "/*\n!/*/\n/to/\n!/to/*/\n/to/ki/\n");
+}
+
+#[test]
+fn pulls_respect_sparse_checkout() {
+    let upstream = Upstream::with_crates(&["serde", "tokio"]);
+    let server = Server::start(
+        &upstream,
+        ServerConfig::new().repo("sparse_checkout = true\nsparse_checkout_patterns = [\"se/\"]\nupdate_cron = \"* * * * * *\""),
+    );
+    upstream.commit(
+        "add crates",
+        &[
+            ("se/rd/serde", Some(&(index_line("serde", "1.0.0") + &index_line("serde", "1.0.1")))),
+            ("ra/nd/rand", Some(&index_line("rand", "0.8.0"))),
+        ],
+    );
+    let updated = common::wait_until(Duration::from_secs(20), || {
+        fs::read_to_string(server.index_path().join("se/rd/serde")).is_ok_and(|index| index.contains("1.0.1"))
+    });
+    assert!(updated, "{}", server.log());
+    assert_eq!(work_tree_files(&server.index_path()), ["se/rd/serde"]);
+}
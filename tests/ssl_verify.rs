@@ -0,0 +1,98 @@
+mod common;
+
+use common::{Server, ServerConfig, Upstream};
+use openssl::{
+    asn1::Asn1Time,
+    hash::MessageDigest,
+    pkey::PKey,
+    rsa::Rsa,
+    x509::{extension::SubjectAlternativeName, X509NameBuilder, X509},
+};
+use std::{fs, net::TcpListener, thread, time::Duration};
+
+// 127.0.0.1 的自签名证书，返回 (证书 PEM, 私钥 PEM)
+fn self_signed() -> (Vec<u8>, Vec<u8>) {
+    let key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+    let mut name = X509NameBuilder::new().unwrap();
+    name.append_entry_by_text("CN", "127.0.0.1").unwrap();
+    let name = name.build();
+    let mut cert = X509::builder().unwrap();
+    cert.set_version(2).unwrap();
+    cert.set_subject_name(&name).unwrap();
+    cert.set_issuer_name(&name).unwrap();
+    cert.set_pubkey(&key).unwrap();
+    cert.set_not_before(&Asn1Time::days_from_now(0).unwrap()).unwrap();
+    cert.set_not_after(&Asn1Time::days_from_now(1).unwrap()).unwrap();
+    let san = SubjectAlternativeName::new().ip("127.0.0.1").build(&cert.x509v3_context(None, None)).unwrap();
+    cert.append_extension(san).unwrap();
+    cert.sign(&key, MessageDigest::sha256()).unwrap();
+    (cert.build().to_pem().unwrap(), key.private_key_to_pem_pkcs8().unwrap())
+}
+
+// 终止 TLS 后转发到 backend 端口，返回代理的端口
+fn tls_proxy(cert: &[u8], key: &[u8], backend: u16) -> u16 {
+    let identity = native_tls::Identity::from_pkcs8(cert, key).unwrap();
+    let acceptor = tokio_native_tls::TlsAcceptor::from(native_tls::TlsAcceptor::new(identity).unwrap());
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    listener.set_nonblocking(true).unwrap();
+    thread::spawn(move || {
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        runtime.block_on(async move {
+            let listener = tokio::net::TcpListener::from_std(listener).unwrap();
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let acceptor = acceptor.clone();
+                tokio::spawn(async move {
+                    // 证书校验失败时客户端直接断开
+                    let Ok(mut tls) = acceptor.accept(stream).await else {
+                        return;
+                    };
+                    let mut plain = tokio::net::TcpStream::connect(("127.0.0.1", backend)).await.unwrap();
+                    let _ = tokio::io::copy_bidirectional(&mut tls, &mut plain).await;
+                });
+            }
+        });
+    });
+    port
+}
+
+fn clone_over_https(url: &str, repo: &str) -> (bool, Server) {
+    let server = Server::spawn_url(url, ServerConfig::new().repo(repo));
+    let finished = common::wait_until(Duration::from_secs(30), || {
+        let log = server.log();
+        log.contains("Initial clone completed") || log.contains("Failed to clone repository")
+    });
+    assert!(finished, "{}", server.log());
+    (server.log().contains("Initial clone completed"), server)
+}
+
+#[test]
+fn self_signed_upstream() {
+    // 上游是另一个开启了 git_smart_http 的镜像，前面套一层 TLS
+    let upstream = Upstream::with_crates(&["serde"]);
+    let backend = Server::start(&upstream, ServerConfig::new().web("git_smart_http = true"));
+    let (cert, key) = self_signed();
+    let url = format!("https://127.0.0.1:{}/", tls_proxy(&cert, &key, backend.port));
+    let dir = tempfile::tempdir().unwrap();
+    let ca = dir.path().join("ca.pem");
+    fs::write(&ca, &cert).unwrap();
+
+    // 默认校验证书，自签名证书不被信任
+    let (cloned, server) = clone_over_https(&url, "");
+    assert!(!cloned, "{}", server.log());
+    assert!(server.log().contains("certificate"), "{}", server.log());
+    assert!(!server.index_path().join("se/rd/serde").exists());
+
+    let (cloned, server) = clone_over_https(&url, "ssl_verify = false");
+    assert!(cloned, "{}", server.log());
+    assert!(server.log().contains("repo.ssl_verify is disabled"), "{}", server.log());
+    server.wait_ready();
+    assert_eq!(server.get("/se/rd/serde").status(), 200);
+
+    let (cloned, server) = clone_over_https(&url, &format!("ssl_ca_cert = \"{}\"", ca.display()));
+    assert!(cloned, "{}", server.log());
+    assert!(!server.log().contains("repo.ssl_verify is disabled"));
+    server.wait_ready();
+    assert_eq!(server.get("/se/rd/serde").status(), 200);
+}
@@ -0,0 +1,76 @@
+mod common;
+
+use common::{index_line, index_path, wait_until, Server, ServerConfig, Upstream};
+use std::time::Duration;
+
+const PREFIXES: [&str; 4] = ["serde", "tokio", "rand", "log"];
+
+fn names() -> Vec<String> {
+    (0..10_000).map(|i| format!("{}-{}", PREFIXES[i % 4], i)).collect()
+}
+
+fn suggest(server: &Server, query: &str) -> Vec<String> {
+    let res = server.get(&format!("/api/v1/suggest?{}", query));
+    assert_eq!(res.status(), 200);
+    serde_json::from_str(&res.text().unwrap()).unwrap()
+}
+
+// 排序后线性查找的结果
+fn expected(names: &[String], prefix: &str, limit: usize) -> Vec<String> {
+    let mut names: Vec<&String> = names.iter().collect();
+    names.sort();
+    names.into_iter().filter(|name| name.starts_with(prefix)).take(limit).cloned().collect()
+}
+
+fn check_suggestions(trie_enabled: bool) {
+    let names = names();
+    let names_ref: Vec<&str> = names.iter().map(String::as_str).collect();
+    let upstream = Upstream::with_crates(&names_ref);
+    let server = Server::start(
+        &upstream,
+        ServerConfig::new()
+            .repo("update_cron = \"* * * * * *\"")
+            .rest(&format!("[search]\ntrie_enabled = {}", trie_enabled)),
+    );
+
+    assert_eq!(suggest(&server, "q=serde-1"), expected(&names, "serde-1", 10));
+    assert_eq!(suggest(&server, "q=tokio-99&limit=5"), expected(&names, "tokio-99", 5));
+    assert_eq!(suggest(&server, "q=ra&limit=20"), expected(&names, "ra", 20));
+    // 最多返回 20 个
+    assert_eq!(suggest(&server, "q=log&limit=100"), expected(&names, "log", 20));
+    assert_eq!(suggest(&server, "q=").len(), 10);
+    // 不区分大小写
+    assert_eq!(suggest(&server, "q=SERDE-12"), expected(&names, "serde-12", 10));
+    assert_eq!(suggest(&server, "q=rand-9999"), Vec::<String>::new());
+    assert_eq!(suggest(&server, "q=rand-9998"), ["rand-9998"]);
+    assert!(suggest(&server, "q=nothing").is_empty());
+
+    // pull 之后重新生成
+    upstream.commit(
+        "update",
+        &[
+            (&index_path("serde-new"), Some(&index_line("serde-new", "1.0.0"))),
+            (&index_path("tokio-9997"), None),
+        ],
+    );
+    let updated = wait_until(Duration::from_secs(15), || suggest(&server, "q=serde-n") == ["serde-new"]);
+    assert!(updated, "suggestions were not rebuilt:\n{}", server.log());
+    assert!(suggest(&server, "q=tokio-9997").is_empty());
+}
+
+#[test]
+fn sorted_list_suggestions() {
+    check_suggestions(false);
+}
+
+#[test]
+fn trie_suggestions() {
+    check_suggestions(true);
+}
+
+#[test]
+fn suggestions_can_be_disabled() {
+    let upstream = Upstream::with_crates(&["serde"]);
+    let server = Server::start(&upstream, ServerConfig::new().web("suggestions_enabled = false"));
+    assert_eq!(server.get("/api/v1/suggest?q=se").status(), 404);
+}
@@ -0,0 +1,41 @@
+mod common;
+
+use common::{free_port, Server, ServerConfig, Upstream};
+use std::time::Duration;
+
+fn console_config(bind: &str) -> ServerConfig {
+    ServerConfig::new().rest(&format!("[debug]\ntokio_console = true\ntokio_console_bind = {:?}", bind))
+}
+
+#[cfg(feature = "tokio-console")]
+#[test]
+fn console_subscriber_initializes() {
+    let upstream = Upstream::with_crates(&["serde"]);
+    let bind = format!("127.0.0.1:{}", free_port());
+    let server = Server::start(&upstream, console_config(&bind));
+    let listening = format!("tokio-console listening on {}", bind);
+    assert!(server.log().contains(&listening), "{}", server.log());
+    // console 的 gRPC 服务在监听，日志输出照常
+    assert!(common::wait_until(Duration::from_secs(5), || std::net::TcpStream::connect(&bind).is_ok()));
+    assert_eq!(server.get("/se/rd/serde").status(), 200);
+    assert!(server.log().contains("Web server started"), "{}", server.log());
+}
+
+#[cfg(not(feature = "tokio-console"))]
+#[test]
+fn console_needs_the_feature() {
+    let upstream = Upstream::with_crates(&["serde"]);
+    let bind = format!("127.0.0.1:{}", free_port());
+    let server = Server::start(&upstream, console_config(&bind));
+    assert!(server.log().contains("this build does not include the tokio-console feature"), "{}", server.log());
+    assert!(std::net::TcpStream::connect(&bind).is_err());
+}
+
+#[test]
+fn invalid_bind_address_is_rejected() {
+    let upstream = Upstream::with_crates(&["serde"]);
+    let mut server = Server::spawn(&upstream, console_config("localhost"));
+    let status = server.wait_exit(Duration::from_secs(10)).expect("server did not exit");
+    assert!(!status.success());
+    assert!(server.log().contains("Invalid debug.tokio_console_bind \"localhost\""), "{}", server.log());
+}
@@ -0,0 +1,45 @@
+mod common;
+
+use common::{HttpRecorder, Server, ServerConfig};
+use std::time::Duration;
+
+const NOT_FOUND: &str = "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+
+// 启动镜像，返回首次 clone 请求里的 User-Agent
+fn upstream_user_agent(config: ServerConfig) -> String {
+    let upstream = HttpRecorder::start(|_| NOT_FOUND.to_string());
+    let server = Server::spawn_url(&upstream.url("/index"), config);
+    assert!(
+        common::wait_until(Duration::from_secs(30), || !upstream.requests().is_empty()),
+        "no request reached the upstream:\n{}",
+        server.log()
+    );
+    let request = &upstream.requests()[0];
+    assert!(request.starts_with("GET /index/info/refs?service=git-upload-pack "), "{}", request);
+    common::header(request, "User-Agent").unwrap().to_string()
+}
+
+#[test]
+fn sends_default_user_agent() {
+    assert_eq!(
+        upstream_user_agent(ServerConfig::new()),
+        format!("git/2.0 (local-crates-io-index/{})", env!("CARGO_PKG_VERSION"))
+    );
+}
+
+#[test]
+fn sends_configured_user_agent() {
+    assert_eq!(
+        upstream_user_agent(ServerConfig::new().repo("user_agent = \"acme-mirror/7\"")),
+        "git/2.0 (acme-mirror/7)"
+    );
+}
+
+#[test]
+fn appends_hostname() {
+    let hostname = gethostname::gethostname().into_string().unwrap();
+    assert_eq!(
+        upstream_user_agent(ServerConfig::new().repo("user_agent = \"acme-mirror/7\"\nuser_agent_append_hostname = true")),
+        format!("git/2.0 (acme-mirror/7 ({}))", hostname)
+    );
+}